@@ -0,0 +1,74 @@
+-- Tables that have been introduced after the initial release.
+-- Every statement in this file has to be idempotent, as it is executed on every start.
+CREATE TABLE IF NOT EXISTS TrustedNetworks (
+	since INTEGER NOT NULL,
+	network TEXT NOT NULL PRIMARY KEY NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ClientCertificates (
+	serial TEXT NOT NULL PRIMARY KEY,
+	name TEXT NOT NULL,
+	username TEXT NOT NULL,
+	fingerprint TEXT NOT NULL,
+	issued_at INTEGER NOT NULL,
+	expires_at INTEGER NOT NULL,
+	revoked_at INTEGER
+);
+CREATE TABLE IF NOT EXISTS UpdatePolicy (
+	enabled INTEGER NOT NULL,
+	kind TEXT NOT NULL, -- security | all
+	days TEXT NOT NULL, -- Comma separated lowercase names of week days
+	window_start TEXT NOT NULL, -- HH:MM in local time
+	window_duration INTEGER NOT NULL, -- Minutes
+	reboot INTEGER NOT NULL,
+	id INTEGER PRIMARY KEY CHECK (id = 0) NOT NULL
+);
+CREATE TABLE IF NOT EXISTS UpdateRuns (
+	id TEXT NOT NULL PRIMARY KEY,
+	started_at INTEGER NOT NULL,
+	finished_at INTEGER,
+	triggered_by TEXT NOT NULL, -- scheduled | manual
+	kind TEXT NOT NULL, -- security | all
+	status TEXT NOT NULL, -- running | success | failed
+	reboot_required INTEGER,
+	rebooted INTEGER NOT NULL,
+	output TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS PackageJobs (
+	id TEXT NOT NULL PRIMARY KEY,
+	started_at INTEGER NOT NULL,
+	finished_at INTEGER,
+	username TEXT,
+	action TEXT NOT NULL, -- install | remove | update | updateAll | removeOrphaned | undo | downgrade
+	package_manager TEXT, -- NULL if every package manager was used
+	package TEXT,
+	status TEXT NOT NULL, -- running | success | failed
+	output TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS PackageIndex (
+	package_manager TEXT NOT NULL PRIMARY KEY,
+	installed TEXT NOT NULL, -- Newline separated package names
+	available TEXT NOT NULL, -- Newline separated package names
+	updates TEXT, -- Newline separated package names, NULL if the updates could not be listed
+	foreign_packages TEXT NOT NULL, -- Newline separated package names
+	refreshed_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS CronjobRuns (
+	id TEXT NOT NULL PRIMARY KEY,
+	job TEXT NOT NULL, -- History key of the cronjob
+	triggered_by TEXT NOT NULL, -- cron | manual
+	started_at INTEGER NOT NULL,
+	finished_at INTEGER,
+	status TEXT NOT NULL, -- running | success | failed
+	exit_code INTEGER, -- NULL if the command was killed by a signal or could not be started
+	stdout TEXT NOT NULL,
+	stderr TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS CronjobRunsJob ON CronjobRuns (job, started_at);
+CREATE TABLE IF NOT EXISTS SnapshotSchedules (
+	kind TEXT NOT NULL, -- btrfs | zfs
+	volume TEXT NOT NULL, -- Path of a btrfs subvolume or name of a ZFS dataset
+	hourly INTEGER NOT NULL, -- Number of snapshots kept
+	daily INTEGER NOT NULL,
+	weekly INTEGER NOT NULL,
+	PRIMARY KEY (kind, volume)
+);
@@ -0,0 +1,754 @@
+//! Client for the ACME protocol (RFC 8555) that obtains certificates from Let's Encrypt or any
+//! other ACME server.
+//!
+//! Control over the domains is proven using either the `http-01` challenge, which is answered by
+//! the `/.well-known/acme-challenge/{token}` route, or the `dns-01` challenge, for which a user
+//! provided hook command creates and removes TXT records.
+//!
+//! Issued certificates are stored as `acme.crt` and `acme.key` in the certificates directory,
+//! activated in the database and loaded into the running server without a restart.
+//! The account key is kept as `acme_account.pem` and reused for every order.
+
+use aws_lc_rs::rand::SystemRandom;
+use aws_lc_rs::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+use crate::AppState;
+use crate::certificates::{self, CertificateError, certificate_path, write_private};
+use crate::config::{AcmeChallengeType, AcmeConfig};
+
+/// File names of the certificate obtained through ACME and its private key.
+pub const ACME_CERTIFICATE_NAME: &str = "acme.crt";
+pub const ACME_KEY_NAME: &str = "acme.key";
+/// File name of the private key of the ACME account.
+const ACME_ACCOUNT_KEY_NAME: &str = "acme_account.pem";
+
+/// Pending `http-01` challenges mapped from token to key authorization.
+pub type HttpChallenges = Arc<Mutex<HashMap<String, String>>>;
+
+/// Number of times an order or authorization is checked before giving up.
+const POLL_ATTEMPTS: u32 = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Error)]
+pub enum AcmeError {
+    #[error("ACME is not configured.")]
+    NotConfigured,
+    #[error("The request to the ACME server failed: {0}")]
+    Http(#[from] ureq::Error),
+    #[error("The ACME server sent an unexpected response: {0}")]
+    UnexpectedResponse(String),
+    #[error("The ACME server rejected the request: {0}")]
+    Rejected(Problem),
+    #[error("The {0} challenge for {1} failed: {2}")]
+    ChallengeFailed(String, String, String),
+    #[error("The ACME server did not finish the request in time.")]
+    Timeout,
+    #[error("The DNS hook failed: {0}")]
+    Hook(String),
+    #[error("The account key is unusable: {0}")]
+    AccountKey(String),
+    #[error("The certificate request could not be created: {0}")]
+    CertificateRequest(#[from] rcgen::Error),
+    #[error("The certificate could not be stored: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The certificate could not be activated: {0}")]
+    Certificate(#[from] CertificateError),
+}
+
+/// Problem document (RFC 7807) returned by the ACME server.
+#[derive(Debug, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.detail, self.kind)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pending,
+    Ready,
+    Processing,
+    Valid,
+    Invalid,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: Status,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: Status,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+    error: Option<Problem>,
+}
+
+struct AcmeResponse {
+    location: Option<String>,
+    body: String,
+}
+
+/// Proves control over a domain for a single challenge.
+enum Solver<'a> {
+    Http(&'a HttpChallenges),
+    Dns { hook: &'a Path, delay: Duration },
+}
+
+impl Solver<'_> {
+    fn challenge_type(&self) -> &'static str {
+        match self {
+            Solver::Http(_) => "http-01",
+            Solver::Dns { .. } => "dns-01",
+        }
+    }
+
+    fn present(&self, domain: &str, token: &str, key_authorization: &str) -> Result<(), AcmeError> {
+        match self {
+            Solver::Http(challenges) => {
+                challenges
+                    .lock()
+                    .unwrap()
+                    .insert(token.to_string(), key_authorization.to_string());
+                Ok(())
+            }
+            Solver::Dns { hook, delay } => {
+                run_hook(hook, "present", domain, key_authorization)?;
+                debug!(
+                    "Waiting {}s for the TXT record to propagate",
+                    delay.as_secs()
+                );
+                std::thread::sleep(*delay);
+                Ok(())
+            }
+        }
+    }
+
+    fn cleanup(&self, domain: &str, token: &str, key_authorization: &str) {
+        match self {
+            Solver::Http(challenges) => {
+                challenges.lock().unwrap().remove(token);
+            }
+            Solver::Dns { hook, .. } => {
+                if let Err(e) = run_hook(hook, "cleanup", domain, key_authorization) {
+                    warn!("Removing the TXT record for {domain} failed: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Name of the TXT record used for the `dns-01` challenge of a domain.
+fn dns_record_name(domain: &str) -> String {
+    format!("_acme-challenge.{}", domain.trim_start_matches("*."))
+}
+
+/// Value of the TXT record used for the `dns-01` challenge.
+fn dns_record_value(key_authorization: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(key_authorization.as_bytes()))
+}
+
+fn run_hook(
+    hook: &Path,
+    action: &str,
+    domain: &str,
+    key_authorization: &str,
+) -> Result<(), AcmeError> {
+    let output = Command::new(hook)
+        .arg(action)
+        .arg(dns_record_name(domain))
+        .arg(dns_record_value(key_authorization))
+        .output()
+        .map_err(|e| AcmeError::Hook(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(AcmeError::Hook(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// A certificate chain and its private key in PEM format.
+pub struct IssuedCertificate {
+    pub chain: String,
+    pub key: String,
+}
+
+pub struct AcmeClient {
+    agent: ureq::Agent,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// Connects to an ACME server using the account key in PKCS#8 DER format.
+    ///
+    /// * `directory_url` - URL of the directory of the ACME server
+    /// * `account_key` - ECDSA P-256 key of the account
+    /// * `ca_certificate` - Root certificate the server is trusted with instead of the WebPKI roots
+    pub fn new(
+        directory_url: &str,
+        account_key: &[u8],
+        ca_certificate: Option<&[u8]>,
+    ) -> Result<Self, AcmeError> {
+        let mut tls_config = ureq::tls::TlsConfig::builder();
+        if let Some(pem) = ca_certificate {
+            let root = ureq::tls::Certificate::from_pem(pem)?;
+            tls_config =
+                tls_config.root_certs(ureq::tls::RootCerts::Specific(Arc::new(vec![root])));
+        }
+
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(Duration::from_secs(30)))
+            .tls_config(tls_config.build())
+            .build()
+            .into();
+
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, account_key)
+            .map_err(|e| AcmeError::AccountKey(e.to_string()))?;
+
+        let mut response = agent.get(directory_url).call()?;
+        let directory = serde_json::from_str(&response.body_mut().read_to_string()?)
+            .map_err(|e| AcmeError::UnexpectedResponse(e.to_string()))?;
+
+        Ok(AcmeClient {
+            agent,
+            directory,
+            key,
+            rng: SystemRandom::new(),
+            account_url: None,
+            nonce: None,
+        })
+    }
+
+    /// JSON Web Key of the public account key.
+    fn jwk(&self) -> Value {
+        let point = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    /// Thumbprint of the account key as described in RFC 7638.
+    fn thumbprint(&self) -> String {
+        let jwk = self.jwk();
+        // The members have to be in lexicographic order without any whitespace
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            jwk["x"].as_str().unwrap(),
+            jwk["y"].as_str().unwrap()
+        );
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+
+    fn key_authorization(&self, token: &str) -> String {
+        format!("{token}.{}", self.thumbprint())
+    }
+
+    fn fresh_nonce(&mut self) -> Result<String, AcmeError> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+
+        let response = self.agent.head(&self.directory.new_nonce).call()?;
+        header(&response, "replay-nonce")
+            .ok_or_else(|| AcmeError::UnexpectedResponse("The nonce is missing.".to_string()))
+    }
+
+    /// Creates a JWS in flattened JSON serialization. A missing payload results in a
+    /// POST-as-GET request.
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<String, AcmeError> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.account_url {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = payload
+            .map(|p| URL_SAFE_NO_PAD.encode(p.to_string()))
+            .unwrap_or_default();
+
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{protected}.{payload}").as_bytes())
+            .map_err(|e| AcmeError::AccountKey(e.to_string()))?;
+
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        })
+        .to_string())
+    }
+
+    fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<AcmeResponse, AcmeError> {
+        let mut retried = false;
+        loop {
+            let nonce = self.fresh_nonce()?;
+            let body = self.sign(url, &nonce, payload)?;
+
+            let mut response = self
+                .agent
+                .post(url)
+                .header("Content-Type", "application/jose+json")
+                .send(body.as_bytes())?;
+
+            self.nonce = header(&response, "replay-nonce");
+            let location = header(&response, "location");
+            let status = response.status();
+            let body = response.body_mut().read_to_string()?;
+
+            if status.is_success() {
+                return Ok(AcmeResponse { location, body });
+            }
+
+            let problem = serde_json::from_str::<Problem>(&body).unwrap_or(Problem {
+                kind: String::new(),
+                detail: format!("HTTP status {status}"),
+            });
+
+            // Nonces may expire or be rejected at any time, which calls for a single retry
+            if problem.kind == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+
+            return Err(AcmeError::Rejected(problem));
+        }
+    }
+
+    fn post_json<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<(T, Option<String>), AcmeError> {
+        let response = self.post(url, payload)?;
+        let parsed = serde_json::from_str(&response.body)
+            .map_err(|e| AcmeError::UnexpectedResponse(e.to_string()))?;
+        Ok((parsed, response.location))
+    }
+
+    /// Registers the account or looks up the existing account of the key.
+    pub fn register(&mut self, contact: &[String]) -> Result<(), AcmeError> {
+        let payload = json!({ "termsOfServiceAgreed": true, "contact": contact });
+        let new_account = self.directory.new_account.clone();
+        let response = self.post(&new_account, Some(&payload))?;
+
+        self.account_url = Some(response.location.ok_or_else(|| {
+            AcmeError::UnexpectedResponse("The account URL is missing.".to_string())
+        })?);
+
+        Ok(())
+    }
+
+    fn poll_order(&mut self, url: &str, until: &[Status]) -> Result<Order, AcmeError> {
+        for _ in 0..POLL_ATTEMPTS {
+            let (order, _) = self.post_json::<Order>(url, None)?;
+            if until.contains(&order.status) {
+                return Ok(order);
+            }
+            if order.status == Status::Invalid {
+                return Err(match order.error {
+                    Some(problem) => AcmeError::Rejected(problem),
+                    None => AcmeError::UnexpectedResponse("The order is invalid.".to_string()),
+                });
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Err(AcmeError::Timeout)
+    }
+
+    fn authorize(&mut self, url: &str, solver: &Solver) -> Result<(), AcmeError> {
+        let (authorization, _) = self.post_json::<Authorization>(url, None)?;
+
+        if authorization.status == Status::Valid {
+            return Ok(());
+        }
+
+        let domain = authorization.identifier.value;
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|c| c.kind == solver.challenge_type())
+            .ok_or_else(|| {
+                AcmeError::ChallengeFailed(
+                    solver.challenge_type().to_string(),
+                    domain.clone(),
+                    "The challenge is not offered by the server.".to_string(),
+                )
+            })?;
+
+        let key_authorization = self.key_authorization(&challenge.token);
+
+        solver.present(&domain, &challenge.token, &key_authorization)?;
+        let result = self.complete_challenge(url, &challenge, &domain);
+        solver.cleanup(&domain, &challenge.token, &key_authorization);
+
+        result
+    }
+
+    fn complete_challenge(
+        &mut self,
+        authorization_url: &str,
+        challenge: &Challenge,
+        domain: &str,
+    ) -> Result<(), AcmeError> {
+        self.post(&challenge.url, Some(&json!({})))?;
+
+        for _ in 0..POLL_ATTEMPTS {
+            std::thread::sleep(POLL_INTERVAL);
+            let (authorization, _) = self.post_json::<Authorization>(authorization_url, None)?;
+
+            match authorization.status {
+                Status::Valid => return Ok(()),
+                Status::Pending | Status::Processing => continue,
+                _ => {
+                    let reason = authorization
+                        .challenges
+                        .into_iter()
+                        .find(|c| c.url == challenge.url)
+                        .and_then(|c| c.error)
+                        .map(|p| p.to_string())
+                        .unwrap_or_else(|| "The authorization is invalid.".to_string());
+
+                    return Err(AcmeError::ChallengeFailed(
+                        challenge.kind.clone(),
+                        domain.to_string(),
+                        reason,
+                    ));
+                }
+            }
+        }
+
+        Err(AcmeError::Timeout)
+    }
+
+    /// Orders a certificate for the domains and completes all required challenges.
+    /// [`AcmeClient::register`] has to be called first.
+    fn order(
+        &mut self,
+        domains: &[String],
+        solver: &Solver,
+    ) -> Result<IssuedCertificate, AcmeError> {
+        let identifiers = domains
+            .iter()
+            .map(|d| json!({ "type": "dns", "value": d }))
+            .collect::<Vec<Value>>();
+
+        let new_order = self.directory.new_order.clone();
+        let (order, order_url) =
+            self.post_json::<Order>(&new_order, Some(&json!({ "identifiers": identifiers })))?;
+
+        let order_url = order_url.ok_or_else(|| {
+            AcmeError::UnexpectedResponse("The order URL is missing.".to_string())
+        })?;
+
+        for authorization in &order.authorizations {
+            self.authorize(authorization, solver)?;
+        }
+
+        self.poll_order(&order_url, &[Status::Ready, Status::Valid])?;
+
+        let certificate_key = rcgen::KeyPair::generate()?;
+        let mut params = rcgen::CertificateParams::new(domains.to_vec())?;
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, domains[0].clone());
+        let csr = params.serialize_request(&certificate_key)?;
+
+        self.post(
+            &order.finalize,
+            Some(&json!({ "csr": URL_SAFE_NO_PAD.encode(csr.der()) })),
+        )?;
+
+        let order = self.poll_order(&order_url, &[Status::Valid])?;
+        let certificate_url = order.certificate.ok_or_else(|| {
+            AcmeError::UnexpectedResponse("The certificate URL is missing.".to_string())
+        })?;
+
+        let chain = self.post(&certificate_url, None)?.body;
+
+        Ok(IssuedCertificate {
+            chain,
+            key: certificate_key.serialize_pem(),
+        })
+    }
+}
+
+fn header<T>(response: &ureq::http::Response<T>, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Loads the account key or creates a new one if none exists yet.
+fn account_key(path: &Path) -> Result<Vec<u8>, AcmeError> {
+    if let Ok(pem) = std::fs::read_to_string(path) {
+        let key_pair =
+            rcgen::KeyPair::from_pem(&pem).map_err(|e| AcmeError::AccountKey(e.to_string()))?;
+        return Ok(key_pair.serialize_der());
+    }
+
+    info!("Creating a new ACME account key");
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)?;
+    write_private(path, key_pair.serialize_pem().as_bytes())?;
+    Ok(key_pair.serialize_der())
+}
+
+/// Obtains a new certificate as configured.
+///
+/// * `config` - ACME settings of the server configuration
+/// * `account_key_path` - File the account key is loaded from or stored in
+/// * `challenges` - Pending `http-01` challenges that are served to the ACME server
+pub fn obtain_certificate(
+    config: &AcmeConfig,
+    account_key_path: &Path,
+    challenges: &HttpChallenges,
+) -> Result<IssuedCertificate, AcmeError> {
+    let ca_certificate = match &config.ca_certificate {
+        Some(path) => Some(std::fs::read(path)?),
+        None => None,
+    };
+
+    let mut client = AcmeClient::new(
+        &config.directory,
+        &account_key(account_key_path)?,
+        ca_certificate.as_deref(),
+    )?;
+    client.register(&config.contact)?;
+
+    let solver = match config.challenge {
+        AcmeChallengeType::Http01 => Solver::Http(challenges),
+        AcmeChallengeType::Dns01 => Solver::Dns {
+            hook: config
+                .dns_hook
+                .as_deref()
+                .ok_or_else(|| AcmeError::Hook("No DNS hook is configured.".to_string()))?,
+            delay: Duration::from_secs(config.dns_propagation_delay),
+        },
+    };
+
+    client.order(&config.domains, &solver)
+}
+
+/// Obtains a new certificate, stores it and uses it for all new connections.
+pub fn issue(state: &AppState) -> Result<(), AcmeError> {
+    let config = state.config.acme.as_ref().ok_or(AcmeError::NotConfigured)?;
+
+    info!("Requesting a certificate for {}", config.domains.join(", "));
+
+    let certificate = obtain_certificate(
+        config,
+        &certificate_path(ACME_ACCOUNT_KEY_NAME),
+        &state.acme_challenges,
+    )?;
+
+    certificates::store(
+        ACME_CERTIFICATE_NAME,
+        ACME_KEY_NAME,
+        certificate.chain.as_bytes(),
+        certificate.key.as_bytes(),
+    )?;
+    certificates::activate(state, ACME_CERTIFICATE_NAME, Some(ACME_KEY_NAME))?;
+
+    info!("Obtained and activated a new certificate from the ACME server");
+    Ok(())
+}
+
+/// Obtains a new certificate if there is none yet or the current one expires soon.
+/// Returns whether a new certificate has been issued.
+pub fn renew_if_due(state: &AppState) -> Result<bool, AcmeError> {
+    let config = state.config.acme.as_ref().ok_or(AcmeError::NotConfigured)?;
+
+    let renew_before = Duration::from_secs(config.renew_before_days * 24 * 60 * 60);
+
+    match certificates::expiry(&certificate_path(ACME_CERTIFICATE_NAME)) {
+        Ok(expires) if expires > SystemTime::now() + renew_before => {
+            debug!("The ACME certificate does not have to be renewed yet");
+            Ok(false)
+        }
+        _ => issue(state).map(|_| true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+    use std::path::PathBuf;
+
+    fn client() -> AcmeClient {
+        let key = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        AcmeClient {
+            agent: ureq::Agent::new_with_defaults(),
+            directory: Directory {
+                new_nonce: String::new(),
+                new_account: String::new(),
+                new_order: String::new(),
+            },
+            key: EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &key.serialize_der())
+                .unwrap(),
+            rng: SystemRandom::new(),
+            account_url: None,
+            nonce: None,
+        }
+    }
+
+    #[test]
+    fn signature_is_verifiable() {
+        let mut client = client();
+        let payload = json!({ "identifiers": [] });
+
+        let jws: Value =
+            serde_json::from_str(&client.sign("https://a/b", "abc", Some(&payload)).unwrap())
+                .unwrap();
+        let protected: Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(jws["protected"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(protected["nonce"], "abc");
+        assert_eq!(protected["jwk"]["crv"], "P-256");
+
+        let message = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, client.key.public_key().as_ref())
+            .verify(message.as_bytes(), &signature)
+            .expect("The signature should be valid");
+
+        // Once the account is known, it is referenced instead of embedding the key
+        client.account_url = Some("https://a/acct/1".to_string());
+        let jws: Value =
+            serde_json::from_str(&client.sign("https://a/b", "abc", None).unwrap()).unwrap();
+        assert_eq!(jws["payload"], "");
+        let protected: Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(jws["protected"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(protected["kid"], "https://a/acct/1");
+        assert!(protected.get("jwk").is_none());
+    }
+
+    #[test]
+    fn key_authorization_uses_thumbprint() {
+        let client = client();
+        let thumbprint = client.thumbprint();
+        assert_eq!(thumbprint.len(), 43);
+        assert_eq!(
+            client.key_authorization("token"),
+            format!("token.{thumbprint}")
+        );
+    }
+
+    #[test]
+    fn dns_record() {
+        assert_eq!(
+            dns_record_name("*.example.com"),
+            "_acme-challenge.example.com"
+        );
+        // Unpadded base64url of the SHA-256 digest of "abc"
+        assert_eq!(
+            dns_record_value("abc"),
+            "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0"
+        );
+    }
+
+    /// Requests a certificate from a local Pebble instance started with
+    /// `PEBBLE_VA_ALWAYS_VALID=1`, so that challenges do not have to be reachable.
+    ///
+    /// `ZENTROX_PEBBLE_DIRECTORY` has to contain the directory URL (e.g.
+    /// `https://localhost:14000/dir`) and `ZENTROX_PEBBLE_CA` the path of Pebble's
+    /// `pebble.minica.pem`.
+    #[test]
+    #[ignore]
+    fn issue_with_pebble() {
+        let directory = std::env::var("ZENTROX_PEBBLE_DIRECTORY").unwrap();
+        let ca_certificate = std::env::var("ZENTROX_PEBBLE_CA").unwrap();
+
+        let config = AcmeConfig {
+            directory,
+            domains: vec!["zentrox.test".to_string()],
+            ca_certificate: Some(PathBuf::from(ca_certificate)),
+            ..AcmeConfig::default()
+        };
+
+        let account_key_path = std::env::temp_dir().join("zentrox_pebble_account.pem");
+        let challenges = HttpChallenges::default();
+
+        let certificate = obtain_certificate(&config, &account_key_path, &challenges)
+            .expect("Pebble should issue a certificate");
+
+        assert!(certificate.chain.contains("BEGIN CERTIFICATE"));
+        assert!(challenges.lock().unwrap().is_empty());
+    }
+}
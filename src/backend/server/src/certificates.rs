@@ -0,0 +1,385 @@
+//! Certificates used by the HTTPS server.
+//!
+//! The TLS configuration does not contain a fixed certificate, but asks the
+//! [`CertificateResolver`] during every handshake. Replacing the certificate of the resolver
+//! therefore takes effect for all new connections without restarting the server.
+//!
+//! A certificate and its private key are stored as separate PEM files in the `certificates`
+//! directory. Installations from before this split use a single file containing both, in which
+//! case no key file is configured and the key is read from the certificate file.
+
+use diesel::prelude::*;
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use utoipa::ToSchema;
+use x509_parser::extensions::GeneralName;
+
+use crate::{AppState, get_zentrox_directory};
+
+/// File names of the self-signed certificate generated by Zentrox.
+pub const SELF_SIGNED_CERTIFICATE_NAME: &str = "selfsigned.crt";
+pub const SELF_SIGNED_KEY_NAME: &str = "selfsigned.key";
+
+#[derive(Debug, Error)]
+pub enum CertificateError {
+    #[error("The certificate file could not be read: {0}")]
+    Unreadable(#[from] std::io::Error),
+    #[error("The file does not contain a certificate.")]
+    NoCertificate,
+    #[error("The file does not contain a private key.")]
+    NoPrivateKey,
+    #[error("The certificate is malformed.")]
+    Malformed,
+    #[error("The certificate can not be used: {0}")]
+    Unusable(#[from] rustls::Error),
+    #[error("The certificate could not be generated: {0}")]
+    Generation(#[from] rcgen::Error),
+    #[error("The certificate could not be activated: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Details of a certificate as shown to the user.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses the certificate is valid for
+    pub subject_alt_names: Vec<String>,
+    /// Unix timestamp in seconds
+    pub not_before: i64,
+    /// Unix timestamp in seconds
+    pub not_after: i64,
+    /// SHA-256 fingerprint as colon separated hex
+    pub fingerprint: String,
+    pub serial: String,
+    pub self_signed: bool,
+}
+
+/// Path of a certificate file in the `certificates` directory of the data directory.
+pub fn certificate_path(name: &str) -> PathBuf {
+    get_zentrox_directory().join("certificates").join(name)
+}
+
+/// Builds a certificate and its matching private key from PEM data.
+/// The key may also be contained in `certificate`, in which case `key` can be the same data.
+pub fn parse_certified_key(
+    certificate: &[u8],
+    key: &[u8],
+) -> Result<CertifiedKey, CertificateError> {
+    let certs = rustls_pemfile::certs(&mut &*certificate).collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        return Err(CertificateError::NoCertificate);
+    }
+
+    let key = rustls_pemfile::private_key(&mut &*key)?.ok_or(CertificateError::NoPrivateKey)?;
+
+    let provider = CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+
+    Ok(CertifiedKey::from_der(certs, key, &provider)?)
+}
+
+/// Reads a certificate chain and its private key. Without a key file, the key is read from the
+/// certificate file.
+pub fn load_certified_key(
+    certificate: &Path,
+    key: Option<&Path>,
+) -> Result<CertifiedKey, CertificateError> {
+    let certificate = std::fs::read(certificate)?;
+    let key = match key {
+        Some(path) => std::fs::read(path)?,
+        None => certificate.clone(),
+    };
+    parse_certified_key(&certificate, &key)
+}
+
+/// Extracts the details of the first certificate in PEM data.
+pub fn inspect(pem: &[u8]) -> Result<CertificateInfo, CertificateError> {
+    let (_, pem) =
+        x509_parser::pem::parse_x509_pem(pem).map_err(|_| CertificateError::NoCertificate)?;
+    let certificate = pem.parse_x509().map_err(|_| CertificateError::Malformed)?;
+
+    let subject_alt_names = certificate
+        .subject_alternative_name()
+        .map_err(|_| CertificateError::Malformed)?
+        .map(|extension| {
+            extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(bytes) => match bytes.len() {
+                        4 => Some(
+                            std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*bytes).ok()?).to_string(),
+                        ),
+                        16 => Some(
+                            std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*bytes).ok()?)
+                                .to_string(),
+                        ),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let fingerprint = Sha256::digest(&pem.contents)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<String>>()
+        .join(":");
+
+    Ok(CertificateInfo {
+        subject: certificate.subject().to_string(),
+        issuer: certificate.issuer().to_string(),
+        subject_alt_names,
+        not_before: certificate.validity().not_before.timestamp(),
+        not_after: certificate.validity().not_after.timestamp(),
+        fingerprint,
+        serial: certificate.raw_serial_as_string(),
+        self_signed: certificate.subject() == certificate.issuer(),
+    })
+}
+
+/// Time at which the first certificate in a PEM file expires.
+pub fn expiry(path: &Path) -> Result<SystemTime, CertificateError> {
+    let info = inspect(&std::fs::read(path)?)?;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(info.not_after.max(0) as u64))
+}
+
+/// Names a self-signed certificate is issued for if none are specified.
+pub fn default_subject_alt_names() -> Vec<String> {
+    let mut names = vec!["localhost".to_string()];
+    if let Ok(hostname) = std::fs::read_to_string("/etc/hostname")
+        && !hostname.trim().is_empty()
+        && hostname.trim() != "localhost"
+    {
+        names.push(hostname.trim().to_string());
+    }
+    names
+}
+
+/// Creates a self-signed certificate and returns the certificate and key in PEM format.
+///
+/// * `subject_alt_names` - DNS names or IP addresses the certificate is valid for
+/// * `valid_days` - Number of days the certificate is valid for starting now
+pub fn generate_self_signed(
+    subject_alt_names: Vec<String>,
+    valid_days: u32,
+) -> Result<(String, String), CertificateError> {
+    let key_pair = rcgen::KeyPair::generate()?;
+    let mut params = rcgen::CertificateParams::new(subject_alt_names.clone())?;
+
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(
+        rcgen::DnType::CommonName,
+        subject_alt_names
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "Zentrox".to_string()),
+    );
+    params
+        .distinguished_name
+        .push(rcgen::DnType::OrganizationName, "Zentrox");
+
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now - time::Duration::days(1);
+    params.not_after = now + time::Duration::days(valid_days.into());
+
+    let certificate = params.self_signed(&key_pair)?;
+
+    Ok((certificate.pem(), key_pair.serialize_pem()))
+}
+
+/// Writes a file that is only readable by the current user.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents)
+}
+
+/// Stores a certificate and its key in the certificates directory, replacing existing files.
+/// The pair is validated before anything is written.
+pub fn store(
+    certificate_name: &str,
+    key_name: &str,
+    certificate: &[u8],
+    key: &[u8],
+) -> Result<(), CertificateError> {
+    parse_certified_key(certificate, key)?;
+
+    // Replacing the files through a rename prevents a half written certificate from being loaded
+    for (name, contents) in [(certificate_name, certificate), (key_name, key)] {
+        let path = certificate_path(name);
+        let staging_path = PathBuf::from(format!("{}.new", path.display()));
+        write_private(&staging_path, contents)?;
+        std::fs::rename(&staging_path, &path)?;
+    }
+
+    Ok(())
+}
+
+/// Uses a stored certificate for all new connections and remembers it for the next start.
+///
+/// * `certificate_name` - Name of the certificate file in the certificates directory
+/// * `key_name` - Name of the key file, `None` if the key is part of the certificate file
+pub fn activate(
+    state: &AppState,
+    certificate_name: &str,
+    key_name: Option<&str>,
+) -> Result<(), CertificateError> {
+    use utils::schema::Configuration::dsl::*;
+
+    state.certificates.reload(
+        &certificate_path(certificate_name),
+        key_name.map(certificate_path).as_deref(),
+    )?;
+
+    diesel::update(Configuration)
+        .set((
+            tls_cert.eq(certificate_name),
+            tls_key.eq(key_name.map(str::to_string)),
+        ))
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    Ok(())
+}
+
+/// Creates, stores and activates a new self-signed certificate.
+pub fn regenerate_self_signed(
+    state: &AppState,
+    subject_alt_names: Vec<String>,
+    valid_days: u32,
+) -> Result<CertificateInfo, CertificateError> {
+    let (certificate, key) = generate_self_signed(subject_alt_names, valid_days)?;
+    store(
+        SELF_SIGNED_CERTIFICATE_NAME,
+        SELF_SIGNED_KEY_NAME,
+        certificate.as_bytes(),
+        key.as_bytes(),
+    )?;
+    activate(
+        state,
+        SELF_SIGNED_CERTIFICATE_NAME,
+        Some(SELF_SIGNED_KEY_NAME),
+    )?;
+    inspect(certificate.as_bytes())
+}
+
+/// Uses a new self-signed certificate until Zentrox is restarted. Neither the certificate nor the
+/// configuration is stored, so the configured certificate is tried again on the next start.
+pub fn use_temporary_self_signed(resolver: &CertificateResolver) -> Result<(), CertificateError> {
+    let (certificate, key) = generate_self_signed(default_subject_alt_names(), 3650)?;
+    resolver.set(parse_certified_key(certificate.as_bytes(), key.as_bytes())?);
+    Ok(())
+}
+
+/// Provides the certificate that is currently in use to rustls.
+#[derive(Debug, Default)]
+pub struct CertificateResolver {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl CertificateResolver {
+    /// Loads a certificate and uses it for all following handshakes.
+    /// The certificate in use is kept if the files can not be loaded.
+    pub fn reload(&self, certificate: &Path, key: Option<&Path>) -> Result<(), CertificateError> {
+        let certified_key = load_certified_key(certificate, key)?;
+        self.set(certified_key);
+        Ok(())
+    }
+
+    /// Uses a certificate for all following handshakes without storing it.
+    pub fn set(&self, certified_key: CertifiedKey) {
+        *self.current.write().unwrap() = Some(Arc::new(certified_key));
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_certificate_can_be_inspected() {
+        let (certificate, key) = generate_self_signed(
+            vec!["zentrox.local".to_string(), "192.168.1.2".to_string()],
+            30,
+        )
+        .unwrap();
+
+        parse_certified_key(certificate.as_bytes(), key.as_bytes())
+            .expect("The generated pair should match");
+
+        let info = inspect(certificate.as_bytes()).unwrap();
+        assert_eq!(info.subject_alt_names, vec!["zentrox.local", "192.168.1.2"]);
+        assert!(info.subject.contains("CN=zentrox.local"));
+        assert!(info.self_signed);
+        assert_eq!(info.fingerprint.len(), 32 * 3 - 1);
+        let validity = info.not_after - info.not_before;
+        assert_eq!(validity, 31 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn mismatching_key_is_rejected() {
+        let (certificate, _) = generate_self_signed(vec!["a.local".to_string()], 1).unwrap();
+        let (_, other_key) = generate_self_signed(vec!["b.local".to_string()], 1).unwrap();
+
+        assert!(parse_certified_key(certificate.as_bytes(), other_key.as_bytes()).is_err());
+        assert!(matches!(
+            parse_certified_key(certificate.as_bytes(), certificate.as_bytes()),
+            Err(CertificateError::NoPrivateKey)
+        ));
+        assert!(matches!(
+            inspect(b"not a certificate"),
+            Err(CertificateError::NoCertificate)
+        ));
+    }
+
+    #[test]
+    fn combined_file_is_accepted() {
+        let (certificate, key) = generate_self_signed(vec!["a.local".to_string()], 1).unwrap();
+        let combined = format!("{key}{certificate}");
+
+        parse_certified_key(combined.as_bytes(), combined.as_bytes())
+            .expect("A file with key and certificate should be accepted");
+    }
+
+    #[test]
+    fn temporary_certificate_is_only_kept_in_memory() {
+        let resolver = CertificateResolver::default();
+        let missing = std::env::temp_dir().join("zentrox_missing_certificate.pem");
+        assert!(resolver.reload(&missing, None).is_err());
+        assert!(resolver.current.read().unwrap().is_none());
+
+        use_temporary_self_signed(&resolver).unwrap();
+        assert!(resolver.current.read().unwrap().is_some());
+        assert!(!missing.exists());
+    }
+}
@@ -0,0 +1,93 @@
+use clap::{Parser, Subcommand};
+use ipnet::IpNet;
+use log::LevelFilter;
+use std::path::PathBuf;
+
+use crate::config::{BindAddress, ProxyHeader};
+use crate::permissions::parse_network;
+
+fn parse_trusted_proxy(value: &str) -> Result<IpNet, String> {
+    parse_network(value).ok_or_else(|| format!("{value} is not a valid IP address or network."))
+}
+
+/// Zentrox is a Linux server administration application.
+///
+/// Flags take precedence over the values in the configuration file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the TOML configuration file [env: ZENTROX_CONFIG] [default:
+    /// ~/.config/zentrox/config.toml]
+    #[arg(long, short, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, either an IPv4/IPv6 address or `unix:/path/to/socket`.
+    /// Can be repeated.
+    #[arg(long, short, value_name = "ADDRESS")]
+    pub bind: Vec<BindAddress>,
+
+    /// Port used for all IP bind addresses
+    #[arg(long, short, value_name = "PORT")]
+    pub port: Option<u16>,
+
+    /// Directory for the database, certificates and other data
+    #[arg(long, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Level of messages written to the terminal (off, error, warn, info, debug, trace)
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Log file that is appended to
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Duration in seconds that a session may be alive
+    #[arg(long, value_name = "SECONDS")]
+    pub session_ttl: Option<u64>,
+
+    /// Address or network of a reverse proxy that is trusted to forward client addresses.
+    /// Can be repeated.
+    #[arg(long, value_name = "NETWORK", value_parser = parse_trusted_proxy)]
+    pub trusted_proxy: Vec<IpNet>,
+
+    /// Header the trusted proxies report the client address in
+    #[arg(long, value_name = "HEADER", value_enum)]
+    pub proxy_header: Option<ProxyHeader>,
+
+    /// Address an unencrypted HTTP server listens on for use behind a reverse proxy.
+    /// Can be repeated.
+    #[arg(long, value_name = "ADDRESS")]
+    pub http_bind: Vec<BindAddress>,
+
+    /// Port used for all IP addresses of the HTTP server
+    #[arg(long, value_name = "PORT")]
+    pub http_port: Option<u16>,
+
+    /// Permit any request to restricted routes. Only use this for development.
+    #[arg(long)]
+    pub no_auth: bool,
+
+    /// Use a permissive CORS policy. Only use this for development.
+    #[arg(long)]
+    pub no_cors: bool,
+
+    /// Generate the OpenAPI documentation in JSON format and store it at PATH or print it
+    #[arg(long, value_name = "PATH")]
+    pub docs: Option<Option<PathBuf>>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Run the command of a cronjob and record the run in its history. This is called by cron
+    /// for cronjobs whose runs are recorded.
+    RunJob {
+        /// Key of the history of the cronjob
+        key: String,
+        /// Command that is run using `sh -c`
+        command: String,
+    },
+}
@@ -0,0 +1,451 @@
+//! Client certificate authentication (mutual TLS).
+//!
+//! Zentrox keeps a private CA in the certificates directory, which issues client certificates
+//! for accounts. Depending on [`ClientCertificateMode`], the HTTPS server asks clients for such a
+//! certificate during the handshake and rejects certificates that were not issued by the CA or
+//! have been revoked.
+//!
+//! The serial number of the certificate presented on a connection is stored as [`PeerCertificate`]
+//! in the connection data, so routes can map it to the account it was issued for.
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::HttpRequest;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use diesel::prelude::*;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::pki_types::{CertificateDer, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme};
+use std::any::Any;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use utils::models::ClientCertificate;
+
+use crate::AppState;
+use crate::certificates::{self, CertificateError, certificate_path};
+use crate::config::ClientCertificateMode;
+
+/// File names of the private CA that issues client certificates.
+pub const CA_CERTIFICATE_NAME: &str = "client_ca.crt";
+pub const CA_KEY_NAME: &str = "client_ca.key";
+
+/// Serial numbers of revoked client certificates.
+pub type RevokedSerials = Arc<RwLock<HashSet<String>>>;
+
+#[derive(Debug, Error)]
+pub enum ClientCertificateError {
+    #[error("{0}")]
+    Certificate(#[from] CertificateError),
+    #[error("The certificate could not be generated: {0}")]
+    Generation(#[from] rcgen::Error),
+    #[error("The client certificate verifier could not be built: {0}")]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+    #[error("The certificate could not be stored: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Client certificate presented during the TLS handshake of a connection.
+#[derive(Clone, Debug)]
+pub struct PeerCertificate {
+    pub serial: String,
+}
+
+/// A newly issued client certificate and its private key in PEM format.
+/// The key is not stored by Zentrox.
+pub struct IssuedClientCertificate {
+    pub certificate: String,
+    pub key: String,
+    pub record: ClientCertificate,
+}
+
+/// Serial number of a DER encoded certificate in the format used by [`certificates::inspect`].
+fn serial_of(certificate: &[u8]) -> Option<String> {
+    x509_parser::parse_x509_certificate(certificate)
+        .ok()
+        .map(|(_, c)| c.raw_serial_as_string())
+}
+
+/// Parameters of the CA. They are rebuilt to sign certificates, which works as long as the
+/// distinguished name and key stay the same.
+fn ca_params() -> rcgen::CertificateParams {
+    let mut params = rcgen::CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "Zentrox Client CA");
+    params
+        .distinguished_name
+        .push(rcgen::DnType::OrganizationName, "Zentrox");
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        rcgen::KeyUsagePurpose::KeyCertSign,
+        rcgen::KeyUsagePurpose::CrlSign,
+        rcgen::KeyUsagePurpose::DigitalSignature,
+    ];
+
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now - time::Duration::days(1);
+    params.not_after = now + time::Duration::days(365 * 20);
+    params
+}
+
+/// Creates the CA unless it already exists.
+pub fn ensure_ca() -> Result<(), ClientCertificateError> {
+    if certificate_path(CA_CERTIFICATE_NAME).exists() && certificate_path(CA_KEY_NAME).exists() {
+        return Ok(());
+    }
+
+    let key_pair = rcgen::KeyPair::generate()?;
+    let certificate = ca_params().self_signed(&key_pair)?;
+
+    certificates::store(
+        CA_CERTIFICATE_NAME,
+        CA_KEY_NAME,
+        certificate.pem().as_bytes(),
+        key_pair.serialize_pem().as_bytes(),
+    )?;
+
+    Ok(())
+}
+
+/// Certificate of the CA in PEM format.
+pub fn ca_certificate() -> Result<String, ClientCertificateError> {
+    ensure_ca()?;
+    Ok(
+        std::fs::read_to_string(certificate_path(CA_CERTIFICATE_NAME))
+            .map_err(CertificateError::from)?,
+    )
+}
+
+/// Creates a key pair and a certificate for client authentication signed by the CA.
+fn sign(
+    issuer: &rcgen::Certificate,
+    issuer_key: &rcgen::KeyPair,
+    name: &str,
+    account: &str,
+    valid_days: u32,
+) -> Result<(rcgen::Certificate, rcgen::KeyPair), rcgen::Error> {
+    let key_pair = rcgen::KeyPair::generate()?;
+    let mut params = rcgen::CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, name);
+    params
+        .distinguished_name
+        .push(rcgen::DnType::OrganizationalUnitName, account);
+
+    // The most significant bit is cleared to keep the serial number positive
+    let mut serial_bytes = rand::random::<[u8; 16]>();
+    serial_bytes[0] = (serial_bytes[0] & 0x7f) | 0x01;
+    params.serial_number = Some(rcgen::SerialNumber::from_slice(&serial_bytes));
+
+    params.key_usages = vec![rcgen::KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+    params.use_authority_key_identifier_extension = true;
+
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now - time::Duration::minutes(5);
+    params.not_after = now + time::Duration::days(valid_days.into());
+
+    let certificate = params.signed_by(&key_pair, issuer, issuer_key)?;
+    Ok((certificate, key_pair))
+}
+
+/// Issues a certificate for an account and records it in the database.
+///
+/// * `name` - Name of the device or person the certificate is used by
+/// * `account` - Username of the account the certificate belongs to
+/// * `valid_days` - Number of days the certificate is valid for starting now
+pub fn issue(
+    state: &AppState,
+    name: &str,
+    account: &str,
+    valid_days: u32,
+) -> Result<IssuedClientCertificate, ClientCertificateError> {
+    ensure_ca()?;
+
+    let ca_key = rcgen::KeyPair::from_pem(
+        &std::fs::read_to_string(certificate_path(CA_KEY_NAME)).map_err(CertificateError::from)?,
+    )?;
+    let issuer = ca_params().self_signed(&ca_key)?;
+
+    let (certificate, key_pair) = sign(&issuer, &ca_key, name, account, valid_days)?;
+    let info = certificates::inspect(certificate.pem().as_bytes())?;
+
+    let record = ClientCertificate {
+        serial: info.serial,
+        name: name.to_string(),
+        username: account.to_string(),
+        fingerprint: info.fingerprint,
+        issued_at: utils::time::current_timestamp_unix() as i64,
+        expires_at: info.not_after,
+        revoked_at: None,
+    };
+
+    diesel::insert_into(utils::schema::ClientCertificates::table)
+        .values(record.clone())
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    Ok(IssuedClientCertificate {
+        certificate: certificate.pem(),
+        key: key_pair.serialize_pem(),
+        record,
+    })
+}
+
+/// Revokes a certificate, which immediately prevents new connections using it.
+///
+/// Returns the number of revoked database entries.
+pub fn revoke(state: &AppState, revoked_serial: &str) -> Result<usize, diesel::result::Error> {
+    use utils::schema::ClientCertificates::dsl::*;
+
+    let affected = diesel::update(ClientCertificates)
+        .filter(serial.eq(revoked_serial))
+        .filter(revoked_at.is_null())
+        .set(revoked_at.eq(utils::time::current_timestamp_unix() as i64))
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    if affected > 0 {
+        state
+            .revoked_client_certificates
+            .write()
+            .unwrap()
+            .insert(revoked_serial.to_string());
+    }
+
+    Ok(affected)
+}
+
+/// Given a reference of the app state, this function will read the serial numbers of revoked
+/// certificates from the database into the app state.
+pub fn load_revoked(state: &AppState) {
+    use utils::schema::ClientCertificates::dsl::*;
+
+    let serials: Vec<String> = ClientCertificates
+        .select(serial)
+        .filter(revoked_at.is_not_null())
+        .get_results(&mut state.db_pool.lock().unwrap().get().unwrap())
+        .unwrap();
+
+    state
+        .revoked_client_certificates
+        .write()
+        .unwrap()
+        .extend(serials);
+}
+
+/// Account of the valid client certificate the request was sent with.
+pub fn certificate_account(req: &HttpRequest, state: &AppState) -> Option<String> {
+    use utils::schema::ClientCertificates::dsl::*;
+
+    let peer = req.conn_data::<PeerCertificate>()?;
+
+    // The connection may have been established before the certificate was revoked
+    if state
+        .revoked_client_certificates
+        .read()
+        .unwrap()
+        .contains(&peer.serial)
+    {
+        return None;
+    }
+
+    ClientCertificates
+        .select(username)
+        .filter(serial.eq(&peer.serial))
+        .filter(revoked_at.is_null())
+        .filter(expires_at.gt(utils::time::current_timestamp_unix() as i64))
+        .first(&mut state.db_pool.lock().unwrap().get().unwrap())
+        .ok()
+}
+
+/// Stores the client certificate of a new TLS connection in its connection data.
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>()
+        && let Some(certificate) = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|c| c.first())
+        && let Some(serial) = serial_of(certificate)
+    {
+        data.insert(PeerCertificate { serial });
+    }
+}
+
+/// Verifies client certificates against the CA and rejects revoked certificates.
+#[derive(Debug)]
+pub struct RevocationCheckingVerifier {
+    inner: Arc<dyn ClientCertVerifier>,
+    revoked: RevokedSerials,
+}
+
+impl RevocationCheckingVerifier {
+    /// Builds a verifier that trusts the CA of Zentrox.
+    /// Clients without a certificate are only accepted in the optional mode.
+    pub fn new(
+        mode: ClientCertificateMode,
+        revoked: RevokedSerials,
+    ) -> Result<Self, ClientCertificateError> {
+        let pem = ca_certificate()?;
+        let mut roots = RootCertStore::empty();
+        for certificate in rustls_pemfile::certs(&mut pem.as_bytes()) {
+            roots
+                .add(certificate.map_err(CertificateError::from)?)
+                .map_err(CertificateError::from)?;
+        }
+
+        Self::with_roots(roots, mode, revoked)
+    }
+
+    fn with_roots(
+        roots: RootCertStore,
+        mode: ClientCertificateMode,
+        revoked: RevokedSerials,
+    ) -> Result<Self, ClientCertificateError> {
+        let mut builder = WebPkiClientVerifier::builder(Arc::new(roots));
+        if mode != ClientCertificateMode::Required {
+            builder = builder.allow_unauthenticated();
+        }
+
+        Ok(RevocationCheckingVerifier {
+            inner: builder.build()?,
+            revoked,
+        })
+    }
+}
+
+impl ClientCertVerifier for RevocationCheckingVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_client_cert(end_entity, intermediates, now)?;
+
+        let serial = serial_of(end_entity).ok_or(rustls::Error::InvalidCertificate(
+            rustls::CertificateError::BadEncoding,
+        ))?;
+
+        if self.revoked.read().unwrap().contains(&serial) {
+            return Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::Revoked,
+            ));
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revoked_certificates_are_rejected() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let ca = ca_params().self_signed(&ca_key).unwrap();
+        let (certificate, _) = sign(&ca, &ca_key, "Laptop", "admin", 30).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let revoked = RevokedSerials::default();
+        let verifier = RevocationCheckingVerifier::with_roots(
+            roots,
+            ClientCertificateMode::Required,
+            revoked.clone(),
+        )
+        .unwrap();
+        assert!(verifier.client_auth_mandatory());
+
+        verifier
+            .verify_client_cert(certificate.der(), &[], UnixTime::now())
+            .expect("A certificate issued by the CA should be accepted");
+
+        let serial = serial_of(certificate.der()).unwrap();
+        assert_eq!(
+            serial,
+            certificates::inspect(certificate.pem().as_bytes())
+                .unwrap()
+                .serial
+        );
+        revoked.write().unwrap().insert(serial);
+
+        assert!(matches!(
+            verifier.verify_client_cert(certificate.der(), &[], UnixTime::now()),
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::Revoked
+            ))
+        ));
+    }
+
+    #[test]
+    fn foreign_certificates_are_rejected() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let ca = ca_params().self_signed(&ca_key).unwrap();
+        let other_key = rcgen::KeyPair::generate().unwrap();
+        let other = ca_params().self_signed(&other_key).unwrap();
+        let (certificate, _) = sign(&other, &other_key, "Laptop", "admin", 30).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let verifier = RevocationCheckingVerifier::with_roots(
+            roots,
+            ClientCertificateMode::Optional,
+            RevokedSerials::default(),
+        )
+        .unwrap();
+        assert!(!verifier.client_auth_mandatory());
+
+        assert!(
+            verifier
+                .verify_client_cert(certificate.der(), &[], UnixTime::now())
+                .is_err()
+        );
+    }
+}
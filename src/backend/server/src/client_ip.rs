@@ -0,0 +1,281 @@
+//! Resolution of the client address for requests that may have passed through reverse proxies.
+//!
+//! The address of the connected peer is only replaced by an address from the forwarding header if
+//! the peer is a trusted proxy. Only the header configured as `proxy_header` is read, as a client
+//! could send the other one itself. Forwarded addresses are read from right to left, skipping
+//! trusted proxies, so a client can not spoof its address by adding addresses to the header.
+//! Connections over a Unix socket have no peer address and are always treated as coming from a
+//! trusted proxy.
+
+use actix_governor::{KeyExtractor, SimpleKeyExtractionError};
+use actix_web::HttpRequest;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::HeaderMap;
+use ipnet::IpNet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+
+use crate::AppState;
+use crate::config::ProxyHeader;
+
+/// Parses a single node of a `Forwarded` header (RFC 7239) or an entry of `X-Forwarded-For`.
+/// Ports and the brackets around IPv6 addresses are removed. Obfuscated identifiers and
+/// `unknown` yield `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = IpAddr::from_str(node) {
+        return Some(ip);
+    }
+    if let Ok(socket) = SocketAddr::from_str(node) {
+        return Some(socket.ip());
+    }
+    // IPv6 address in brackets without a port
+    IpAddr::from_str(node.trim_start_matches('[').trim_end_matches(']')).ok()
+}
+
+/// Collects the addresses of the forwarding header in the order the proxies appended them. For
+/// `Forwarded`, these are the `for` parameters.
+fn forwarded_chain(headers: &HeaderMap, header: ProxyHeader) -> Vec<Option<IpAddr>> {
+    match header {
+        ProxyHeader::Forwarded => headers
+            .get_all("forwarded")
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| parse_node(value))
+                })
+            })
+            .collect(),
+        ProxyHeader::XForwardedFor => headers
+            .get_all("x-forwarded-for")
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(parse_node)
+            .collect(),
+    }
+}
+
+/// Determines the address of the client that sent a request.
+///
+/// * `peer` - Address of the connected peer, `None` for Unix sockets
+/// * `headers` - Headers of the request
+/// * `trusted_proxies` - Networks of proxies that are trusted to forward client addresses
+/// * `header` - The header the trusted proxies set
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+    header: ProxyHeader,
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|n| n.contains(ip));
+
+    let fallback = peer.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    if let Some(peer_ip) = peer
+        && !is_trusted(&peer_ip)
+    {
+        return peer_ip;
+    }
+
+    let mut client = fallback;
+    for hop in forwarded_chain(headers, header).into_iter().rev() {
+        match hop {
+            Some(ip) if is_trusted(&ip) => client = ip,
+            Some(ip) => return ip,
+            // A proxy did not disclose the address, nothing before it can be trusted
+            None => return client,
+        }
+    }
+
+    client
+}
+
+/// Determines the address of the client that sent a request using the trusted proxies of the
+/// server configuration.
+pub fn client_ip(req: &HttpRequest, state: &AppState) -> IpAddr {
+    resolve_client_ip(
+        req.peer_addr().map(|a| a.ip()),
+        req.headers(),
+        &state.config.trusted_proxies,
+        state.config.proxy_header,
+    )
+}
+
+/// Key extractor for `actix-governor` that limits requests per client instead of per peer.
+#[derive(Clone, Debug)]
+pub struct ClientIpKeyExtractor {
+    trusted_proxies: Vec<IpNet>,
+    header: ProxyHeader,
+}
+
+impl ClientIpKeyExtractor {
+    pub fn new(trusted_proxies: Vec<IpNet>, header: ProxyHeader) -> Self {
+        ClientIpKeyExtractor {
+            trusted_proxies,
+            header,
+        }
+    }
+}
+
+impl KeyExtractor for ClientIpKeyExtractor {
+    type Key = IpAddr;
+    type KeyExtractionError = SimpleKeyExtractionError<&'static str>;
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let ip = resolve_client_ip(
+            req.peer_addr().map(|a| a.ip()),
+            req.headers(),
+            &self.trusted_proxies,
+            self.header,
+        );
+
+        // Clients often get their own /56 prefix, so IPv6 addresses are limited per prefix
+        if let IpAddr::V6(ipv6) = ip {
+            let mut octets = ipv6.octets();
+            octets[7..16].fill(0);
+            return Ok(IpAddr::V6(octets.into()));
+        }
+
+        Ok(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (k, v) in entries {
+            map.append(HeaderName::from_static(k), HeaderValue::from_static(v));
+        }
+        map
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    fn proxies() -> Vec<IpNet> {
+        vec![
+            IpNet::from_str("127.0.0.1/32").unwrap(),
+            IpNet::from_str("10.0.0.0/8").unwrap(),
+        ]
+    }
+
+    #[test]
+    fn untrusted_peer_is_client() {
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("203.0.113.7")),
+                &h,
+                &proxies(),
+                ProxyHeader::XForwardedFor
+            ),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn trusted_peer_without_header() {
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("127.0.0.1")),
+                &HeaderMap::new(),
+                &proxies(),
+                ProxyHeader::XForwardedFor
+            ),
+            ip("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn x_forwarded_for_skips_trusted_hops() {
+        let h = headers(&[("x-forwarded-for", "6.6.6.6, 203.0.113.7, 10.1.2.3")]);
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("127.0.0.1")),
+                &h,
+                &proxies(),
+                ProxyHeader::XForwardedFor
+            ),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn only_configured_header_is_read() {
+        let h = headers(&[
+            ("x-forwarded-for", "6.6.6.6"),
+            (
+                "forwarded",
+                "for=192.0.2.60;proto=https, for=\"[2001:db8:cafe::17]:4711\"",
+            ),
+        ]);
+        assert_eq!(
+            resolve_client_ip(Some(ip("10.0.0.2")), &h, &proxies(), ProxyHeader::Forwarded),
+            ip("2001:db8:cafe::17")
+        );
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("10.0.0.2")),
+                &h,
+                &proxies(),
+                ProxyHeader::XForwardedFor
+            ),
+            ip("6.6.6.6")
+        );
+    }
+
+    #[test]
+    fn header_sent_by_client_is_ignored() {
+        // The proxy appends to X-Forwarded-For, the client made up the Forwarded header
+        let h = headers(&[
+            ("forwarded", "for=198.51.100.1"),
+            ("x-forwarded-for", "203.0.113.7"),
+        ]);
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("127.0.0.1")),
+                &h,
+                &proxies(),
+                ProxyHeader::XForwardedFor
+            ),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn unix_socket_peer_is_trusted() {
+        let h = headers(&[("x-forwarded-for", "198.51.100.4:1234")]);
+        assert_eq!(
+            resolve_client_ip(None, &h, &[], ProxyHeader::XForwardedFor),
+            ip("198.51.100.4")
+        );
+        assert_eq!(
+            resolve_client_ip(None, &HeaderMap::new(), &[], ProxyHeader::XForwardedFor),
+            ip("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn unknown_hop_stops_resolution() {
+        let h = headers(&[("forwarded", "for=198.51.100.4, for=unknown, for=10.0.0.9")]);
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("127.0.0.1")),
+                &h,
+                &proxies(),
+                ProxyHeader::Forwarded
+            ),
+            ip("10.0.0.9")
+        );
+    }
+}
@@ -0,0 +1,655 @@
+//! Server configuration read from a TOML file and overridden by command line flags.
+//!
+//! The configuration file is looked up at `~/.config/zentrox/config.toml` unless another path is
+//! provided using `--config` or the `ZENTROX_CONFIG` environment variable. Every key is optional
+//! and falls back to its default value.
+//!
+//! ```toml
+//! port = 8080
+//! bind = ["0.0.0.0", "::", "unix:/run/zentrox/zentrox.sock"]
+//! data_directory = "/var/lib/zentrox"
+//! session_ttl = 43200
+//! trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]
+//! proxy_header = "x-forwarded-for"
+//! http_bind = ["127.0.0.1"]
+//! http_port = 8081
+//!
+//! [log]
+//! level = "info"
+//! file_level = "debug"
+//! path = "/var/log/zentrox.log"
+//!
+//! [rate_limit]
+//! burst_size = 100
+//! period_ms = 250
+//! login_per_minute = 2
+//! shared_files_per_minute = 9
+//!
+//! [acme]
+//! directory = "https://acme-v02.api.letsencrypt.org/directory"
+//! contact = ["mailto:admin@example.com"]
+//! domains = ["example.com", "www.example.com"]
+//! challenge = "http-01"
+//!
+//! [client_certificates]
+//! mode = "optional"
+//! login = "certificate"
+//!
+//! [updates]
+//! pre_hook = "/usr/local/bin/zentrox-pre-update"
+//! post_hook = "/usr/local/bin/zentrox-post-update"
+//! ```
+
+use ipnet::IpNet;
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{fmt::Display, fs, net::IpAddr, path::PathBuf, str::FromStr};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::cli::Cli;
+use crate::permissions::parse_network;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("The configuration file {0} could not be read: {1}")]
+    Unreadable(PathBuf, std::io::Error),
+    #[error("The configuration file {0} is malformed: {1}")]
+    Malformed(PathBuf, toml::de::Error),
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// An address the server listens on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindAddress {
+    /// An IPv4 or IPv6 address that is combined with the configured port.
+    Ip(IpAddr),
+    /// A Unix domain socket, written as `unix:/path/to/socket`.
+    /// Connections over a Unix socket are not encrypted and intended for a local reverse proxy.
+    Unix(PathBuf),
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("A Unix socket requires a path.".to_string());
+            }
+            Ok(BindAddress::Unix(PathBuf::from(path)))
+        } else {
+            // Allow IPv6 addresses in the bracket notation known from URLs
+            let trimmed = s.trim_start_matches('[').trim_end_matches(']');
+            IpAddr::from_str(trimmed)
+                .map(BindAddress::Ip)
+                .map_err(|_| format!("{s} is neither an IP address nor a Unix socket."))
+        }
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindAddress::Ip(ip) => write!(f, "{ip}"),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BindAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        BindAddress::from_str(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+fn deserialize_networks<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<IpNet>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|v| {
+            parse_network(v)
+                .ok_or_else(|| serde::de::Error::custom(format!("{v} is not a valid network.")))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level of messages written to the terminal.
+    pub level: LevelFilter,
+    /// Level of messages written to the log file.
+    pub file_level: LevelFilter,
+    /// Log file that is appended to. If unset, a new file named after the start time is created
+    /// in the `logs` directory inside of the data directory.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: LevelFilter::Info,
+            file_level: LevelFilter::Debug,
+            path: None,
+        }
+    }
+}
+
+/// Settings for the request rate limits applied to all routes, the login route and the routes
+/// for shared files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub burst_size: u32,
+    pub period_ms: u64,
+    pub login_per_minute: u64,
+    pub shared_files_per_minute: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            burst_size: 100,
+            period_ms: 250,
+            login_per_minute: 2,
+            shared_files_per_minute: 9, // ~3 downloads / minute
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Addresses the HTTPS server listens on.
+    pub bind: Vec<BindAddress>,
+    /// Port used for every IP address in `bind`.
+    pub port: u16,
+    /// Directory for the database, certificates and other data.
+    pub data_directory: Option<PathBuf>,
+    /// Duration in seconds that a session may be alive.
+    pub session_ttl: u64,
+    /// Proxies that are trusted to report the address of the client they forward requests for.
+    #[serde(deserialize_with = "deserialize_networks")]
+    pub trusted_proxies: Vec<IpNet>,
+    /// The header the trusted proxies report the client address in. Other forwarding headers are
+    /// ignored, as they may have been sent by the client.
+    pub proxy_header: ProxyHeader,
+    /// Addresses an unencrypted HTTP server listens on.
+    /// This is only intended for a reverse proxy that terminates TLS.
+    pub http_bind: Vec<BindAddress>,
+    /// Port used for every IP address in `http_bind`.
+    pub http_port: u16,
+    pub log: LogConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Certificates are obtained automatically if this section is present.
+    pub acme: Option<AcmeConfig>,
+    pub client_certificates: ClientCertificateConfig,
+    pub updates: UpdatesConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: vec![BindAddress::Ip(IpAddr::from([0, 0, 0, 0]))],
+            port: 8080,
+            data_directory: None,
+            session_ttl: 60 * 60 * 12, // = 12h
+            trusted_proxies: vec![],
+            proxy_header: ProxyHeader::default(),
+            http_bind: vec![],
+            http_port: 8081,
+            log: LogConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            acme: None,
+            client_certificates: ClientCertificateConfig::default(),
+            updates: UpdatesConfig::default(),
+        }
+    }
+}
+
+/// Header a reverse proxy appends the address of the client it forwards a request for to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyHeader {
+    /// `X-Forwarded-For`, as set by nginx, Caddy, HAProxy and Traefik
+    #[default]
+    XForwardedFor,
+    /// `Forwarded` as defined in RFC 7239
+    Forwarded,
+}
+
+/// Whether clients have to present a certificate issued by the private CA of Zentrox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientCertificateMode {
+    /// Clients are not asked for a certificate.
+    #[default]
+    Off,
+    /// Clients may present a certificate, which has to be valid if present.
+    Optional,
+    /// Connections without a valid certificate are rejected during the handshake.
+    Required,
+}
+
+/// How a client certificate is used during login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CertificateLogin {
+    /// Only the password login is available.
+    #[default]
+    Password,
+    /// A certificate that belongs to an account logs in without a password.
+    Certificate,
+    /// The password login additionally requires a certificate of the same account.
+    Both,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientCertificateConfig {
+    pub mode: ClientCertificateMode,
+    pub login: CertificateLogin,
+}
+
+/// Commands run around unattended package updates.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdatesConfig {
+    /// Command that is run before updates are installed. No updates are installed if it fails.
+    pub pre_hook: Option<PathBuf>,
+    /// Command that is run as `<hook> <status>` after the updates, where the status is `success`
+    /// or `failed`.
+    pub post_hook: Option<PathBuf>,
+}
+
+/// Challenge used to prove control over the domains of a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub enum AcmeChallengeType {
+    /// The ACME server requests a token from `/.well-known/acme-challenge/` on port 80.
+    #[serde(rename = "http-01")]
+    Http01,
+    /// A TXT record is created by running `dns_hook`.
+    #[serde(rename = "dns-01")]
+    Dns01,
+}
+
+/// Settings for obtaining and renewing the TLS certificate from an ACME server such as
+/// Let's Encrypt.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AcmeConfig {
+    /// Directory URL of the ACME server.
+    pub directory: String,
+    /// Contact addresses of the account, e.g. `mailto:admin@example.com`.
+    pub contact: Vec<String>,
+    /// Domains the certificate is issued for.
+    pub domains: Vec<String>,
+    pub challenge: AcmeChallengeType,
+    /// Command that is run as `<hook> present <record> <value>` to create a TXT record and as
+    /// `<hook> cleanup <record> <value>` to remove it again.
+    pub dns_hook: Option<PathBuf>,
+    /// Seconds to wait after creating a TXT record before the ACME server is asked to check it.
+    pub dns_propagation_delay: u64,
+    /// The certificate is renewed once it expires in less than this number of days.
+    pub renew_before_days: u64,
+    /// Additional root certificate the ACME server is trusted with, e.g. the one of a local
+    /// Pebble instance.
+    pub ca_certificate: Option<PathBuf>,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        AcmeConfig {
+            directory: "https://acme-v02.api.letsencrypt.org/directory".to_string(),
+            contact: vec![],
+            domains: vec![],
+            challenge: AcmeChallengeType::Http01,
+            dns_hook: None,
+            dns_propagation_delay: 60,
+            renew_before_days: 30,
+            ca_certificate: None,
+        }
+    }
+}
+
+impl AcmeConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.domains.is_empty() {
+            return Err(ConfigError::Invalid(
+                "ACME requires at least one domain.".to_string(),
+            ));
+        }
+        if !self.directory.starts_with("https://") {
+            return Err(ConfigError::Invalid(
+                "The ACME directory has to be an HTTPS URL.".to_string(),
+            ));
+        }
+        if self.renew_before_days == 0 {
+            return Err(ConfigError::Invalid(
+                "The ACME renewal period has to be at least one day.".to_string(),
+            ));
+        }
+        match self.challenge {
+            AcmeChallengeType::Http01 if self.domains.iter().any(|d| d.starts_with("*.")) => Err(
+                ConfigError::Invalid("Wildcard domains require the dns-01 challenge.".to_string()),
+            ),
+            AcmeChallengeType::Dns01 if self.dns_hook.is_none() => Err(ConfigError::Invalid(
+                "The dns-01 challenge requires a DNS hook.".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The configuration file used if no path was provided.
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| dirs::home_dir().unwrap().join(".config"))
+            .join("zentrox")
+            .join("config.toml")
+    }
+
+    /// Reads and parses a configuration file.
+    /// A missing file is only accepted if `required` is `false`, in which case the default
+    /// configuration is returned.
+    pub fn from_file(path: PathBuf, required: bool) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(ServerConfig::default());
+            }
+            Err(e) => return Err(ConfigError::Unreadable(path, e)),
+        };
+
+        toml::from_str(&contents).map_err(|e| ConfigError::Malformed(path, e))
+    }
+
+    /// Loads the configuration file selected through the command line or environment and
+    /// applies all command line overrides.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let explicit_path = cli
+            .config
+            .clone()
+            .or_else(|| std::env::var_os("ZENTROX_CONFIG").map(PathBuf::from));
+
+        let mut config = match explicit_path {
+            Some(path) => ServerConfig::from_file(path, true)?,
+            None => ServerConfig::from_file(ServerConfig::default_path(), false)?,
+        };
+
+        config.apply_overrides(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Replaces values of the configuration with those provided as command line flags.
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if !cli.bind.is_empty() {
+            self.bind = cli.bind.clone();
+        }
+        if let Some(port) = cli.port {
+            self.port = port;
+        }
+        if let Some(data_directory) = &cli.data_dir {
+            self.data_directory = Some(data_directory.clone());
+        }
+        if let Some(level) = cli.log_level {
+            self.log.level = level;
+        }
+        if let Some(path) = &cli.log_file {
+            self.log.path = Some(path.clone());
+        }
+        if let Some(ttl) = cli.session_ttl {
+            self.session_ttl = ttl;
+        }
+        if !cli.trusted_proxy.is_empty() {
+            self.trusted_proxies = cli.trusted_proxy.clone();
+        }
+        if let Some(header) = cli.proxy_header {
+            self.proxy_header = header;
+        }
+        if !cli.http_bind.is_empty() {
+            self.http_bind = cli.http_bind.clone();
+        }
+        if let Some(port) = cli.http_port {
+            self.http_port = port;
+        }
+    }
+
+    /// Checks for values that can be parsed but not be used.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.bind.is_empty() {
+            return Err(ConfigError::Invalid(
+                "At least one bind address is required.".to_string(),
+            ));
+        }
+        if self.port == 0 && self.bind.iter().any(|b| matches!(b, BindAddress::Ip(_))) {
+            return Err(ConfigError::Invalid("The port may not be 0.".to_string()));
+        }
+        if self.http_port == 0
+            && self
+                .http_bind
+                .iter()
+                .any(|b| matches!(b, BindAddress::Ip(_)))
+        {
+            return Err(ConfigError::Invalid(
+                "The HTTP port may not be 0.".to_string(),
+            ));
+        }
+        if self.http_port == self.port
+            && self
+                .http_bind
+                .iter()
+                .any(|b| matches!(b, BindAddress::Ip(_)))
+            && self.bind.iter().any(|b| matches!(b, BindAddress::Ip(_)))
+        {
+            return Err(ConfigError::Invalid(
+                "HTTP and HTTPS can not share the same port.".to_string(),
+            ));
+        }
+        if self.session_ttl == 0 {
+            return Err(ConfigError::Invalid(
+                "The session TTL has to be at least one second.".to_string(),
+            ));
+        }
+        let limits = &self.rate_limit;
+        if limits.burst_size == 0
+            || limits.period_ms == 0
+            || limits.login_per_minute == 0
+            || limits.shared_files_per_minute == 0
+        {
+            return Err(ConfigError::Invalid(
+                "Rate limit settings have to be positive.".to_string(),
+            ));
+        }
+        if let Some(data_directory) = &self.data_directory
+            && !data_directory.is_absolute()
+        {
+            return Err(ConfigError::Invalid(
+                "The data directory has to be an absolute path.".to_string(),
+            ));
+        }
+        if let Some(acme) = &self.acme {
+            acme.validate()?;
+        }
+        if self.client_certificates.mode == ClientCertificateMode::Off
+            && self.client_certificates.login != CertificateLogin::Password
+        {
+            return Err(ConfigError::Invalid(
+                "Logging in with client certificates requires a client certificate mode."
+                    .to_string(),
+            ));
+        }
+        // Certificates are only checked during the TLS handshake
+        if self.client_certificates.mode == ClientCertificateMode::Required
+            && (!self.http_bind.is_empty()
+                || self.bind.iter().any(|b| matches!(b, BindAddress::Unix(_))))
+        {
+            return Err(ConfigError::Invalid(
+                "Client certificates can not be required while Zentrox is also served without TLS using http_bind or a Unix socket.".to_string(),
+            ));
+        }
+        if [&self.updates.pre_hook, &self.updates.post_hook]
+            .into_iter()
+            .flatten()
+            .any(|hook| !hook.is_absolute())
+        {
+            return Err(ConfigError::Invalid(
+                "Update hooks have to be absolute paths.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bind_addresses() {
+        assert_eq!(
+            BindAddress::from_str("::").unwrap(),
+            BindAddress::Ip(IpAddr::from_str("::").unwrap())
+        );
+        assert_eq!(
+            BindAddress::from_str("[::1]").unwrap(),
+            BindAddress::Ip(IpAddr::from_str("::1").unwrap())
+        );
+        assert_eq!(
+            BindAddress::from_str("unix:/run/zentrox.sock").unwrap(),
+            BindAddress::Unix(PathBuf::from("/run/zentrox.sock"))
+        );
+        assert!(BindAddress::from_str("unix:").is_err());
+        assert!(BindAddress::from_str("localhost").is_err());
+    }
+
+    #[test]
+    fn parse_full_configuration() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            port = 8443
+            bind = ["0.0.0.0", "::", "unix:/run/zentrox.sock"]
+            session_ttl = 3600
+            trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]
+            proxy_header = "forwarded"
+            http_bind = ["127.0.0.1"]
+
+            [log]
+            level = "warn"
+
+            [rate_limit]
+            login_per_minute = 5
+            "#,
+        )
+        .expect("Failed to parse configuration");
+
+        assert_eq!(config.port, 8443);
+        assert_eq!(config.bind.len(), 3);
+        assert_eq!(config.trusted_proxies.len(), 2);
+        assert_eq!(config.proxy_header, ProxyHeader::Forwarded);
+        assert_eq!(config.http_bind.len(), 1);
+        assert_eq!(config.http_port, 8081);
+        assert_eq!(config.log.level, LevelFilter::Warn);
+        assert_eq!(config.log.file_level, LevelFilter::Debug);
+        assert_eq!(config.rate_limit.login_per_minute, 5);
+        assert_eq!(config.rate_limit.burst_size, 100);
+        config.validate().expect("Configuration should be valid");
+    }
+
+    #[test]
+    fn reject_invalid_configuration() {
+        assert!(toml::from_str::<ServerConfig>("prot = 8080").is_err());
+        assert!(toml::from_str::<ServerConfig>("trusted_proxies = [\"proxy\"]").is_err());
+        assert!(toml::from_str::<ServerConfig>("proxy_header = \"x-real-ip\"").is_err());
+
+        let config: ServerConfig = toml::from_str("session_ttl = 0").unwrap();
+        assert!(config.validate().is_err());
+
+        let config: ServerConfig = toml::from_str("bind = []").unwrap();
+        assert!(config.validate().is_err());
+
+        let config: ServerConfig =
+            toml::from_str("http_bind = [\"127.0.0.1\"]\nhttp_port = 8080").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_acme_configuration() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            [acme]
+            directory = "https://localhost:14000/dir"
+            domains = ["*.example.com"]
+            challenge = "dns-01"
+            dns_hook = "/usr/local/bin/dns-hook"
+            "#,
+        )
+        .expect("Failed to parse configuration");
+
+        let acme = config.acme.as_ref().unwrap();
+        assert_eq!(acme.challenge, AcmeChallengeType::Dns01);
+        assert_eq!(acme.renew_before_days, 30);
+        config.validate().expect("Configuration should be valid");
+
+        let config: ServerConfig = toml::from_str("[acme]\ndomains = [\"*.example.com\"]").unwrap();
+        assert!(config.validate().is_err());
+
+        let config: ServerConfig = toml::from_str("[acme]\nchallenge = \"dns-01\"").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_client_certificate_configuration() {
+        let config: ServerConfig =
+            toml::from_str("[client_certificates]\nmode = \"required\"\nlogin = \"both\"").unwrap();
+        assert_eq!(
+            config.client_certificates.mode,
+            ClientCertificateMode::Required
+        );
+        assert_eq!(config.client_certificates.login, CertificateLogin::Both);
+        config.validate().expect("Configuration should be valid");
+
+        let config: ServerConfig =
+            toml::from_str("[client_certificates]\nlogin = \"certificate\"").unwrap();
+        assert!(config.validate().is_err());
+
+        // Listeners without TLS would serve everything without a certificate
+        for listeners in [
+            "http_bind = [\"127.0.0.1\"]",
+            "bind = [\"0.0.0.0\", \"unix:/run/zentrox.sock\"]",
+        ] {
+            let config: ServerConfig = toml::from_str(&format!(
+                "{listeners}\n[client_certificates]\nmode = \"required\""
+            ))
+            .unwrap();
+            assert!(config.validate().is_err());
+        }
+        let config: ServerConfig = toml::from_str(
+            "http_bind = [\"127.0.0.1\"]\n[client_certificates]\nmode = \"optional\"",
+        )
+        .unwrap();
+        config.validate().expect("Configuration should be valid");
+    }
+
+    #[test]
+    fn parse_update_hooks() {
+        let config: ServerConfig =
+            toml::from_str("[updates]\npre_hook = \"/usr/local/bin/pre-update\"").unwrap();
+        assert_eq!(
+            config.updates.pre_hook,
+            Some(PathBuf::from("/usr/local/bin/pre-update"))
+        );
+        assert!(config.updates.post_hook.is_none());
+        config.validate().expect("Configuration should be valid");
+
+        let config: ServerConfig =
+            toml::from_str("[updates]\npost_hook = \"post-update\"").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! History of the runs of cronjobs.
+//!
+//! Cronjobs whose runs are recorded call `zentrox run-job <key> <command>` instead of their
+//! command. The runner records the start and end of the run, the exit code and the end of the
+//! output in the `CronjobRuns` table, passes the output on so cron can still mail it and exits
+//! with the exit code of the command. The key is stored in the crontab, so the history survives
+//! editing the cronjob, which changes its ID.
+
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::ops::DerefMut;
+use std::process::{Command, Stdio};
+use utils::cron::RecordedCommand;
+use utils::models::CronjobRun;
+use utils::time::current_timestamp_secs;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::AppState;
+
+/// Bytes of stdout and stderr that are kept of every run.
+const MAX_OUTPUT: usize = 16 * 1024;
+/// Runs that are kept for every cronjob.
+const KEPT_RUNS: usize = 50;
+
+/// A new key for the history of a cronjob.
+pub fn new_key() -> String {
+    Uuid::new_v4().simple().to_string()[..12].to_string()
+}
+
+/// Wrap a command so its runs are recorded in the history with the given key.
+pub fn wrap(key: &str, command: &str) -> std::io::Result<String> {
+    let executable = std::env::current_exe()?;
+    Ok(RecordedCommand::wrap(
+        &executable.to_string_lossy(),
+        &utils::database::data_directory().to_string_lossy(),
+        key,
+        command,
+    ))
+}
+
+/// Keep the end of the output, which usually contains the error, if it is too long.
+fn truncate(output: &[u8]) -> String {
+    let output = String::from_utf8_lossy(output);
+    if output.len() <= MAX_OUTPUT {
+        return output.into_owned();
+    }
+    let mut start = output.len() - MAX_OUTPUT;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[...]\n{}", &output[start..])
+}
+
+fn save(connection: &mut SqliteConnection, run: &CronjobRun) -> QueryResult<()> {
+    use utils::schema::CronjobRuns::dsl::*;
+
+    diesel::insert_into(CronjobRuns)
+        .values(run)
+        .on_conflict(id)
+        .do_update()
+        .set(run)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Remove all but the latest [`KEPT_RUNS`] runs of a cronjob.
+fn prune(connection: &mut SqliteConnection, key: &str) -> QueryResult<()> {
+    use utils::schema::CronjobRuns::dsl::*;
+
+    let kept = CronjobRuns
+        .filter(job.eq(key))
+        .order(started_at.desc())
+        .select(id)
+        .limit(KEPT_RUNS as i64)
+        .load::<String>(connection)?;
+    diesel::delete(CronjobRuns.filter(job.eq(key)).filter(id.ne_all(kept))).execute(connection)?;
+    Ok(())
+}
+
+/// Save a run using a new connection.
+fn record<C: DerefMut<Target = SqliteConnection>, E: Display>(
+    connect: impl Fn() -> Result<C, E>,
+    run: &CronjobRun,
+    prune_runs: bool,
+) -> Result<(), String> {
+    let mut connection = connect().map_err(|e| e.to_string())?;
+    save(&mut connection, run)
+        .and_then(|_| {
+            if prune_runs {
+                prune(&mut connection, &run.job)
+            } else {
+                Ok(())
+            }
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Run a command using `sh -c` and record the run. Failing to connect to the database or to
+/// record the run does not stop the command from running. A connection is only taken to record
+/// the start and the end of the run, so commands that take long do not hold one.
+///
+/// Returns the finished run, including the complete output.
+fn record_run<C: DerefMut<Target = SqliteConnection>, E: Display>(
+    connect: impl Fn() -> Result<C, E>,
+    key: &str,
+    command: &str,
+    triggered_by: &str,
+) -> (CronjobRun, Vec<u8>, Vec<u8>) {
+    let mut run = CronjobRun {
+        id: Uuid::new_v4().to_string(),
+        job: key.to_string(),
+        triggered_by: triggered_by.to_string(),
+        started_at: current_timestamp_secs(),
+        finished_at: None,
+        status: "running".to_string(),
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+    };
+    let recording = record(&connect, &run, false);
+    if let Err(e) = &recording {
+        eprintln!("Recording the start of the run failed: {e}");
+    }
+
+    let (stdout, stderr) = match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => {
+            run.exit_code = output.status.code();
+            run.status = if output.status.success() {
+                "success"
+            } else {
+                "failed"
+            }
+            .to_string();
+            (output.stdout, output.stderr)
+        }
+        Err(e) => {
+            run.status = "failed".to_string();
+            (
+                Vec::new(),
+                format!("The command could not be started: {e}\n").into_bytes(),
+            )
+        }
+    };
+    run.finished_at = Some(current_timestamp_secs());
+    run.stdout = truncate(&stdout);
+    run.stderr = truncate(&stderr);
+
+    if recording.is_ok()
+        && let Err(e) = record(&connect, &run, true)
+    {
+        eprintln!("Recording the end of the run failed: {e}");
+    }
+    (run, stdout, stderr)
+}
+
+/// Run the command of a cronjob on request and record the run, see [`record_run`].
+pub fn run(
+    state: &AppState,
+    key: &str,
+    command: &str,
+    triggered_by: &str,
+) -> (CronjobRun, Vec<u8>, Vec<u8>) {
+    record_run(
+        || state.db_pool.lock().unwrap().get(),
+        key,
+        command,
+        triggered_by,
+    )
+}
+
+/// Entry point of `zentrox run-job`, which is called by cron. Returns the exit code of the
+/// command, or 1 if it did not exit normally.
+pub fn run_job(key: &str, command: &str) -> i32 {
+    // Zentrox may have been updated without being restarted yet
+    if let Err(e) = utils::database::upgrade_database() {
+        eprintln!("Upgrading the database failed: {e}");
+    }
+    let (run, stdout, stderr) = record_run(
+        || utils::database::try_establish_direct_connection().map(Box::new),
+        key,
+        command,
+        "cron",
+    );
+    // cron mails the output of jobs
+    let _ = std::io::stdout().write_all(&stdout);
+    let _ = std::io::stderr().write_all(&stderr);
+    run.exit_code.unwrap_or(1)
+}
+
+/// Mark runs that were interrupted as failed. They are considered finished now, as the time they
+/// were interrupted at is not known.
+///
+/// Manual runs are interrupted by Zentrox being stopped. Runs started by cron do not depend on
+/// Zentrox and may still be running, unless they started before the system was booted.
+pub fn finish_interrupted(state: &AppState) -> QueryResult<()> {
+    use utils::schema::CronjobRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    let booted_at = sysinfo::System::boot_time() as i64;
+    diesel::update(
+        CronjobRuns
+            .filter(status.eq("running"))
+            .filter(triggered_by.ne("cron").or(started_at.lt(booted_at))),
+    )
+    .set((
+        status.eq("failed"),
+        finished_at.eq(current_timestamp_secs()),
+    ))
+    .execute(connection)?;
+    Ok(())
+}
+
+/// The runs of a cronjob, starting with the latest.
+pub fn runs(state: &AppState, key: &str) -> QueryResult<Vec<CronjobRun>> {
+    use utils::schema::CronjobRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    CronjobRuns
+        .filter(job.eq(key))
+        .order(started_at.desc())
+        .select(CronjobRun::as_select())
+        .load(connection)
+}
+
+/// A run without its output
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    /// Seconds since the UNIX epoch
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// `running`, `success` or `failed`
+    pub status: String,
+    pub exit_code: Option<i32>,
+}
+
+/// The latest run of every cronjob by history key.
+pub fn latest_runs(state: &AppState) -> QueryResult<HashMap<String, RunSummary>> {
+    use utils::schema::CronjobRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    let mut latest = HashMap::new();
+    for (key, started, finished, run_status, code) in CronjobRuns
+        .order(started_at.asc())
+        .select((job, started_at, finished_at, status, exit_code))
+        .load::<(String, i64, Option<i64>, String, Option<i32>)>(connection)?
+    {
+        latest.insert(
+            key,
+            RunSummary {
+                started_at: started,
+                finished_at: finished,
+                status: run_status,
+                exit_code: code,
+            },
+        );
+    }
+    Ok(latest)
+}
+
+/// Remove the history of a cronjob.
+pub fn delete(state: &AppState, key: &str) -> QueryResult<()> {
+    use utils::schema::CronjobRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    diesel::delete(CronjobRuns.filter(job.eq(key))).execute(connection)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_output() {
+        assert_eq!(truncate(b"done\n"), "done\n");
+
+        let mut long = "ä".repeat(MAX_OUTPUT);
+        long.push_str("error");
+        let truncated = truncate(long.as_bytes());
+        assert!(truncated.starts_with("[...]\n"));
+        assert!(truncated.ends_with("error"));
+        assert!(truncated.len() <= MAX_OUTPUT + "[...]\n".len());
+    }
+
+    #[test]
+    fn run_without_database() {
+        let (run, stdout, _) = record_run(
+            || Err::<Box<SqliteConnection>, _>("The database is unavailable"),
+            "key",
+            "echo done; exit 3",
+            "cron",
+        );
+        assert_eq!(stdout, b"done\n");
+        assert_eq!(run.exit_code, Some(3));
+        assert_eq!(run.status, "failed");
+    }
+}
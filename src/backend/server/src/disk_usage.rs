@@ -0,0 +1,57 @@
+//! Results of the disk usage analyzer.
+//!
+//! Scanning a large directory tree takes minutes, so scans run as background jobs and their
+//! results are kept in memory to be browsed afterwards. Only the [`MAX_SCANS`] most recent scans
+//! are kept, as a scan of a filesystem with millions of files takes up a lot of memory.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use utils::drives::analyzer::DiskUsage;
+use uuid::Uuid;
+
+/// Number of finished scans that are kept.
+pub const MAX_SCANS: usize = 4;
+
+pub type DiskUsageCache = Arc<Mutex<DiskUsageScans>>;
+
+#[derive(Debug, Default)]
+pub struct DiskUsageScans {
+    /// Finished scans, oldest first
+    scans: Vec<Arc<DiskUsage>>,
+    /// Jobs of running scans by the directory they scan
+    running: HashMap<PathBuf, Uuid>,
+}
+
+impl DiskUsageScans {
+    /// The job scanning `root`, if there is one.
+    pub fn running(&self, root: &Path) -> Option<Uuid> {
+        self.running.get(root).copied()
+    }
+
+    pub fn start(&mut self, root: PathBuf, job_id: Uuid) {
+        self.running.insert(root, job_id);
+    }
+
+    /// Store a finished scan, replacing an older scan of the same directory.
+    pub fn finish(&mut self, root: &Path, result: Option<DiskUsage>) {
+        self.running.remove(root);
+        if let Some(usage) = result {
+            self.scans.retain(|s| s.root() != usage.root());
+            self.scans.push(Arc::new(usage));
+            if self.scans.len() > MAX_SCANS {
+                self.scans.remove(0);
+            }
+        }
+    }
+
+    /// The scan that contains `path` in the most detail, which is the one of the deepest
+    /// directory.
+    pub fn find(&self, path: &Path) -> Option<Arc<DiskUsage>> {
+        self.scans
+            .iter()
+            .filter(|s| s.contains(path))
+            .max_by_key(|s| s.root().components().count())
+            .cloned()
+    }
+}
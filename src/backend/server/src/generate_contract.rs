@@ -1,19 +1,34 @@
-use std::{fs, process::exit};
+use std::{fs, path::PathBuf, process::exit};
 
 use utoipa::{OpenApi, openapi::ServerBuilder};
 
-pub fn generate(store_path: Option<&String>) {
+pub fn generate(store_path: Option<&PathBuf>) {
     println!("Generating OpenAPI contract in pretty printed JSON format.");
 
     #[derive(OpenApi)]
     #[openapi(
         paths(
             crate::routes::auth::login,
+            crate::routes::auth::certificate_login,
             crate::routes::auth::logout,
             crate::routes::auth::verify_sudo_password,
+            crate::routes::security::blocked_ips,
+            crate::routes::security::block,
+            crate::routes::security::unblock,
+            crate::routes::security::trusted_networks,
+            crate::routes::security::trust,
+            crate::routes::security::distrust,
+            crate::routes::security::limits,
+            crate::routes::security::update_limits,
+            crate::routes::security::client_certificates,
+            crate::routes::security::client_ca,
+            crate::routes::security::issue_client_certificate,
+            crate::routes::security::revoke_client_certificate,
             crate::routes::dashboard::information,
             crate::routes::packages::database,
             crate::routes::packages::statistics,
+            crate::routes::packages::search,
+            crate::routes::packages::details,
             crate::routes::packages::update_db,
             crate::routes::packages::install_package,
             crate::routes::packages::remove_package,
@@ -21,6 +36,23 @@ pub fn generate(store_path: Option<&String>) {
             crate::routes::packages::update_all,
             crate::routes::packages::remove_orphaned,
             crate::routes::packages::orphaned,
+            crate::routes::packages::unattended_updates,
+            crate::routes::packages::update_policy,
+            crate::routes::packages::authorize_unattended_updates,
+            crate::routes::packages::revoke_unattended_updates,
+            crate::routes::packages::run_unattended_updates,
+            crate::routes::packages::update_history,
+            crate::routes::packages::history,
+            crate::routes::packages::transaction,
+            crate::routes::packages::undo_transaction,
+            crate::routes::packages::downgrade,
+            crate::routes::packages::holds,
+            crate::routes::packages::hold_package,
+            crate::routes::packages::unhold_package,
+            crate::routes::packages::repositories,
+            crate::routes::packages::set_repository_enabled,
+            crate::routes::packages::add_repository,
+            crate::routes::packages::remove_repository,
             crate::routes::jobs::status,
             crate::routes::firewall::has_ufw,
             crate::routes::firewall::status,
@@ -35,9 +67,41 @@ pub fn generate(store_path: Option<&String>) {
             crate::routes::files::metadata,
             crate::routes::files::upload,
             crate::routes::drives::list,
+            crate::routes::drives::usage,
+            crate::routes::drives::usage_scan,
+            crate::routes::drives::usage_analysis,
+            crate::routes::drives::smart,
+            crate::routes::drives::self_test,
+            crate::routes::drives::mounts,
+            crate::routes::drives::mount,
+            crate::routes::drives::unmount,
+            crate::routes::drives::partitions,
+            crate::routes::drives::partition_table,
+            crate::routes::drives::new_partition,
+            crate::routes::drives::format,
+            crate::routes::drives::label,
+            crate::routes::drives::fstab,
+            crate::routes::drives::new_fstab_entry,
+            crate::routes::drives::edit_fstab_entry,
+            crate::routes::drives::delete_fstab_entry,
+            crate::routes::storage::list,
+            crate::routes::storage::snapshots,
+            crate::routes::storage::new_snapshot,
+            crate::routes::storage::delete_snapshot,
+            crate::routes::storage::rollback,
+            crate::routes::storage::scrub,
+            crate::routes::storage::scrub_status,
+            crate::routes::storage::schedules,
+            crate::routes::storage::new_schedule,
+            crate::routes::storage::delete_schedule,
             crate::routes::power::off,
             crate::routes::tls::name,
             crate::routes::tls::upload,
+            crate::routes::tls::certificate,
+            crate::routes::tls::self_signed,
+            crate::routes::tls::acme_status,
+            crate::routes::tls::acme_issue,
+            crate::routes::tls::acme_challenge,
             crate::routes::account::details,
             crate::routes::account::enable_otp,
             crate::routes::account::update_details,
@@ -57,13 +121,31 @@ pub fn generate(store_path: Option<&String>) {
             crate::routes::network::routes,
             crate::routes::network::delete_route,
             crate::routes::network::activate_interface,
+            crate::routes::network::sockets,
+            crate::routes::network::sockets_privileged,
             crate::routes::processes::list,
             crate::routes::processes::kill,
             crate::routes::processes::details,
+            crate::routes::processes::signal,
+            crate::routes::processes::priority,
+            crate::routes::processes::affinity,
+            crate::routes::processes::tree,
+            crate::routes::processes::inspect,
+            crate::routes::processes::inspect_privileged,
             crate::routes::cron::run_command,
             crate::routes::cron::delete,
             crate::routes::cron::create,
             crate::routes::cron::list,
+            crate::routes::cron::crontab,
+            crate::routes::cron::edit,
+            crate::routes::cron::set_enabled,
+            crate::routes::cron::preview,
+            crate::routes::cron::set_recording,
+            crate::routes::cron::runs,
+            crate::routes::cron::create_timer,
+            crate::routes::cron::edit_timer,
+            crate::routes::cron::set_timer_enabled,
+            crate::routes::cron::delete_timer,
             crate::routes::sharing::share,
             crate::routes::sharing::list,
             crate::routes::sharing::unshare,
@@ -88,7 +170,9 @@ pub fn generate(store_path: Option<&String>) {
             (name = "packages", description = "Package manager connections"),
             (name = "power", description = "Power settings"),
             (name = "processes", description = "System process managment"),
+            (name = "security", description = "IP blocking and brute-force protection"),
             (name = "sharing", description = "File sharing managment"),
+            (name = "storage", description = "Btrfs and ZFS pools, snapshots and scrubs"),
             (name = "tls", description = "TLS encryption settings"),
         ),
     )]
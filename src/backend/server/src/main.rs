@@ -25,41 +25,53 @@ use actix_web::{
     App, HttpResponse, HttpServer, cookie::time::Duration as ActixDuration, get, middleware, web,
     web::Data,
 };
+use clap::Parser;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
-use log::{debug, info, warn};
+use ipnet::IpNet;
+use log::{debug, error, info, warn};
 use permissions::is_privileged;
 use routes::media::get_media_enabled_database;
 use serde::{Deserialize, Serialize};
-use simplelog::{
-    self, ColorChoice, CombinedLogger, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger,
-};
-use std::net::IpAddr;
+use simplelog::{self, ColorChoice, CombinedLogger, Config, TermLogger, TerminalMode, WriteLogger};
+use std::net::SocketAddr;
 use std::time::SystemTime;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
     fs::File,
-    io::BufReader,
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
 use utils::database::create_connection_pool;
+use utils::models::LoginLimits;
 use utils::net_data::Interface;
 use utils::status_com::ErrorCode;
 use utoipa::ToSchema;
 use uuid::Uuid;
+mod acme;
+mod certificates;
+mod cli;
+mod client_certificates;
+mod client_ip;
+mod config;
+mod cron_history;
+mod disk_usage;
 mod generate_contract;
-mod help;
+mod package_history;
+mod package_index;
 mod permissions;
 mod routes;
 mod setup;
+mod snapshot_schedule;
+mod updates;
 use routes::*;
 
-use crate::permissions::{ACCOUNT_TIME_WINDOW, SESSION_TIMEOUT, is_blocked_ip};
-
-const SERVER_PORT: u16 = 8080;
+use crate::certificates::{CertificateResolver, certificate_path};
+use crate::client_ip::ClientIpKeyExtractor;
+use crate::config::{BindAddress, ClientCertificateMode, ServerConfig};
+use crate::permissions::is_blocked_ip;
 
 #[derive(Clone, Debug)]
 #[allow(unused)]
@@ -98,38 +110,67 @@ impl Default for Environment {
 struct AppState {
     login_requests: Arc<Mutex<Vec<permissions::LoginRequest>>>,
     sessions: Arc<Mutex<Vec<permissions::LoginSession>>>,
-    blocked_ips: Arc<Mutex<Vec<IpAddr>>>,
+    blocked_ips: Arc<Mutex<Vec<IpNet>>>,
+    trusted_networks: Arc<Mutex<Vec<IpNet>>>,
+    login_limits: Arc<Mutex<LoginLimits>>,
     system: Arc<Mutex<sysinfo::System>>,
     network_interfaces: Arc<Mutex<Vec<Interface>>>,
     background_jobs: Arc<Mutex<HashMap<Uuid, BackgroundTaskState>>>,
     db_pool: Arc<Mutex<Pool<ConnectionManager<SqliteConnection>>>>,
     environment: Arc<Environment>,
+    config: Arc<ServerConfig>,
+    certificates: Arc<CertificateResolver>,
+    acme_challenges: acme::HttpChallenges,
+    revoked_client_certificates: client_certificates::RevokedSerials,
+    /// Held while unattended updates are being installed
+    update_lock: Arc<Mutex<()>>,
+    /// Held while the package index is being refreshed
+    package_index_lock: Arc<Mutex<()>>,
+    /// When refreshing the package index last failed for any package manager
+    package_index_failed_at: Arc<Mutex<Option<i64>>>,
+    disk_usage: disk_usage::DiskUsageCache,
 }
 
 impl AppState {
     /// Initiate a new AppState
-    fn new() -> Self {
+    ///
+    /// The environment is determined by the command line flags and the `ZENTROX_MODE`
+    /// environment variable, which may contain `NO_AUTH` and `NO_CORS` separated by `;`.
+    fn new(config: ServerConfig, cli: &cli::Cli) -> Self {
         let env_vars = env::vars().collect::<HashMap<String, String>>();
         let current_environment = match env_vars.get("ZENTROX_MODE") {
             Some(v) => {
                 let s: Vec<&str> = v.split(";").collect();
                 Environment {
-                    disable_authorization: s.contains(&"NO_AUTH"),
-                    disable_cors: s.contains(&"NO_CORS"),
+                    disable_authorization: s.contains(&"NO_AUTH") || cli.no_auth,
+                    disable_cors: s.contains(&"NO_CORS") || cli.no_cors,
                 }
             }
-            None => Environment::default(),
+            None => Environment {
+                disable_authorization: cli.no_auth,
+                disable_cors: cli.no_cors,
+            },
         };
 
         AppState {
             login_requests: Arc::new(Mutex::new(vec![])),
             sessions: Arc::new(Mutex::new(vec![])),
             blocked_ips: Arc::new(Mutex::new(vec![])),
+            trusted_networks: Arc::new(Mutex::new(vec![])),
+            login_limits: Arc::new(Mutex::new(LoginLimits::default())),
             system: Arc::new(Mutex::new(sysinfo::System::new())),
             network_interfaces: Arc::new(Mutex::new(Vec::new())),
             background_jobs: Arc::new(Mutex::new(HashMap::new())),
             db_pool: Arc::new(Mutex::new(create_connection_pool())),
             environment: Arc::new(current_environment),
+            config: Arc::new(config),
+            certificates: Arc::new(CertificateResolver::default()),
+            acme_challenges: Arc::new(Mutex::new(HashMap::new())),
+            revoked_client_certificates: Arc::new(RwLock::new(HashSet::new())),
+            update_lock: Arc::new(Mutex::new(())),
+            package_index_lock: Arc::new(Mutex::new(())),
+            package_index_failed_at: Arc::new(Mutex::new(None)),
+            disk_usage: Arc::new(Mutex::new(disk_usage::DiskUsageScans::default())),
         }
     }
 
@@ -155,16 +196,18 @@ impl AppState {
     }
 
     fn clear_request_history(&self) {
+        let limits = *self.login_limits.lock().unwrap();
+        // Requests have to be kept as long as the largest time window they may be counted in
+        let retention = limits
+            .account_time_window
+            .max(limits.large_time_window)
+            .max(limits.base_time_window) as u64;
         if let Ok(mut v) = self.login_requests.lock() {
-            v.retain(|e| {
-                SystemTime::now().duration_since(e.time).unwrap().as_secs() < ACCOUNT_TIME_WINDOW
-            });
+            v.retain(|e| SystemTime::now().duration_since(e.time).unwrap().as_secs() < retention);
             drop(v)
         }
         if let Ok(mut v) = self.sessions.lock() {
-            v.retain(|e| {
-                SystemTime::now().duration_since(e.since).unwrap().as_secs() < SESSION_TIMEOUT
-            });
+            v.retain(|e| e.is_valid(self.config.session_ttl));
             drop(v)
         }
     }
@@ -184,6 +227,59 @@ impl AppState {
                 auth_requests_clone.clear_request_history();
             }
         });
+        let updates_clone = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = updates::finish_interrupted(&updates_clone) {
+                error!("Reading the update history failed with error: {e}");
+            }
+            if let Err(e) = package_history::finish_interrupted(&updates_clone) {
+                error!("Reading the package history failed with error: {e}");
+            }
+            if let Err(e) = cron_history::finish_interrupted(&updates_clone) {
+                error!("Reading the cronjob run history failed with error: {e}");
+            }
+            loop {
+                if let Err(e) = updates::run_if_due(&updates_clone) {
+                    error!("Installing unattended updates failed with error: {e}");
+                }
+                std::thread::sleep(Duration::from_secs(60));
+            }
+        });
+        let package_index_clone = self.clone();
+        std::thread::spawn(move || {
+            loop {
+                if let Err(e) = package_index::refresh_if_stale(&package_index_clone) {
+                    error!("Refreshing the package index failed with error: {e}");
+                }
+                std::thread::sleep(Duration::from_secs(60));
+            }
+        });
+        let snapshots_clone = self.clone();
+        std::thread::spawn(move || {
+            loop {
+                if let Err(e) = snapshot_schedule::run_due(&snapshots_clone) {
+                    error!("Reading the snapshot schedules failed with error: {e}");
+                }
+                std::thread::sleep(Duration::from_secs(5 * 60));
+            }
+        });
+        if self.config.acme.is_some() {
+            let acme_clone = self.clone();
+            std::thread::spawn(move || {
+                // http-01 challenges can only be answered once the server is listening
+                std::thread::sleep(Duration::from_secs(10));
+                loop {
+                    let next_check = match acme::renew_if_due(&acme_clone) {
+                        Ok(_) => Duration::from_secs(12 * 60 * 60),
+                        Err(e) => {
+                            error!("Renewing the certificate failed with error: {e}");
+                            Duration::from_secs(60 * 60)
+                        }
+                    };
+                    std::thread::sleep(next_check);
+                }
+            });
+        }
     }
 }
 
@@ -254,7 +350,7 @@ async fn ip_ban_middleware(
         warn!("Bypassed authorization!");
         return next.call(req).await;
     }
-    let ip = req.peer_addr().unwrap().ip();
+    let ip = client_ip::client_ip(req.request(), &app_state);
     if is_blocked_ip(&app_state, ip) {
         warn!("The blocked peer {ip} tried to contact this server.");
         Ok(req.into_response(HttpResponse::Forbidden().finish()))
@@ -297,12 +393,25 @@ async fn media_enabled_authorization_middleware(
     }
 }
 
+/// Get the directory Zentrox stores its database, certificates and other data in.
 pub fn get_zentrox_directory() -> PathBuf {
-    dirs::home_dir()
-        .unwrap()
-        .join(".local")
-        .join("share")
-        .join("zentrox")
+    utils::database::data_directory()
+}
+
+/// Remove a socket left behind by a previous run, which would prevent binding. Anything else at
+/// the path is left alone and fails the start.
+fn remove_stale_socket(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
 }
 
 #[actix_web::main]
@@ -310,112 +419,191 @@ async fn main() -> std::io::Result<()> {
     use utils::models::Configurations;
     use utils::schema::Configuration::dsl::*;
 
+    let cli = cli::Cli::parse();
+
+    if let Some(cli::CliCommand::RunJob { key, command }) = &cli.command {
+        if let Some(data_directory) = &cli.data_dir {
+            let _ = utils::database::set_data_directory(data_directory.clone());
+        }
+        std::process::exit(cron_history::run_job(key, command));
+    }
+
+    if let Some(docs_path) = &cli.docs {
+        generate_contract::generate(docs_path.as_ref());
+    }
+
+    let config = match ServerConfig::load(&cli) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2)
+        }
+    };
+
+    if let Some(data_directory) = &config.data_directory {
+        let _ = utils::database::set_data_directory(data_directory.clone());
+    }
+
+    let log_file_path = match &config.log.path {
+        Some(p) => p.clone(),
+        None => get_zentrox_directory().join("logs").join(format!(
+            "zentrox_{}.log",
+            utils::time::current_timestamp_iso()
+        )),
+    };
+
+    if let Some(parent) = log_file_path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let log_file = match File::options()
+        .create(true)
+        .append(true)
+        .open(&log_file_path)
+    {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!(
+                "The log file {} could not be opened: {e}",
+                log_file_path.display()
+            );
+            std::process::exit(2)
+        }
+    };
+
     CombinedLogger::init(vec![
         TermLogger::new(
-            LevelFilter::Info,
+            config.log.level,
             Config::default(),
             TerminalMode::Mixed,
             ColorChoice::Auto,
         ),
-        WriteLogger::new(
-            LevelFilter::Debug,
-            Config::default(),
-            File::create(format!(
-                "zentrox_{}.log",
-                utils::time::current_timestamp_iso()
-            ))
-            .unwrap(),
-        ),
+        WriteLogger::new(config.log.file_level, Config::default(), log_file),
     ])
     .unwrap();
 
-    let os_args = std::env::args().collect::<Vec<String>>();
-
-    match os_args.get(1) {
-        Some(arg) if arg == "--docs" => generate_contract::generate(os_args.get(2)),
-        Some(arg) if arg == "--help" => help::print(),
-        _ => {}
-    }
-
     let zentrox_env_dir = get_zentrox_directory();
 
     if !zentrox_env_dir.join("database.db").exists() {
         debug!("No configuration found, running setup.");
-        let _ = setup::run_setup();
+        let _ = setup::run_setup(config.port);
     } else {
-        debug!("Found configurations in {}", zentrox_env_dir.display())
+        debug!("Found configurations in {}", zentrox_env_dir.display());
+        if let Err(e) = utils::database::upgrade_database() {
+            error!("Upgrading the database failed with error: {e}");
+        }
     }
 
     if !env::current_dir().unwrap().join("static").exists() {
         let _ = env::set_current_dir(&zentrox_env_dir);
     }
 
-    let app_state = Data::new(AppState::new());
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .unwrap();
+
+    let app_state = Data::new(AppState::new(config.clone(), &cli));
     permissions::load_blocked_ips(&app_state);
-    app_state.start_interval_tasks();
-    debug!("Started interval tasks");
+    permissions::load_trusted_networks(&app_state);
+    permissions::load_login_limits(&app_state);
 
-    let tls_cert_filename = Configuration
+    let active_configuration = Configuration
         .select(Configurations::as_select())
         .first(&mut app_state.db_pool.lock().unwrap().get().unwrap())
-        .unwrap()
-        .tls_cert;
+        .unwrap();
+
+    let tls_cert_filename = active_configuration.tls_cert;
+    let tls_key_filename = active_configuration.tls_key;
 
-    if tls_cert_filename == "selfsigned.pem" {
+    if let Some(acme_config) = &config.acme {
+        if tls_cert_filename != acme::ACME_CERTIFICATE_NAME {
+            info!("The current certificate will be replaced by one obtained through ACME.");
+        }
+        if acme_config.challenge == config::AcmeChallengeType::Http01 && config.http_bind.is_empty()
+        {
+            warn!("http-01 challenges are answered by the plain HTTP server, but none is bound.");
+        }
+    } else if tls_cert_filename == "selfsigned.pem"
+        || tls_cert_filename == certificates::SELF_SIGNED_CERTIFICATE_NAME
+    {
         warn!("You may be using a self-signed certificate.");
     }
 
-    rustls::crypto::aws_lc_rs::default_provider()
-        .install_default()
-        .unwrap();
-
-    let cert_file_path = zentrox_env_dir
-        .join("certificates")
-        .join(&tls_cert_filename);
-
-    let mut certs_file = BufReader::new(File::open(&cert_file_path).unwrap());
+    let cert_file_path = certificate_path(&tls_cert_filename);
 
     debug!("Using certificate file from {}", cert_file_path.display());
 
-    let mut key_file = BufReader::new(
-        File::open(zentrox_env_dir.join("certificates").join(tls_cert_filename)).unwrap(),
-    );
+    if let Err(e) = app_state.certificates.reload(
+        &cert_file_path,
+        tls_key_filename.as_deref().map(certificate_path).as_deref(),
+    ) {
+        error!(
+            "The certificate {} could not be loaded: {e}",
+            cert_file_path.display()
+        );
+        // A broken certificate should not lock the administrator out. The configured files are
+        // left untouched, so they can be fixed and are used again after a restart.
+        warn!("Falling back to a temporary self-signed certificate for this run.");
+        if let Err(e) = certificates::use_temporary_self_signed(&app_state.certificates) {
+            error!("Generating a self-signed certificate failed with error: {e}");
+            std::process::exit(1)
+        }
+    }
 
-    let tls_certs = rustls_pemfile::certs(&mut certs_file)
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
+    app_state.start_interval_tasks();
+    debug!("Started interval tasks");
 
-    let tls_key = rustls_pemfile::pkcs8_private_keys(&mut key_file)
-        .next()
-        .unwrap()
-        .unwrap();
+    let tls_config = if config.client_certificates.mode == ClientCertificateMode::Off {
+        rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(app_state.certificates.clone())
+    } else {
+        client_certificates::load_revoked(&app_state);
+        let verifier = match client_certificates::RevocationCheckingVerifier::new(
+            config.client_certificates.mode,
+            app_state.revoked_client_certificates.clone(),
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("The client certificate authority could not be loaded: {e}");
+                std::process::exit(1)
+            }
+        };
+        rustls::ServerConfig::builder()
+            .with_client_cert_verifier(Arc::new(verifier))
+            .with_cert_resolver(app_state.certificates.clone())
+    };
 
-    let tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(tls_certs, rustls::pki_types::PrivateKeyDer::Pkcs8(tls_key))
-        .unwrap();
+    let key_extractor =
+        ClientIpKeyExtractor::new(config.trusted_proxies.clone(), config.proxy_header);
 
     let governor_conf = GovernorConfigBuilder::default()
-        .burst_size(100)
-        .period(Duration::from_millis(250))
+        .burst_size(config.rate_limit.burst_size)
+        .period(Duration::from_millis(config.rate_limit.period_ms))
+        .key_extractor(key_extractor.clone())
         .finish()
         .unwrap();
 
     let harsh_governor_conf = GovernorConfigBuilder::default()
-        .requests_per_minute(2)
+        .requests_per_minute(config.rate_limit.login_per_minute)
+        .key_extractor(key_extractor.clone())
         .finish()
         .unwrap();
 
     let shared_files_governor_conf = GovernorConfigBuilder::default()
-        .requests_per_minute(9) // ~3 downloads / minute
+        .requests_per_minute(config.rate_limit.shared_files_per_minute)
+        .key_extractor(key_extractor)
         .finish()
         .unwrap();
 
-    info!("Zentrox is being serverd on port {}", SERVER_PORT);
+    if !config.http_bind.is_empty() && config.trusted_proxies.is_empty() {
+        warn!("Plain HTTP is enabled, but no trusted proxies are configured.");
+    }
 
     let secret_session_key = Key::try_generate().expect("Failed to generate session key.");
+    let session_ttl = config.session_ttl;
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::new("%a %U %s"))
             .wrap(
@@ -425,7 +613,8 @@ async fn main() -> std::io::Result<()> {
                 )
                 .cookie_content_security(CookieContentSecurity::Private)
                 .session_lifecycle(
-                    PersistentSession::default().session_ttl(ActixDuration::hours(12)),
+                    PersistentSession::default()
+                        .session_ttl(ActixDuration::seconds(session_ttl as i64)),
                 )
                 .cookie_secure(true)
                 .cookie_name("session".to_string())
@@ -444,6 +633,10 @@ async fn main() -> std::io::Result<()> {
             .service(index)
             .service(dashboard_page)
             .service(robots_txt)
+            .route(
+                "/.well-known/acme-challenge/{token}",
+                web::get().to(tls::acme_challenge),
+            )
             .service(
                 web::scope("/api")
                     .wrap(from_fn(ip_ban_middleware))
@@ -453,7 +646,11 @@ async fn main() -> std::io::Result<()> {
                             .service(
                                 web::scope("/auth")
                                     .wrap(Governor::new(&harsh_governor_conf))
-                                    .route("/login", web::post().to(auth::login)),
+                                    .route("/login", web::post().to(auth::login))
+                                    .route(
+                                        "/certificateLogin",
+                                        web::post().to(auth::certificate_login),
+                                    ),
                             )
                             .service(
                                 web::scope("/shared")
@@ -476,6 +673,39 @@ async fn main() -> std::io::Result<()> {
                                         web::post().to(auth::verify_sudo_password),
                                     )),
                             )
+                            .service(
+                                web::scope("/security")
+                                    .route("/blockedIps", web::get().to(security::blocked_ips))
+                                    .route("/blockedIps/block", web::post().to(security::block))
+                                    .route("/blockedIps/unblock", web::post().to(security::unblock))
+                                    .route(
+                                        "/trustedNetworks",
+                                        web::get().to(security::trusted_networks),
+                                    )
+                                    .route("/trustedNetworks/add", web::post().to(security::trust))
+                                    .route(
+                                        "/trustedNetworks/remove",
+                                        web::post().to(security::distrust),
+                                    )
+                                    .route("/limits", web::get().to(security::limits))
+                                    .route("/limits", web::post().to(security::update_limits))
+                                    .route(
+                                        "/clientCertificates",
+                                        web::get().to(security::client_certificates),
+                                    )
+                                    .route(
+                                        "/clientCertificates/ca",
+                                        web::get().to(security::client_ca),
+                                    )
+                                    .route(
+                                        "/clientCertificates/issue",
+                                        web::post().to(security::issue_client_certificate),
+                                    )
+                                    .route(
+                                        "/clientCertificates/revoke",
+                                        web::post().to(security::revoke_client_certificate),
+                                    ),
+                            )
                             .service(
                                 web::scope("/dashboard")
                                     .route("/information", web::get().to(dashboard::information)),
@@ -484,6 +714,8 @@ async fn main() -> std::io::Result<()> {
                                 web::scope("/packages")
                                     .route("/database", web::get().to(packages::database))
                                     .route("/statistics", web::get().to(packages::statistics))
+                                    .route("/search", web::get().to(packages::search))
+                                    .route("/details/{name}", web::get().to(packages::details))
                                     .route("/updateDatabase", web::post().to(packages::update_db))
                                     .route("/install", web::post().to(packages::install_package))
                                     .route("/remove", web::post().to(packages::remove_package))
@@ -493,7 +725,51 @@ async fn main() -> std::io::Result<()> {
                                         "/removeOrphaned",
                                         web::post().to(packages::remove_orphaned),
                                     )
-                                    .route("/orphaned", web::get().to(packages::orphaned)),
+                                    .route("/orphaned", web::get().to(packages::orphaned))
+                                    .route(
+                                        "/unattended",
+                                        web::get().to(packages::unattended_updates),
+                                    )
+                                    .route("/unattended", web::post().to(packages::update_policy))
+                                    .route(
+                                        "/unattended/authorize",
+                                        web::post().to(packages::authorize_unattended_updates),
+                                    )
+                                    .route(
+                                        "/unattended/revoke",
+                                        web::post().to(packages::revoke_unattended_updates),
+                                    )
+                                    .route(
+                                        "/unattended/run",
+                                        web::post().to(packages::run_unattended_updates),
+                                    )
+                                    .route(
+                                        "/unattended/history",
+                                        web::get().to(packages::update_history),
+                                    )
+                                    .route("/history", web::get().to(packages::history))
+                                    .route(
+                                        "/history/undo",
+                                        web::post().to(packages::undo_transaction),
+                                    )
+                                    .route("/history/{id}", web::get().to(packages::transaction))
+                                    .route("/downgrade", web::post().to(packages::downgrade))
+                                    .route("/holds", web::get().to(packages::holds))
+                                    .route("/hold", web::post().to(packages::hold_package))
+                                    .route("/unhold", web::post().to(packages::unhold_package))
+                                    .route("/repositories", web::get().to(packages::repositories))
+                                    .route(
+                                        "/repositories/enabled",
+                                        web::post().to(packages::set_repository_enabled),
+                                    )
+                                    .route(
+                                        "/repositories/add",
+                                        web::post().to(packages::add_repository),
+                                    )
+                                    .route(
+                                        "/repositories/remove",
+                                        web::post().to(packages::remove_repository),
+                                    ),
                             )
                             .service(
                                 web::scope("/jobs")
@@ -518,13 +794,60 @@ async fn main() -> std::io::Result<()> {
                                     .route("/upload", web::post().to(files::upload)),
                             )
                             .service(
-                                web::scope("/drives").route("/list", web::get().to(drives::list)),
+                                web::scope("/drives")
+                                    .route("/list", web::get().to(drives::list))
+                                    .route("/usage", web::get().to(drives::usage))
+                                    .route("/usage/scan", web::post().to(drives::usage_scan))
+                                    .route("/usage/analysis", web::get().to(drives::usage_analysis))
+                                    .route("/smart", web::post().to(drives::smart))
+                                    .route("/smart/test", web::post().to(drives::self_test))
+                                    .route("/mounts", web::get().to(drives::mounts))
+                                    .route("/mount", web::post().to(drives::mount))
+                                    .route("/unmount", web::post().to(drives::unmount))
+                                    .route("/partitions", web::post().to(drives::partitions))
+                                    .route(
+                                        "/partitions/table",
+                                        web::post().to(drives::partition_table),
+                                    )
+                                    .route("/partitions/new", web::post().to(drives::new_partition))
+                                    .route("/format", web::post().to(drives::format))
+                                    .route("/label", web::post().to(drives::label))
+                                    .route("/fstab", web::get().to(drives::fstab))
+                                    .route("/fstab/new", web::post().to(drives::new_fstab_entry))
+                                    .route("/fstab/edit", web::post().to(drives::edit_fstab_entry))
+                                    .route(
+                                        "/fstab/delete",
+                                        web::post().to(drives::delete_fstab_entry),
+                                    ),
+                            )
+                            .service(
+                                web::scope("/storage")
+                                    .route("/list", web::post().to(storage::list))
+                                    .route("/snapshots", web::post().to(storage::snapshots))
+                                    .route("/snapshots/new", web::post().to(storage::new_snapshot))
+                                    .route(
+                                        "/snapshots/delete",
+                                        web::post().to(storage::delete_snapshot),
+                                    )
+                                    .route("/snapshots/rollback", web::post().to(storage::rollback))
+                                    .route("/scrub", web::post().to(storage::scrub))
+                                    .route("/scrub/status", web::post().to(storage::scrub_status))
+                                    .route("/schedules", web::get().to(storage::schedules))
+                                    .route("/schedules/new", web::post().to(storage::new_schedule))
+                                    .route(
+                                        "/schedules/delete",
+                                        web::post().to(storage::delete_schedule),
+                                    ),
                             )
                             .service(web::scope("/power").route("/off", web::post().to(power::off)))
                             .service(
                                 web::scope("/tls")
                                     .route("/name", web::get().to(tls::name))
-                                    .route("/upload", web::post().to(tls::upload)),
+                                    .route("/certificate", web::get().to(tls::certificate))
+                                    .route("/upload", web::post().to(tls::upload))
+                                    .route("/selfSigned", web::post().to(tls::self_signed))
+                                    .route("/acme", web::get().to(tls::acme_status))
+                                    .route("/acme/issue", web::post().to(tls::acme_issue)),
                             )
                             .service(
                                 web::scope("/account")
@@ -563,6 +886,8 @@ async fn main() -> std::io::Result<()> {
                                     .route("/interfaces", web::get().to(network::interfaces))
                                     .route("/routes", web::get().to(network::routes))
                                     .route("/route/delete", web::post().to(network::delete_route))
+                                    .route("/sockets", web::get().to(network::sockets))
+                                    .route("/sockets", web::post().to(network::sockets_privileged))
                                     .route(
                                         "/interface/active",
                                         web::post().to(network::activate_interface),
@@ -572,14 +897,36 @@ async fn main() -> std::io::Result<()> {
                                 web::scope("/processes")
                                     .route("/list", web::get().to(processes::list))
                                     .route("/kill/{pid}", web::post().to(processes::kill))
-                                    .route("/details/{pid}", web::get().to(processes::details)),
+                                    .route("/details/{pid}", web::get().to(processes::details))
+                                    .route("/signal/{pid}", web::post().to(processes::signal))
+                                    .route("/priority/{pid}", web::post().to(processes::priority))
+                                    .route("/affinity/{pid}", web::post().to(processes::affinity))
+                                    .route("/tree", web::get().to(processes::tree))
+                                    .route("/inspect/{pid}", web::get().to(processes::inspect))
+                                    .route(
+                                        "/inspect/{pid}",
+                                        web::post().to(processes::inspect_privileged),
+                                    ),
                             )
                             .service(
                                 web::scope("/cronjobs")
                                     .route("/runCommand", web::post().to(cron::run_command))
-                                    .route("/delete", web::post().to(cron::delete))
+                                    .route("/delete/{id}", web::post().to(cron::delete))
                                     .route("/new", web::post().to(cron::create))
-                                    .route("/list", web::get().to(cron::list)),
+                                    .route("/edit", web::post().to(cron::edit))
+                                    .route("/enabled", web::post().to(cron::set_enabled))
+                                    .route("/preview", web::post().to(cron::preview))
+                                    .route("/recording", web::post().to(cron::set_recording))
+                                    .route("/runs/{id}", web::get().to(cron::runs))
+                                    .route("/list", web::get().to(cron::list))
+                                    .route("/crontab", web::post().to(cron::crontab))
+                                    .route("/timers/new", web::post().to(cron::create_timer))
+                                    .route("/timers/edit", web::post().to(cron::edit_timer))
+                                    .route(
+                                        "/timers/enabled",
+                                        web::post().to(cron::set_timer_enabled),
+                                    )
+                                    .route("/timers/delete", web::post().to(cron::delete_timer)),
                             )
                             .service(
                                 web::scope("/sharing")
@@ -591,9 +938,41 @@ async fn main() -> std::io::Result<()> {
             )
             .service(afs::Files::new("/", "static/"))
     })
+    .on_connect(client_certificates::on_connect)
     .workers(16)
-    .keep_alive(Duration::from_secs(60 * 6))
-    .bind_rustls_0_23(("0.0.0.0", SERVER_PORT), tls_config)? // TODO Allow user to decide port and IP
-    .run()
-    .await
+    .keep_alive(Duration::from_secs(60 * 6));
+
+    for address in &config.bind {
+        server = match address {
+            BindAddress::Ip(ip) => {
+                info!(
+                    "Zentrox is being served on {}",
+                    SocketAddr::new(*ip, config.port)
+                );
+                server.bind_rustls_0_23((*ip, config.port), tls_config.clone())?
+            }
+            BindAddress::Unix(path) => {
+                remove_stale_socket(path)?;
+                info!("Zentrox is being served on {address}");
+                server.bind_uds(path)?
+            }
+        };
+    }
+
+    for address in &config.http_bind {
+        server = match address {
+            BindAddress::Ip(ip) => {
+                let socket = SocketAddr::new(*ip, config.http_port);
+                info!("Zentrox is being served without TLS on {socket}");
+                server.bind(socket)?
+            }
+            BindAddress::Unix(path) => {
+                remove_stale_socket(path)?;
+                info!("Zentrox is being served on {address}");
+                server.bind_uds(path)?
+            }
+        };
+    }
+
+    server.run().await
 }
@@ -0,0 +1,290 @@
+//! History of package transactions, combining the jobs started using Zentrox with the history
+//! kept by the package managers.
+//!
+//! Every package operation started using Zentrox is recorded in the `PackageJobs` table along
+//! with the output of the package managers. Transactions of the package managers that happened
+//! while a job was running are attributed to the job and the Zentrox account that started it.
+//! Jobs without a matching transaction, e.g. because the package manager keeps no history, are
+//! listed as transactions without changes.
+
+use diesel::prelude::*;
+use std::str::FromStr;
+use utils::models::PackageJob;
+use utils::packages::history::Transaction;
+use utils::packages::{self, PackageManager};
+use utils::sudo::{SudoError, SudoOutput};
+use utils::time::current_timestamp_secs;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::updates::record;
+
+/// Seconds a transaction may start before or after a job, as dnf only logs the minute.
+const CLOCK_SLACK: i64 = 60;
+/// Seconds an interrupted job is assumed to have run for at most.
+const INTERRUPTED_JOB_DURATION: i64 = 60 * 60;
+
+/// Record the start of a job.
+pub fn start(
+    state: &AppState,
+    id: Uuid,
+    username: Option<String>,
+    action: &str,
+    package_manager: Option<PackageManager>,
+    package: Option<String>,
+) -> Result<PackageJob, diesel::result::Error> {
+    let job = PackageJob {
+        id: id.to_string(),
+        started_at: current_timestamp_secs(),
+        finished_at: None,
+        username,
+        action: action.to_string(),
+        package_manager: package_manager.map(|m| m.to_string()),
+        package,
+        status: "running".to_string(),
+        output: String::new(),
+    };
+    save(state, &job)?;
+    Ok(job)
+}
+
+/// Record the output of every step of a job and whether all of them succeeded.
+pub fn finish(
+    state: &AppState,
+    job: &mut PackageJob,
+    steps: Vec<(String, Result<SudoOutput, SudoError>)>,
+) -> Result<bool, diesel::result::Error> {
+    let mut success = true;
+    for (step, result) in steps {
+        success &= record(&mut job.output, &step, result);
+    }
+    job.status = if success { "success" } else { "failed" }.to_string();
+    job.finished_at = Some(current_timestamp_secs());
+    save(state, job)?;
+    Ok(success)
+}
+
+fn save(state: &AppState, job: &PackageJob) -> Result<(), diesel::result::Error> {
+    use utils::schema::PackageJobs::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    diesel::insert_into(PackageJobs)
+        .values(job)
+        .on_conflict(id)
+        .do_update()
+        .set(job)
+        .execute(connection)?;
+    Ok(())
+}
+
+fn jobs(state: &AppState) -> Result<Vec<PackageJob>, diesel::result::Error> {
+    use utils::schema::PackageJobs::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    PackageJobs
+        .select(PackageJob::as_select())
+        .order(started_at.desc())
+        .load(connection)
+}
+
+/// When a job that was interrupted is considered finished, as the time it was interrupted at is
+/// not known. Zentrox may have been stopped for a long time, so the job is limited to
+/// [`INTERRUPTED_JOB_DURATION`] and later transactions are not attributed to it.
+fn interrupted_at(started_at: i64, now: i64) -> i64 {
+    now.min(started_at + INTERRUPTED_JOB_DURATION)
+}
+
+/// Mark jobs that were interrupted, e.g. by Zentrox being stopped, as failed.
+pub fn finish_interrupted(state: &AppState) -> Result<(), diesel::result::Error> {
+    use utils::schema::PackageJobs::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    let now = current_timestamp_secs();
+    let interrupted = PackageJobs
+        .filter(status.eq("running"))
+        .select((id, started_at))
+        .load::<(String, i64)>(connection)?;
+    for (job_id, started) in interrupted {
+        diesel::update(PackageJobs.filter(id.eq(job_id)))
+            .set((
+                status.eq("failed"),
+                finished_at.eq(interrupted_at(started, now)),
+            ))
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// All transactions of the package managers on the system and all jobs, starting with the latest.
+pub fn transactions(state: &AppState) -> Result<Vec<Transaction>, diesel::result::Error> {
+    let history = packages::backends()
+        .iter()
+        .flat_map(|b| b.history().unwrap_or_default())
+        .collect();
+    Ok(combine(history, jobs(state)?))
+}
+
+/// A transaction and the job that made it.
+pub fn find(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<(Transaction, Option<PackageJob>)>, diesel::result::Error> {
+    let jobs = jobs(state)?;
+    let history = match id.split_once('-') {
+        Some(("zentrox", _)) => Vec::new(),
+        Some((manager, _)) => match PackageManager::from_str(manager) {
+            Ok(manager) => manager.backend().history().unwrap_or_default(),
+            Err(_) => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let Some(transaction) = combine(history, jobs.clone())
+        .into_iter()
+        .find(|t| t.id == id)
+    else {
+        return Ok(None);
+    };
+    let job = jobs
+        .into_iter()
+        .find(|j| Some(&j.id) == transaction.job.as_ref());
+    Ok(Some((transaction, job)))
+}
+
+/// Attribute transactions to the jobs that were running when they started and add the jobs
+/// without any transaction.
+fn combine(mut history: Vec<Transaction>, jobs: Vec<PackageJob>) -> Vec<Transaction> {
+    for job in jobs {
+        let finished_at = job.finished_at.unwrap_or(i64::MAX - CLOCK_SLACK);
+        let mut matched = false;
+        for transaction in history.iter_mut().filter(|t| {
+            t.job.is_none()
+                && t.started_at >= job.started_at - CLOCK_SLACK
+                && t.started_at <= finished_at + CLOCK_SLACK
+                && (job.package_manager.is_none()
+                    || t.package_manager.map(|m| m.to_string()) == job.package_manager)
+        }) {
+            transaction.job = Some(job.id.clone());
+            if job.username.is_some() {
+                transaction.user = job.username.clone();
+            }
+            matched = true;
+        }
+
+        if !matched {
+            history.push(Transaction {
+                id: format!("zentrox-{}", job.id),
+                package_manager: job
+                    .package_manager
+                    .as_deref()
+                    .and_then(|m| PackageManager::from_str(m).ok()),
+                started_at: job.started_at,
+                user: job.username,
+                command: Some(match job.package {
+                    Some(package) => format!("{} {package}", job.action),
+                    None => job.action,
+                }),
+                changes: Vec::new(),
+                undoable: false,
+                job: Some(job.id),
+            });
+        }
+    }
+
+    history.sort_by_key(|t| std::cmp::Reverse(t.started_at));
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(id: &str, started_at: i64) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            package_manager: Some(PackageManager::Apt),
+            started_at,
+            user: Some("root".to_string()),
+            command: None,
+            changes: Vec::new(),
+            undoable: false,
+            job: None,
+        }
+    }
+
+    #[test]
+    fn combine_jobs() {
+        let job = |id: &str, started_at: i64, package_manager: Option<&str>| PackageJob {
+            id: id.to_string(),
+            started_at,
+            finished_at: Some(started_at + 30),
+            username: Some("admin".to_string()),
+            action: "updateAll".to_string(),
+            package_manager: package_manager.map(String::from),
+            package: None,
+            status: "success".to_string(),
+            output: String::new(),
+        };
+
+        let combined = combine(
+            vec![transaction("apt-1000", 1000), transaction("apt-500", 500)],
+            vec![job("a", 990, None), job("b", 3000, Some("dnf"))],
+        );
+
+        assert_eq!(
+            combined
+                .iter()
+                .map(|t| t.id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["zentrox-b", "apt-1000", "apt-500"]
+        );
+        assert_eq!(combined[0].package_manager, Some(PackageManager::Dnf));
+        assert_eq!(combined[0].command.as_deref(), Some("updateAll"));
+        assert_eq!(combined[1].job.as_deref(), Some("a"));
+        assert_eq!(combined[1].user.as_deref(), Some("admin"));
+        assert_eq!(combined[2].job, None);
+        assert_eq!(combined[2].user.as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn combine_interrupted_job() {
+        // As left by finish_interrupted after Zentrox was restarted a day later
+        let interrupted = PackageJob {
+            id: "a".to_string(),
+            started_at: 1000,
+            finished_at: Some(interrupted_at(1000, 1000 + 24 * 60 * 60)),
+            username: Some("admin".to_string()),
+            action: "updateAll".to_string(),
+            package_manager: None,
+            package: None,
+            status: "failed".to_string(),
+            output: String::new(),
+        };
+
+        let combined = combine(
+            vec![
+                // Made over SSH while Zentrox was stopped
+                transaction("apt-50000", 50_000),
+                transaction("apt-5000", 5000),
+                transaction("apt-3000", 3000),
+                transaction("apt-1010", 1010),
+            ],
+            vec![interrupted],
+        );
+
+        assert_eq!(
+            combined
+                .iter()
+                .map(|t| (t.id.as_str(), t.job.as_deref()))
+                .collect::<Vec<(&str, Option<&str>)>>(),
+            vec![
+                ("apt-50000", None),
+                ("apt-5000", None),
+                ("apt-3000", Some("a")),
+                ("apt-1010", Some("a"))
+            ]
+        );
+        assert_eq!(combined[0].user.as_deref(), Some("root"));
+        assert_eq!(interrupted_at(1000, 1500), 1500);
+    }
+}
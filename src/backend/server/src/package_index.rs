@@ -0,0 +1,213 @@
+//! Cache of the packages known to the package managers on the system.
+//!
+//! Listing packages runs commands like `apt list` that take several seconds, so the lists are
+//! stored in the `PackageIndex` table and served from there. The index is refreshed after the
+//! package databases were updated, after every package job and unattended update run and once
+//! it is older than [`MAX_AGE`], which also picks up changes made outside of Zentrox. If a
+//! package manager fails to list its packages, the index is not refreshed for [`RETRY_DELAY`]
+//! unless a package operation happened and the previous index is served.
+
+use diesel::prelude::*;
+use log::warn;
+use utils::models::PackageIndexEntry;
+use utils::packages::{self, PackageBackend, PackageManager};
+use utils::time::current_timestamp_secs;
+
+use crate::AppState;
+
+/// Seconds after which the index is refreshed even if no package operation happened.
+pub const MAX_AGE: i64 = 60 * 60;
+
+/// Seconds to wait after a failed refresh before the index is refreshed again because it is stale
+/// or incomplete.
+pub const RETRY_DELAY: i64 = 15 * 60;
+
+/// The packages known to a package manager
+pub struct IndexedPackages {
+    pub package_manager: PackageManager,
+    pub installed: Vec<String>,
+    pub available: Vec<String>,
+    /// Missing if the updates could not be listed
+    pub updates: Option<Vec<String>>,
+    pub foreign: Vec<String>,
+    /// Seconds since the UNIX epoch
+    pub refreshed_at: i64,
+}
+
+impl IndexedPackages {
+    fn from_entry(entry: PackageIndexEntry) -> Option<Self> {
+        Some(IndexedPackages {
+            package_manager: entry.package_manager.parse().ok()?,
+            installed: split(&entry.installed),
+            available: split(&entry.available),
+            updates: entry.updates.as_deref().map(split),
+            foreign: split(&entry.foreign_packages),
+            refreshed_at: entry.refreshed_at,
+        })
+    }
+
+    fn to_entry(&self) -> PackageIndexEntry {
+        PackageIndexEntry {
+            package_manager: self.package_manager.to_string(),
+            installed: self.installed.join("\n"),
+            available: self.available.join("\n"),
+            updates: self.updates.as_ref().map(|u| u.join("\n")),
+            foreign_packages: self.foreign.join("\n"),
+            refreshed_at: self.refreshed_at,
+        }
+    }
+}
+
+fn split(names: &str) -> Vec<String> {
+    names
+        .lines()
+        .filter(|n| !n.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// List the packages of a package manager. Fails if the installed or available packages can not
+/// be listed.
+fn list(backend: &dyn PackageBackend) -> Option<IndexedPackages> {
+    let manager = backend.package_manager();
+    let (installed, available) = match (backend.list_installed(), backend.list_available()) {
+        (Ok(installed), Ok(available)) => (installed, available),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Listing the packages of {manager} failed: {e:?}");
+            return None;
+        }
+    };
+
+    Some(IndexedPackages {
+        package_manager: manager,
+        installed,
+        available,
+        updates: backend.list_updates().ok(),
+        foreign: backend.list_foreign().unwrap_or_default(),
+        refreshed_at: current_timestamp_secs(),
+    })
+}
+
+/// The indexed packages of every package manager.
+pub fn load(state: &AppState) -> Result<Vec<IndexedPackages>, diesel::result::Error> {
+    use utils::schema::PackageIndex::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    Ok(PackageIndex
+        .select(PackageIndexEntry::as_select())
+        .load(connection)?
+        .into_iter()
+        .filter_map(IndexedPackages::from_entry)
+        .collect())
+}
+
+/// List the packages of every package manager on the system and store them in the index.
+///
+/// Package managers that fail to list their packages keep their previous entry, package managers
+/// that are no longer installed are removed. Blocks until a refresh that is already running has
+/// finished, as it may have started before the latest package operation.
+pub fn refresh(state: &AppState) -> Result<(), diesel::result::Error> {
+    let _refreshing = state.package_index_lock.lock().unwrap();
+    let backends = packages::backends();
+    let indexed: Vec<IndexedPackages> = backends.iter().filter_map(|b| list(&**b)).collect();
+    *state.package_index_failed_at.lock().unwrap() =
+        (indexed.len() < backends.len()).then(current_timestamp_secs);
+
+    use utils::schema::PackageIndex::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+    connection.transaction(|connection| {
+        let detected: Vec<String> = backends
+            .iter()
+            .map(|b| b.package_manager().to_string())
+            .collect();
+        diesel::delete(PackageIndex.filter(package_manager.ne_all(detected)))
+            .execute(connection)?;
+
+        for packages in &indexed {
+            let entry = packages.to_entry();
+            diesel::insert_into(PackageIndex)
+                .values(&entry)
+                .on_conflict(package_manager)
+                .do_update()
+                .set(&entry)
+                .execute(connection)?;
+        }
+        Ok(())
+    })
+}
+
+/// Whether a refresh failed less than [`RETRY_DELAY`] ago.
+fn failed_recently(state: &AppState) -> bool {
+    state
+        .package_index_failed_at
+        .lock()
+        .unwrap()
+        .is_some_and(|failed_at| current_timestamp_secs() - failed_at < RETRY_DELAY)
+}
+
+/// Refresh the index if it is empty or the oldest entry is older than [`MAX_AGE`], unless a
+/// refresh failed recently.
+pub fn refresh_if_stale(state: &AppState) -> Result<(), diesel::result::Error> {
+    if failed_recently(state) {
+        return Ok(());
+    }
+    let oldest = load(state)?.iter().map(|p| p.refreshed_at).min();
+    match oldest {
+        Some(refreshed_at) if current_timestamp_secs() - refreshed_at < MAX_AGE => Ok(()),
+        _ => refresh(state),
+    }
+}
+
+/// The indexed packages, refreshing the index first if the system package manager has not been
+/// indexed yet, e.g. right after the first start. The index is served as it is if a refresh
+/// failed recently, so a failing package manager is not run on every request.
+pub fn load_or_refresh(state: &AppState) -> Result<Vec<IndexedPackages>, diesel::result::Error> {
+    let primary = packages::get_package_manager().ok();
+    let indexed = load(state)?;
+    if indexed.iter().any(|p| Some(p.package_manager) == primary) || failed_recently(state) {
+        return Ok(indexed);
+    }
+    refresh(state)?;
+    load(state)
+}
+
+/// Refresh the index after a package operation, failures are only logged.
+pub fn refresh_after_change(state: &AppState) {
+    if let Err(e) = refresh(state) {
+        warn!("Refreshing the package index failed with error: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_index_entry() {
+        let packages = IndexedPackages {
+            package_manager: PackageManager::Pacman,
+            installed: vec!["bash".to_string(), "yay".to_string()],
+            available: vec!["bash".to_string()],
+            updates: Some(Vec::new()),
+            foreign: vec!["yay".to_string()],
+            refreshed_at: 1700000000,
+        };
+
+        let entry = packages.to_entry();
+        assert_eq!(entry.package_manager, "pacman");
+        assert_eq!(entry.installed, "bash\nyay");
+        assert_eq!(entry.updates.as_deref(), Some(""));
+
+        let restored = IndexedPackages::from_entry(entry).unwrap();
+        assert_eq!(restored.installed, packages.installed);
+        assert_eq!(restored.available, packages.available);
+        assert_eq!(restored.updates, Some(Vec::new()));
+        assert_eq!(restored.foreign, packages.foreign);
+
+        let unknown_manager = PackageIndexEntry {
+            package_manager: "unknown".to_string(),
+            ..packages.to_entry()
+        };
+        assert!(IndexedPackages::from_entry(unknown_manager).is_none());
+    }
+}
@@ -2,6 +2,7 @@ use actix_session::Session;
 use actix_web::web;
 use diesel::RunQueryDsl;
 use diesel::prelude::*;
+use ipnet::IpNet;
 use log::warn;
 use rand::Rng;
 use std::str::FromStr;
@@ -11,21 +12,6 @@ use uuid::Uuid;
 
 use crate::AppState;
 
-// Surpassing 5 failed requests from one peer per 10 minutes will lead to a temporary block
-pub const BASE_TIME_WINDOW: u64 = 10 * 60;
-pub const BASE_REQUEST_LIMIT: usize = 5;
-
-// Surpassing 15 failed requests from one peer per 30 minutes will lead to a permanent IP block
-pub const LARGE_TIME_WINDOW: u64 = 30 * 60;
-pub const LARGE_REQUEST_LIMIT: usize = 15;
-
-// Surpassing 60 failed requests per 60 minutes will lead to the affected account being locked down for any IP
-pub const ACCOUNT_TIME_WINDOW: u64 = 60 * 60;
-pub const ACCOUNT_REQUEST_LIMIT: usize = 60;
-
-// Duration in seconds that a session may be alive.
-pub const SESSION_TIMEOUT: u64 = 60 * 60 * 12; // = 12h
-
 #[derive(PartialEq, Debug, Clone)]
 pub enum LoginAction {
     Limited,
@@ -74,13 +60,13 @@ pub struct LoginSession {
 }
 
 impl LoginSession {
-    /// Checks if a session has not yet timed out.
-    fn is_valid(&self) -> bool {
+    /// Checks if a session has not yet outlived the session TTL in seconds.
+    pub fn is_valid(&self, session_ttl: u64) -> bool {
         SystemTime::now()
             .duration_since(self.since)
             .unwrap()
             .as_secs()
-            <= SESSION_TIMEOUT
+            <= session_ttl
     }
 }
 
@@ -193,7 +179,7 @@ pub fn register_session(session: Session, state: &AppState, username: String, ip
 /// In case no token is provided by the sessions, false is returned as default.
 pub fn is_privileged(session: &Session, state: web::Data<AppState>) -> bool {
     if let Ok(current_session) = locate_session(session, &state) {
-        if current_session.is_valid() {
+        if current_session.is_valid(state.config.session_ttl) {
             true
         } else {
             remove_session(current_session, &state);
@@ -205,28 +191,99 @@ pub fn is_privileged(session: &Session, state: web::Data<AppState>) -> bool {
     }
 }
 
+/// Parses either a single IP address or a network in CIDR notation.
+/// A single address is treated as a network containing only this address.
+pub fn parse_network(value: &str) -> Option<IpNet> {
+    let trimmed = value.trim();
+    IpNet::from_str(trimmed)
+        .map(|n| n.trunc())
+        .or_else(|_| IpAddr::from_str(trimmed).map(IpNet::from))
+        .ok()
+}
+
+/// Formats a network the way it is stored in the database.
+/// Networks that only contain a single address are stored without a prefix length.
+pub fn format_network(network: &IpNet) -> String {
+    if network.prefix_len() == network.max_prefix_len() {
+        network.addr().to_string()
+    } else {
+        network.to_string()
+    }
+}
+
 /// Adds an IP address to the list of blocked peers both in the app state and the database.
+/// Addresses inside of a trusted network are never blocked.
 pub fn block_ip(state: &AppState, to_be_blocked_ip: IpAddr) {
+    if is_trusted_ip(state, to_be_blocked_ip) {
+        warn!("The trusted peer {to_be_blocked_ip} would have been blocked.");
+        return;
+    }
+
+    if let Err(e) = block_network(state, IpNet::from(to_be_blocked_ip)) {
+        log::error!("Failed to store IP {to_be_blocked_ip} as blocked in database: {e}");
+    }
+}
+
+/// Adds a network to the list of blocked networks both in the app state and the database.
+pub fn block_network(state: &AppState, network: IpNet) -> Result<(), diesel::result::Error> {
     use utils::models::BlockedIp;
     use utils::schema::BlockedIPs::dsl::*;
 
-    let exec = diesel::insert_into(BlockedIPs)
+    diesel::insert_into(BlockedIPs)
         .values(BlockedIp {
-            ip: to_be_blocked_ip.to_string(),
+            ip: format_network(&network),
             since: utils::time::current_timestamp_unix() as i64,
         })
-        .execute(&mut state.db_pool.lock().unwrap().get().unwrap());
+        .on_conflict_do_nothing()
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
 
-    if exec.is_err() {
-        log::error!("Failed to store IP {to_be_blocked_ip} as blocked in database.");
+    let mut blocked = state.blocked_ips.lock().unwrap();
+    if !blocked.contains(&network) {
+        blocked.push(network);
     }
 
-    state.blocked_ips.lock().unwrap().push(to_be_blocked_ip);
+    Ok(())
+}
+
+/// Removes a network from the list of blocked networks both in the app state and the database.
+/// The network has to match an existing entry exactly, ranges are not split up.
+///
+/// Returns the number of removed database entries.
+pub fn unblock_network(state: &AppState, network: IpNet) -> Result<usize, diesel::result::Error> {
+    use utils::schema::BlockedIPs::dsl::*;
+
+    let removed = diesel::delete(BlockedIPs.filter(ip.eq(format_network(&network))))
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    state.blocked_ips.lock().unwrap().retain(|n| *n != network);
+
+    Ok(removed)
+}
+
+/// Checks if an IP address is contained in one of the networks.
+fn contains_ip(networks: &[IpNet], ip: IpAddr) -> bool {
+    networks.iter().any(|n| n.contains(&ip))
 }
 
-/// Checks if an IP address is in the app states list of blocked ips.
+/// Checks if an IP address is contained in one of the blocked networks, but in none of the
+/// trusted networks.
+fn is_blocked(blocked: &[IpNet], trusted: &[IpNet], ip: IpAddr) -> bool {
+    !contains_ip(trusted, ip) && contains_ip(blocked, ip)
+}
+
+/// Checks if an IP address is contained in one of the app states blocked networks.
+/// Trusted addresses are never considered blocked.
 pub fn is_blocked_ip(state: &AppState, ip: IpAddr) -> bool {
-    state.blocked_ips.lock().unwrap().contains(&ip)
+    is_blocked(
+        &state.blocked_ips.lock().unwrap(),
+        &state.trusted_networks.lock().unwrap(),
+        ip,
+    )
+}
+
+/// Checks if an IP address is contained in one of the app states trusted networks.
+pub fn is_trusted_ip(state: &AppState, ip: IpAddr) -> bool {
+    contains_ip(&state.trusted_networks.lock().unwrap(), ip)
 }
 
 /// Given a reference of the app state, this function will insert all blocked IPs from the database into
@@ -243,14 +300,156 @@ pub fn load_blocked_ips(state: &AppState) {
     state.blocked_ips.lock().unwrap().append(
         &mut blocked_ips
             .iter()
-            .map(|x| IpAddr::from_str(&x.ip).unwrap())
-            .collect::<Vec<IpAddr>>(),
+            .filter_map(|x| {
+                let parsed = parse_network(&x.ip);
+                if parsed.is_none() {
+                    warn!("Ignoring malformed blocked network {} in database.", x.ip);
+                }
+                parsed
+            })
+            .collect::<Vec<IpNet>>(),
+    )
+}
+
+/// Adds a network to the list of trusted networks both in the app state and the database.
+pub fn trust_network(state: &AppState, to_be_trusted: IpNet) -> Result<(), diesel::result::Error> {
+    use utils::models::TrustedNetwork;
+    use utils::schema::TrustedNetworks::dsl::*;
+
+    diesel::insert_into(TrustedNetworks)
+        .values(TrustedNetwork {
+            network: format_network(&to_be_trusted),
+            since: utils::time::current_timestamp_unix() as i64,
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    let mut trusted = state.trusted_networks.lock().unwrap();
+    if !trusted.contains(&to_be_trusted) {
+        trusted.push(to_be_trusted);
+    }
+
+    Ok(())
+}
+
+/// Removes a network from the list of trusted networks both in the app state and the database.
+///
+/// Returns the number of removed database entries.
+pub fn distrust_network(
+    state: &AppState,
+    to_be_distrusted: IpNet,
+) -> Result<usize, diesel::result::Error> {
+    use utils::schema::TrustedNetworks::dsl::*;
+
+    let removed =
+        diesel::delete(TrustedNetworks.filter(network.eq(format_network(&to_be_distrusted))))
+            .execute(&mut state.db_pool.lock().unwrap().get().unwrap())?;
+
+    state
+        .trusted_networks
+        .lock()
+        .unwrap()
+        .retain(|n| *n != to_be_distrusted);
+
+    Ok(removed)
+}
+
+/// Given a reference of the app state, this function will insert all trusted networks from the
+/// database into the app state.
+pub fn load_trusted_networks(state: &AppState) {
+    use utils::models::TrustedNetwork;
+    use utils::schema::TrustedNetworks::dsl::*;
+
+    let trusted_networks: Vec<TrustedNetwork> = TrustedNetworks
+        .select(TrustedNetwork::as_select())
+        .get_results(&mut state.db_pool.lock().unwrap().get().unwrap())
+        .unwrap();
+
+    state.trusted_networks.lock().unwrap().append(
+        &mut trusted_networks
+            .iter()
+            .filter_map(|x| parse_network(&x.network))
+            .collect::<Vec<IpNet>>(),
     )
 }
 
+/// Given a reference of the app state, this function will read the brute-force thresholds from
+/// the database into the app state.
+pub fn load_login_limits(state: &AppState) {
+    use utils::models::LoginLimits;
+    use utils::schema::Configuration::dsl::*;
+
+    match Configuration
+        .select(LoginLimits::as_select())
+        .first(&mut state.db_pool.lock().unwrap().get().unwrap())
+    {
+        Ok(limits) => *state.login_limits.lock().unwrap() = limits,
+        Err(e) => warn!("Failed to read login limits, falling back to defaults: {e}"),
+    }
+}
+
 /// Generates a new random string that can be used as a session token.
 pub fn generate_random_token() -> String {
     let mut rng = rand::rngs::OsRng;
     let token: [u8; 32] = rng.r#gen();
     hex::encode(token)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    fn net(s: &str) -> IpNet {
+        IpNet::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_hosts_and_networks() {
+        assert_eq!(parse_network("203.0.113.7"), Some(net("203.0.113.7/32")));
+        assert_eq!(parse_network(" 2001:db8::1 "), Some(net("2001:db8::1/128")));
+        assert_eq!(parse_network("10.0.0.0/8"), Some(net("10.0.0.0/8")));
+        // Host bits are dropped
+        assert_eq!(
+            parse_network("192.168.1.77/24"),
+            Some(net("192.168.1.0/24"))
+        );
+        assert_eq!(parse_network("2001:db8::1/32"), Some(net("2001:db8::/32")));
+        for invalid in ["", "localhost", "10.0.0.0/33", "300.0.0.1", "10.0.0.0/"] {
+            assert_eq!(parse_network(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn format_networks() {
+        for stored in ["203.0.113.7", "10.0.0.0/8", "2001:db8::1", "2001:db8::/32"] {
+            let network = parse_network(stored).unwrap();
+            assert_eq!(format_network(&network), stored);
+        }
+        assert_eq!(
+            format_network(&parse_network("192.168.1.77/24").unwrap()),
+            "192.168.1.0/24"
+        );
+    }
+
+    #[test]
+    fn trusted_peers_are_never_blocked() {
+        let blocked = vec![net("10.0.0.0/8"), net("203.0.113.7/32")];
+        let trusted = vec![net("10.1.0.0/16")];
+
+        assert!(is_blocked(&blocked, &trusted, ip("10.2.3.4")));
+        assert!(is_blocked(&blocked, &trusted, ip("203.0.113.7")));
+        assert!(!is_blocked(&blocked, &trusted, ip("10.1.2.3")));
+        assert!(!is_blocked(&blocked, &trusted, ip("198.51.100.1")));
+        assert!(!is_blocked(
+            &blocked,
+            &[net("0.0.0.0/0")],
+            ip("203.0.113.7")
+        ));
+        assert!(contains_ip(&trusted, ip("10.1.255.255")));
+        assert!(!contains_ip(&[], ip("10.1.2.3")));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    AppState,
+    AppState, get_zentrox_directory,
     permissions::{self, locate_session},
 };
 use actix_multipart::form::{MultipartForm, tempfile::TempFile};
@@ -13,7 +13,7 @@ use diesel::prelude::*;
 use log::error;
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
-use std::{fs, io::Read, path, time::UNIX_EPOCH};
+use std::{fs, io::Read, time::UNIX_EPOCH};
 use utils::{
     crypto_utils::encrypt_bytes,
     otp::{derive_otp_url, generate_otp_secret},
@@ -196,13 +196,7 @@ pub async fn enable_otp(
     tags = ["private", "account"]
 )]
 pub async fn picture() -> HttpResponse {
-    let f = fs::read(
-        path::Path::new(&dirs::home_dir().unwrap())
-            .join(".local")
-            .join("share")
-            .join("zentrox")
-            .join("profile.png"),
-    );
+    let f = fs::read(get_zentrox_directory().join("profile.png"));
 
     match f {
         Ok(fh) => {
@@ -232,11 +226,7 @@ pub struct ProfilePictureUploadForm {
 pub async fn upload_picture(
     MultipartForm(form): MultipartForm<ProfilePictureUploadForm>,
 ) -> HttpResponse {
-    let profile_picture_path = path::Path::new(&dirs::home_dir().unwrap())
-        .join(".local")
-        .join("share")
-        .join("zentrox")
-        .join("profile.png");
+    let profile_picture_path = get_zentrox_directory().join("profile.png");
 
     let tmp_file_path = form.file.file.path().to_owned();
     let _ = fs::copy(&tmp_file_path, &profile_picture_path);
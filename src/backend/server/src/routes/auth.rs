@@ -13,11 +13,10 @@ use utils::models::LoginRequest;
 use utils::status_com::{ErrorCode, MessageRes};
 use utoipa::ToSchema;
 
-use crate::permissions::{
-    ACCOUNT_REQUEST_LIMIT, ACCOUNT_TIME_WINDOW, BASE_REQUEST_LIMIT, BASE_TIME_WINDOW,
-    LARGE_REQUEST_LIMIT, LARGE_TIME_WINDOW, LoginAction, locate_session, register_session,
-    remove_session,
-};
+use crate::client_certificates::certificate_account;
+use crate::client_ip::client_ip;
+use crate::config::CertificateLogin;
+use crate::permissions::{LoginAction, locate_session, register_session, remove_session};
 use crate::{AppState, SudoPasswordReq, permissions};
 
 #[derive(Deserialize, ToSchema)]
@@ -65,19 +64,20 @@ fn detects_spamming(state: &AppState, req_username: &String, req_ip: IpAddr) ->
     let lock = state.login_requests.lock().unwrap();
     let past_requests = lock.clone();
     drop(lock);
+    let limits = *state.login_limits.lock().unwrap();
 
     // Get the total number of all requests to the specified account that failed in the
-    // ACCOUNT_TIME_WINDOW
+    // account time window
     let total_denying_request = past_requests
         .iter()
         .filter(|r| {
             r.username == *req_username
                 && r.action.is_denying()
-                && since_now(r.time) < ACCOUNT_TIME_WINDOW
+                && since_now(r.time) < limits.account_time_window as u64
         })
         .count();
 
-    if total_denying_request > ACCOUNT_REQUEST_LIMIT {
+    if total_denying_request > limits.account_request_limit as usize {
         // It appears, someone is trying to brute-force the credentials through multiple peers.
         store_request(
             req_username.to_string(),
@@ -93,18 +93,21 @@ fn detects_spamming(state: &AppState, req_username: &String, req_ip: IpAddr) ->
         .iter()
         .filter(|r| r.username == *req_username && r.action.is_denying() && r.ip == req_ip);
 
-    // Get the number of all requests by this IP to the specified account in the BASE_TIME_WINDOW
+    // Get the number of all requests by this IP to the specified account in the base time window
     let ip_base_window_reqs = ip_matching_denying_request
         .clone()
-        .filter(|r| since_now(r.time) < BASE_TIME_WINDOW)
+        .filter(|r| since_now(r.time) < limits.base_time_window as u64)
         .count();
-    // Get the number of all requests by this IP to the specified account in the LARGE_TIME_WINDOW
+    // Get the number of all requests by this IP to the specified account in the large time window
     let ip_large_window_reqs = ip_matching_denying_request
         .clone()
-        .filter(|r| since_now(r.time) < LARGE_TIME_WINDOW)
+        .filter(|r| since_now(r.time) < limits.large_time_window as u64)
         .count();
 
-    if ip_large_window_reqs > LARGE_REQUEST_LIMIT {
+    // Trusted peers are never blocked permanently, but may still be limited temporarily
+    if ip_large_window_reqs > limits.large_request_limit as usize
+        && !permissions::is_trusted_ip(state, req_ip)
+    {
         permissions::block_ip(state, req_ip);
         store_request(
             req_username.to_string(),
@@ -116,7 +119,7 @@ fn detects_spamming(state: &AppState, req_username: &String, req_ip: IpAddr) ->
         return true;
     }
 
-    if ip_base_window_reqs > BASE_REQUEST_LIMIT {
+    if ip_base_window_reqs > limits.base_request_limit as usize {
         store_request(req_username.clone(), req_ip, LoginAction::Limited, state);
         warn!("Login requests seem suspicious and will be blocked for this cycle.");
         return true;
@@ -147,7 +150,7 @@ pub async fn login(
     use utils::models::Account;
     use utils::schema::Users::dsl::*;
 
-    let req_ip = req.peer_addr().unwrap().ip();
+    let req_ip = client_ip(&req, &state);
     let req_username = &json.username;
     let req_password = &json.password;
     let req_otp_token = &json.otp;
@@ -200,6 +203,20 @@ pub async fn login(
         return HttpResponse::Forbidden().json(ErrorCode::WrongPassword);
     }
 
+    if state.config.client_certificates.login == CertificateLogin::Both
+        && certificate_account(&req, &state).as_ref() != Some(req_username)
+    {
+        store_request(
+            req_username.to_string(),
+            req_ip,
+            LoginAction::Rejected,
+            &state,
+        );
+        warn!("Login request lacks a client certificate for {req_username}.");
+        return HttpResponse::Forbidden()
+            .json(ErrorCode::ClientCertificateRequired.as_error_message());
+    }
+
     if !correct_user.use_otp {
         store_request(
             req_username.to_string(),
@@ -259,6 +276,57 @@ pub async fn login(
     }
 }
 
+/// Log in using the client certificate of the connection
+///
+/// The session is created for the account the certificate was issued for. This is only available
+/// if the server is configured with `login = "certificate"` in the `client_certificates` section.
+#[utoipa::path(
+    post,
+    path = "/public/auth/certificateLogin",
+    responses(
+        (status = 200, description = "The login was successful"),
+        (status = 403, description = "No valid client certificate was presented."),
+        (status = 404, description = "Logging in with client certificates is disabled.")
+    ),
+    tags = ["public", "authentication"]
+)]
+pub async fn certificate_login(
+    session: Session,
+    state: Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    use utils::schema::Users::dsl::*;
+
+    if state.config.client_certificates.login != CertificateLogin::Certificate {
+        return HttpResponse::NotFound()
+            .json(ErrorCode::CertificateLoginDisabled.as_error_message());
+    }
+
+    let req_ip = client_ip(&req, &state);
+
+    // Certificates of deleted accounts must not log in
+    let account = certificate_account(&req, &state).filter(|a| {
+        Users
+            .select(username)
+            .filter(username.eq(a))
+            .first::<String>(&mut state.db_pool.lock().unwrap().get().unwrap())
+            .is_ok()
+    });
+
+    let Some(account) = account else {
+        warn!("Certificate login from {req_ip} without a valid client certificate.");
+        return HttpResponse::Forbidden()
+            .json(ErrorCode::ClientCertificateRequired.as_error_message());
+    };
+
+    store_request(account.clone(), req_ip, LoginAction::Approved, &state);
+    info!("New certificate login as {account} from {req_ip}");
+    register_session(session, state.as_ref(), account.clone(), req_ip);
+    HttpResponse::Ok().json(MessageRes::from(format!(
+        "You have been logged in as {account}."
+    )))
+}
+
 /// Verifies a given sudo password
 #[utoipa::path(
     post,
@@ -1,4 +1,5 @@
-use crate::{AppState, BackgroundTaskState, routes::cron};
+use crate::cron_history::RunSummary;
+use crate::{AppState, BackgroundTaskState, cron_history};
 use actix_web::web::Json;
 use actix_web::{
     HttpResponse,
@@ -7,20 +8,26 @@ use actix_web::{
 use log::{debug, info};
 use log::{error, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::process::{Command, Stdio};
-use utils::cron::list_cronjobs;
+use utils::models::CronjobRun;
 use utils::status_com::ErrorCode;
+use utils::sudo::{SudoCredential, SudoError};
+use utils::timers::{self, SystemdTimer, TimerDefinition, TimerError, UnitScope};
 use utils::{
     cron::{
-        CronError, CronJob, DayOfWeek, Digit, Interval, IntervalCronJob, Month, SpecificCronJob,
-        create_new_interval_cronjob, create_new_specific_cronjob, delete_interval_cronjob,
-        delete_specific_cronjob,
+        CronError, CronJob, Crontab, CrontabEntry, CrontabSource, Interval, IntervalCronJob,
+        SpecificCronJob, can_record, create_cronjob, delete_cronjob, edit_cronjob, find_cronjob,
+        is_user_name, list_cronjobs, list_crontabs, set_cronjob_enabled,
     },
     status_com::MessageRes,
 };
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+/// Upper limit of previewed runs
+const MAX_PREVIEW: usize = 100;
+
 #[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum CronjobVariant {
@@ -30,57 +37,272 @@ pub enum CronjobVariant {
     Interval,
 }
 
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct SpecificCronjobRes {
+    /// Stays the same while other cronjobs are changed, but changes when the cronjob is edited
+    id: String,
+    /// Disabled cronjobs are commented out in the crontab
+    enabled: bool,
+    /// The next run in seconds since the UNIX epoch
+    next_run: Option<i64>,
+    /// The user the cronjob runs as, only listed for `/etc/crontab` and `/etc/cron.d`
+    user: Option<String>,
+    /// Whether runs are recorded in the history of the cronjob
+    recorded: bool,
+    /// Missing if runs are not recorded or the cronjob has not run yet
+    last_run: Option<RunSummary>,
+    #[serde(flatten)]
+    job: SpecificCronJob,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct IntervalCronjobRes {
+    /// Stays the same while other cronjobs are changed, but changes when the cronjob is edited
+    id: String,
+    /// Disabled cronjobs are commented out in the crontab
+    enabled: bool,
+    /// The next run in seconds since the UNIX epoch, missing for `@reboot`
+    next_run: Option<i64>,
+    /// The user the cronjob runs as, only listed for `/etc/crontab` and `/etc/cron.d`
+    user: Option<String>,
+    /// Whether runs are recorded in the history of the cronjob
+    recorded: bool,
+    /// Missing if runs are not recorded or the cronjob has not run yet
+    last_run: Option<RunSummary>,
+    #[serde(flatten)]
+    job: IntervalCronJob,
+}
+
 #[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct ListCronjobsRes {
-    specific_jobs: Vec<SpecificCronJob>,
-    interval_jobs: Vec<IntervalCronJob>,
+    specific_jobs: Vec<SpecificCronjobRes>,
+    interval_jobs: Vec<IntervalCronjobRes>,
     crontab_exists: bool,
+    /// systemd timers of the system and the user running Zentrox
+    timers: Vec<SystemdTimer>,
+    /// Crontabs that can be read using `/private/cronjobs/crontab`, crontabs of other users are
+    /// not listed
+    #[schema(value_type = Vec<String>)]
+    crontabs: Vec<CrontabSource>,
 }
 
-/// List users' cronjobs
-#[utoipa::path(get, path = "/private/cronjobs/list", responses((status = 200, body = ListCronjobsRes), (status = 404, description = "No crontab file was found."), (status = 500, description = "Cronjobs could not be read.")), tags = ["private", "cronjobs"])]
-pub async fn list() -> HttpResponse {
-    let crons = cron::list_cronjobs();
-    let mut interval_cronjobs: Vec<IntervalCronJob> = vec![];
-    let mut specific_cronjobs: Vec<SpecificCronJob> = vec![];
-    match crons {
-        Ok(crons_unwrapped) => {
-            for ele in crons_unwrapped {
-                match ele {
-                    CronJob::Specific(spec) => specific_cronjobs.push(spec),
-                    CronJob::Interval(inter) => interval_cronjobs.push(inter),
-                }
-            }
+/// Split the jobs of a crontab by their variant. Recorded jobs are listed with their actual
+/// command and their last run if `latest_runs` are given.
+fn crontab_jobs(
+    entries: Vec<CrontabEntry>,
+    mut latest_runs: Option<HashMap<String, RunSummary>>,
+) -> (Vec<SpecificCronjobRes>, Vec<IntervalCronjobRes>) {
+    let mut specific_cronjobs = Vec::new();
+    let mut interval_cronjobs = Vec::new();
+    for CrontabEntry {
+        id,
+        enabled,
+        mut job,
+        user,
+        ..
+    } in entries
+    {
+        let next_run = job.next_runs(1).first().copied();
+        let recorded = latest_runs.as_ref().and_then(|_| job.recorded());
+        let last_run = recorded
+            .as_ref()
+            .and_then(|r| latest_runs.as_mut()?.remove(&r.key));
+        if let Some(recorded) = &recorded {
+            job.set_command(recorded.command.clone());
+        }
+        let recorded = recorded.is_some();
+        match job {
+            CronJob::Specific(job) => specific_cronjobs.push(SpecificCronjobRes {
+                id,
+                enabled,
+                next_run,
+                user,
+                recorded,
+                last_run,
+                job,
+            }),
+            CronJob::Interval(job) => interval_cronjobs.push(IntervalCronjobRes {
+                id,
+                enabled,
+                next_run,
+                user,
+                recorded,
+                last_run,
+                job,
+            }),
         }
-        Err(e) => match e {
-            CronError::NoCronFile => {
-                return HttpResponse::NotFound().json(ErrorCode::NoCronjobs.as_error_message());
-            }
-            _ => {
-                return HttpResponse::InternalServerError()
-                    .json(ErrorCode::NoCronjobs.as_error_message());
-            }
-        },
     }
+    (specific_cronjobs, interval_cronjobs)
+}
+
+/// List scheduled jobs
+///
+/// Cronjobs of the user running Zentrox and systemd timers. Timers are missing if systemd is
+/// not used on the system. Recorded cronjobs are listed with their actual command instead of the
+/// call of `zentrox run-job` and the status of their last run.
+#[utoipa::path(get, path = "/private/cronjobs/list", responses((status = 200, body = ListCronjobsRes), (status = 500, description = "Cronjobs could not be read.")), tags = ["private", "cronjobs"])]
+pub async fn list(state: Data<AppState>) -> HttpResponse {
+    let Ok((timers, latest_runs, crons, crontabs)) = actix_web::web::block(move || {
+        (
+            timers::list_all(),
+            cron_history::latest_runs(&state),
+            list_cronjobs(&Crontab::own()),
+            list_crontabs(),
+        )
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    let timers = timers.unwrap_or_default();
+    let latest_runs = match latest_runs {
+        Ok(runs) => runs,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message());
+        }
+    };
+
+    let (entries, crontab_exists) = match crons {
+        Ok(entries) => (entries, true),
+        Err(CronError::NoCronFile) => (Vec::new(), false),
+        Err(_) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::NoCronjobs.as_error_message());
+        }
+    };
+    let (specific_jobs, interval_jobs) = crontab_jobs(entries, Some(latest_runs));
 
     HttpResponse::Ok().json(ListCronjobsRes {
-        specific_jobs: specific_cronjobs,
-        interval_jobs: interval_cronjobs,
-        crontab_exists: true,
+        specific_jobs,
+        interval_jobs,
+        crontab_exists,
+        timers,
+        crontabs,
     })
 }
 
+/// The crontab a request refers to
+#[derive(Deserialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CrontabReq {
+    /// `own`, `user:<name>`, `system` or `cron.d:<name>`, defaults to `own`
+    #[schema(value_type = Option<String>)]
+    crontab: Option<CrontabSource>,
+    /// Required for crontabs of other users and to change system crontabs, unless sudo is
+    /// allowed without a password
+    sudo_password: Option<String>,
+}
+
+impl CrontabReq {
+    fn crontab(&self) -> Crontab {
+        Crontab::new(
+            self.crontab.clone().unwrap_or(CrontabSource::Own),
+            SudoCredential::from(self.sudo_password.clone()),
+        )
+    }
+
+    fn is_own(&self) -> bool {
+        matches!(self.crontab, None | Some(CrontabSource::Own))
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct CrontabRes {
+    specific_jobs: Vec<SpecificCronjobRes>,
+    interval_jobs: Vec<IntervalCronjobRes>,
+    crontab_exists: bool,
+}
+
+/// Read crontab
+///
+/// Lists the cronjobs of any crontab, including the crontabs of other users, `/etc/crontab` and
+/// the files in `/etc/cron.d`. Jobs of the latter two are listed with the user they run as.
+#[utoipa::path(post, path = "/private/cronjobs/crontab", request_body = CrontabReq, responses((status = 200, body = CrontabRes), (status = 401, description = "The sudo password is wrong or required."), (status = 500, description = "The crontab could not be read.")), tags = ["private", "cronjobs"])]
+pub async fn crontab(state: Data<AppState>, json: Json<CrontabReq>) -> HttpResponse {
+    let own = json.is_own();
+    let crontab = json.crontab();
+    let Ok((crons, latest_runs)) = actix_web::web::block(move || {
+        let latest_runs = own.then(|| cron_history::latest_runs(&state));
+        (list_cronjobs(&crontab), latest_runs)
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    let latest_runs = match latest_runs.transpose() {
+        Ok(runs) => runs,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message());
+        }
+    };
+
+    let (entries, crontab_exists) = match crons {
+        Ok(entries) => (entries, true),
+        Err(CronError::NoCronFile) => (Vec::new(), false),
+        Err(error) => return cron_error(error),
+    };
+    let (specific_jobs, interval_jobs) = crontab_jobs(entries, latest_runs);
+    HttpResponse::Ok().json(CrontabRes {
+        specific_jobs,
+        interval_jobs,
+        crontab_exists,
+    })
+}
+
+/// Respond to a failed change of a cronjob.
+fn cron_error(error: CronError) -> HttpResponse {
+    match error {
+        CronError::NoSuchJob | CronError::NoCronFile => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownCronjob.as_error_message())
+        }
+        CronError::MissingUser => HttpResponse::BadRequest().json(
+            ErrorCode::InvalidCronjob(
+                "Cronjobs of this crontab require a valid user to run as.".to_string(),
+            )
+            .as_error_message(),
+        ),
+        CronError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        CronError::ReadingError => {
+            HttpResponse::InternalServerError().json(ErrorCode::NoCronjobs.as_error_message())
+        }
+        _ => HttpResponse::InternalServerError()
+            .json(ErrorCode::CronjobCreationFailed.as_error_message()),
+    }
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct CronjobCommandReq {
-    index: usize,
-    variant: CronjobVariant,
+    /// As listed by `/private/cronjobs/list`
+    id: String,
 }
 
 /// Run cronjob command
-#[utoipa::path(post, path = "/private/cronjobs/runCommand", request_body = CronjobCommandReq, responses((status = 200)), tags = ["private", "cronjobs", "responding_job"])]
+///
+/// The run is recorded if runs of the cronjob are recorded.
+#[utoipa::path(post, path = "/private/cronjobs/runCommand", request_body = CronjobCommandReq, responses((status = 200), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs", "responding_job"])]
 pub async fn run_command(state: Data<AppState>, json: Json<CronjobCommandReq>) -> HttpResponse {
     info!("Executing aribitrary cronjob command using runCommand.");
+
+    let id = json.id.clone();
+    let Ok(found) = actix_web::web::block(move || find_cronjob(&Crontab::own(), &id)).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    let (command_from_cronjob, recorded) = match found {
+        Ok(entry) => match entry.job.recorded() {
+            Some(recorded) => (recorded.command, Some(recorded.key)),
+            None => (entry.job.command().to_string(), None),
+        },
+        Err(error) => return cron_error(error),
+    };
+
     let uuid = Uuid::new_v4();
     state
         .background_jobs
@@ -88,72 +310,55 @@ pub async fn run_command(state: Data<AppState>, json: Json<CronjobCommandReq>) -
         .unwrap()
         .insert(uuid, BackgroundTaskState::Pending);
 
-    let cronjobs_list_request = cron::list_cronjobs();
-
-    let mut command_from_cronjob = None;
-
-    if let Ok(cronjobs) = cronjobs_list_request {
-        let relevant_cronjobs: Vec<&cron::CronJob> = match &json.variant {
-            CronjobVariant::Specific => cronjobs
-                .iter()
-                .filter(|e| matches!(e, cron::CronJob::Specific(_)))
-                .collect(),
-            CronjobVariant::Interval => cronjobs
-                .iter()
-                .filter(|e| matches!(e, cron::CronJob::Interval(_)))
-                .collect(),
-        };
-
-        if let Some(cronjob_at_index) = relevant_cronjobs.get(json.index) {
-            command_from_cronjob = Some(match cronjob_at_index {
-                cron::CronJob::Specific(c) => c.command.clone(),
-                cron::CronJob::Interval(c) => c.command.clone(),
-            });
-        }
-    } else {
-        HttpResponse::InternalServerError().json(ErrorCode::NoCronjobs.as_error_message());
-    }
-
-    if command_from_cronjob.is_none() {
-        return HttpResponse::NotFound().json(ErrorCode::NoSuchVariant.as_error_message());
-    }
-
     drop(actix_web::web::block(move || {
         let status;
         debug!("Cronjob command: {:?}", command_from_cronjob);
-        match Command::new("sh")
-            .arg("-c")
-            .arg(command_from_cronjob.unwrap())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .stdin(Stdio::null())
-            .spawn()
-        {
-            Ok(h) => {
-                let waited = h.wait_with_output();
-                if let Ok(s) = waited {
-                    if s.status.success() {
-                        info!("Cronjob command exited sucessfully.");
-                        status = BackgroundTaskState::SuccessOutput(
-                            String::from_utf8(s.stdout).unwrap(),
-                        );
+        if let Some(key) = recorded {
+            let (run, stdout, stderr) =
+                cron_history::run(&state, &key, &command_from_cronjob, "manual");
+            status = if run.status == "success" {
+                info!("Cronjob command exited sucessfully.");
+                BackgroundTaskState::SuccessOutput(String::from_utf8_lossy(&stdout).into_owned())
+            } else {
+                warn!("Cronjob command failed with output.");
+                BackgroundTaskState::FailOutput(String::from_utf8_lossy(&stderr).into_owned())
+            };
+        } else {
+            match Command::new("sh")
+                .arg("-c")
+                .arg(command_from_cronjob)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .stdin(Stdio::null())
+                .spawn()
+            {
+                Ok(h) => {
+                    let waited = h.wait_with_output();
+                    if let Ok(s) = waited {
+                        if s.status.success() {
+                            info!("Cronjob command exited sucessfully.");
+                            status = BackgroundTaskState::SuccessOutput(
+                                String::from_utf8(s.stdout).unwrap(),
+                            );
+                        } else {
+                            warn!("Cronjob command failed with output.");
+                            status = BackgroundTaskState::FailOutput(
+                                String::from_utf8(s.stderr).unwrap(),
+                            );
+                        }
                     } else {
-                        warn!("Cronjob command failed with output.");
-                        status =
-                            BackgroundTaskState::FailOutput(String::from_utf8(s.stderr).unwrap());
+                        warn!("Cronjob command execution failed.");
+                        status = BackgroundTaskState::Fail;
                     }
-                } else {
-                    warn!("Cronjob command execution failed.");
-                    status = BackgroundTaskState::Fail;
                 }
-            }
-            Err(_) => {
-                status = {
-                    warn!("Cronjob command initiation failed.");
-                    BackgroundTaskState::Fail
+                Err(_) => {
+                    status = {
+                        warn!("Cronjob command initiation failed.");
+                        BackgroundTaskState::Fail
+                    }
                 }
-            }
-        };
+            };
+        }
 
         debug!("Cronjob command execution was given task id {uuid}");
         state.background_jobs.lock().unwrap().insert(uuid, status);
@@ -163,24 +368,46 @@ pub async fn run_command(state: Data<AppState>, json: Json<CronjobCommandReq>) -
 }
 
 /// Delete cronjob
-#[utoipa::path(post, path = "/private/cronjobs/delete/{index}/{variant}", params(("index" = u32, Path), ("variant" = CronjobVariant, Path)),responses((status = 200)), tags = ["private", "cronjobs"])]
-pub async fn delete(path: Path<(u32, CronjobVariant)>) -> HttpResponse {
-    let index = path.0;
-    let variant = &path.1;
-
-    let _ = match variant {
-        CronjobVariant::Specific => delete_specific_cronjob(index),
-        CronjobVariant::Interval => delete_interval_cronjob(index),
+///
+/// The history of the cronjob is removed as well. The body is optional and defaults to the
+/// crontab of the user running Zentrox.
+#[utoipa::path(post, path = "/private/cronjobs/delete/{id}", params(("id" = String, Path)), request_body(content = CrontabReq, description = "Optional"), responses((status = 200), (status = 401, description = "The sudo password is wrong or required."), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs"])]
+pub async fn delete(
+    state: Data<AppState>,
+    path: Path<String>,
+    json: Option<Json<CrontabReq>>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    let target = json.map(Json::into_inner).unwrap_or_default();
+    let own = target.is_own();
+    let crontab = target.crontab();
+    let Ok(result) = actix_web::web::block(move || {
+        let entry = find_cronjob(&crontab, &id)?;
+        delete_cronjob(&crontab, &id)?;
+        Ok(entry.job.recorded().filter(|_| own))
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    let recorded = match result {
+        Ok(recorded) => recorded,
+        Err(error) => return cron_error(error),
     };
 
+    if let Some(recorded) = recorded
+        && let Err(e) = cron_history::delete(&state, &recorded.key)
+    {
+        warn!("Deleting the history of a cronjob failed with error: {e}");
+    }
     HttpResponse::Ok().json(MessageRes::from("The cronjob has been deleted."))
 }
 
+/// When a cronjob runs
 #[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub struct CreateCronjobReq {
+pub struct CronScheduleReq {
     variant: CronjobVariant,
-    command: String,
     interval: Option<Interval>,
     minute: Option<String>,
     hour: Option<String>,
@@ -189,70 +416,514 @@ pub struct CreateCronjobReq {
     month: Option<String>,
 }
 
+/// A field of a specific schedule, which is required.
+fn schedule_field<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str, String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .ok_or_else(|| format!("The {name} is missing."))
+}
+
+impl CronScheduleReq {
+    /// Validate the schedule and build a job running `command`.
+    fn job(&self, command: String) -> Result<CronJob, String> {
+        match self.variant {
+            CronjobVariant::Specific => SpecificCronJob::new(
+                schedule_field(&self.minute, "minute")?,
+                schedule_field(&self.hour, "hour")?,
+                schedule_field(&self.day_of_month, "day of the month")?,
+                schedule_field(&self.month, "month")?,
+                schedule_field(&self.day_of_week, "day of the week")?,
+                command,
+            )
+            .map(CronJob::Specific),
+            CronjobVariant::Interval => match self.interval {
+                Some(interval) => Ok(CronJob::Interval(IntervalCronJob { interval, command })),
+                None => Err("The interval is missing.".to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCronjobReq {
+    command: String,
+    /// Whether runs are recorded in the history of the cronjob. Defaults to false when creating
+    /// a cronjob and is kept when editing one. Only runs of the crontab of the user running
+    /// Zentrox can be recorded.
+    record: Option<bool>,
+    /// The user the cronjob runs as, required for `/etc/crontab` and `/etc/cron.d` and kept when
+    /// editing a cronjob if it is missing
+    user: Option<String>,
+    #[serde(flatten)]
+    schedule: CronScheduleReq,
+    #[serde(flatten)]
+    target: CrontabReq,
+}
+
+impl CreateCronjobReq {
+    /// Validate the request and build the job. The runs of the job are recorded using the
+    /// history `key`.
+    fn job(&self, key: Option<&str>) -> Result<CronJob, ErrorCode> {
+        let invalid = |message: &str| ErrorCode::InvalidCronjob(message.to_string());
+        let command = self.command.trim();
+        if command.is_empty() {
+            return Err(invalid("The command is empty."));
+        }
+        // Every line of the crontab is a separate job
+        if command.contains(['\n', '\r']) {
+            return Err(invalid("The command may not span multiple lines."));
+        }
+        if self.user.as_deref().is_some_and(|u| !is_user_name(u)) {
+            return Err(invalid("The user name is invalid."));
+        }
+        let mut job = self
+            .schedule
+            .job(command.to_string())
+            .map_err(|message| invalid(&message))?;
+        if let Some(key) = key {
+            if !self.target.is_own() {
+                return Err(invalid(
+                    "Only runs of cronjobs of the user running Zentrox can be recorded.",
+                ));
+            }
+            record_job(&mut job, key)?;
+        }
+        Ok(job)
+    }
+}
+
+/// Wrap the command of a job in `zentrox run-job`, so its runs are recorded using the history
+/// `key`.
+fn record_job(job: &mut CronJob, key: &str) -> Result<(), ErrorCode> {
+    if !can_record(job.command()) {
+        return Err(ErrorCode::InvalidCronjob(
+            "Runs of commands with unescaped % signs can not be recorded.".to_string(),
+        ));
+    }
+    match cron_history::wrap(key, job.command()) {
+        Ok(command) => {
+            job.set_command(command);
+            Ok(())
+        }
+        Err(e) => {
+            error!("The path of the Zentrox executable could not be determined: {e}");
+            Err(ErrorCode::CronjobCreationFailed)
+        }
+    }
+}
+
+/// Respond to a cronjob that could not be built.
+fn invalid_cronjob(error: ErrorCode) -> HttpResponse {
+    match error {
+        ErrorCode::InvalidCronjob(_) => HttpResponse::BadRequest().json(error.as_error_message()),
+        _ => HttpResponse::InternalServerError().json(error.as_error_message()),
+    }
+}
+
 /// Create new cronjob
-#[utoipa::path(post, path = "/private/cronjobs/new", request_body = CreateCronjobReq, responses((status = 200)), tags = ["private", "cronjobs"])]
+#[utoipa::path(post, path = "/private/cronjobs/new", request_body = CreateCronjobReq, responses((status = 200, body = EditCronjobRes), (status = 400, description = "The schedule or command is invalid."), (status = 401, description = "The sudo password is wrong or required.")), tags = ["private", "cronjobs"])]
 pub async fn create(json: Json<CreateCronjobReq>) -> HttpResponse {
-    let variant = &json.variant;
-
-    match variant {
-        CronjobVariant::Specific => {
-            let day_of_month =
-                match cron::Digit::try_from(json.day_of_month.clone().unwrap().as_str()) {
-                    Ok(c) => c,
-                    Err(_) => {
-                        return HttpResponse::BadRequest()
-                            .json(ErrorCode::SanitizationError.as_error_message());
-                    }
-                };
+    let json = json.into_inner();
+    let key = json.record.unwrap_or(false).then(cron_history::new_key);
+    let job = match json.job(key.as_deref()) {
+        Ok(job) => job,
+        Err(error) => return invalid_cronjob(error),
+    };
 
-            let day_of_week = DayOfWeek::from(json.day_of_week.clone().unwrap().as_str());
-            let month = Month::from(json.month.clone().unwrap().as_str());
-            let minute = match Digit::try_from(json.minute.clone().unwrap().as_str()) {
-                Ok(c) => c,
-                Err(_) => {
-                    return HttpResponse::BadRequest()
-                        .json(ErrorCode::SanitizationError.as_error_message());
-                }
-            };
+    let crontab = json.target.crontab();
+    let Ok(result) =
+        actix_web::web::block(move || create_cronjob(&crontab, job, json.user.as_deref())).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(id) => HttpResponse::Ok().json(EditCronjobRes { id }),
+        Err(error) => {
+            error!("Failed to create cronjob");
+            cron_error(error)
+        }
+    }
+}
 
-            let hour = match Digit::try_from(json.hour.clone().unwrap().as_str()) {
-                Ok(c) => c,
-                Err(_) => {
-                    return HttpResponse::BadRequest()
-                        .json(ErrorCode::SanitizationError.as_error_message());
-                }
-            };
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EditCronjobReq {
+    /// As listed by `/private/cronjobs/list`
+    id: String,
+    #[serde(flatten)]
+    job: CreateCronjobReq,
+}
 
-            match create_new_specific_cronjob(SpecificCronJob {
-                command: json.command.clone(),
-                day_of_week,
-                day_of_month,
-                minute,
-                hour,
-                month,
-            }) {
-                Ok(_) => HttpResponse::Ok()
-                    .json(MessageRes::from("A new specific cronjob has been created.")),
-                Err(_) => {
-                    error!("Failed to create specific cronjob");
-                    HttpResponse::InternalServerError()
-                        .json(ErrorCode::CronjobCreationFailed.as_error_message())
-                }
-            }
+#[derive(Serialize, ToSchema)]
+struct EditCronjobRes {
+    /// The new ID of the cronjob
+    id: String,
+}
+
+/// Edit cronjob
+///
+/// Replaces the schedule and command of a cronjob in place, so the order of the crontab is kept.
+/// A disabled cronjob stays disabled. The ID of the cronjob changes, its history is kept.
+#[utoipa::path(post, path = "/private/cronjobs/edit", request_body = EditCronjobReq, responses((status = 200, body = EditCronjobRes), (status = 400, description = "The schedule or command is invalid."), (status = 401, description = "The sudo password is wrong or required."), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs"])]
+pub async fn edit(json: Json<EditCronjobReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let crontab = json.job.target.crontab();
+    let Ok(result) = actix_web::web::block(move || {
+        let previous_key = find_cronjob(&crontab, &json.id)?
+            .job
+            .recorded()
+            .filter(|_| json.job.target.is_own())
+            .map(|r| r.key);
+        let key = match json.job.record {
+            Some(false) => None,
+            Some(true) => Some(previous_key.unwrap_or_else(cron_history::new_key)),
+            None => previous_key,
+        };
+        let job = match json.job.job(key.as_deref()) {
+            Ok(job) => job,
+            Err(error) => return Ok(Err(error)),
+        };
+        edit_cronjob(&crontab, &json.id, job, json.job.user.as_deref()).map(Ok)
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(Ok(id)) => HttpResponse::Ok().json(EditCronjobRes { id }),
+        Ok(Err(error)) => invalid_cronjob(error),
+        Err(error) => cron_error(error),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CronjobEnabledReq {
+    /// As listed by `/private/cronjobs/list`
+    id: String,
+    enabled: bool,
+    #[serde(flatten)]
+    target: CrontabReq,
+}
+
+/// Enable or disable cronjob
+///
+/// Disabled cronjobs are commented out and keep their ID.
+#[utoipa::path(post, path = "/private/cronjobs/enabled", request_body = CronjobEnabledReq, responses((status = 200), (status = 401, description = "The sudo password is wrong or required."), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs"])]
+pub async fn set_enabled(json: Json<CronjobEnabledReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let enabled = json.enabled;
+    let crontab = json.target.crontab();
+    let Ok(result) =
+        actix_web::web::block(move || set_cronjob_enabled(&crontab, &json.id, enabled)).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from(if enabled {
+            "The cronjob has been enabled."
+        } else {
+            "The cronjob has been disabled."
+        })),
+        Err(error) => cron_error(error),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewCronjobReq {
+    #[serde(flatten)]
+    schedule: CronScheduleReq,
+    /// Number of runs, defaults to 5 and may not exceed 100
+    count: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PreviewCronjobRes {
+    /// Seconds since the UNIX epoch, empty for `@reboot` and schedules that never match
+    runs: Vec<i64>,
+}
+
+/// Preview cronjob schedule
+///
+/// Validates a schedule and lists the times of its next runs in the local time of the system.
+#[utoipa::path(post, path = "/private/cronjobs/preview", request_body = PreviewCronjobReq, responses((status = 200, body = PreviewCronjobRes), (status = 400, description = "The schedule is invalid.")), tags = ["private", "cronjobs"])]
+pub async fn preview(json: Json<PreviewCronjobReq>) -> HttpResponse {
+    let count = json.count.unwrap_or(5).clamp(1, MAX_PREVIEW);
+    match json.schedule.job(String::new()) {
+        Ok(job) => HttpResponse::Ok().json(PreviewCronjobRes {
+            runs: job.next_runs(count),
+        }),
+        Err(message) => {
+            HttpResponse::BadRequest().json(ErrorCode::InvalidCronjob(message).as_error_message())
         }
-        CronjobVariant::Interval => {
-            match create_new_interval_cronjob(cron::IntervalCronJob {
-                interval: json.interval.unwrap(),
-                command: json.command.clone(),
-            }) {
-                Ok(_) => HttpResponse::Ok()
-                    .json(MessageRes::from("A new interval cronjob has been created.")),
-                Err(_) => {
-                    error!("Failed to create interval cronjob");
-                    HttpResponse::InternalServerError()
-                        .json(ErrorCode::CronjobCreationFailed.as_error_message())
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CronjobRecordingReq {
+    /// As listed by `/private/cronjobs/list`
+    id: String,
+    enabled: bool,
+}
+
+/// Record cronjob runs
+///
+/// Wraps the command of the cronjob in `zentrox run-job`, which records every run including
+/// the exit code and the end of the output, or removes the wrapper and the history again.
+/// The ID of the cronjob changes.
+#[utoipa::path(post, path = "/private/cronjobs/recording", request_body = CronjobRecordingReq, responses((status = 200, body = EditCronjobRes), (status = 400, description = "The command can not be recorded."), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs"])]
+pub async fn set_recording(state: Data<AppState>, json: Json<CronjobRecordingReq>) -> HttpResponse {
+    let Ok(result) = actix_web::web::block(move || {
+        let crontab = Crontab::own();
+        let mut job = find_cronjob(&crontab, &json.id)?.job;
+
+        let removed_key = match (job.recorded(), json.enabled) {
+            (Some(_), true) | (None, false) => return Ok(Ok(json.id.clone())),
+            (None, true) => {
+                if let Err(error) = record_job(&mut job, &cron_history::new_key()) {
+                    return Ok(Err(error));
                 }
+                None
             }
+            (Some(recorded), false) => {
+                job.set_command(recorded.command);
+                Some(recorded.key)
+            }
+        };
+
+        let id = edit_cronjob(&crontab, &json.id, job, None)?;
+        if let Some(key) = removed_key
+            && let Err(e) = cron_history::delete(&state, &key)
+        {
+            warn!("Deleting the history of a cronjob failed with error: {e}");
         }
+        Ok(Ok(id))
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(Ok(id)) => HttpResponse::Ok().json(EditCronjobRes { id }),
+        Ok(Err(error)) => invalid_cronjob(error),
+        Err(error) => cron_error(error),
     }
 }
+
+#[derive(Serialize, ToSchema)]
+struct CronjobRunsRes {
+    /// Whether runs of the cronjob are recorded
+    recorded: bool,
+    /// Starting with the latest run
+    runs: Vec<CronjobRun>,
+}
+
+/// Cronjob run history
+///
+/// The latest runs of a cronjob including their output, which is cut to the last 16 KiB.
+#[utoipa::path(get, path = "/private/cronjobs/runs/{id}", params(("id" = String, Path)), responses((status = 200, body = CronjobRunsRes), (status = 404, description = "The cronjob does not exist.")), tags = ["private", "cronjobs"])]
+pub async fn runs(state: Data<AppState>, path: Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let Ok(result) = actix_web::web::block(move || {
+        let Some(recorded) = find_cronjob(&Crontab::own(), &id)?.job.recorded() else {
+            return Ok(None);
+        };
+        Ok(Some(cron_history::runs(&state, &recorded.key)))
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(None) => HttpResponse::Ok().json(CronjobRunsRes {
+            recorded: false,
+            runs: Vec::new(),
+        }),
+        Ok(Some(Ok(runs))) => HttpResponse::Ok().json(CronjobRunsRes {
+            recorded: true,
+            runs,
+        }),
+        Ok(Some(Err(e))) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message()),
+        Err(error) => cron_error(error),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TimerReq {
+    #[serde(flatten)]
+    timer: TimerDefinition,
+    /// Required for system timers
+    sudo_password: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TimerEnabledReq {
+    /// Name of the timer without `.timer`
+    name: String,
+    scope: UnitScope,
+    enabled: bool,
+    /// Required for system timers
+    sudo_password: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteTimerReq {
+    /// Name of the timer without `.timer`
+    name: String,
+    scope: UnitScope,
+    /// Required for system timers
+    sudo_password: Option<String>,
+}
+
+/// Change a systemd timer off the async executor and respond to the result.
+async fn change_timers<F>(change: F, message: &'static str) -> HttpResponse
+where
+    F: FnOnce() -> Result<(), TimerError> + Send + 'static,
+{
+    let Ok(result) = actix_web::web::block(change).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from(message)),
+        Err(TimerError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired)) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(TimerError::UnknownTimer) => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownTimer.as_error_message())
+        }
+        Err(TimerError::Unmanaged) => {
+            HttpResponse::BadRequest().json(ErrorCode::TimerNotManaged.as_error_message())
+        }
+        Err(TimerError::Exists) => HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidTimer(TimerError::Exists.to_string()).as_error_message()),
+        Err(TimerError::InvalidTimer(message)) => {
+            HttpResponse::BadRequest().json(ErrorCode::InvalidTimer(message).as_error_message())
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::TimerChangeFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Create systemd timer
+///
+/// Creates a timer and a service unit that runs the command using `/bin/sh -c`, then enables and
+/// starts the timer. System timers are run as root and require the sudo password.
+#[utoipa::path(
+    post,
+    path = "/private/cronjobs/timers/new",
+    request_body = TimerReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The timer is malformed or a unit with its name exists already."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "cronjobs"]
+)]
+pub async fn create_timer(json: Json<TimerReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_timers(
+        move || timers::create(&json.timer, SudoCredential::from(json.sudo_password)),
+        "The timer has been created.",
+    )
+    .await
+}
+
+/// Edit systemd timer
+///
+/// Replaces the units of a timer that was created using Zentrox. Timers shipped by the
+/// distribution or created otherwise can only be enabled and disabled.
+#[utoipa::path(
+    post,
+    path = "/private/cronjobs/timers/edit",
+    request_body = TimerReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The timer is malformed or was not created using Zentrox."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The timer does not exist.")
+    ),
+    tags = ["private", "cronjobs"]
+)]
+pub async fn edit_timer(json: Json<TimerReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_timers(
+        move || timers::edit(&json.timer, SudoCredential::from(json.sudo_password)),
+        "The timer has been changed.",
+    )
+    .await
+}
+
+/// Enable or disable systemd timer
+///
+/// Enabled timers are started immediately and on every boot.
+#[utoipa::path(
+    post,
+    path = "/private/cronjobs/timers/enabled",
+    request_body = TimerEnabledReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The timer does not exist.")
+    ),
+    tags = ["private", "cronjobs"]
+)]
+pub async fn set_timer_enabled(json: Json<TimerEnabledReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let message = if json.enabled {
+        "The timer has been enabled."
+    } else {
+        "The timer has been disabled."
+    };
+    change_timers(
+        move || {
+            timers::set_enabled(
+                json.scope,
+                &json.name,
+                json.enabled,
+                SudoCredential::from(json.sudo_password),
+            )
+        },
+        message,
+    )
+    .await
+}
+
+/// Delete systemd timer
+///
+/// Stops a timer that was created using Zentrox and removes it along with its service.
+#[utoipa::path(
+    post,
+    path = "/private/cronjobs/timers/delete",
+    request_body = DeleteTimerReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The timer was not created using Zentrox."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The timer does not exist.")
+    ),
+    tags = ["private", "cronjobs"]
+)]
+pub async fn delete_timer(json: Json<DeleteTimerReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_timers(
+        move || {
+            timers::delete(
+                json.scope,
+                &json.name,
+                SudoCredential::from(json.sudo_password),
+            )
+        },
+        "The timer has been deleted.",
+    )
+    .await
+}
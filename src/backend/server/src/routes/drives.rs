@@ -1,9 +1,22 @@
 use actix_web::HttpResponse;
+use actix_web::web::{Data, Json, Query};
 use log::error;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use utils::drives::analyzer::{DiskUsage, UsageView};
+use utils::drives::fstab::{Fstab, FstabEntry};
+use utils::drives::mounts::{self, Mount, MountError, MountRequest};
+use utils::drives::partitions::{self, DiskLayout, Filesystem, NewPartition, PartitionError};
+use utils::drives::smart::{self, SelfTestKind, SmartError, SmartReport};
+use utils::drives::usage::FilesystemUsage;
 use utils::drives::{self};
-use utils::status_com::ErrorCode;
+use utils::status_com::{ErrorCode, MessageRes};
+use utils::sudo::{self, SudoCredential, SudoError};
 use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{AppState, BackgroundTaskState};
 
 #[derive(Serialize, ToSchema)]
 struct DriveListRes {
@@ -31,3 +44,826 @@ pub async fn list() -> HttpResponse {
 
     HttpResponse::Ok().json(DriveListRes { drives })
 }
+
+fn smart_error(error: SmartError) -> HttpResponse {
+    match error {
+        SmartError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        SmartError::InvalidDevice(_) => {
+            HttpResponse::NotFound().json(ErrorCode::NoSuchDisk.as_error_message())
+        }
+        SmartError::Unavailable(message) => HttpResponse::InternalServerError()
+            .json(ErrorCode::SmartUnavailable(message).as_error_message()),
+        e => HttpResponse::InternalServerError()
+            .json(ErrorCode::SmartFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartReq {
+    /// Path of a disk, like `/dev/sda`
+    device: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/smart",
+    request_body = SmartReq,
+    responses(
+        (status = 200, body = SmartReport),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The device is not a disk.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// SMART health of a disk
+///
+/// Reports the health, temperature, power-on hours, reallocated and pending sectors, the wear
+/// of NVMe drives and the progress and log of self-tests using `smartctl`.
+pub async fn smart(json: Json<SmartReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    let Ok(result) = actix_web::web::block(move || smart::report(&json.device, &credential)).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => smart_error(e),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReq {
+    /// Path of a disk, like `/dev/sda`
+    device: String,
+    kind: SelfTestKind,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/smart/test",
+    request_body = SelfTestReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The device is not a disk.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Start SMART self-test
+///
+/// The drive runs the test in the background. Its progress and result are part of the SMART
+/// health of the disk.
+pub async fn self_test(json: Json<SelfTestReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    let Ok(result) =
+        actix_web::web::block(move || smart::start_self_test(&json.device, json.kind, &credential))
+            .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(()) => HttpResponse::Ok().json(MessageRes::from("The self-test has been started.")),
+        Err(e) => smart_error(e),
+    }
+}
+
+fn mount_error(error: MountError) -> HttpResponse {
+    match error {
+        MountError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        MountError::Invalid(message) => {
+            HttpResponse::BadRequest().json(ErrorCode::InvalidMount(message).as_error_message())
+        }
+        MountError::NotMounted => {
+            HttpResponse::NotFound().json(ErrorCode::NotMounted.as_error_message())
+        }
+        MountError::NoSuchEntry => {
+            HttpResponse::NotFound().json(ErrorCode::NoSuchFstabEntry.as_error_message())
+        }
+        MountError::CheckFailed(message) => {
+            HttpResponse::BadRequest().json(ErrorCode::FstabCheckFailed(message).as_error_message())
+        }
+        e => HttpResponse::InternalServerError()
+            .json(ErrorCode::MountFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Change mounts off the async executor and respond to the result.
+async fn change_mounts<F>(change: F, message: &'static str) -> HttpResponse
+where
+    F: FnOnce() -> Result<(), MountError> + Send + 'static,
+{
+    let Ok(result) = actix_web::web::block(change).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(()) => HttpResponse::Ok().json(MessageRes::from(message)),
+        Err(e) => mount_error(e),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct MountsRes {
+    mounts: Vec<Mount>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/drives/mounts",
+    responses((status = 200, body = MountsRes)),
+    tags = ["private", "drives"]
+)]
+/// List of mounted filesystems
+pub async fn mounts() -> HttpResponse {
+    match mounts::list_mounts() {
+        Ok(mounts) => HttpResponse::Ok().json(MountsRes { mounts }),
+        Err(e) => mount_error(e),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MountReq {
+    #[serde(flatten)]
+    mount: MountRequest,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/mount",
+    request_body = MountReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The mount is malformed, targets a system directory or would make the fstab invalid."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Mount filesystem
+///
+/// Mounts a device, a filesystem by UUID or label or a CIFS or NFS share. The credentials of
+/// CIFS shares are stored in a file that is only readable by root.
+pub async fn mount(json: Json<MountReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_mounts(
+        move || mounts::mount(json.mount, &SudoCredential::from(json.sudo_password)),
+        "The filesystem has been mounted.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmountReq {
+    /// Mountpoint or device
+    target: String,
+    /// Detach the filesystem now and clean up once it is no longer busy
+    #[serde(default)]
+    lazy: bool,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/unmount",
+    request_body = UnmountReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The filesystem is part of the operating system."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "Nothing is mounted there.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Unmount filesystem
+pub async fn unmount(json: Json<UnmountReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_mounts(
+        move || {
+            mounts::unmount(
+                &json.target,
+                json.lazy,
+                &SudoCredential::from(json.sudo_password),
+            )
+        },
+        "The filesystem has been unmounted.",
+    )
+    .await
+}
+
+#[derive(Serialize, ToSchema)]
+struct FstabRes {
+    /// In the order of `/etc/fstab`, entries are addressed by their position in this list
+    entries: Vec<FstabEntry>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/drives/fstab",
+    responses((status = 200, body = FstabRes)),
+    tags = ["private", "drives"]
+)]
+/// Entries of /etc/fstab
+pub async fn fstab() -> HttpResponse {
+    match Fstab::read() {
+        Ok(fstab) => HttpResponse::Ok().json(FstabRes {
+            entries: fstab.entries(),
+        }),
+        Err(e) => mount_error(e),
+    }
+}
+
+/// Change `/etc/fstab` and write it if `mount --all --fake` accepts it.
+async fn change_fstab<F>(
+    change: F,
+    credential: SudoCredential,
+    message: &'static str,
+) -> HttpResponse
+where
+    F: FnOnce(&mut Fstab) -> Result<(), MountError> + Send + 'static,
+{
+    change_mounts(
+        move || {
+            let mut fstab = Fstab::read()?;
+            change(&mut fstab)?;
+            fstab.write(&credential)
+        },
+        message,
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FstabEntryReq {
+    entry: FstabEntry,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/fstab/new",
+    request_body = FstabEntryReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The entry is malformed or mount rejected the fstab."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Add fstab entry
+///
+/// Missing mountpoints are created before the fstab is checked.
+pub async fn new_fstab_entry(json: Json<FstabEntryReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    change_fstab(
+        {
+            let credential = credential.clone();
+            move |fstab| {
+                fstab.add(json.entry.clone())?;
+                if json.entry.filesystem != "swap" {
+                    mounts::create_mountpoint(&json.entry.mountpoint, &credential)?;
+                }
+                Ok(())
+            }
+        },
+        credential,
+        "The entry has been added.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EditFstabEntryReq {
+    /// Position of the entry among the entries
+    index: usize,
+    entry: FstabEntry,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/fstab/edit",
+    request_body = EditFstabEntryReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The entry is malformed or mount rejected the fstab."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The entry does not exist.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Edit fstab entry
+///
+/// Missing mountpoints are created before the fstab is checked.
+pub async fn edit_fstab_entry(json: Json<EditFstabEntryReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    change_fstab(
+        {
+            let credential = credential.clone();
+            move |fstab| {
+                fstab.replace(json.index, json.entry.clone())?;
+                if json.entry.filesystem != "swap" {
+                    mounts::create_mountpoint(&json.entry.mountpoint, &credential)?;
+                }
+                Ok(())
+            }
+        },
+        credential,
+        "The entry has been changed.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteFstabEntryReq {
+    /// Position of the entry among the entries
+    index: usize,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/fstab/delete",
+    request_body = DeleteFstabEntryReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "mount rejected the fstab."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The entry does not exist.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Delete fstab entry
+///
+/// The filesystem stays mounted until it is unmounted or the system is restarted.
+pub async fn delete_fstab_entry(json: Json<DeleteFstabEntryReq>) -> HttpResponse {
+    let json = json.into_inner();
+    change_fstab(
+        move |fstab| fstab.remove(json.index).map(|_| ()),
+        SudoCredential::from(json.sudo_password),
+        "The entry has been deleted.",
+    )
+    .await
+}
+
+#[derive(Serialize, ToSchema)]
+struct FilesystemUsageRes {
+    filesystems: Vec<FilesystemUsage>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/drives/usage",
+    responses((status = 200, body = FilesystemUsageRes)),
+    tags = ["private", "drives"]
+)]
+/// Usage of mounted filesystems
+///
+/// Reports the size, used, available and free space and the inode usage of every mounted
+/// filesystem, like `df`. Pseudo filesystems like `proc` are left out.
+pub async fn usage() -> HttpResponse {
+    let Ok(result) = actix_web::web::block(drives::usage::filesystems).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(filesystems) => HttpResponse::Ok().json(FilesystemUsageRes { filesystems }),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::FilesystemUsageFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UsageScanReq {
+    /// The directory to scan
+    #[schema(value_type = String)]
+    path: PathBuf,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/usage/scan",
+    request_body = UsageScanReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 404, description = "The directory does not exist."),
+        (status = 422, description = "The path is not a directory.")
+    ),
+    tags = ["private", "drives", "responding_job"]
+)]
+/// Scan disk usage of directory
+///
+/// Scans the directory tree like `ncdu` in a background job, staying on the filesystem of the
+/// directory. The result can be browsed using `/private/drives/usage/analysis` once the job
+/// finished. If the directory is already being scanned, the ID of that job is returned.
+pub async fn usage_scan(state: Data<AppState>, json: Json<UsageScanReq>) -> HttpResponse {
+    let Ok(root) = fs::canonicalize(&json.path) else {
+        return HttpResponse::NotFound().json(ErrorCode::DirectoryDoesNotExist.as_error_message());
+    };
+    if !root.is_dir() {
+        return HttpResponse::UnprocessableEntity()
+            .json(ErrorCode::DirectoryError.as_error_message());
+    }
+
+    let job_id = {
+        let mut scans = state.disk_usage.lock().unwrap();
+        if let Some(running) = scans.running(&root) {
+            return HttpResponse::Ok().body(running.to_string());
+        }
+        let job_id = Uuid::new_v4();
+        scans.start(root.clone(), job_id);
+        job_id
+    };
+
+    state
+        .background_jobs
+        .lock()
+        .unwrap()
+        .insert(job_id, BackgroundTaskState::Pending);
+
+    let block = actix_web::web::block(move || {
+        let (status, usage) = match DiskUsage::scan(&root) {
+            Ok(usage) => (BackgroundTaskState::Success, Some(usage)),
+            Err(e) => {
+                error!("Scanning the disk usage of {} failed: {e}", root.display());
+                (BackgroundTaskState::FailOutput(e.to_string()), None)
+            }
+        };
+        state.disk_usage.lock().unwrap().finish(&root, usage);
+        state.background_jobs.lock().unwrap().insert(job_id, status);
+    });
+
+    drop(block);
+
+    HttpResponse::Ok().body(job_id.to_string())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UsageAnalysisQuery {
+    /// A directory of a finished scan
+    #[schema(value_type = String)]
+    path: PathBuf,
+    /// Maximum number of directories and files of each list, defaults to 25
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct UsageAnalysisRes {
+    /// The scanned directory
+    root: String,
+    /// Seconds since the UNIX epoch
+    scanned_at: u64,
+    /// Number of directories and files that could not be read
+    errors: u64,
+    #[serde(flatten)]
+    view: UsageView,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/drives/usage/analysis",
+    params(
+        ("path" = String, Query),
+        ("limit" = Option<usize>, Query)
+    ),
+    responses(
+        (status = 200, body = UsageAnalysisRes),
+        (status = 404, description = "No scan contains the directory or it is not part of the scan.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Disk usage of scanned directory
+///
+/// Lists the largest directories and files in a directory of a finished scan and the largest
+/// files anywhere below it. Any directory inside of a scanned directory can be requested, the
+/// scan of the deepest directory containing it is used.
+pub async fn usage_analysis(
+    state: Data<AppState>,
+    query: Query<UsageAnalysisQuery>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    // The directory may have been deleted since it was scanned
+    let path = fs::canonicalize(&query.path).unwrap_or(query.path);
+
+    let Some(scan) = state.disk_usage.lock().unwrap().find(&path) else {
+        return HttpResponse::NotFound().json(ErrorCode::NotScanned.as_error_message());
+    };
+
+    match scan.view(&path, query.limit.unwrap_or(25)) {
+        Some(view) => HttpResponse::Ok().json(UsageAnalysisRes {
+            root: scan.root().to_string_lossy().to_string(),
+            scanned_at: scan.scanned_at(),
+            errors: scan.errors(),
+            view,
+        }),
+        None => HttpResponse::NotFound().json(ErrorCode::DirectoryDoesNotExist.as_error_message()),
+    }
+}
+
+fn partition_error(error: PartitionError) -> HttpResponse {
+    match error {
+        PartitionError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        PartitionError::NoSuchDevice(_) => {
+            HttpResponse::NotFound().json(ErrorCode::NoSuchDisk.as_error_message())
+        }
+        PartitionError::Unconfirmed => {
+            HttpResponse::BadRequest().json(ErrorCode::ConfirmationMismatch.as_error_message())
+        }
+        PartitionError::Invalid(message) => HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidPartitioning(message).as_error_message()),
+        PartitionError::Refused(message) => HttpResponse::Conflict()
+            .json(ErrorCode::PartitioningRefused(message).as_error_message()),
+        e => HttpResponse::InternalServerError()
+            .json(ErrorCode::PartitioningFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Verify the sudo password and check the device, then run `operation` as a background job.
+///
+/// The check is repeated by the operation itself, but answers requests for disks that can not be
+/// changed right away instead of failing the job.
+async fn start_partition_job<C, F>(
+    state: Data<AppState>,
+    sudo_password: String,
+    check: C,
+    operation: F,
+) -> HttpResponse
+where
+    C: FnOnce() -> Result<(), PartitionError> + Send + 'static,
+    F: FnOnce(&SudoCredential) -> Result<Option<String>, PartitionError> + Send + 'static,
+{
+    let password = sudo_password.clone();
+    let Ok(checked) = actix_web::web::block(move || {
+        if !sudo::verify_password(password) {
+            return Err(PartitionError::Sudo(SudoError::WrongPassword));
+        }
+        check()
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    if let Err(e) = checked {
+        return partition_error(e);
+    }
+
+    let job_id = Uuid::new_v4();
+    state
+        .background_jobs
+        .lock()
+        .unwrap()
+        .insert(job_id, BackgroundTaskState::Pending);
+
+    let block = actix_web::web::block(move || {
+        let status = match operation(&SudoCredential::Password(sudo_password)) {
+            Ok(Some(output)) => BackgroundTaskState::SuccessOutput(output),
+            Ok(None) => BackgroundTaskState::Success,
+            Err(e) => {
+                error!("Changing a disk failed with error: {e}");
+                BackgroundTaskState::FailOutput(e.to_string())
+            }
+        };
+        state.background_jobs.lock().unwrap().insert(job_id, status);
+    });
+
+    drop(block);
+
+    HttpResponse::Ok().body(job_id.to_string())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionLayoutReq {
+    /// Path of a disk, like `/dev/sdb`
+    device: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/partitions",
+    request_body = PartitionLayoutReq,
+    responses(
+        (status = 200, body = DiskLayout),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The device is not a disk.")
+    ),
+    tags = ["private", "drives"]
+)]
+/// Partitions of a disk
+///
+/// Lists the partition table, the partitions and the unpartitioned space of a disk using
+/// `parted`.
+pub async fn partitions(json: Json<PartitionLayoutReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    let Ok(result) =
+        actix_web::web::block(move || partitions::layout(&json.device, &credential)).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok(layout) => HttpResponse::Ok().json(layout),
+        Err(e) => partition_error(e),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionTableReq {
+    /// Path of a disk, like `/dev/sdb`
+    device: String,
+    /// Has to be the path of the disk
+    confirmation: String,
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/partitions/table",
+    request_body = PartitionTableReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The confirmation is not the path of the disk."),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The device is not a disk."),
+        (status = 409, description = "The disk is mounted, in use or holds the system.")
+    ),
+    tags = ["private", "drives", "responding_job"]
+)]
+/// Create partition table
+///
+/// Wipes the disk and creates an empty GPT partition table. All data on the disk is lost.
+pub async fn partition_table(state: Data<AppState>, json: Json<PartitionTableReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let (device, confirmation) = (json.device.clone(), json.confirmation.clone());
+    start_partition_job(
+        state,
+        json.sudo_password,
+        move || {
+            partitions::confirm(&device, &confirmation)?;
+            partitions::check_disk(&device)
+        },
+        move |credential| {
+            partitions::create_table(&json.device, &json.confirmation, credential).map(|_| None)
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPartitionReq {
+    /// Path of a disk with a GPT partition table, like `/dev/sdb`
+    device: String,
+    #[serde(flatten)]
+    partition: NewPartition,
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/partitions/new",
+    request_body = NewPartitionReq,
+    responses(
+        (status = 200, description = "Job started with ID, the job outputs the path of the partition"),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The device is not a disk."),
+        (status = 409, description = "The disk is mounted, in use or holds the system.")
+    ),
+    tags = ["private", "drives", "responding_job"]
+)]
+/// Create partition
+///
+/// Creates a partition in the first unpartitioned space it fits into. Existing partitions are
+/// not changed.
+pub async fn new_partition(state: Data<AppState>, json: Json<NewPartitionReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let device = json.device.clone();
+    start_partition_job(
+        state,
+        json.sudo_password,
+        move || partitions::check_disk(&device),
+        move |credential| {
+            partitions::create_partition(&json.device, &json.partition, credential).map(Some)
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatReq {
+    /// Path of a disk or partition, like `/dev/sdb1`
+    device: String,
+    filesystem: Filesystem,
+    label: Option<String>,
+    /// Has to be the path of the disk or partition
+    confirmation: String,
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/format",
+    request_body = FormatReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The confirmation is not the path of the device."),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The device is not a disk or partition."),
+        (status = 409, description = "The device is mounted, in use or on the system disk.")
+    ),
+    tags = ["private", "drives", "responding_job"]
+)]
+/// Format disk or partition
+///
+/// Creates an ext4, xfs, btrfs, exFAT or FAT filesystem. All data on the device is lost.
+pub async fn format(state: Data<AppState>, json: Json<FormatReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let (device, confirmation) = (json.device.clone(), json.confirmation.clone());
+    start_partition_job(
+        state,
+        json.sudo_password,
+        move || {
+            partitions::confirm(&device, &confirmation)?;
+            partitions::check_filesystem(&device)
+        },
+        move |credential| {
+            partitions::format(
+                &json.device,
+                json.filesystem,
+                json.label.as_deref(),
+                &json.confirmation,
+                credential,
+            )
+            .map(|_| None)
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelReq {
+    /// Path of a disk or partition, like `/dev/sdb1`
+    device: String,
+    label: String,
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/drives/label",
+    request_body = LabelReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The device is not a disk or partition."),
+        (status = 409, description = "The device is mounted, in use or on the system disk.")
+    ),
+    tags = ["private", "drives", "responding_job"]
+)]
+/// Change filesystem label
+///
+/// The filesystem has to be unmounted, as xfs can not be relabeled while it is mounted.
+pub async fn label(state: Data<AppState>, json: Json<LabelReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let device = json.device.clone();
+    start_partition_job(
+        state,
+        json.sudo_password,
+        move || partitions::check_filesystem(&device),
+        move |credential| {
+            partitions::set_label(&json.device, &json.label, credential).map(|_| None)
+        },
+    )
+    .await
+}
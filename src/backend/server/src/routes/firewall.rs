@@ -24,7 +24,9 @@ struct HasUfwReq {
 )]
 /// Is UFW installed
 pub async fn has_ufw() -> HttpResponse {
-    let check = utils::packages::list_installed_packages().contains(&String::from("ufw"));
+    let check = utils::packages::primary_backend()
+        .and_then(|b| b.list_installed())
+        .is_ok_and(|p| p.contains(&String::from("ufw")));
     HttpResponse::Ok().json(HasUfwReq { has: check })
 }
 
@@ -12,5 +12,7 @@ pub mod network;
 pub mod packages;
 pub mod power;
 pub mod processes;
+pub mod security;
 pub mod sharing;
+pub mod storage;
 pub mod tls;
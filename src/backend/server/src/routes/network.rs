@@ -1,9 +1,12 @@
 use actix_web::{
     HttpResponse,
-    web::{Data, Json},
+    web::{Data, Json, Query},
 };
 use serde::{Deserialize, Serialize};
-use std::{net::IpAddr, str::FromStr};
+use std::{net::IpAddr, path::Path, str::FromStr};
+use utils::sockets::{self, Protocol, Socket, SocketOwner, SocketState};
+use utils::sudo::{self, SudoCredential, SudoError};
+use utils::ufw::{self, PortVerdict};
 use utils::{net_data::Interface, status_com::ErrorCode};
 use utils::{
     net_data::{self, DeletionRoute, Destination, IpAddrWithSubnet, Route},
@@ -105,3 +108,162 @@ pub async fn activate_interface(json: Json<NetworkingInterfaceActivityReq>) -> H
     }
     HttpResponse::Ok().json(MessageRes::from("The interface has been updated."))
 }
+
+/// Filters for sockets, all of which are optional
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SocketFilter {
+    state: Option<SocketState>,
+    /// Matches the local or the remote port
+    port: Option<u16>,
+    protocol: Option<Protocol>,
+}
+
+impl SocketFilter {
+    fn matches(&self, socket: &Socket) -> bool {
+        self.state.is_none_or(|s| s == socket.state)
+            && self.protocol.is_none_or(|p| p == socket.protocol)
+            && self
+                .port
+                .is_none_or(|p| socket.local_port == Some(p) || socket.remote_port == Some(p))
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct SocketEntry {
+    #[serde(flatten)]
+    socket: Socket,
+    /// Only processes of the user running Zentrox are listed unless sudo is used
+    processes: Vec<SocketOwner>,
+    /// Whether UFW accepts connections to a listening TCP or UDP socket. Missing for other
+    /// sockets, sockets listening on loopback addresses and if the firewall was not checked.
+    firewall: Option<PortVerdict>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct SocketsRes {
+    sockets: Vec<SocketEntry>,
+    /// Missing if the firewall was not checked or UFW is not available
+    firewall_enabled: Option<bool>,
+}
+
+/// List the sockets that match the filter with the processes using them and check listening
+/// sockets against the firewall.
+async fn list_sockets(
+    filter: SocketFilter,
+    credential: Option<SudoCredential>,
+    firewall: Option<ufw::Status>,
+) -> HttpResponse {
+    let Ok(result) = actix_web::web::block(move || {
+        let owners = sockets::socket_owners(credential)?;
+        let listed = sockets::read_sockets(Path::new("/proc"))
+            .into_iter()
+            .filter(|s| filter.matches(s))
+            .map(|socket| {
+                let listening = matches!(
+                    (socket.protocol, socket.state),
+                    (Protocol::Tcp | Protocol::Tcp6, SocketState::Listen)
+                        | (Protocol::Udp | Protocol::Udp6, SocketState::Unconnected)
+                );
+                let verdict = match (&firewall, socket.local_address, socket.local_port) {
+                    (Some(status), Some(address), Some(port))
+                        if status.enabled && listening && !address.is_loopback() =>
+                    {
+                        let protocol = match socket.protocol {
+                            Protocol::Udp | Protocol::Udp6 => ufw::Protocol::Udp,
+                            _ => ufw::Protocol::Tcp,
+                        };
+                        Some(ufw::port_verdict(status, protocol, address, port))
+                    }
+                    _ => None,
+                };
+                SocketEntry {
+                    processes: owners.get(&socket.inode).cloned().unwrap_or_default(),
+                    socket,
+                    firewall: verdict,
+                }
+            })
+            .collect::<Vec<SocketEntry>>();
+        Ok::<_, SudoError>((listed, firewall.map(|s| s.enabled)))
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    match result {
+        Ok((sockets, firewall_enabled)) => HttpResponse::Ok().json(SocketsRes {
+            sockets,
+            firewall_enabled,
+        }),
+        Err(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::CommandFailed(format!("{e:?}")).as_error_message()),
+    }
+}
+
+/// Listening ports and connections
+///
+/// Lists TCP, UDP and UNIX sockets and the processes of the user running Zentrox using them.
+#[utoipa::path(get,
+    path = "/private/network/sockets",
+    params(
+        ("state" = Option<SocketState>, Query),
+        ("port" = Option<u16>, Query, description = "Matches the local or the remote port"),
+        ("protocol" = Option<Protocol>, Query)
+    ),
+    responses((status = 200, body = SocketsRes)),
+    tags = ["private", "network"]
+)]
+pub async fn sockets(filter: Query<SocketFilter>) -> HttpResponse {
+    list_sockets(filter.into_inner(), None, None).await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivilegedSocketsReq {
+    #[serde(flatten)]
+    filter: SocketFilter,
+    sudo_password: String,
+}
+
+/// Listening ports and connections using sudo
+///
+/// Like `/private/network/sockets`, but lists the processes of all users using the sockets and
+/// whether UFW accepts connections to listening ports.
+#[utoipa::path(post,
+    path = "/private/network/sockets",
+    request_body = PrivilegedSocketsReq,
+    responses(
+        (status = 200, body = SocketsRes),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "network"]
+)]
+pub async fn sockets_privileged(json: Json<PrivilegedSocketsReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let password = json.sudo_password.clone();
+    let Ok(verified) = actix_web::web::block(move || sudo::verify_password(password)).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    if !verified {
+        return HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message());
+    }
+
+    // UFW may not be installed, which only leaves out the firewall
+    let password = json.sudo_password.clone();
+    let firewall = actix_web::web::block(move || ufw::status(password).ok())
+        .await
+        .ok()
+        .flatten();
+    list_sockets(
+        json.filter,
+        Some(SudoCredential::from(json.sudo_password)),
+        firewall,
+    )
+    .await
+}
@@ -1,17 +1,42 @@
+use actix_session::Session;
 use actix_web::{
     HttpResponse,
-    web::{Data, Json},
+    web::{Data, Json, Path, Query},
 };
 use diesel::prelude::*;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::time::UNIX_EPOCH;
-use utils::packages;
-use utils::status_com::ErrorCode;
+use utils::models::{PackageJob, UpdateRun};
+use utils::packages::history::Transaction;
+use utils::packages::repositories::{NewRepository, Repository, RepositoryError};
+use utils::packages::unattended::{self, UnattendedError, UpdateKind, UpdatePolicy};
+use utils::packages::{
+    self, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+};
+use utils::status_com::{ErrorCode, MessageRes};
+use utils::sudo::{SudoError, SudoOutput};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::package_history;
+use crate::package_index::{self, IndexedPackages};
+use crate::permissions;
+use crate::updates::{self, Trigger};
 use crate::{AppState, BackgroundTaskState, SudoPasswordReq};
 
+/// Packages known to a secondary package manager like Flatpak or Snap
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct PackageSourceRes {
+    package_manager: PackageManager,
+    installed: Vec<String>,
+    available: Vec<String>,
+    updates: Option<Vec<String>>,
+    /// When the packages were last listed expressed as seconds since the UNIX epoch
+    refreshed_at: i64,
+}
+
 #[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct PackageDatabaseRes {
@@ -19,8 +44,26 @@ struct PackageDatabaseRes {
     available: Vec<String>,
     package_manager: Option<packages::PackageManager>,
     updates: Option<Vec<String>>,
+    /// Installed packages that do not belong to any repository, like packages from the AUR
+    foreign: Vec<String>,
     last_database_update: Option<i64>, // The last database update expressed as seconds since the
-                                       // UNIX epoch
+    // UNIX epoch
+    /// When the packages were last listed expressed as seconds since the UNIX epoch
+    refreshed_at: i64,
+    /// Secondary package managers detected on the system
+    sources: Vec<PackageSourceRes>,
+}
+
+/// Package counts of a secondary package manager like Flatpak or Snap
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct PackageSourceStatisticsRes {
+    package_manager: PackageManager,
+    installed: usize,
+    available: usize,
+    updates: Option<usize>,
+    /// When the packages were last listed expressed as seconds since the UNIX epoch
+    refreshed_at: i64,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -30,55 +73,186 @@ struct PackageStatisticsRes {
     available: usize,
     package_manager: Option<packages::PackageManager>,
     updates: Option<usize>,
+    /// Installed packages that do not belong to any repository, like packages from the AUR
+    foreign: usize,
     last_database_update: Option<i64>, // The last database update expressed as seconds since the
-                                       // UNIX epoch
+    // UNIX epoch
+    /// When the packages were last listed expressed as seconds since the UNIX epoch
+    refreshed_at: i64,
+    /// Secondary package managers detected on the system
+    sources: Vec<PackageSourceStatisticsRes>,
 }
 
-#[utoipa::path(
-    get,
-    path = "/private/packages/database",
-    responses((status = 200, body = PackageDatabaseRes)),
-    tags = ["private", "packages"]
-)]
-/// Package database
+/// The backend of the requested package manager, which defaults to the system package manager.
 ///
-/// This includes the full names of all packages known to the package manager. Updates can not be listed if the package
-/// manager is PacMan.
-pub async fn database(state: Data<AppState>) -> HttpResponse {
+/// Package managers that are not installed on the system are rejected. Detecting the package
+/// managers runs commands, so it happens off the async executor.
+async fn requested_backend(
+    requested: Option<PackageManager>,
+) -> Result<Box<dyn PackageBackend>, ErrorCode> {
+    actix_web::web::block(move || {
+        let manager = match requested {
+            Some(m) if packages::detect_package_managers().contains(&m) => m,
+            Some(_) => return Err(ErrorCode::PackageManagerUnavailable),
+            None => packages::get_package_manager().map_err(|_| ErrorCode::PackageManagerFailed)?,
+        };
+        Ok(manager.backend())
+    })
+    .await
+    .map_err(|_| ErrorCode::TaskFailed)?
+}
+
+/// Respond to a failed [`requested_backend`].
+fn backend_error(error: ErrorCode) -> HttpResponse {
+    match error {
+        ErrorCode::PackageManagerUnavailable => {
+            HttpResponse::BadRequest().json(error.as_error_message())
+        }
+        _ => HttpResponse::InternalServerError().json(error.as_error_message()),
+    }
+}
+
+/// Steps of a package job, named after the package manager that runs them
+type JobSteps = Vec<(String, Result<SudoOutput, SudoError>)>;
+
+/// Run a package operation as a background job and record it in the package history.
+///
+/// The job fails if any step exits with a non-zero status. Responds with the ID of the job.
+fn start_job<F>(
+    state: Data<AppState>,
+    session: &Session,
+    action: &str,
+    package_manager: Option<PackageManager>,
+    package: Option<String>,
+    operation: F,
+) -> HttpResponse
+where
+    F: FnOnce() -> JobSteps + Send + 'static,
+{
+    let job_id = Uuid::new_v4();
+    let username = permissions::locate_session(session, &state)
+        .ok()
+        .map(|s| s.username);
+    let mut job =
+        match package_history::start(&state, job_id, username, action, package_manager, package) {
+            Ok(v) => v,
+            Err(database_error) => {
+                return HttpResponse::InternalServerError().json(
+                    ErrorCode::DatabaseUpdateFailed(database_error.to_string()).as_error_message(),
+                );
+            }
+        };
+
+    state
+        .background_jobs
+        .lock()
+        .unwrap()
+        .insert(job_id, BackgroundTaskState::Pending);
+
+    drop(actix_web::web::block(move || {
+        let result = package_history::finish(&state, &mut job, operation());
+        package_index::refresh_after_change(&state);
+        let status = match result {
+            Ok(true) => BackgroundTaskState::Success,
+            Ok(false) => BackgroundTaskState::FailOutput(job.output),
+            Err(database_error) => BackgroundTaskState::FailOutput(database_error.to_string()),
+        };
+        state.background_jobs.lock().unwrap().insert(job_id, status)
+    }));
+
+    HttpResponse::Ok().body(job_id.to_string())
+}
+
+/// The time of the last database update expressed as seconds since the UNIX epoch.
+fn last_database_update(state: &AppState) -> Result<Option<i64>, ErrorCode> {
     use utils::models::PackageAction;
     use utils::schema::PackageActions::dsl::*;
     let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
 
-    let stored_last_database_update = match PackageActions
+    PackageActions
         .select(PackageAction::as_select())
         .first(connection)
-    {
-        Ok(v) => v.last_database_update,
-        Err(database_error) => {
-            return HttpResponse::InternalServerError().json(
-                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
-            );
-        }
-    };
+        .map(|v| v.last_database_update)
+        .map_err(|database_error| ErrorCode::DatabaseReadFailed(database_error.to_string()))
+}
 
-    let installed = packages::list_installed_packages();
+/// Packages of the system package manager and the secondary package managers
+struct PackageIndexRes {
+    last_database_update: Option<i64>,
+    primary: IndexedPackages,
+    sources: Vec<IndexedPackages>,
+}
 
-    let available = match packages::list_available_packages() {
-        Ok(packages) => packages,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(ErrorCode::PackageManagerFailed.as_error_message());
+/// Read the package index off the async executor, as it has to be built when it is empty.
+async fn indexed_packages(state: Data<AppState>) -> Result<PackageIndexRes, ErrorCode> {
+    let (primary_manager, last_database_update, indexed) = actix_web::web::block(move || {
+        let primary_manager =
+            packages::get_package_manager().map_err(|_| ErrorCode::PackageManagerFailed)?;
+        let indexed = package_index::load_or_refresh(&state)
+            .map_err(|database_error| ErrorCode::DatabaseReadFailed(database_error.to_string()))?;
+        Ok::<_, ErrorCode>((primary_manager, last_database_update(&state)?, indexed))
+    })
+    .await
+    .map_err(|_| ErrorCode::TaskFailed)??;
+
+    let (mut primary, sources): (Vec<IndexedPackages>, Vec<IndexedPackages>) = indexed
+        .into_iter()
+        .filter(|p| p.package_manager == primary_manager || p.package_manager.is_secondary())
+        .partition(|p| p.package_manager == primary_manager);
+
+    Ok(PackageIndexRes {
+        last_database_update,
+        // The system package manager is missing if listing its packages failed
+        primary: primary.pop().ok_or(ErrorCode::PackageManagerFailed)?,
+        sources,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/database",
+    responses((status = 200, body = PackageDatabaseRes)),
+    tags = ["private", "packages"]
+)]
+/// Package database
+///
+/// This includes the full names of all packages known to the system package manager. Foreign
+/// packages, like packages from the AUR, are listed separately as they are not updated with the
+/// other packages. Packages from Flatpak and Snap are listed in `sources`, the Snap Store can not
+/// be listed and only includes installed snaps.
+///
+/// The packages are served from an index that is refreshed after every package operation and
+/// at least once an hour. `refreshedAt` tells when the packages were last listed.
+pub async fn database(state: Data<AppState>) -> HttpResponse {
+    let index = match indexed_packages(state).await {
+        Ok(v) => v,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(error.as_error_message());
         }
     };
 
-    let updates = packages::list_updates().ok();
+    let sources = index
+        .sources
+        .into_iter()
+        .map(|p| PackageSourceRes {
+            package_manager: p.package_manager,
+            installed: p.installed,
+            available: p.available,
+            updates: p.updates,
+            refreshed_at: p.refreshed_at,
+        })
+        .collect();
 
+    let primary = index.primary;
     HttpResponse::Ok().json(PackageDatabaseRes {
-        installed,
-        available,
-        package_manager: packages::get_package_manager().ok(),
-        updates,
-        last_database_update: stored_last_database_update,
+        installed: primary.installed,
+        available: primary.available,
+        package_manager: Some(primary.package_manager),
+        updates: primary.updates,
+        foreign: primary.foreign,
+        last_database_update: index.last_database_update,
+        refreshed_at: primary.refreshed_at,
+        sources,
     })
 }
 
@@ -89,44 +263,38 @@ pub async fn database(state: Data<AppState>) -> HttpResponse {
     tags = ["private", "packages"]
 )]
 /// Package database counts
+///
+/// The counts are taken from the same index as the package database.
 pub async fn statistics(state: Data<AppState>) -> HttpResponse {
-    use utils::models::PackageAction;
-    use utils::schema::PackageActions::dsl::*;
-    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
-
-    let stored_last_database_update = match PackageActions
-        .select(PackageAction::as_select())
-        .first(connection)
-    {
-        Ok(v) => v.last_database_update,
-        Err(database_error) => {
-            return HttpResponse::InternalServerError().json(
-                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
-            );
-        }
-    };
-
-    let installed = packages::list_installed_packages().len();
-
-    let available = match packages::list_available_packages() {
-        Ok(packages) => packages.len(),
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(ErrorCode::PackageManagerFailed.as_error_message());
+    let index = match indexed_packages(state).await {
+        Ok(v) => v,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(error.as_error_message());
         }
     };
 
-    let updates = match packages::list_updates() {
-        Ok(packages) => Some(packages.len()),
-        Err(_) => None,
-    };
+    let sources = index
+        .sources
+        .iter()
+        .map(|p| PackageSourceStatisticsRes {
+            package_manager: p.package_manager,
+            installed: p.installed.len(),
+            available: p.available.len(),
+            updates: p.updates.as_ref().map(Vec::len),
+            refreshed_at: p.refreshed_at,
+        })
+        .collect();
 
+    let primary = index.primary;
     HttpResponse::Ok().json(PackageStatisticsRes {
-        installed,
-        available,
-        package_manager: packages::get_package_manager().ok(),
-        updates,
-        last_database_update: stored_last_database_update,
+        installed: primary.installed.len(),
+        available: primary.available.len(),
+        package_manager: Some(primary.package_manager),
+        updates: primary.updates.as_ref().map(Vec::len),
+        foreign: primary.foreign.len(),
+        last_database_update: index.last_database_update,
+        refreshed_at: primary.refreshed_at,
+        sources,
     })
 }
 
@@ -143,10 +311,212 @@ struct OrphanedPackagesRes {
     tags = ["private", "packages"]
 )]
 pub async fn orphaned() -> HttpResponse {
-    if let Ok(packages) = packages::list_orphaned_packages() {
-        HttpResponse::Ok().json(OrphanedPackagesRes { packages })
+    let listed =
+        actix_web::web::block(|| packages::primary_backend().and_then(|b| b.list_orphaned())).await;
+
+    match listed {
+        Ok(Ok(packages)) => HttpResponse::Ok().json(OrphanedPackagesRes { packages }),
+        Ok(Err(_)) => HttpResponse::InternalServerError()
+            .json(ErrorCode::PackageManagerFailed.as_error_message()),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+/// Which packages to include in search results
+#[derive(Deserialize, ToSchema, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum PackageFilter {
+    #[default]
+    All,
+    Installed,
+    Available,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageSearchReq {
+    query: String,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    filter: Option<PackageFilter>,
+    package_manager: Option<PackageManager>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct PackageSearchRes {
+    packages: Vec<PackageSummary>,
+    /// Number of matching packages on all pages
+    total: usize,
+    page: usize,
+    per_page: usize,
+}
+
+/// Order search results by relevance: exact matches, then names starting with the query, then
+/// names containing the query and finally matches in the description.
+fn rank(package: &PackageSummary, query: &str) -> u8 {
+    let name = package.name.to_lowercase();
+    if name == query {
+        0
+    } else if name.starts_with(query) {
+        1
+    } else if name.contains(query) {
+        2
     } else {
-        HttpResponse::InternalServerError().json(ErrorCode::PackageManagerFailed.as_error_message())
+        3
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/search",
+    params(
+        ("query" = String, Query, description = "Text to search for in names and descriptions"),
+        ("page" = Option<usize>, Query, description = "Page starting at 0"),
+        ("perPage" = Option<usize>, Query, description = "Results per page, defaults to 50 and may not exceed 500"),
+        ("filter" = Option<PackageFilter>, Query),
+        ("packageManager" = Option<PackageManager>, Query, description = "Only search this package manager")
+    ),
+    responses(
+        (status = 200, body = PackageSearchRes),
+        (status = 400, description = "The query is empty or starts with a dash or the package manager is not installed.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Search packages
+///
+/// The names and descriptions of all packages known to the package managers on the system are
+/// searched. Results are ordered by relevance and split into pages.
+pub async fn search(info: Query<PackageSearchReq>) -> HttpResponse {
+    let query = info.query.trim();
+    if query.is_empty() || query.starts_with('-') {
+        return HttpResponse::BadRequest().json(ErrorCode::InsufficientData.as_error_message());
+    }
+
+    let page = info.page.unwrap_or(0);
+    let per_page = info.per_page.unwrap_or(50).clamp(1, 500);
+    let filter = info.filter.unwrap_or_default();
+
+    let searched = match info.package_manager {
+        Some(_) => match requested_backend(info.package_manager).await {
+            Ok(backend) => Some(backend),
+            Err(error) => return backend_error(error),
+        },
+        None => None,
+    };
+
+    let all_sources = info.package_manager.is_none();
+    let searched_query = query.to_string();
+    let found = actix_web::web::block(move || {
+        let searched = match searched {
+            Some(backend) => vec![backend],
+            None => packages::backends(),
+        };
+        let mut results = Vec::new();
+        for backend in searched {
+            match backend.search(&searched_query) {
+                Ok(found) => results.extend(found),
+                // A failing secondary source should not hide results of the system package manager
+                Err(e) if backend.package_manager().is_secondary() && all_sources => {
+                    warn!(
+                        "Searching packages using {} failed: {e:?}",
+                        backend.package_manager()
+                    );
+                }
+                Err(_) => return None,
+            }
+        }
+        Some(results)
+    })
+    .await;
+
+    let mut results = match found {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::PackageManagerFailed.as_error_message());
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::TaskFailed.as_error_message());
+        }
+    };
+
+    results.retain(|p| match filter {
+        PackageFilter::All => true,
+        PackageFilter::Installed => p.installed,
+        PackageFilter::Available => !p.installed,
+    });
+
+    let lowercase_query = query.to_lowercase();
+    results.sort_by(|a, b| {
+        rank(a, &lowercase_query)
+            .cmp(&rank(b, &lowercase_query))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let total = results.len();
+    let packages = results
+        .into_iter()
+        .skip(page.saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+
+    HttpResponse::Ok().json(PackageSearchRes {
+        packages,
+        total,
+        page,
+        per_page,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageManagerReq {
+    package_manager: Option<PackageManager>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/details/{name}",
+    params(
+        ("name" = String, Path, description = "Exact name of the package"),
+        ("packageManager" = Option<PackageManager>, Query, description = "Defaults to the system package manager")
+    ),
+    responses(
+        (status = 200, body = PackageInfo),
+        (status = 400, description = "The package name is malformed or the package manager is not installed."),
+        (status = 404, description = "The package is not known to the package manager.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Package details
+///
+/// Version, size, description, repository and dependencies of a package. Reverse dependencies
+/// only include installed packages.
+pub async fn details(path: Path<String>, info: Query<PackageManagerReq>) -> HttpResponse {
+    let name = path.into_inner();
+    if name.is_empty() || name.starts_with('-') || !utils::sanitize::is_clean(&name) {
+        return HttpResponse::BadRequest().json(ErrorCode::SanitizationError.as_error_message());
+    }
+
+    let backend = match requested_backend(info.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    match actix_web::web::block(move || backend.info(&name)).await {
+        Ok(Ok(info)) => HttpResponse::Ok().json(info),
+        Ok(Err(PackageManagerError::UnknownPackage)) => {
+            HttpResponse::NotFound().json(ErrorCode::NoSuchPackage.as_error_message())
+        }
+        Ok(Err(_)) => HttpResponse::InternalServerError()
+            .json(ErrorCode::PackageManagerFailed.as_error_message()),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
     }
 }
 
@@ -160,6 +530,7 @@ pub async fn orphaned() -> HttpResponse {
 /// Update package database
 ///
 /// This action may take several minutes and is useful for discovering outdated packages.
+/// The databases of all package managers on the system are updated.
 /// The task is ran asynchronous to the rest of the program and a job id is given which can be
 /// polled to get the state of the job.
 pub async fn update_db(state: Data<AppState>, json: Json<SudoPasswordReq>) -> HttpResponse {
@@ -175,7 +546,13 @@ pub async fn update_db(state: Data<AppState>, json: Json<SudoPasswordReq>) -> Ht
 
     let block = actix_web::web::block(move || {
         let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
-        if packages::update_database(json.into_inner().sudo_password).is_ok() {
+        let sudo_password = json.into_inner().sudo_password;
+        let updated = packages::backends()
+            .iter()
+            .all(|b| b.update_database(sudo_password.clone().into()).is_ok());
+        package_index::refresh_after_change(&state);
+
+        if updated {
             let updated_new_database_update = std::time::SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
@@ -225,111 +602,134 @@ pub async fn update_db(state: Data<AppState>, json: Json<SudoPasswordReq>) -> Ht
 pub struct PackageActionReq {
     package_name: String,
     sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
 }
 
 #[utoipa::path(
     post,
     path = "/private/packages/install",
     request_body = PackageActionReq,
-    responses((status = 200, description = "Job started with ID")),
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The package manager is not installed.")
+    ),
     tags = ["private", "packages", "responding_job"]
 )]
 /// Install package
 ///
 /// It requires the package name along side the sudo password in the request body.
-/// This only works under apt, dnf and pacman. This request responds only with a job id.
-pub async fn install_package(json: Json<PackageActionReq>, state: Data<AppState>) -> HttpResponse {
-    let job_id = Uuid::new_v4();
+/// This only works under apt, dnf, pacman, zypper, apk, Flatpak and Snap. This request responds
+/// only with a job id.
+pub async fn install_package(
+    json: Json<PackageActionReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+    let manager = backend.package_manager();
+    let json = json.into_inner();
 
-    drop(actix_web::web::block(
-        move || match packages::install_package(
-            json.package_name.to_string(),
-            json.sudo_password.to_string(),
-        ) {
-            Ok(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Success),
-            Err(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Fail),
+    start_job(
+        state,
+        &session,
+        "install",
+        Some(manager),
+        Some(json.package_name.clone()),
+        move || {
+            vec![(
+                manager.to_string(),
+                backend.install(&json.package_name, json.sudo_password.into()),
+            )]
         },
-    ));
-
-    HttpResponse::Ok().body(job_id.to_string())
+    )
 }
 
 #[utoipa::path(
     post,
     path = "/private/packages/remove",
     request_body = PackageActionReq,
-    responses((status = 200, description = "Job started with ID")),
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The package manager is not installed.")
+    ),
     tags = ["private", "packages", "responding_job"]
 )]
 /// Remove package
 ///
 /// It requires the package name along side the sudo password in the request body.
-/// This only works under apt, dnf and pacman. This request responds only with a job id.
-pub async fn remove_package(json: Json<PackageActionReq>, state: Data<AppState>) -> HttpResponse {
-    let job_id = Uuid::new_v4();
+/// This only works under apt, dnf, pacman, zypper, apk, Flatpak and Snap. This request responds
+/// only with a job id.
+pub async fn remove_package(
+    json: Json<PackageActionReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+    let manager = backend.package_manager();
+    let json = json.into_inner();
 
-    drop(actix_web::web::block(
-        move || match packages::remove_package(
-            json.package_name.to_string(),
-            json.sudo_password.to_string(),
-        ) {
-            Ok(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Success),
-            Err(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Fail),
+    start_job(
+        state,
+        &session,
+        "remove",
+        Some(manager),
+        Some(json.package_name.clone()),
+        move || {
+            vec![(
+                manager.to_string(),
+                backend.remove(&json.package_name, json.sudo_password.into()),
+            )]
         },
-    ));
-
-    HttpResponse::Ok().body(job_id.to_string())
+    )
 }
 
 #[utoipa::path(
     post,
     path = "/private/packages/update",
     request_body = PackageActionReq,
-    responses((status = 200, description = "Job started with ID")),
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The package manager is not installed.")
+    ),
     tags = ["private", "packages", "responding_job"]
 )]
 /// Update packages
 ///
 /// It requires the package name along side the sudo password in the request body.
-/// This only works under apt, dnf and pacman. This request responds only with a job id.
-pub async fn update_package(state: Data<AppState>, json: Json<PackageActionReq>) -> HttpResponse {
-    let job_id = Uuid::new_v4();
+/// This only works under apt, dnf, pacman, zypper, apk, Flatpak and Snap. This request responds
+/// only with a job id.
+pub async fn update_package(
+    json: Json<PackageActionReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+    let manager = backend.package_manager();
+    let json = json.into_inner();
 
-    drop(actix_web::web::block(
-        move || match packages::update_package(
-            json.package_name.to_string(),
-            json.sudo_password.to_string(),
-        ) {
-            Ok(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Success),
-            Err(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Fail),
+    start_job(
+        state,
+        &session,
+        "update",
+        Some(manager),
+        Some(json.package_name.clone()),
+        move || {
+            vec![(
+                manager.to_string(),
+                backend.update(&json.package_name, json.sudo_password.into()),
+            )]
         },
-    ));
-
-    HttpResponse::Ok().body(job_id.to_string())
+    )
 }
 
 #[utoipa::path(
@@ -341,34 +741,27 @@ pub async fn update_package(state: Data<AppState>, json: Json<PackageActionReq>)
 )]
 /// Update all packages
 ///
-/// It requires the package name along side the sudo password in the request body.
-/// This only works under apt, dnf and pacman.
-pub async fn update_all(state: Data<AppState>, json: Json<SudoPasswordReq>) -> HttpResponse {
-    let sudo_password = json.sudo_password.clone();
-    let job_id = Uuid::new_v4();
+/// It requires the sudo password in the request body.
+/// Packages of all package managers on the system are updated, starting with the system package
+/// manager.
+pub async fn update_all(
+    json: Json<SudoPasswordReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let sudo_password = json.into_inner().sudo_password;
 
-    state
-        .background_jobs
-        .lock()
-        .unwrap()
-        .insert(job_id, BackgroundTaskState::Pending);
-
-    drop(actix_web::web::block(
-        move || match packages::update_all_packages(sudo_password.to_string()) {
-            Ok(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Success),
-            Err(_) => state
-                .background_jobs
-                .lock()
-                .unwrap()
-                .insert(job_id, BackgroundTaskState::Fail),
-        },
-    ));
-
-    HttpResponse::Ok().body(job_id.to_string())
+    start_job(state, &session, "updateAll", None, None, move || {
+        packages::backends()
+            .iter()
+            .map(|b| {
+                (
+                    b.package_manager().to_string(),
+                    b.update_all(sudo_password.to_string().into()),
+                )
+            })
+            .collect()
+    })
 }
 
 #[utoipa::path(
@@ -379,8 +772,218 @@ pub async fn update_all(state: Data<AppState>, json: Json<SudoPasswordReq>) -> H
     tags = ["private", "packages", "responding_job"]
 )]
 /// Auto-remove packages
-pub async fn remove_orphaned(json: Json<SudoPasswordReq>, state: Data<AppState>) -> HttpResponse {
-    let sudo_password = json.sudo_password.clone();
+///
+/// Unused Flatpak runtimes are removed as well.
+pub async fn remove_orphaned(
+    json: Json<SudoPasswordReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let sudo_password = json.into_inner().sudo_password;
+
+    start_job(state, &session, "removeOrphaned", None, None, move || {
+        packages::backends()
+            .iter()
+            .map(|b| {
+                (
+                    b.package_manager().to_string(),
+                    b.remove_orphaned(sudo_password.to_string().into()),
+                )
+            })
+            .collect()
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct UnattendedUpdatesRes {
+    policy: UpdatePolicy,
+    /// Whether the sudoers rule that allows updates without the sudo password is in place
+    authorized: bool,
+    /// Whether the system package manager can install only security updates
+    security_updates_supported: bool,
+    /// Unknown if it can not be determined on this system
+    reboot_required: Option<bool>,
+    /// The next time the maintenance window opens expressed as seconds since the UNIX epoch
+    next_window: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/unattended",
+    responses((status = 200, body = UnattendedUpdatesRes)),
+    tags = ["private", "packages"]
+)]
+/// Unattended updates
+///
+/// The update policy and whether updates can be installed without the sudo password.
+pub async fn unattended_updates(state: Data<AppState>) -> HttpResponse {
+    let policy = match updates::load_policy(&state) {
+        Ok(v) => v,
+        Err(database_error) => {
+            return HttpResponse::InternalServerError().json(
+                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
+            );
+        }
+    };
+
+    let checked = actix_web::web::block(|| {
+        (
+            unattended::is_authorized(),
+            packages::primary_backend().is_ok_and(|b| b.supports_security_updates()),
+            unattended::reboot_required(),
+        )
+    })
+    .await;
+    let Ok((authorized, security_updates_supported, reboot_required)) = checked else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    HttpResponse::Ok().json(UnattendedUpdatesRes {
+        next_window: policy.next_window(),
+        policy,
+        authorized,
+        security_updates_supported,
+        reboot_required,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/unattended",
+    request_body = UpdatePolicy,
+    responses(
+        (status = 200),
+        (status = 400, description = "The policy is malformed or security updates are not supported by the package manager.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Change update policy
+///
+/// Updates are installed once every time the maintenance window opens. The times of the window
+/// are in the local time of the system.
+pub async fn update_policy(json: Json<UpdatePolicy>, state: Data<AppState>) -> HttpResponse {
+    let policy = json.into_inner();
+    if let Err(message) = policy.window() {
+        return HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidUpdatePolicy(message).as_error_message());
+    }
+    if policy.kind == UpdateKind::Security {
+        let supported = actix_web::web::block(|| {
+            packages::primary_backend().is_ok_and(|b| b.supports_security_updates())
+        })
+        .await;
+        match supported {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::BadRequest()
+                    .json(ErrorCode::SecurityUpdatesUnsupported.as_error_message());
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError()
+                    .json(ErrorCode::TaskFailed.as_error_message());
+            }
+        }
+    }
+
+    match updates::store_policy(&state, &policy) {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from("The update policy has been saved.")),
+        Err(database_error) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseUpdateFailed(database_error.to_string()).as_error_message()),
+    }
+}
+
+/// Respond to a failed change of the sudoers rule for unattended updates.
+fn authorization_error(error: UnattendedError) -> HttpResponse {
+    match error {
+        UnattendedError::Sudo(SudoError::WrongPassword) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        e => HttpResponse::InternalServerError()
+            .json(ErrorCode::UnattendedAuthorizationFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/unattended/authorize",
+    request_body = SudoPasswordReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 500, description = "The sudoers rule could not be installed.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Allow unattended updates
+///
+/// Installs a sudoers rule that allows the user running Zentrox to run the package managers and
+/// `reboot` as root without a password. The sudo password is only used to install the rule and
+/// is not stored.
+pub async fn authorize_unattended_updates(json: Json<SudoPasswordReq>) -> HttpResponse {
+    match actix_web::web::block(|| unattended::authorize(json.into_inner().sudo_password)).await {
+        Ok(Ok(_)) => HttpResponse::Ok().json(MessageRes::from(
+            "Updates can be installed without a password.",
+        )),
+        Ok(Err(e)) => authorization_error(e),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/unattended/revoke",
+    request_body = SudoPasswordReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 500, description = "The sudoers rule could not be removed.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Disallow unattended updates
+///
+/// Removes the sudoers rule, so scheduled updates fail until it is installed again.
+pub async fn revoke_unattended_updates(json: Json<SudoPasswordReq>) -> HttpResponse {
+    match actix_web::web::block(|| unattended::revoke(json.into_inner().sudo_password)).await {
+        Ok(Ok(_)) => {
+            HttpResponse::Ok().json(MessageRes::from("Updates require the sudo password again."))
+        }
+        Ok(Err(e)) => authorization_error(e),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/unattended/run",
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "Unattended updates have not been allowed."),
+        (status = 409, description = "Updates are already being installed.")
+    ),
+    tags = ["private", "packages", "responding_job"]
+)]
+/// Install updates now
+///
+/// Updates are installed according to the update policy without the sudo password, including the
+/// hooks. The system is only rebooted within the maintenance window.
+pub async fn run_unattended_updates(state: Data<AppState>) -> HttpResponse {
+    if !actix_web::web::block(unattended::is_authorized)
+        .await
+        .unwrap_or(false)
+    {
+        return HttpResponse::BadRequest()
+            .json(ErrorCode::UnattendedUpdatesNotAuthorized.as_error_message());
+    }
+    if state.update_lock.try_lock().is_err() {
+        return HttpResponse::Conflict().json(ErrorCode::UpdateRunInProgress.as_error_message());
+    }
+
     let job_id = Uuid::new_v4();
 
     state
@@ -390,12 +993,553 @@ pub async fn remove_orphaned(json: Json<SudoPasswordReq>, state: Data<AppState>)
         .insert(job_id, BackgroundTaskState::Pending);
 
     drop(actix_web::web::block(move || {
-        let status = match packages::remove_orphaned_packages(sudo_password.to_string()) {
-            Ok(_) => BackgroundTaskState::Success,
-            Err(_) => BackgroundTaskState::Fail,
+        let result = updates::load_policy(&state)
+            .map_err(updates::UpdateError::from)
+            .and_then(|policy| updates::run(&state, &policy, Trigger::Manual));
+        let status = match result {
+            Ok(run) if run.status == "success" => BackgroundTaskState::Success,
+            Ok(run) => BackgroundTaskState::FailOutput(run.output),
+            Err(e) => BackgroundTaskState::FailOutput(e.to_string()),
         };
         state.background_jobs.lock().unwrap().insert(job_id, status)
     }));
 
     HttpResponse::Ok().body(job_id.to_string())
 }
+
+#[derive(Serialize, ToSchema)]
+struct UpdateHistoryRes {
+    runs: Vec<UpdateRun>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/unattended/history",
+    responses((status = 200, body = UpdateHistoryRes)),
+    tags = ["private", "packages"]
+)]
+/// Update history
+///
+/// The last 100 runs of unattended updates, starting with the latest.
+pub async fn update_history(state: Data<AppState>) -> HttpResponse {
+    match updates::history(&state, 100) {
+        Ok(runs) => HttpResponse::Ok().json(UpdateHistoryRes { runs }),
+        Err(database_error) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct PackageHistoryRes {
+    transactions: Vec<Transaction>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/history",
+    responses((status = 200, body = PackageHistoryRes)),
+    tags = ["private", "packages"]
+)]
+/// Package history
+///
+/// Transactions recorded by apt, dnf and pacman and package jobs started using Zentrox, starting
+/// with the latest. Transactions that were made by a job are attributed to the account that
+/// started the job. Under dnf, only the latest 25 transactions are listed.
+pub async fn history(state: Data<AppState>) -> HttpResponse {
+    match actix_web::web::block(move || package_history::transactions(&state)).await {
+        Ok(Ok(transactions)) => HttpResponse::Ok().json(PackageHistoryRes { transactions }),
+        Ok(Err(database_error)) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message()),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct TransactionRes {
+    transaction: Transaction,
+    /// The Zentrox job that made the transaction including the output of the package manager
+    job: Option<PackageJob>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/history/{id}",
+    responses(
+        (status = 200, body = TransactionRes),
+        (status = 404, description = "The transaction is not known.")
+    ),
+    tags = ["private", "packages"],
+    params(("id" = String, Path))
+)]
+/// Package transaction
+pub async fn transaction(path: Path<String>, state: Data<AppState>) -> HttpResponse {
+    let id = path.into_inner();
+    match actix_web::web::block(move || package_history::find(&state, &id)).await {
+        Ok(Ok(Some((transaction, job)))) => {
+            HttpResponse::Ok().json(TransactionRes { transaction, job })
+        }
+        Ok(Ok(None)) => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownTransaction.as_error_message())
+        }
+        Ok(Err(database_error)) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message()),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoTransactionReq {
+    transaction_id: String,
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/history/undo",
+    request_body = UndoTransactionReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The transaction can not be undone."),
+        (status = 404, description = "The transaction is not known.")
+    ),
+    tags = ["private", "packages", "responding_job"]
+)]
+/// Undo package transaction
+///
+/// Reverts all changes of a transaction. This is only supported by dnf.
+pub async fn undo_transaction(
+    json: Json<UndoTransactionReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let json = json.into_inner();
+    let lookup_state = state.clone();
+    let id = json.transaction_id.clone();
+    let transaction = match actix_web::web::block(move || package_history::find(&lookup_state, &id))
+        .await
+    {
+        Ok(Ok(Some((transaction, _)))) => transaction,
+        Ok(Ok(None)) => {
+            return HttpResponse::NotFound().json(ErrorCode::UnknownTransaction.as_error_message());
+        }
+        Ok(Err(database_error)) => {
+            return HttpResponse::InternalServerError().json(
+                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
+            );
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorCode::TaskFailed.as_error_message());
+        }
+    };
+
+    let Some(manager) = transaction.package_manager.filter(|_| transaction.undoable) else {
+        return HttpResponse::BadRequest()
+            .json(ErrorCode::TransactionNotUndoable.as_error_message());
+    };
+    let backend = match requested_backend(Some(manager)).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    start_job(
+        state,
+        &session,
+        "undo",
+        Some(manager),
+        Some(transaction.id.clone()),
+        move || {
+            vec![(
+                manager.to_string(),
+                backend.undo(&transaction, json.sudo_password.into()),
+            )]
+        },
+    )
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DowngradeReq {
+    package_name: String,
+    /// The version to install, as listed in the package history
+    version: String,
+    sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/downgrade",
+    request_body = DowngradeReq,
+    responses(
+        (status = 200, description = "Job started with ID"),
+        (status = 400, description = "The package manager is not installed or can not downgrade packages.")
+    ),
+    tags = ["private", "packages", "responding_job"]
+)]
+/// Downgrade package
+///
+/// Installs an older version of a package, usually the version it was upgraded from according
+/// to the package history. This is supported by apt, dnf and pacman. Under apt and dnf, the
+/// version has to be available from a repository, pacman uses the package cache.
+pub async fn downgrade(
+    json: Json<DowngradeReq>,
+    state: Data<AppState>,
+    session: Session,
+) -> HttpResponse {
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+    if !backend.supports_downgrade() {
+        return HttpResponse::BadRequest().json(ErrorCode::DowngradeUnsupported.as_error_message());
+    }
+    let manager = backend.package_manager();
+    let json = json.into_inner();
+
+    start_job(
+        state,
+        &session,
+        "downgrade",
+        Some(manager),
+        Some(format!("{} {}", json.package_name, json.version)),
+        move || {
+            vec![(
+                manager.to_string(),
+                backend.downgrade(&json.package_name, &json.version, json.sudo_password.into()),
+            )]
+        },
+    )
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct HeldPackageRes {
+    name: String,
+    package_manager: PackageManager,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HoldsRes {
+    holds: Vec<HeldPackageRes>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/holds",
+    responses((status = 200, body = HoldsRes)),
+    tags = ["private", "packages"]
+)]
+/// Held packages
+///
+/// Packages that are held back are not updated, neither when updating all packages nor by
+/// unattended updates.
+pub async fn holds() -> HttpResponse {
+    let listed = actix_web::web::block(|| {
+        packages::backends()
+            .iter()
+            .filter(|b| b.supports_holds())
+            .flat_map(|b| {
+                let package_manager = b.package_manager();
+                b.list_holds()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |name| HeldPackageRes {
+                        name,
+                        package_manager,
+                    })
+            })
+            .collect()
+    })
+    .await;
+
+    match listed {
+        Ok(holds) => HttpResponse::Ok().json(HoldsRes { holds }),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageHoldReq {
+    package_name: String,
+    sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
+}
+
+/// Hold or release a package using the requested package manager.
+async fn change_hold(json: PackageHoldReq, hold: bool) -> HttpResponse {
+    if !packages::is_valid_package_name(&json.package_name) {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidPackageName.as_error_message());
+    }
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    let result = actix_web::web::block(move || {
+        // Checking for support may run the package manager
+        if !backend.supports_holds() {
+            return None;
+        }
+        let credential = json.sudo_password.into();
+        Some(if hold {
+            backend.hold(&json.package_name, credential)
+        } else {
+            backend.unhold(&json.package_name, credential)
+        })
+    })
+    .await;
+
+    let Ok(result) = result else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    let Some(result) = result else {
+        return HttpResponse::BadRequest().json(ErrorCode::HoldsUnsupported.as_error_message());
+    };
+    match result {
+        Ok(output) if output.status == Some(0) => {
+            HttpResponse::Ok().json(MessageRes::from(if hold {
+                "The package is held back."
+            } else {
+                "The package is no longer held back."
+            }))
+        }
+        Ok(output) => HttpResponse::InternalServerError().json(
+            ErrorCode::PackageHoldFailed(output.stderr.trim().to_string()).as_error_message(),
+        ),
+        Err(SudoError::WrongPassword) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::PackageHoldFailed(format!("{e:?}")).as_error_message()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/hold",
+    request_body = PackageHoldReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The package name is invalid or the package manager is not installed or can not hold packages."),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Hold package
+///
+/// Uses `apt-mark hold` under apt, the versionlock plugin under dnf, `IgnorePkg` under pacman
+/// and package locks under zypper.
+pub async fn hold_package(json: Json<PackageHoldReq>) -> HttpResponse {
+    change_hold(json.into_inner(), true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/unhold",
+    request_body = PackageHoldReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The package name is invalid or the package manager is not installed or can not hold packages."),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Release held package
+pub async fn unhold_package(json: Json<PackageHoldReq>) -> HttpResponse {
+    change_hold(json.into_inner(), false).await
+}
+
+#[derive(Serialize, ToSchema)]
+struct RepositoriesRes {
+    repositories: Vec<Repository>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/packages/repositories",
+    responses((status = 200, body = RepositoriesRes)),
+    tags = ["private", "packages"]
+)]
+/// Repositories
+///
+/// Repositories of the system package manager as configured in its configuration files.
+pub async fn repositories() -> HttpResponse {
+    let listed = actix_web::web::block(|| {
+        packages::backends()
+            .iter()
+            .flat_map(|b| b.list_repositories())
+            .collect()
+    })
+    .await;
+
+    match listed {
+        Ok(repositories) => HttpResponse::Ok().json(RepositoriesRes { repositories }),
+        Err(_) => {
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message())
+        }
+    }
+}
+
+/// Change the repository configuration off the async executor and respond to the result.
+async fn change_repositories<F>(change: F, message: &'static str) -> HttpResponse
+where
+    F: FnOnce() -> Result<SudoOutput, RepositoryError> + Send + 'static,
+{
+    let Ok(result) = actix_web::web::block(change).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from(message)),
+        Err(RepositoryError::Sudo(SudoError::WrongPassword)) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(RepositoryError::UnknownRepository) => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownRepository.as_error_message())
+        }
+        Err(RepositoryError::Unsupported) => {
+            HttpResponse::BadRequest().json(ErrorCode::RepositoriesUnsupported.as_error_message())
+        }
+        Err(RepositoryError::InvalidRepository(message)) => HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidRepository(message).as_error_message()),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::RepositoryChangeFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RepositoryEnabledReq {
+    /// As listed by `/private/packages/repositories`
+    id: String,
+    enabled: bool,
+    sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/repositories/enabled",
+    request_body = RepositoryEnabledReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The repository does not exist.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Enable or disable repository
+///
+/// Disabled repositories are commented out or marked as disabled in their file.
+pub async fn set_repository_enabled(json: Json<RepositoryEnabledReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    let message = if json.enabled {
+        "The repository has been enabled."
+    } else {
+        "The repository has been disabled."
+    };
+    change_repositories(
+        move || backend.set_repository_enabled(&json.id, json.enabled, json.sudo_password.into()),
+        message,
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddRepositoryReq {
+    #[serde(flatten)]
+    repository: NewRepository,
+    sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/repositories/add",
+    request_body = AddRepositoryReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The repository is malformed, exists already or the package manager does not support repositories."),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Add repository
+///
+/// The signing key is imported along with the repository:
+/// - apt: It is stored in `/etc/apt/keyrings` and only trusted for this repository.
+/// - dnf and zypper: It is stored in `/etc/pki/rpm-gpg` and imported using `rpm --import`.
+/// - pacman: It is added using `pacman-key --add` and signed locally.
+/// - apk: It is stored in `/etc/apk/keys`.
+///
+/// The package database has to be updated before packages of the repository can be installed.
+pub async fn add_repository(json: Json<AddRepositoryReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    change_repositories(
+        move || backend.add_repository(&json.repository, json.sudo_password.into()),
+        "The repository has been added.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveRepositoryReq {
+    /// As listed by `/private/packages/repositories`
+    id: String,
+    sudo_password: String,
+    /// Defaults to the system package manager
+    package_manager: Option<PackageManager>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/packages/repositories/remove",
+    request_body = RemoveRepositoryReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong."),
+        (status = 404, description = "The repository does not exist.")
+    ),
+    tags = ["private", "packages"]
+)]
+/// Remove repository
+///
+/// Files that do not configure any other repository are deleted. Imported signing keys are
+/// kept.
+pub async fn remove_repository(json: Json<RemoveRepositoryReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let backend = match requested_backend(json.package_manager).await {
+        Ok(backend) => backend,
+        Err(error) => return backend_error(error),
+    };
+
+    change_repositories(
+        move || backend.remove_repository(&json.id, json.sudo_password.into()),
+        "The repository has been removed.",
+    )
+    .await
+}
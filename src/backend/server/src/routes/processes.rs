@@ -1,9 +1,12 @@
 use actix_web::HttpResponse;
-use actix_web::web::Path;
-use serde::Serialize;
+use actix_web::web::{Json, Path};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::{ffi::OsString, fs, ops::Div, path::PathBuf};
 use sysinfo::{MemoryRefreshKind, Pid, ProcessRefreshKind, RefreshKind, UpdateKind};
+use utils::processes::{self, IoPriority, ProcessError, ProcessInspection, ProcessNode, Signal};
 use utils::status_com::ErrorCode;
+use utils::sudo::{SudoCredential, SudoError};
 use utils::{status_com::MessageRes, users::NativeUser};
 use utoipa::ToSchema;
 
@@ -129,6 +132,13 @@ struct ProcessDetailsRes {
     priority: isize,
     threads: isize,
     parent: String,
+    /// From -20 (highest priority) to 19
+    nice: Option<i32>,
+    io_priority: Option<IoPriority>,
+    /// CPUs the process may run on, like `0-3,6`
+    affinity: Option<String>,
+    /// The systemd unit the process belongs to
+    unit: Option<String>,
 }
 
 #[utoipa::path(get,
@@ -209,5 +219,267 @@ pub async fn details(path: Path<u32>) -> HttpResponse {
         run_time,
         executable_path,
         parent: parent_name,
+        nice: processes::nice(pid.as_u32()),
+        io_priority: processes::io_priority(pid.as_u32()),
+        affinity: processes::affinity(pid.as_u32()),
+        unit: processes::systemd_unit(pid.as_u32()),
+    })
+}
+
+/// Change a process off the async executor and respond to the result.
+async fn change_process<F>(change: F, message: &'static str) -> HttpResponse
+where
+    F: FnOnce() -> Result<(), ProcessError> + Send + 'static,
+{
+    let Ok(result) = actix_web::web::block(change).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from(message)),
+        Err(ProcessError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired)) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(ProcessError::NoSuchProcess) => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownPid.as_error_message())
+        }
+        Err(ProcessError::InvalidValue(message)) => HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidProcessChange(message).as_error_message()),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::ProcessChangeFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Inspect a process off the async executor and respond with the result.
+async fn respond_inspection(pid: u32, credential: Option<SudoCredential>) -> HttpResponse {
+    let Ok(result) = actix_web::web::block(move || processes::inspect(pid, credential)).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(inspection) => HttpResponse::Ok().json(inspection),
+        Err(ProcessError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired)) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        Err(ProcessError::NoSuchProcess) => {
+            HttpResponse::NotFound().json(ErrorCode::UnknownPid.as_error_message())
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::CommandFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Inspect process
+///
+/// Lists the open files, sockets, working directory, environment, cgroups and memory maps of a
+/// process. Parts of processes of other users that can not be read are missing.
+#[utoipa::path(get,
+    path = "/private/processes/inspect/{pid}",
+    params(("pid" = u32, Path)),
+    responses((status = 200, body = ProcessInspection), (status = 404, description = "The pid was not found.")),
+    tags = ["processes", "private"]
+)]
+pub async fn inspect(path: Path<u32>) -> HttpResponse {
+    respond_inspection(path.into_inner(), None).await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectReq {
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+/// Inspect process using sudo
+///
+/// Like `/private/processes/inspect/{pid}`, but reads `/proc/<pid>` using sudo, so processes of
+/// other users can be inspected completely.
+#[utoipa::path(post,
+    path = "/private/processes/inspect/{pid}",
+    params(("pid" = u32, Path)),
+    request_body = InspectReq,
+    responses(
+        (status = 200, body = ProcessInspection),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The pid was not found.")
+    ),
+    tags = ["processes", "private"]
+)]
+pub async fn inspect_privileged(path: Path<u32>, json: Json<InspectReq>) -> HttpResponse {
+    respond_inspection(
+        path.into_inner(),
+        Some(SudoCredential::from(json.into_inner().sudo_password)),
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalReq {
+    signal: Signal,
+    /// Only required for processes of other users
+    sudo_password: Option<String>,
+}
+
+/// Send signal to process
+///
+/// Signals to processes of other users are sent using sudo.
+#[utoipa::path(post,
+    path = "/private/processes/signal/{pid}",
+    params(("pid" = u32, Path)),
+    request_body = SignalReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The pid was not found.")
+    ),
+    tags = ["processes", "private"]
+)]
+pub async fn signal(path: Path<u32>, json: Json<SignalReq>) -> HttpResponse {
+    let pid = path.into_inner();
+    let json = json.into_inner();
+    change_process(
+        move || processes::send_signal(pid, json.signal, &SudoCredential::from(json.sudo_password)),
+        "The signal has been sent successfully.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityReq {
+    /// From -20 (highest priority) to 19, unchanged if missing
+    nice: Option<i32>,
+    /// Unchanged if missing
+    io_priority: Option<IoPriority>,
+    /// Required for processes of other users and to raise the priority
+    sudo_password: Option<String>,
+}
+
+/// Change priority of process
+///
+/// Changes the nice value and the I/O scheduling class and priority of a process.
+#[utoipa::path(post,
+    path = "/private/processes/priority/{pid}",
+    params(("pid" = u32, Path)),
+    request_body = PriorityReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The priority is out of range."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The pid was not found.")
+    ),
+    tags = ["processes", "private"]
+)]
+pub async fn priority(path: Path<u32>, json: Json<PriorityReq>) -> HttpResponse {
+    let pid = path.into_inner();
+    let json = json.into_inner();
+    change_process(
+        move || {
+            let credential = SudoCredential::from(json.sudo_password);
+            if let Some(nice) = json.nice {
+                processes::renice(pid, nice, &credential)?;
+            }
+            if let Some(io_priority) = json.io_priority {
+                processes::set_io_priority(pid, io_priority, &credential)?;
+            }
+            Ok(())
+        },
+        "The priority has been changed.",
+    )
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AffinityReq {
+    /// List of CPUs like `0-3,6`
+    cpus: String,
+    /// Only required for processes of other users
+    sudo_password: Option<String>,
+}
+
+/// Change CPU affinity of process
+///
+/// Restricts all threads of a process to the listed CPUs.
+#[utoipa::path(post,
+    path = "/private/processes/affinity/{pid}",
+    params(("pid" = u32, Path)),
+    request_body = AffinityReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The list of CPUs is malformed or lists unknown CPUs."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 404, description = "The pid was not found.")
+    ),
+    tags = ["processes", "private"]
+)]
+pub async fn affinity(path: Path<u32>, json: Json<AffinityReq>) -> HttpResponse {
+    let pid = path.into_inner();
+    let json = json.into_inner();
+    change_process(
+        move || processes::set_affinity(pid, &json.cpus, &SudoCredential::from(json.sudo_password)),
+        "The CPU affinity has been changed.",
+    )
+    .await
+}
+
+#[derive(Serialize, ToSchema)]
+struct ProcessTreeRes {
+    /// Processes without a listed parent, usually `init` and `kthreadd`
+    processes: Vec<ProcessNode>,
+}
+
+#[utoipa::path(get, path = "/private/processes/tree", responses((status = 200, body = ProcessTreeRes)), tags = ["processes", "private"])]
+/// Tree of processes
+///
+/// Lists processes below the processes that started them, with the systemd unit they belong to.
+pub async fn tree() -> HttpResponse {
+    let process_refresh = ProcessRefreshKind::nothing()
+        .without_tasks()
+        .with_cpu()
+        .with_memory()
+        .with_user(UpdateKind::OnlyIfNotSet);
+
+    let Ok(nodes) = actix_web::web::block(move || {
+        let mut system = sysinfo::System::new_with_specifics(
+            RefreshKind::nothing().with_processes(process_refresh),
+        );
+        std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        system.refresh_processes_specifics(sysinfo::ProcessesToUpdate::All, false, process_refresh);
+
+        let mut usernames: HashMap<u32, Option<String>> = HashMap::new();
+        system
+            .processes()
+            .values()
+            .map(|process| {
+                let pid = process.pid().as_u32();
+                let username = process.user_id().and_then(|uid| {
+                    usernames
+                        .entry(uid.div(1))
+                        .or_insert_with(|| {
+                            NativeUser::from_uid(uid.div(1)).ok().map(|u| u.username)
+                        })
+                        .clone()
+                });
+                ProcessNode {
+                    pid,
+                    parent: process.parent().map(|p| p.as_u32()),
+                    name: process.name().to_string_lossy().to_string(),
+                    username,
+                    unit: processes::systemd_unit(pid),
+                    cpu_usage: process.cpu_usage() / 100_f32,
+                    memory_usage_bytes: process.memory(),
+                    children: Vec::new(),
+                }
+            })
+            .collect::<Vec<ProcessNode>>()
+    })
+    .await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+
+    HttpResponse::Ok().json(ProcessTreeRes {
+        processes: processes::build_tree(nodes),
     })
 }
@@ -0,0 +1,428 @@
+use actix_web::{
+    HttpRequest, HttpResponse,
+    web::{Data, Json},
+};
+use diesel::prelude::*;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utils::models::{BlockedIp, ClientCertificate, LoginLimits, TrustedNetwork};
+use utils::status_com::{ErrorCode, MessageRes};
+use utoipa::ToSchema;
+
+use crate::AppState;
+use crate::client_certificates;
+use crate::client_ip::client_ip;
+use crate::permissions::{self, parse_network};
+
+#[derive(Serialize, ToSchema)]
+struct BlockedIpsRes {
+    blocked: Vec<BlockedIp>,
+}
+
+/// List of blocked IP addresses and networks
+///
+/// Addresses are blocked automatically after persistent brute-forcing or manually through
+/// `/private/security/blockedIps/block`.
+#[utoipa::path(
+    get,
+    path = "/private/security/blockedIps",
+    responses((status = 200, body = BlockedIpsRes)),
+    tags = ["private", "security"]
+)]
+pub async fn blocked_ips(state: Data<AppState>) -> HttpResponse {
+    use utils::schema::BlockedIPs::dsl::*;
+
+    let exec = BlockedIPs
+        .select(BlockedIp::as_select())
+        .order(since.desc())
+        .get_results(&mut state.db_pool.lock().unwrap().get().unwrap());
+
+    match exec {
+        Ok(blocked) => HttpResponse::Ok().json(BlockedIpsRes { blocked }),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Either a single IP address (e.g. `192.168.1.10`) or a network in CIDR notation (e.g.
+/// `10.0.0.0/8`).
+#[derive(Deserialize, ToSchema)]
+pub struct NetworkReq {
+    network: String,
+}
+
+/// Block an IP address or network
+///
+/// Networks that contain the address of the requesting peer or overlap with a trusted network are
+/// rejected to prevent locking out the administrator.
+#[utoipa::path(
+    post,
+    path = "/private/security/blockedIps/block",
+    request_body = NetworkReq,
+    responses(
+        (status = 200, description = "The network has been blocked."),
+        (status = 400, description = "The network is malformed."),
+        (status = 409, description = "The network contains the requesting peer or a trusted network.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn block(
+    json: Json<NetworkReq>,
+    state: Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(network) = parse_network(&json.network) else {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidIpNetwork.as_error_message());
+    };
+
+    if network.contains(&client_ip(&req, &state)) {
+        warn!("Refusing to block {network}, as it contains the requesting peer.");
+        return HttpResponse::Conflict().json(ErrorCode::WouldBlockRequester.as_error_message());
+    }
+
+    let overlaps_trusted = state
+        .trusted_networks
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|t| t.contains(&network) || network.contains(t));
+
+    if overlaps_trusted {
+        return HttpResponse::Conflict().json(ErrorCode::TrustedNetworkConflict.as_error_message());
+    }
+
+    match permissions::block_network(&state, network) {
+        Ok(_) => {
+            info!("Manually blocked {network}.");
+            HttpResponse::Ok().json(MessageRes::from(format!("{network} has been blocked.")))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseInsertFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Unblock an IP address or network
+///
+/// The network has to match a blocked entry exactly.
+#[utoipa::path(
+    post,
+    path = "/private/security/blockedIps/unblock",
+    request_body = NetworkReq,
+    responses(
+        (status = 200, description = "The network has been unblocked."),
+        (status = 400, description = "The network is malformed."),
+        (status = 404, description = "The network is not blocked.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn unblock(json: Json<NetworkReq>, state: Data<AppState>) -> HttpResponse {
+    let Some(network) = parse_network(&json.network) else {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidIpNetwork.as_error_message());
+    };
+
+    match permissions::unblock_network(&state, network) {
+        Ok(0) => HttpResponse::NotFound().json(ErrorCode::NoSuchBlockedNetwork.as_error_message()),
+        Ok(_) => {
+            info!("Unblocked {network}.");
+            HttpResponse::Ok().json(MessageRes::from(format!("{network} has been unblocked.")))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseDeletionFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct TrustedNetworksRes {
+    networks: Vec<TrustedNetwork>,
+}
+
+/// List of trusted networks
+///
+/// Peers in a trusted network are never blocked, neither automatically nor manually.
+#[utoipa::path(
+    get,
+    path = "/private/security/trustedNetworks",
+    responses((status = 200, body = TrustedNetworksRes)),
+    tags = ["private", "security"]
+)]
+pub async fn trusted_networks(state: Data<AppState>) -> HttpResponse {
+    use utils::schema::TrustedNetworks::dsl::*;
+
+    let exec = TrustedNetworks
+        .select(TrustedNetwork::as_select())
+        .get_results(&mut state.db_pool.lock().unwrap().get().unwrap());
+
+    match exec {
+        Ok(networks) => HttpResponse::Ok().json(TrustedNetworksRes { networks }),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Add a trusted network
+#[utoipa::path(
+    post,
+    path = "/private/security/trustedNetworks/add",
+    request_body = NetworkReq,
+    responses(
+        (status = 200, description = "The network is now trusted."),
+        (status = 400, description = "The network is malformed.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn trust(json: Json<NetworkReq>, state: Data<AppState>) -> HttpResponse {
+    let Some(network) = parse_network(&json.network) else {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidIpNetwork.as_error_message());
+    };
+
+    match permissions::trust_network(&state, network) {
+        Ok(_) => {
+            info!("Added {network} to the trusted networks.");
+            HttpResponse::Ok().json(MessageRes::from(format!("{network} is now trusted.")))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseInsertFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Remove a trusted network
+#[utoipa::path(
+    post,
+    path = "/private/security/trustedNetworks/remove",
+    request_body = NetworkReq,
+    responses(
+        (status = 200, description = "The network is no longer trusted."),
+        (status = 400, description = "The network is malformed."),
+        (status = 404, description = "The network is not trusted.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn distrust(json: Json<NetworkReq>, state: Data<AppState>) -> HttpResponse {
+    let Some(network) = parse_network(&json.network) else {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidIpNetwork.as_error_message());
+    };
+
+    match permissions::distrust_network(&state, network) {
+        Ok(0) => HttpResponse::NotFound().json(ErrorCode::NoSuchTrustedNetwork.as_error_message()),
+        Ok(_) => {
+            info!("Removed {network} from the trusted networks.");
+            HttpResponse::Ok().json(MessageRes::from(format!("{network} is no longer trusted.")))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseDeletionFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Brute-force detection thresholds
+#[utoipa::path(
+    get,
+    path = "/private/security/limits",
+    responses((status = 200, body = LoginLimits)),
+    tags = ["private", "security"]
+)]
+pub async fn limits(state: Data<AppState>) -> HttpResponse {
+    let current = *state.login_limits.lock().unwrap();
+    HttpResponse::Ok().json(current)
+}
+
+/// Update brute-force detection thresholds
+///
+/// Time windows are expressed in seconds. All values have to be positive and the base window and
+/// limit may not exceed the large window and limit.
+#[utoipa::path(
+    post,
+    path = "/private/security/limits",
+    request_body = LoginLimits,
+    responses(
+        (status = 200, description = "The limits have been updated."),
+        (status = 400, description = "The limits are invalid.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn update_limits(json: Json<LoginLimits>, state: Data<AppState>) -> HttpResponse {
+    use utils::schema::Configuration::dsl::*;
+
+    let new_limits = json.into_inner();
+
+    if !new_limits.is_valid() {
+        return HttpResponse::BadRequest().json(ErrorCode::InvalidLoginLimits.as_error_message());
+    }
+
+    let exec = diesel::update(Configuration)
+        .set(&new_limits)
+        .execute(&mut state.db_pool.lock().unwrap().get().unwrap());
+
+    if let Err(e) = exec {
+        return HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseUpdateFailed(e.to_string()).as_error_message());
+    }
+
+    *state.login_limits.lock().unwrap() = new_limits;
+
+    HttpResponse::Ok().json(MessageRes::from("The login limits have been updated."))
+}
+
+#[derive(Serialize, ToSchema)]
+struct ClientCertificatesRes {
+    certificates: Vec<ClientCertificate>,
+}
+
+/// List of issued client certificates
+///
+/// Revoked and expired certificates are included.
+#[utoipa::path(
+    get,
+    path = "/private/security/clientCertificates",
+    responses((status = 200, body = ClientCertificatesRes)),
+    tags = ["private", "security"]
+)]
+pub async fn client_certificates(state: Data<AppState>) -> HttpResponse {
+    use utils::schema::ClientCertificates::dsl::*;
+
+    let exec = ClientCertificates
+        .select(ClientCertificate::as_select())
+        .order(issued_at.desc())
+        .get_results(&mut state.db_pool.lock().unwrap().get().unwrap());
+
+    match exec {
+        Ok(certificates) => HttpResponse::Ok().json(ClientCertificatesRes { certificates }),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Certificate of the client certificate authority
+///
+/// The certificate is returned in PEM format. It is created on first use.
+#[utoipa::path(
+    get,
+    path = "/private/security/clientCertificates/ca",
+    responses((status = 200, content_type = "application/x-pem-file")),
+    tags = ["private", "security"]
+)]
+pub async fn client_ca() -> HttpResponse {
+    match client_certificates::ca_certificate() {
+        Ok(pem) => HttpResponse::Ok()
+            .content_type("application/x-pem-file")
+            .body(pem),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::ClientCertificateIssuingFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IssueClientCertificateReq {
+    /// Name of the device or person the certificate is used by
+    name: String,
+    /// Account the certificate belongs to
+    username: String,
+    /// Defaults to 365 days
+    valid_days: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct IssuedClientCertificateRes {
+    /// Certificate in PEM format
+    certificate: String,
+    /// Private key in PEM format, which is not stored by Zentrox
+    key: String,
+    details: ClientCertificate,
+}
+
+/// Issue a client certificate
+///
+/// The private key is only returned once and has to be kept by the client.
+#[utoipa::path(
+    post,
+    path = "/private/security/clientCertificates/issue",
+    request_body = IssueClientCertificateReq,
+    responses(
+        (status = 200, body = IssuedClientCertificateRes),
+        (status = 400, description = "The name is empty or the validity is out of range."),
+        (status = 404, description = "The account does not exist.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn issue_client_certificate(
+    json: Json<IssueClientCertificateReq>,
+    state: Data<AppState>,
+) -> HttpResponse {
+    use utils::schema::Users::dsl::*;
+
+    let valid_days = json.valid_days.unwrap_or(365);
+    if json.name.trim().is_empty() || !(1..=3650).contains(&valid_days) {
+        return HttpResponse::BadRequest().json(
+            ErrorCode::ClientCertificateIssuingFailed(
+                "The name may not be empty and the validity has to be between 1 and 3650 days."
+                    .to_string(),
+            )
+            .as_error_message(),
+        );
+    }
+
+    let account_exists = Users
+        .select(username)
+        .filter(username.eq(&json.username))
+        .first::<String>(&mut state.db_pool.lock().unwrap().get().unwrap())
+        .is_ok();
+
+    if !account_exists {
+        return HttpResponse::NotFound().json(ErrorCode::UnkownUsername.as_error_message());
+    }
+
+    match client_certificates::issue(&state, json.name.trim(), &json.username, valid_days) {
+        Ok(issued) => {
+            info!(
+                "Issued client certificate {} for {}.",
+                issued.record.serial, issued.record.username
+            );
+            HttpResponse::Ok().json(IssuedClientCertificateRes {
+                certificate: issued.certificate,
+                key: issued.key,
+                details: issued.record,
+            })
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::ClientCertificateIssuingFailed(e.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RevokeClientCertificateReq {
+    serial: String,
+}
+
+/// Revoke a client certificate
+///
+/// New connections using the certificate are rejected immediately. Existing connections can no
+/// longer be used to log in.
+#[utoipa::path(
+    post,
+    path = "/private/security/clientCertificates/revoke",
+    request_body = RevokeClientCertificateReq,
+    responses(
+        (status = 200, description = "The certificate has been revoked."),
+        (status = 404, description = "No unrevoked certificate with this serial number exists.")
+    ),
+    tags = ["private", "security"]
+)]
+pub async fn revoke_client_certificate(
+    json: Json<RevokeClientCertificateReq>,
+    state: Data<AppState>,
+) -> HttpResponse {
+    match client_certificates::revoke(&state, &json.serial) {
+        Ok(0) => {
+            HttpResponse::NotFound().json(ErrorCode::NoSuchClientCertificate.as_error_message())
+        }
+        Ok(_) => {
+            info!("Revoked client certificate {}.", json.serial);
+            HttpResponse::Ok().json(MessageRes::from(format!(
+                "The certificate {} has been revoked.",
+                json.serial
+            )))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseUpdateFailed(e.to_string()).as_error_message()),
+    }
+}
@@ -0,0 +1,446 @@
+use actix_web::HttpResponse;
+use actix_web::web::{Data, Json};
+use log::error;
+use serde::{Deserialize, Serialize};
+use utils::status_com::{ErrorCode, MessageRes};
+use utils::storage::{
+    self, Pool, ScrubStatus, Snapshot, SnapshotSchedule, StorageError, StorageKind, Volume,
+};
+use utils::sudo::{SudoCredential, SudoError};
+use utoipa::ToSchema;
+
+use crate::{AppState, snapshot_schedule};
+
+fn storage_error(error: StorageError) -> HttpResponse {
+    match error {
+        StorageError::Sudo(SudoError::WrongPassword | SudoError::PasswordRequired) => {
+            HttpResponse::Unauthorized().json(ErrorCode::BadSudoPassword.as_error_message())
+        }
+        StorageError::Invalid(message) => HttpResponse::BadRequest()
+            .json(ErrorCode::InvalidStorageChange(message).as_error_message()),
+        e => HttpResponse::InternalServerError()
+            .json(ErrorCode::StorageFailed(e.to_string()).as_error_message()),
+    }
+}
+
+/// Run a storage operation off the async executor and respond with its result.
+async fn run_storage<T, F>(operation: F) -> HttpResponse
+where
+    T: Serialize + Send + 'static,
+    F: FnOnce() -> Result<T, StorageError> + Send + 'static,
+{
+    let Ok(result) = actix_web::web::block(operation).await else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    match result {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageListReq {
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StorageListRes {
+    pools: Vec<Pool>,
+    volumes: Vec<Volume>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/list",
+    request_body = StorageListReq,
+    responses(
+        (status = 200, body = StorageListRes),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Btrfs and ZFS storage
+///
+/// Lists the mounted btrfs filesystems with their subvolumes and the ZFS pools with their
+/// datasets.
+pub async fn list(json: Json<StorageListReq>) -> HttpResponse {
+    let credential = SudoCredential::from(json.into_inner().sudo_password);
+    run_storage(move || {
+        Ok(StorageListRes {
+            pools: storage::pools(&credential)?,
+            volumes: storage::volumes(&credential)?,
+        })
+    })
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotsReq {
+    kind: StorageKind,
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    volume: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SnapshotsRes {
+    snapshots: Vec<Snapshot>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/snapshots",
+    request_body = SnapshotsReq,
+    responses(
+        (status = 200, body = SnapshotsRes),
+        (status = 400, description = "The volume is malformed."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Snapshots of a volume
+///
+/// Lists the snapshots of a btrfs subvolume or ZFS dataset, oldest first.
+pub async fn snapshots(json: Json<SnapshotsReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || {
+        Ok(SnapshotsRes {
+            snapshots: storage::snapshots(json.kind, &json.volume, &credential)?,
+        })
+    })
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotReq {
+    kind: StorageKind,
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    volume: String,
+    /// Name of the snapshot, like `before-upgrade`
+    name: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/snapshots/new",
+    request_body = SnapshotReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The volume or snapshot name is malformed."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Create snapshot
+///
+/// Snapshots of btrfs subvolumes are read-only subvolumes in the `.snapshots` directory of the
+/// subvolume.
+pub async fn new_snapshot(json: Json<SnapshotReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || {
+        storage::create_snapshot(json.kind, &json.volume, &json.name, &credential)?;
+        Ok(MessageRes::from("The snapshot has been created."))
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/snapshots/delete",
+    request_body = SnapshotReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The volume or snapshot name is malformed."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Delete snapshot
+pub async fn delete_snapshot(json: Json<SnapshotReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || {
+        storage::delete_snapshot(json.kind, &json.volume, &json.name, &credential)?;
+        Ok(MessageRes::from("The snapshot has been deleted."))
+    })
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackReq {
+    kind: StorageKind,
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    volume: String,
+    /// Name of the snapshot
+    name: String,
+    /// Destroy the snapshots of a ZFS dataset that are newer than the snapshot
+    #[serde(default)]
+    discard_newer: bool,
+    /// Has to be the volume
+    confirmation: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/snapshots/rollback",
+    request_body = RollbackReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The confirmation is not the volume, the volume is malformed or it can not be replaced."),
+        (status = 401, description = "The sudo password was wrong or is missing."),
+        (status = 500, description = "Rolling back failed, e.g. because newer ZFS snapshots exist.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Roll back to snapshot
+///
+/// Returns the volume to the state of the snapshot. All changes made since are lost. A btrfs
+/// subvolume that is mounted on its own can not be rolled back.
+pub async fn rollback(json: Json<RollbackReq>) -> HttpResponse {
+    let json = json.into_inner();
+    if json.confirmation != json.volume {
+        return HttpResponse::BadRequest().json(ErrorCode::ConfirmationMismatch.as_error_message());
+    }
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || {
+        storage::rollback(
+            json.kind,
+            &json.volume,
+            &json.name,
+            json.discard_newer,
+            &credential,
+        )?;
+        Ok(MessageRes::from("The volume has been rolled back."))
+    })
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrubReq {
+    kind: StorageKind,
+    /// The mountpoint of a btrfs filesystem or the name of a ZFS pool
+    pool: String,
+    /// Missing if sudo does not require a password
+    sudo_password: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/scrub",
+    request_body = ScrubReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "The pool is malformed."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Start scrub
+///
+/// Verifies the checksums of all data in the pool in the background. Its progress and result are
+/// reported by the scrub status.
+pub async fn scrub(json: Json<ScrubReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || {
+        storage::start_scrub(json.kind, &json.pool, &credential)?;
+        Ok(MessageRes::from("The scrub has been started."))
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/scrub/status",
+    request_body = ScrubReq,
+    responses(
+        (status = 200, body = ScrubStatus),
+        (status = 400, description = "The pool is malformed."),
+        (status = 401, description = "The sudo password was wrong or is missing.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Scrub status
+///
+/// Progress of a running scrub or the result of the last one.
+pub async fn scrub_status(json: Json<ScrubReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let credential = SudoCredential::from(json.sudo_password);
+    run_storage(move || storage::scrub_status(json.kind, &json.pool, &credential)).await
+}
+
+#[derive(Serialize, ToSchema)]
+struct SnapshotSchedulesRes {
+    schedules: Vec<SnapshotSchedule>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/private/storage/schedules",
+    responses((status = 200, body = SnapshotSchedulesRes)),
+    tags = ["private", "storage"]
+)]
+/// Snapshot schedules
+pub async fn schedules(state: Data<AppState>) -> HttpResponse {
+    match snapshot_schedule::list(&state) {
+        Ok(schedules) => HttpResponse::Ok().json(SnapshotSchedulesRes { schedules }),
+        Err(database_error) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleReq {
+    #[serde(flatten)]
+    schedule: SnapshotSchedule,
+    /// Used to update the sudoers rule of the schedules, it is not stored
+    sudo_password: String,
+}
+
+/// Update the sudoers rule to allow the snapshots of `schedules` to be taken without a password.
+async fn authorize_schedules(
+    schedules: Vec<SnapshotSchedule>,
+    sudo_password: String,
+) -> Option<HttpResponse> {
+    let Ok(authorized) =
+        actix_web::web::block(move || storage::authorize_schedules(&schedules, &sudo_password))
+            .await
+    else {
+        return Some(
+            HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message()),
+        );
+    };
+    match authorized {
+        Ok(_) => None,
+        Err(e) => {
+            error!("Updating the sudoers rule of the snapshot schedules failed with error: {e}");
+            Some(storage_error(e))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/schedules/new",
+    request_body = ScheduleReq,
+    responses(
+        (status = 200),
+        (status = 400, description = "No snapshots are kept or the volume is malformed."),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Set snapshot schedule
+///
+/// Takes hourly, daily and weekly snapshots of a volume and keeps the configured number of each.
+/// Replaces the existing schedule of the volume. Snapshots are taken without a sudo password, so
+/// the sudo password is used to install a sudoers rule that only allows the commands of the
+/// schedules.
+pub async fn new_schedule(state: Data<AppState>, json: Json<ScheduleReq>) -> HttpResponse {
+    let ScheduleReq {
+        schedule,
+        sudo_password,
+    } = json.into_inner();
+    if schedule.retention == storage::Retention::default() {
+        return HttpResponse::BadRequest().json(
+            ErrorCode::InvalidStorageChange(
+                "The schedule has to keep at least one snapshot.".to_string(),
+            )
+            .as_error_message(),
+        );
+    }
+
+    let (kind, volume) = (schedule.kind, schedule.volume.clone());
+    let credential = SudoCredential::from(sudo_password.clone());
+    let Ok(checked) =
+        actix_web::web::block(move || storage::snapshots(kind, &volume, &credential)).await
+    else {
+        return HttpResponse::InternalServerError().json(ErrorCode::TaskFailed.as_error_message());
+    };
+    if let Err(e) = checked {
+        error!("Checking the volume of a snapshot schedule failed with error: {e}");
+        return storage_error(e);
+    }
+
+    let mut schedules = match snapshot_schedule::list(&state) {
+        Ok(schedules) => schedules,
+        Err(database_error) => {
+            return HttpResponse::InternalServerError().json(
+                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
+            );
+        }
+    };
+    schedules.retain(|s| s.kind != schedule.kind || s.volume != schedule.volume);
+    schedules.push(schedule.clone());
+    if let Some(response) = authorize_schedules(schedules, sudo_password).await {
+        return response;
+    }
+
+    match snapshot_schedule::store(&state, &schedule) {
+        Ok(_) => HttpResponse::Ok().json(MessageRes::from("The snapshot schedule has been saved.")),
+        Err(database_error) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseUpdateFailed(database_error.to_string()).as_error_message()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteScheduleReq {
+    kind: StorageKind,
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    volume: String,
+    /// Used to update the sudoers rule of the schedules, it is not stored
+    sudo_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/private/storage/schedules/delete",
+    request_body = DeleteScheduleReq,
+    responses(
+        (status = 200),
+        (status = 401, description = "The sudo password was wrong.")
+    ),
+    tags = ["private", "storage"]
+)]
+/// Delete snapshot schedule
+///
+/// The snapshots taken by the schedule are kept. The commands of the schedule are removed from
+/// the sudoers rule of the schedules.
+pub async fn delete_schedule(state: Data<AppState>, json: Json<DeleteScheduleReq>) -> HttpResponse {
+    let json = json.into_inner();
+    let mut schedules = match snapshot_schedule::list(&state) {
+        Ok(schedules) => schedules,
+        Err(database_error) => {
+            return HttpResponse::InternalServerError().json(
+                ErrorCode::DatabaseReadFailed(database_error.to_string()).as_error_message(),
+            );
+        }
+    };
+    schedules.retain(|s| s.kind != json.kind || s.volume != json.volume);
+    if let Some(response) = authorize_schedules(schedules, json.sudo_password).await {
+        return response;
+    }
+
+    match snapshot_schedule::delete(&state, json.kind, &json.volume) {
+        Ok(_) => {
+            HttpResponse::Ok().json(MessageRes::from("The snapshot schedule has been deleted."))
+        }
+        Err(database_error) => HttpResponse::InternalServerError()
+            .json(ErrorCode::DatabaseUpdateFailed(database_error.to_string()).as_error_message()),
+    }
+}
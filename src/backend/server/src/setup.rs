@@ -2,7 +2,6 @@ use argon2::password_hash::SaltString;
 use diesel::RunQueryDsl;
 use dirs::{self, home_dir};
 use rand::rngs::OsRng;
-use rcgen::{CertifiedKey, generate_simple_self_signed};
 use rpassword::prompt_password;
 use std::fs;
 use std::io::{self, BufRead, Write};
@@ -12,6 +11,8 @@ use utils::database::{self, establish_direct_connection};
 use utils::otp::{derive_otp_url, generate_otp_secret};
 use utils::sudo::SudoCommand;
 
+use crate::certificates::{self, SELF_SIGNED_CERTIFICATE_NAME, SELF_SIGNED_KEY_NAME};
+
 fn flush() {
     let _ = io::stdout().flush();
 }
@@ -31,13 +32,7 @@ fn prompt(msg: &str) -> String {
     read().replace("\n", "")
 }
 
-fn hostname() -> Option<String> {
-    let r = fs::read_to_string("/etc/hostname");
-
-    r.ok()
-}
-
-pub fn run_setup() -> Result<(), String> {
+pub fn run_setup(port: u16) -> Result<(), String> {
     // NOTE Prettier TUI would be reasonable
 
     use utils::models::Account;
@@ -52,11 +47,7 @@ pub fn run_setup() -> Result<(), String> {
         .join(".local")
         .join("bin")
         .join("zentrox");
-    let data_path = home_dir()
-        .unwrap()
-        .join(".local")
-        .join("share")
-        .join("zentrox");
+    let data_path = database::data_directory();
 
     let _ = fs::create_dir_all(&data_path);
 
@@ -88,8 +79,9 @@ pub fn run_setup() -> Result<(), String> {
             media_enabled: false,
             vault_enabled: false,
             server_name: servername,
-            tls_cert: "selfsigned.pem".to_string(),
+            tls_cert: SELF_SIGNED_CERTIFICATE_NAME.to_string(),
             id: 0,
+            tls_key: Some(SELF_SIGNED_KEY_NAME.to_string()),
         })
         .execute(connection)
     {
@@ -147,34 +139,42 @@ pub fn run_setup() -> Result<(), String> {
         eprintln!("Datebase insertion failed with error: {e}");
     }
 
-    let subject_alt_names = vec![
-        "localhost".to_string(),
-        hostname().unwrap_or("localhost".to_string()),
-    ];
-
     println!("Generating SSL/TLS certificate");
 
-    let CertifiedKey { cert, key_pair } = generate_simple_self_signed(subject_alt_names).unwrap();
-
-    let _ = fs::create_dir_all(data_path.join("certificates"));
-    let _ = fs::write(
-        data_path.join("certificates").join("selfsigned.pem"),
-        format!("{}{}", key_pair.serialize_pem(), cert.pem()),
-    );
+    let (certificate_pem, key_pem) =
+        certificates::generate_self_signed(certificates::default_subject_alt_names(), 3650)
+            .unwrap();
+
+    if let Err(e) = certificates::store(
+        SELF_SIGNED_CERTIFICATE_NAME,
+        SELF_SIGNED_KEY_NAME,
+        certificate_pem.as_bytes(),
+        key_pem.as_bytes(),
+    ) {
+        eprintln!("Storing the certificate failed with error: {e}");
+    }
 
     println!("System settings");
-    let allow_8080 =
-        { prompt("Add UFW rule to allow port 8080 for Zentrox [y/n]: ").to_lowercase() == "y" };
-    if allow_8080 {
-        let ip_addr = prompt("Only allow port 8080 for specific IP [enter ip/leave empty]: ");
+    let allow_port = {
+        prompt(&format!(
+            "Add UFW rule to allow port {port} for Zentrox [y/n]: "
+        ))
+        .to_lowercase()
+            == "y"
+    };
+    if allow_port {
+        let ip_addr = prompt(&format!(
+            "Only allow port {port} for specific IP [enter ip/leave empty]: "
+        ));
+        let port = port.to_string();
         let sudo_password =
             rpassword::prompt_password("Please enter your sudo password to run UFW: ");
         let ufw_command =
             SudoCommand::new(sudo_password.unwrap().to_string(), "/sbin/ufw".to_string())
                 .args(if ip_addr.is_empty() {
-                    vec!["allow", "8080"]
+                    vec!["allow", &port]
                 } else {
-                    vec!["allow", "from", &ip_addr, "to", "8080"]
+                    vec!["allow", "from", &ip_addr, "to", "any", "port", &port]
                 })
                 .output();
 
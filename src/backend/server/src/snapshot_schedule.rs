@@ -0,0 +1,112 @@
+//! Scheduled snapshots of btrfs subvolumes and ZFS datasets.
+//!
+//! The schedules are stored in the `SnapshotSchedules` table. A background task regularly takes
+//! the snapshots that are due and deletes the ones that are no longer kept, following the
+//! retention of each schedule. Commands are run through sudo without a password, which the
+//! sudoers rule installed by [utils::storage::authorize_schedules] allows.
+
+use chrono::Local;
+use diesel::prelude::*;
+use log::{info, warn};
+use utils::models::SnapshotScheduleEntry;
+use utils::storage::{self, SnapshotSchedule};
+use utils::sudo::SudoCredential;
+
+use crate::AppState;
+
+pub fn list(state: &AppState) -> Result<Vec<SnapshotSchedule>, diesel::result::Error> {
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    Ok(utils::schema::SnapshotSchedules::table
+        .select(SnapshotScheduleEntry::as_select())
+        .load(connection)?
+        .into_iter()
+        .filter_map(|e| SnapshotSchedule::try_from(e).ok())
+        .collect())
+}
+
+/// Add a schedule or replace the schedule of the same volume.
+pub fn store(state: &AppState, schedule: &SnapshotSchedule) -> Result<(), diesel::result::Error> {
+    use utils::schema::SnapshotSchedules::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+    let entry = SnapshotScheduleEntry::from(schedule);
+
+    diesel::insert_into(SnapshotSchedules)
+        .values(&entry)
+        .on_conflict((kind, volume))
+        .do_update()
+        .set(&entry)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Remove the schedule of a volume. Its snapshots are kept.
+pub fn delete(
+    state: &AppState,
+    schedule_kind: storage::StorageKind,
+    schedule_volume: &str,
+) -> Result<(), diesel::result::Error> {
+    use utils::schema::SnapshotSchedules::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    diesel::delete(
+        SnapshotSchedules
+            .filter(kind.eq(schedule_kind.to_string()))
+            .filter(volume.eq(schedule_volume)),
+    )
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Take the snapshots that are due and delete the ones no longer kept for every schedule.
+///
+/// Snapshots are only deleted once all new snapshots of the schedule were created. A failing
+/// schedule is logged and does not keep the other schedules from running.
+pub fn run_due(state: &AppState) -> Result<(), diesel::result::Error> {
+    let credential = SudoCredential::Passwordless;
+    for schedule in list(state)? {
+        let existing = match storage::snapshots(schedule.kind, &schedule.volume, &credential) {
+            Ok(snapshots) => snapshots
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<String>>(),
+            Err(e) => {
+                warn!("Listing the snapshots of {} failed: {e}", schedule.volume);
+                continue;
+            }
+        };
+        let plan = storage::plan(&schedule.retention, &existing, &Local::now());
+
+        let mut created_all = true;
+        for name in plan.create {
+            match storage::create_snapshot(schedule.kind, &schedule.volume, &name, &credential) {
+                Ok(_) => info!("Created the snapshot {name} of {}", schedule.volume),
+                Err(e) => {
+                    warn!(
+                        "Creating the snapshot {name} of {} failed: {e}",
+                        schedule.volume
+                    );
+                    created_all = false;
+                }
+            }
+        }
+        // The plan keeps fewer existing snapshots as it counts the new ones
+        if !created_all {
+            warn!(
+                "No snapshots of {} are deleted, as not all new snapshots were created",
+                schedule.volume
+            );
+            continue;
+        }
+        for name in plan.delete {
+            match storage::delete_snapshot(schedule.kind, &schedule.volume, &name, &credential) {
+                Ok(_) => info!("Deleted the snapshot {name} of {}", schedule.volume),
+                Err(e) => warn!(
+                    "Deleting the snapshot {name} of {} failed: {e}",
+                    schedule.volume
+                ),
+            }
+        }
+    }
+    Ok(())
+}
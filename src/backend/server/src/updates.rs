@@ -0,0 +1,355 @@
+//! Unattended package updates, which are installed in the maintenance window of the update
+//! policy or on request.
+//!
+//! A run refreshes the package databases, installs security or all updates, checks whether a
+//! reboot is required and reboots if the policy allows it. The hooks configured in `[updates]` are
+//! run before and after the updates. Package managers are run through sudo without a password,
+//! which requires the sudoers rule described in [`utils::packages::unattended`].
+//!
+//! Every run is recorded in the `UpdateRuns` table.
+
+use diesel::prelude::*;
+use log::{info, warn};
+use std::fmt::Display;
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+use utils::models::{UpdatePolicyEntry, UpdateRun};
+use utils::packages::unattended::{self, UpdateKind, UpdatePolicy};
+use utils::packages::{self, PackageBackend};
+use utils::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+use utils::time::current_timestamp_secs;
+use uuid::Uuid;
+
+use crate::config::UpdatesConfig;
+use crate::{AppState, package_index};
+
+/// Output of a single command is truncated to its last bytes to keep the history small.
+const OUTPUT_LIMIT: usize = 16 * 1024;
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("Updates are already being installed.")]
+    InProgress,
+    #[error("The update history could not be accessed: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// What started a run
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    /// The maintenance window opened
+    Scheduled,
+    /// A user requested the run
+    Manual,
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Scheduled => f.write_str("scheduled"),
+            Trigger::Manual => f.write_str("manual"),
+        }
+    }
+}
+
+/// The stored update policy or the default policy, which is disabled.
+pub fn load_policy(state: &AppState) -> Result<UpdatePolicy, diesel::result::Error> {
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    Ok(utils::schema::UpdatePolicy::table
+        .select(UpdatePolicyEntry::as_select())
+        .first(connection)
+        .optional()?
+        .map(UpdatePolicy::from)
+        .unwrap_or_default())
+}
+
+pub fn store_policy(state: &AppState, policy: &UpdatePolicy) -> Result<(), diesel::result::Error> {
+    use utils::schema::UpdatePolicy::dsl::id;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+    let entry = UpdatePolicyEntry::from(policy);
+
+    diesel::insert_into(utils::schema::UpdatePolicy::table)
+        .values(&entry)
+        .on_conflict(id)
+        .do_update()
+        .set(&entry)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// The most recent runs, starting with the latest.
+pub fn history(state: &AppState, limit: i64) -> Result<Vec<UpdateRun>, diesel::result::Error> {
+    use utils::schema::UpdateRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    UpdateRuns
+        .select(UpdateRun::as_select())
+        .order(started_at.desc())
+        .limit(limit)
+        .load(connection)
+}
+
+fn save_run(state: &AppState, run: &UpdateRun) -> Result<(), diesel::result::Error> {
+    use utils::schema::UpdateRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    diesel::insert_into(UpdateRuns)
+        .values(run)
+        .on_conflict(id)
+        .do_update()
+        .set(run)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Mark runs that were interrupted, e.g. by Zentrox being stopped, as failed.
+/// They are considered finished now, as the time they were interrupted at is not known.
+pub fn finish_interrupted(state: &AppState) -> Result<(), diesel::result::Error> {
+    use utils::schema::UpdateRuns::dsl::*;
+    let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+
+    diesel::update(UpdateRuns.filter(status.eq("running")))
+        .set((
+            status.eq("failed"),
+            finished_at.eq(current_timestamp_secs()),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Start a scheduled run if the maintenance window is open and no scheduled run has been started
+/// in it yet.
+pub fn run_if_due(state: &AppState) -> Result<(), UpdateError> {
+    let policy = load_policy(state)?;
+
+    let last_run = {
+        use utils::schema::UpdateRuns::dsl::*;
+        let connection = &mut state.db_pool.lock().unwrap().get().unwrap();
+        UpdateRuns
+            .filter(triggered_by.eq(Trigger::Scheduled.to_string()))
+            .select(diesel::dsl::max(started_at))
+            .first::<Option<i64>>(connection)?
+    };
+
+    if !policy.is_due(last_run) {
+        return Ok(());
+    }
+
+    match run(state, &policy, Trigger::Scheduled) {
+        // A manual run is installing the updates already
+        Err(UpdateError::InProgress) => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
+
+/// Install updates according to `policy` and record the run.
+///
+/// The system is only rebooted if the policy allows it, a reboot is required and the
+/// maintenance window is still open.
+pub fn run(
+    state: &AppState,
+    policy: &UpdatePolicy,
+    trigger: Trigger,
+) -> Result<UpdateRun, UpdateError> {
+    let Ok(_running) = state.update_lock.try_lock() else {
+        return Err(UpdateError::InProgress);
+    };
+
+    info!("Installing {} updates ({trigger})", policy.kind);
+    let mut run = UpdateRun {
+        id: Uuid::new_v4().to_string(),
+        started_at: current_timestamp_secs(),
+        finished_at: None,
+        triggered_by: trigger.to_string(),
+        kind: policy.kind.to_string(),
+        status: "running".to_string(),
+        reboot_required: None,
+        rebooted: false,
+        output: String::new(),
+    };
+    save_run(state, &run)?;
+
+    let hooks = &state.config.updates;
+    let mut success = install(hooks, policy.kind, &mut run.output);
+    package_index::refresh_after_change(state);
+    run.reboot_required = unattended::reboot_required();
+
+    if let Some(hook) = &hooks.post_hook {
+        let result = if success { "success" } else { "failed" };
+        success &= run_hook(hook, Some(result), &mut run.output);
+    }
+
+    run.status = if success { "success" } else { "failed" }.to_string();
+    run.finished_at = Some(current_timestamp_secs());
+
+    let reboot =
+        success && policy.reboot && run.reboot_required == Some(true) && policy.in_window();
+    if !reboot {
+        save_run(state, &run)?;
+        return Ok(run);
+    }
+
+    // The run is recorded before rebooting, as Zentrox may be stopped at any time afterwards
+    run.rebooted = true;
+    save_run(state, &run)?;
+    info!("Rebooting to complete the updates");
+    if !record(
+        &mut run.output,
+        "Rebooting",
+        SudoCommand::with_credential(SudoCredential::Passwordless, "reboot").output(),
+    ) {
+        warn!("Rebooting after updates failed");
+        run.rebooted = false;
+        save_run(state, &run)?;
+    }
+    Ok(run)
+}
+
+/// Run the pre-update hook and install the updates. Returns whether every step succeeded.
+fn install(hooks: &UpdatesConfig, kind: UpdateKind, log: &mut String) -> bool {
+    if let Some(hook) = &hooks.pre_hook
+        && !run_hook(hook, None, log)
+    {
+        log.push_str("The pre-update hook failed, no updates were installed.\n");
+        return false;
+    }
+
+    // Flatpak and Snap do not publish security advisories
+    let backends: Vec<Box<dyn PackageBackend>> = match kind {
+        UpdateKind::All => packages::backends(),
+        UpdateKind::Security => packages::primary_backend().into_iter().collect(),
+    };
+    if backends.is_empty() {
+        log.push_str("No supported package manager was found.\n");
+        return false;
+    }
+
+    let mut success = true;
+    for backend in backends {
+        let manager = backend.package_manager();
+        if kind == UpdateKind::Security && !backend.supports_security_updates() {
+            log.push_str(&format!(
+                "{manager} can not tell security updates apart from other updates.\n"
+            ));
+            success = false;
+            continue;
+        }
+
+        success &= record(
+            log,
+            &format!("Updating the {manager} database"),
+            backend.update_database(SudoCredential::Passwordless),
+        ) && record(
+            log,
+            &format!("Installing {kind} updates using {manager}"),
+            match kind {
+                UpdateKind::All => backend.update_all(SudoCredential::Passwordless),
+                UpdateKind::Security => backend.update_security(SudoCredential::Passwordless),
+            },
+        );
+    }
+    success
+}
+
+/// Append the output of a step to the log and return whether it succeeded.
+pub fn record(log: &mut String, step: &str, result: Result<SudoOutput, SudoError>) -> bool {
+    log.push_str(&format!("== {step}\n"));
+    match result {
+        Ok(output) => {
+            append(log, &output.stdout);
+            append(log, &output.stderr);
+            if output.status != Some(0) {
+                log.push_str(&format!("Exited with status {:?}\n", output.status));
+            }
+            output.status == Some(0)
+        }
+        Err(SudoError::PasswordRequired) => {
+            log.push_str(
+                "sudo requires a password, unattended updates have not been authorized.\n",
+            );
+            false
+        }
+        Err(e) => {
+            log.push_str(&format!("Running sudo failed: {e:?}\n"));
+            false
+        }
+    }
+}
+
+/// Run a hook as the user running Zentrox and append its output to the log.
+fn run_hook(hook: &Path, result: Option<&str>, log: &mut String) -> bool {
+    log.push_str(&format!("== Running {}\n", hook.display()));
+    let mut command = Command::new(hook);
+    if let Some(result) = result {
+        command.arg(result);
+    }
+    match command.output() {
+        Ok(output) => {
+            append(log, &String::from_utf8_lossy(&output.stdout));
+            append(log, &String::from_utf8_lossy(&output.stderr));
+            if !output.status.success() {
+                log.push_str(&format!("Exited with status {:?}\n", output.status.code()));
+            }
+            output.status.success()
+        }
+        Err(e) => {
+            log.push_str(&format!("The hook could not be run: {e}\n"));
+            false
+        }
+    }
+}
+
+/// Append the end of the output of a command to the log.
+fn append(log: &mut String, output: &str) {
+    let output = tail(output);
+    if output.is_empty() {
+        return;
+    }
+    log.push_str(output);
+    if !output.ends_with('\n') {
+        log.push('\n');
+    }
+}
+
+/// The last [`OUTPUT_LIMIT`] bytes of `output`.
+fn tail(output: &str) -> &str {
+    let mut start = output.len().saturating_sub(OUTPUT_LIMIT);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_steps() {
+        let mut log = String::new();
+        assert!(record(
+            &mut log,
+            "Updating",
+            Ok(SudoOutput {
+                stdout: "Done".to_string(),
+                stderr: String::new(),
+                status: Some(0),
+            })
+        ));
+        assert!(!record(
+            &mut log,
+            "Installing",
+            Err(SudoError::PasswordRequired)
+        ));
+        assert_eq!(
+            log,
+            "== Updating\nDone\n== Installing\nsudo requires a password, unattended updates have not been authorized.\n"
+        );
+
+        let long = "ä".repeat(OUTPUT_LIMIT);
+        assert!(tail(&long).len() <= OUTPUT_LIMIT);
+        assert!(tail(&long).starts_with('ä'));
+    }
+}
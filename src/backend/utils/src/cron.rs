@@ -1,14 +1,21 @@
 /// Parsing and creating cronjobs whilst trying to comply with most cron as well as anacron variants and keeping the
 /// file and a reasonable complexity.
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone};
 use log::{debug, error, warn};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use utoipa::ToSchema;
 
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, write_system_file};
+
 /// The enumeration Interval is used to denote an Interval present in anacron.
 /// The enum variants are pretty self-explanatory.
 #[allow(unused)]
@@ -107,13 +114,12 @@ impl Display for Digit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Any => f.write_str("*"),
-            Self::List(l) => {
-                for ele in l {
-                    let _ = f.write_str(ele.to_string().as_str());
-                    let _ = f.write_str(",");
-                }
-                Ok(())
-            }
+            Self::List(l) => f.write_str(
+                &l.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
             Self::Range(a, b) => f.write_str(format!("{a}-{b}").as_str()),
             Self::Value(v) => f.write_str(v.to_string().as_str()),
             Self::Repeating(s, r) => f.write_str(format!("{s}/{r}").as_str()),
@@ -182,143 +188,278 @@ pub fn write_cronfile(content: String) -> Option<()> {
     Some(())
 }
 
-fn get_cron_contents() -> Option<String> {
-    debug!("Reading from cron file.");
-    let mut c = Command::new("crontab");
-    c.stdin(Stdio::null());
-    c.arg("-l");
-    let x = c.output();
+/// The directory of system crontab fragments, which are installed by packages or administrators.
+const CRON_D: &str = "/etc/cron.d";
+const SYSTEM_CRONTAB: &str = "/etc/crontab";
+
+/// A crontab that can be managed using Zentrox.
+///
+/// The crontabs are written as `own`, `user:<name>`, `system` and `cron.d:<name>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrontabSource {
+    /// The crontab of the user running Zentrox, which is managed using `crontab`
+    Own,
+    /// The crontab of another user, which is managed using `sudo crontab -u`
+    User(String),
+    /// `/etc/crontab`
+    System,
+    /// A file in `/etc/cron.d`
+    CronD(String),
+}
+
+impl Display for CrontabSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrontabSource::Own => f.write_str("own"),
+            CrontabSource::User(user) => write!(f, "user:{user}"),
+            CrontabSource::System => f.write_str("system"),
+            CrontabSource::CronD(name) => write!(f, "cron.d:{name}"),
+        }
+    }
+}
 
-    match x {
-        Ok(v) => {
-            let err = v.stderr;
-            let out = v.stdout;
-            let status = v.status;
-            if !status.success() || !err.is_empty() {
-                return None;
+impl FromStr for CrontabSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "own" => Ok(CrontabSource::Own),
+            None if s == "system" => Ok(CrontabSource::System),
+            Some(("user", user)) if is_user_name(user) => Ok(CrontabSource::User(user.to_string())),
+            Some(("cron.d", name)) if is_cron_d_name(name) => {
+                Ok(CrontabSource::CronD(name.to_string()))
             }
-            let out_decoded = String::from_utf8(out);
-            Some(out_decoded.unwrap().to_string())
+            _ => Err(format!("{s} is not a valid crontab.")),
         }
-        Err(_) => None,
     }
 }
 
-fn get_cron_lines() -> Option<Vec<String>> {
-    get_cron_contents().map(|v| v.lines().map(String::from).collect::<Vec<String>>())
+impl Serialize for CrontabSource {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
-/// Check if the crontab file exists.
-///
-/// Returns an error if the command to do so failed.
-/// This may likely be a permission error.
-fn crontab_exists() -> Result<bool, CronError> {
-    let mut c = Command::new("crontab");
-    c.arg("-l");
-    let x = c.output();
+impl<'de> Deserialize<'de> for CrontabSource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
 
-    match x {
-        Ok(v) => {
-            let err = v.stderr;
-            let status = v.status;
-            if status.success() {
-                // A crontab file is present
-                Ok(true)
-            } else if err.starts_with(b"must") {
-                // The user does not have permissions to run crontab with -u
-                Err(CronError::ReadingError)
-            } else if err.starts_with(b"no crontab") {
-                // No crontab file is present
-                Ok(false)
-            } else {
-                // Unknown crontab -u response
-                Err(CronError::ReadingError)
-            }
-        }
-        Err(_) => Err(CronError::ReadingError),
-    }
-}
-
-/// Creates a completely new cron job for a given user.
-/// This function does not verify input values.
-pub fn create_new_specific_cronjob(job: SpecificCronJob) -> Result<String, CronError> {
-    debug!("Creating new specific cronjob.");
-    let prompt = format!(
-        "{} {} {} {} {} {}",
-        job.minute, job.hour, job.day_of_month, job.month, job.day_of_week, job.command,
-    );
-
-    if crontab_exists()? {
-        if let Some(crontab_rules) = get_cron_contents() {
-            let pad = if crontab_rules.ends_with("\n") {
-                ""
-            } else if !crontab_rules.is_empty() {
-                "\n"
-            } else {
-                ""
-            };
-            let new_cont = format!("{crontab_rules}{pad}{prompt}\n");
-            match write_cronfile(new_cont.clone()) {
-                Some(_) => Ok(prompt),
-                None => {
-                    error!("Failed to write to cronfile.");
-                    Err(CronError::WritingError)
-                }
-            }
-        } else {
-            error!("Failed to read cronfile");
-            Err(CronError::ReadingError)
-        }
-    } else {
-        match write_cronfile(prompt.clone()) {
-            Some(_) => Ok(prompt),
-            None => {
-                error!("Failed to write to cronfile.");
-                Err(CronError::WritingError)
-            }
+impl CrontabSource {
+    /// Whether the jobs of the crontab name the user they run as, like `/etc/crontab`.
+    pub fn has_user_column(&self) -> bool {
+        matches!(self, CrontabSource::System | CrontabSource::CronD(_))
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        match self {
+            CrontabSource::System => Some(PathBuf::from(SYSTEM_CRONTAB)),
+            CrontabSource::CronD(name) => Some(Path::new(CRON_D).join(name)),
+            _ => None,
         }
     }
 }
 
-/// Creates a completely new cron job for a given user.
-/// This function does not verify input values.
-pub fn create_new_interval_cronjob(job: IntervalCronJob) -> Result<String, CronError> {
-    debug!("Creating new interval cronjob.");
-    let prompt = format!("{} {}", job.interval, job.command);
+/// Whether a user name is accepted by `useradd` and can not be confused with an option.
+pub fn is_user_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_.-".contains(c))
+        && name.len() <= 32
+}
+
+/// Whether cron reads a file in `/etc/cron.d`. Files with other names, like backups made by
+/// package managers, are ignored by cron.
+fn is_cron_d_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The crontabs that can be listed without knowing the name of a user, i.e. the own crontab,
+/// `/etc/crontab` and the files in `/etc/cron.d`.
+pub fn list_crontabs() -> Vec<CrontabSource> {
+    let mut crontabs = vec![CrontabSource::Own];
+    if Path::new(SYSTEM_CRONTAB).exists() {
+        crontabs.push(CrontabSource::System);
+    }
+    let mut fragments = fs::read_dir(CRON_D)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_file())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| is_cron_d_name(name))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    fragments.sort();
+    crontabs.extend(fragments.into_iter().map(CrontabSource::CronD));
+    crontabs
+}
+
+/// A crontab and the way sudo is authenticated if it is needed to access the crontab.
+#[derive(Clone, Debug)]
+pub struct Crontab {
+    pub source: CrontabSource,
+    credential: SudoCredential,
+}
+
+impl Crontab {
+    /// The crontab of the user running Zentrox.
+    pub fn own() -> Self {
+        Crontab::new(CrontabSource::Own, SudoCredential::Passwordless)
+    }
 
-    if crontab_exists()? {
-        match get_cron_contents() {
-            Some(cont) => {
-                let pad = if cont.ends_with("\n") {
-                    ""
-                } else if !cont.is_empty() {
-                    "\n"
-                } else {
-                    ""
-                };
-                let new_cont = format!("{cont}{pad}{prompt}\n");
+    pub fn new(source: CrontabSource, credential: SudoCredential) -> Self {
+        Crontab { source, credential }
+    }
 
-                match write_cronfile(new_cont.clone()) {
-                    Some(_) => Ok(prompt),
-                    None => {
-                        error!("Failed to write to cronfile.");
-                        Err(CronError::WritingError)
+    /// The contents of the crontab, missing if there is no crontab.
+    fn read(&self) -> Result<Option<String>, CronError> {
+        debug!("Reading crontab {}.", self.source);
+        match &self.source {
+            CrontabSource::Own => {
+                let output = Command::new("crontab")
+                    .arg("-l")
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(|_| CronError::ReadingError)?;
+                crontab_output(
+                    output.status.success(),
+                    String::from_utf8_lossy(&output.stdout).into_owned(),
+                    &String::from_utf8_lossy(&output.stderr),
+                )
+            }
+            CrontabSource::User(user) => {
+                let output = SudoCommand::with_credential(self.credential.clone(), "crontab")
+                    .args(vec!["-u", user, "-l"])
+                    .output()
+                    .map_err(CronError::Sudo)?;
+                crontab_output(output.status == Some(0), output.stdout, &output.stderr)
+            }
+            CrontabSource::System | CrontabSource::CronD(_) => {
+                let path = self.source.path().unwrap();
+                match fs::read_to_string(&path) {
+                    Ok(contents) => Ok(Some(contents)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                        let output = SudoCommand::with_credential(self.credential.clone(), "cat")
+                            .arg(path.display())
+                            .output()
+                            .map_err(CronError::Sudo)?;
+                        crontab_output(output.status == Some(0), output.stdout, &output.stderr)
                     }
+                    Err(_) => Err(CronError::ReadingError),
                 }
             }
-            None => Err(CronError::ReadingError),
         }
-    } else {
-        match write_cronfile(prompt.clone()) {
-            Some(_) => Ok(prompt),
-            None => {
-                error!("Failed to write to cronfile.");
+    }
+
+    /// Replace the contents of the crontab.
+    fn write(&self, contents: String) -> Result<(), CronError> {
+        debug!("Writing crontab {}.", self.source);
+        let contents = if contents.ends_with('\n') {
+            contents
+        } else {
+            format!("{contents}\n")
+        };
+        let status = match &self.source {
+            CrontabSource::Own => return write_cronfile(contents).ok_or(CronError::WritingError),
+            CrontabSource::User(user) => {
+                let staged = crate::database::data_directory()
+                    .join(format!("{}.crontab", uuid::Uuid::new_v4()));
+                fs::write(&staged, contents).map_err(|_| CronError::WritingError)?;
+                let output = SudoCommand::with_credential(self.credential.clone(), "crontab")
+                    .args(vec!["-u", user])
+                    .arg(staged.display())
+                    .output();
+                let _ = fs::remove_file(&staged);
+                output.map_err(CronError::Sudo)?.status
+            }
+            CrontabSource::System | CrontabSource::CronD(_) => {
+                write_system_file(
+                    self.credential.clone(),
+                    &self.source.path().unwrap(),
+                    &contents,
+                )
+                .map_err(CronError::Sudo)?
+                .status
+            }
+        };
+
+        match status {
+            Some(0) => Ok(()),
+            _ => {
+                error!("Failed to write crontab {}.", self.source);
                 Err(CronError::WritingError)
             }
         }
     }
 }
 
+/// Interpret the output of `crontab -l` or `cat`.
+fn crontab_output(
+    success: bool,
+    stdout: String,
+    stderr: &str,
+) -> Result<Option<String>, CronError> {
+    if success {
+        Ok(Some(stdout))
+    } else if stderr.starts_with("no crontab") || stderr.contains("No such file") {
+        Ok(None)
+    } else {
+        Err(CronError::ReadingError)
+    }
+}
+
+/// Add a job to the end of the crontab, which is created if it does not exist yet.
+/// Jobs of crontabs with a user column run as `user`.
+///
+/// Returns the ID of the new job. This function does not verify the command.
+pub fn create_cronjob(
+    crontab: &Crontab,
+    job: CronJob,
+    user: Option<&str>,
+) -> Result<String, CronError> {
+    debug!("Creating new cronjob.");
+    let line = crontab_line(&crontab.source, &job, user)?;
+    let contents = crontab.read()?.unwrap_or_default();
+    let pad = if contents.is_empty() || contents.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    let changed = format!("{contents}{pad}{line}\n");
+    crontab.write(changed.clone())?;
+
+    parse_crontab(&changed, crontab.source.has_user_column())
+        .pop()
+        .map(|e| e.id)
+        .ok_or(CronError::ReadingError)
+}
+
+/// The line of a job in a crontab.
+fn crontab_line(
+    source: &CrontabSource,
+    job: &CronJob,
+    user: Option<&str>,
+) -> Result<String, CronError> {
+    match (source.has_user_column(), user) {
+        (true, Some(user)) if is_user_name(user) => {
+            Ok(format!("{} {user} {}", job.timing(), job.command()))
+        }
+        (true, _) => Err(CronError::MissingUser),
+        (false, _) => Ok(job.to_string()),
+    }
+}
+
 impl Interval {
     fn is<T: Display>(v: T) -> bool {
         let intervals = [
@@ -376,27 +517,22 @@ impl TryFrom<&str> for Digit {
             return Ok(Self::Composed(value.to_string()));
         }
 
+        let number = |v: &str| {
+            v.parse::<usize>()
+                .map_err(|_| "Digit contained an incomplete value.")
+        };
         if value == "*" {
             Ok(Digit::Any)
         } else if value.contains(",") {
             let v = value
                 .split(",")
-                .filter(|v| !v.is_empty())
-                .map(|v| v.parse::<usize>().unwrap())
-                .collect();
+                .map(number)
+                .collect::<Result<Vec<usize>, &'static str>>()?;
             Ok(Digit::List(v))
-        } else if value.contains("-") {
-            let s = value
-                .split("-")
-                .map(|x| x.parse::<usize>().unwrap())
-                .collect::<Vec<usize>>();
-            Ok(Digit::Range(s[0], s[1]))
-        } else if value.contains("/") {
-            let s = value.split("/").collect::<Vec<&str>>();
-            Ok(Digit::Repeating(
-                s[0].to_string(),
-                s[1].parse::<usize>().unwrap(),
-            ))
+        } else if let Some((a, b)) = value.split_once("-") {
+            Ok(Digit::Range(number(a)?, number(b)?))
+        } else if let Some((a, b)) = value.split_once("/") {
+            Ok(Digit::Repeating(a.to_string(), number(b)?))
         } else {
             let p = value.parse::<usize>();
             match p {
@@ -407,9 +543,11 @@ impl TryFrom<&str> for Digit {
     }
 }
 
-impl From<&str> for Month {
-    fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
+impl TryFrom<&str> for Month {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(match value.to_lowercase().as_str() {
             "jan" | "january" | "1" => Self::January,
             "feb" | "february" | "2" => Self::February,
             "mar" | "march" | "3" => Self::March,
@@ -422,14 +560,16 @@ impl From<&str> for Month {
             "oct" | "october" | "10" => Self::October,
             "nov" | "november" | "11" => Self::November,
             "dec" | "december" | "12" => Self::December,
-            v => Self::Digit(Digit::try_from(v).expect("Converting to digit failed.")),
-        }
+            v => Self::Digit(Digit::try_from(v)?),
+        })
     }
 }
 
-impl From<&str> for DayOfWeek {
-    fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
+impl TryFrom<&str> for DayOfWeek {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(match value.to_lowercase().as_str() {
             "sun" | "sunday" | "0" => Self::Sunday,
             "mon" | "monday" | "1" => Self::Monday,
             "tue" | "tuesday" | "2" => Self::Tuesday,
@@ -437,8 +577,8 @@ impl From<&str> for DayOfWeek {
             "thu" | "thursday" | "4" => Self::Thursday,
             "fri" | "friday" | "5" => Self::Friday,
             "sat" | "saturday" | "6" => Self::Saturday,
-            v => Self::Digit(Digit::try_from(v).expect("Converting to digit failed.")),
-        }
+            v => Self::Digit(Digit::try_from(v)?),
+        })
     }
 }
 
@@ -458,17 +598,342 @@ pub struct SpecificCronJob {
     pub command: String,
 }
 
+impl Display for IntervalCronJob {
+    // The line of the job in a crontab file.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.interval, self.command)
+    }
+}
+
+impl Display for SpecificCronJob {
+    // The line of the job in a crontab file.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.minute, self.hour, self.day_of_month, self.month, self.day_of_week, self.command
+        )
+    }
+}
+
+impl SpecificCronJob {
+    /// Parse and validate the fields of a specific cronjob.
+    pub fn new(
+        minute: &str,
+        hour: &str,
+        day_of_month: &str,
+        month: &str,
+        day_of_week: &str,
+        command: String,
+    ) -> Result<Self, String> {
+        Schedule::parse(minute, hour, day_of_month, month, day_of_week)?;
+        // Valid fields Digit can not represent, like ranges of names, are kept as they are
+        let digit = |v: &str| {
+            Some(v)
+                .filter(|v| v.chars().all(|c| c.is_ascii_digit() || "*,-/".contains(c)))
+                .and_then(|v| Digit::try_from(v).ok())
+                .unwrap_or_else(|| Digit::Composed(v.to_string()))
+        };
+        // Single names and numbers
+        let is_name = |v: &str| v.chars().all(|c| c.is_ascii_alphanumeric());
+        Ok(SpecificCronJob {
+            minute: digit(minute),
+            hour: digit(hour),
+            day_of_month: digit(day_of_month),
+            month: Some(month)
+                .filter(|v| is_name(v))
+                .and_then(|v| Month::try_from(v).ok())
+                .unwrap_or_else(|| Month::Digit(digit(month))),
+            day_of_week: Some(day_of_week)
+                .filter(|v| is_name(v))
+                .and_then(|v| DayOfWeek::try_from(v).ok())
+                .unwrap_or_else(|| DayOfWeek::Digit(digit(day_of_week))),
+            command,
+        })
+    }
+
+    pub fn schedule(&self) -> Result<Schedule, String> {
+        Schedule::parse(
+            &self.minute.to_string(),
+            &self.hour.to_string(),
+            &self.day_of_month.to_string(),
+            &self.month.to_string(),
+            &self.day_of_week.to_string(),
+        )
+    }
+}
+
+impl IntervalCronJob {
+    /// The schedule the interval stands for, missing for `@reboot`.
+    pub fn schedule(&self) -> Option<Schedule> {
+        let fields = match self.interval {
+            Interval::Hourly => ["0", "*", "*", "*", "*"],
+            Interval::Daily => ["0", "0", "*", "*", "*"],
+            Interval::Weekly => ["0", "0", "*", "*", "0"],
+            Interval::Monthly => ["0", "0", "1", "*", "*"],
+            Interval::Yearly | Interval::Annually => ["0", "0", "1", "1", "*"],
+            Interval::Reboot => return None,
+        };
+        Schedule::parse(fields[0], fields[1], fields[2], fields[3], fields[4]).ok()
+    }
+}
+
 #[derive(Serialize)]
 pub enum CronJob {
     Interval(IntervalCronJob),
     Specific(SpecificCronJob),
 }
 
+impl Display for CronJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CronJob::Interval(job) => job.fmt(f),
+            CronJob::Specific(job) => job.fmt(f),
+        }
+    }
+}
+
+impl CronJob {
+    /// Parse a line of a crontab file. Comments, variable assignments and malformed jobs are
+    /// rejected. Lines of crontabs with a user column name the user the job runs as after the
+    /// schedule, e.g. in `/etc/crontab`.
+    fn parse(line: &str, with_user: bool) -> Option<(CronJob, Option<String>)> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let re = Regex::new(r"\s+").unwrap();
+        let segments = re.split(line).collect::<Vec<&str>>();
+        // If the first segment is an interval prefix, the cronjob is considered an
+        // interval cronjob (aka. anacron).
+        let timing_segments = if Interval::is(segments[0]) { 1 } else { 5 };
+        let user_segments = usize::from(with_user);
+        if segments.len() < timing_segments + user_segments + 1 {
+            return None;
+        }
+        let user = with_user.then(|| segments[timing_segments].to_string());
+        let command = cronjob_line_from_to(line.to_string(), timing_segments + user_segments - 1);
+
+        let job = if timing_segments == 1 {
+            CronJob::Interval(IntervalCronJob {
+                interval: Interval::from(segments[0]),
+                command,
+            })
+        } else {
+            CronJob::Specific(
+                SpecificCronJob::new(
+                    segments[0],
+                    segments[1],
+                    segments[2],
+                    segments[3],
+                    segments[4],
+                    command,
+                )
+                .ok()?,
+            )
+        };
+        Some((job, user))
+    }
+
+    /// The schedule as written in a crontab, e.g. `@daily` or `0 4 * * 1-5`.
+    fn timing(&self) -> String {
+        match self {
+            CronJob::Interval(job) => job.interval.to_string(),
+            CronJob::Specific(job) => format!(
+                "{} {} {} {} {}",
+                job.minute, job.hour, job.day_of_month, job.month, job.day_of_week
+            ),
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        match self {
+            CronJob::Interval(job) => &job.command,
+            CronJob::Specific(job) => &job.command,
+        }
+    }
+
+    pub fn set_command(&mut self, command: String) {
+        match self {
+            CronJob::Interval(job) => job.command = command,
+            CronJob::Specific(job) => job.command = command,
+        }
+    }
+
+    /// The command and history key of a job whose runs are recorded by Zentrox.
+    pub fn recorded(&self) -> Option<RecordedCommand> {
+        RecordedCommand::parse(self.command())
+    }
+
+    /// The next `count` runs after now in seconds since the UNIX epoch. `@reboot` jobs have no
+    /// scheduled runs.
+    pub fn next_runs(&self, count: usize) -> Vec<i64> {
+        let schedule = match self {
+            CronJob::Interval(job) => job.schedule(),
+            CronJob::Specific(job) => job.schedule().ok(),
+        };
+        schedule.map_or_else(Vec::new, |s| s.next_runs(Local::now(), count))
+    }
+}
+
+/// The months and days of the week in the order of their numbers in cron
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Days searched for the next runs. Schedules like `0 0 30 2 *` never match.
+const SEARCHED_DAYS: i64 = 366 * 8;
+
+/// The times matched by the five fields of a cron expression
+#[derive(Debug)]
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    /// Index 0 is unused
+    days_of_month: Vec<bool>,
+    /// Index 0 is unused
+    months: Vec<bool>,
+    /// Starting at Sunday
+    days_of_week: Vec<bool>,
+    /// If both day fields are restricted, cron runs on days matching either of them.
+    days_restricted: bool,
+}
+
+/// Parse a field of a cron expression into the values it matches, which are between `min` and
+/// `max`. `names` are the names of the values starting at `min`.
+fn parse_field(
+    field: &str,
+    description: &str,
+    min: usize,
+    max: usize,
+    names: &[&str],
+) -> Result<Vec<bool>, String> {
+    let invalid = || format!("{field} is not a valid {description}.");
+    let value = |v: &str| {
+        let position = names.iter().position(|n| v.eq_ignore_ascii_case(n));
+        match position {
+            Some(p) => Some(min + p),
+            None => v.parse::<usize>().ok(),
+        }
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(invalid)
+    };
+
+    let mut matched = vec![false; max + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(invalid()),
+            },
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (value(start)?, value(end)?)
+        } else {
+            let start = value(range)?;
+            // `5/10` is short for `5-59/10`
+            (start, if step.is_some() { max } else { start })
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step.unwrap_or(1)) {
+            matched[v] = true;
+        }
+    }
+    Ok(matched)
+}
+
+impl Schedule {
+    /// Parse and validate the fields of a cron expression.
+    pub fn parse(
+        minute: &str,
+        hour: &str,
+        day_of_month: &str,
+        month: &str,
+        day_of_week: &str,
+    ) -> Result<Self, String> {
+        let mut days_of_week = parse_field(day_of_week, "day of the week", 0, 7, &DAY_NAMES)?;
+        // Both 0 and 7 are Sunday
+        if days_of_week.pop() == Some(true) {
+            days_of_week[0] = true;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minute, "minute", 0, 59, &[])?,
+            hours: parse_field(hour, "hour", 0, 23, &[])?,
+            days_of_month: parse_field(day_of_month, "day of the month", 1, 31, &[])?,
+            months: parse_field(month, "month", 1, 12, &MONTH_NAMES)?,
+            days_of_week,
+            days_restricted: !day_of_month.starts_with('*') && !day_of_week.starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !self.months[date.month() as usize] {
+            return false;
+        }
+        let day_of_month = self.days_of_month[date.day() as usize];
+        let day_of_week = self.days_of_week[date.weekday().num_days_from_sunday() as usize];
+        if self.days_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+
+    /// The next `count` times in local time after `after`.
+    fn next_local(&self, after: NaiveDateTime, count: usize) -> Vec<NaiveDateTime> {
+        let mut runs = Vec::new();
+        let first_day = after.date();
+        for offset in 0..SEARCHED_DAYS {
+            let date = first_day + chrono::Duration::days(offset);
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|h| self.hours[*h]) {
+                for minute in (0..60).filter(|m| self.minutes[*m]) {
+                    let Some(time) = date.and_hms_opt(hour as u32, minute as u32, 0) else {
+                        continue;
+                    };
+                    if time <= after {
+                        continue;
+                    }
+                    runs.push(time);
+                    if runs.len() >= count {
+                        return runs;
+                    }
+                }
+            }
+        }
+        runs
+    }
+
+    /// The next `count` runs after `after` in seconds since the UNIX epoch. Times skipped by a
+    /// daylight saving time change are left out.
+    pub fn next_runs(&self, after: DateTime<Local>, count: usize) -> Vec<i64> {
+        self.next_local(after.naive_local(), count)
+            .into_iter()
+            .filter_map(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.timestamp())
+            .collect()
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub enum CronError {
     ReadingError,
     NoCronFile,
     WritingError,
+    /// No job has the requested ID
+    NoSuchJob,
+    /// A job of a crontab with a user column has no valid user
+    MissingUser,
+    #[serde(skip)]
+    Sudo(SudoError),
 }
 
 fn cronjob_line_from_to(l: String, n: usize) -> String {
@@ -478,156 +943,509 @@ fn cronjob_line_from_to(l: String, n: usize) -> String {
         .to_string()
 }
 
-pub fn list_cronjobs() -> Result<Vec<CronJob>, CronError> {
-    debug!("Listing cronjobs.");
-    if !crontab_exists()? {
-        return Err(CronError::NoCronFile);
-    }
-
-    match get_cron_lines() {
-        Some(lines) => {
-            let mut jobs = Vec::new();
-            let re = Regex::new(r"\s+").unwrap();
-            for l in lines {
-                // Lines starting with hashtags are regarded as comments
-                // Lines containing just spaces and/or tabs are ignored
-                if l.starts_with("#") || !l.chars().any(|c| c != '\t' || c != ' ') {
-                    continue;
+/// The subcommand of Zentrox that runs a command and records the run.
+pub const RUN_JOB_SUBCOMMAND: &str = "run-job";
+
+/// A command wrapped in `zentrox run-job`, so its runs are recorded.
+///
+/// The wrapped command looks like `'/usr/bin/zentrox' --data-dir '/data' run-job <key> '<command>'`.
+/// The key identifies the history of the job, as the ID of the job changes when it is edited.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecordedCommand {
+    pub key: String,
+    pub command: String,
+}
+
+impl RecordedCommand {
+    /// Wrap `command` in a call of Zentrox located at `executable`, which stores its data in
+    /// `data_directory`.
+    pub fn wrap(executable: &str, data_directory: &str, key: &str, command: &str) -> String {
+        format!(
+            "{} --data-dir {} {RUN_JOB_SUBCOMMAND} {key} {}",
+            shell_quote(executable),
+            shell_quote(data_directory),
+            shell_quote(command)
+        )
+    }
+
+    /// Unwrap a command created by [`RecordedCommand::wrap`].
+    fn parse(command: &str) -> Option<RecordedCommand> {
+        let words = shell_words(command)?;
+        let subcommand = words.iter().position(|w| w == RUN_JOB_SUBCOMMAND)?;
+        match &words[subcommand + 1..] {
+            [key, command] => Some(RecordedCommand {
+                key: key.clone(),
+                command: command.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the runs of a command can be recorded. cron cuts commands at unescaped `%` signs,
+/// which would break the quoting of the wrapped command.
+pub fn can_record(command: &str) -> bool {
+    let mut escaped = false;
+    for c in command.chars() {
+        if c == '%' && !escaped {
+            return false;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    true
+}
+
+/// Quote a word for sh using single quotes.
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Split a command into words like sh, supporting single quotes and backslash escapes. Returns
+/// `None` for any other quoting or shell syntax.
+fn shell_words(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                if let Some(w) = word.take() {
+                    words.push(w);
                 }
-                let segments = re.split(l.trim()).collect::<Vec<&str>>();
-                if Interval::is(segments[0]) {
-                    // If the first segment is an interval prefix, the cronjob is considered an
-                    // interval cronjob (aka. anacron).
-                    if segments.len() < 2 {
-                        // If the cronjob has less than two segments, it is malformed and ignored
-                        continue;
-                    }
-                    let j = IntervalCronJob {
-                        interval: Interval::from(segments[0]),
-                        command: cronjob_line_from_to(l, 0),
-                    };
-                    jobs.push(CronJob::Interval(j))
-                } else {
-                    if segments.len() < 6 {
-                        // If the cronjob has less than six segments, it is malformed and ignored
-                        continue;
+            }
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => w.push(c),
                     }
-                    let minute =
-                        Digit::try_from(segments[0]).expect("Converting minute to digit failed.");
-                    let hour =
-                        Digit::try_from(segments[1]).expect("Converting hour to digit failed.");
-                    let day_of_month = Digit::try_from(segments[2])
-                        .expect("Converting day of month to digit failed.");
-                    let month = Month::from(segments[3]);
-                    let day_of_week = DayOfWeek::from(segments[4]);
-                    let j = SpecificCronJob {
-                        minute,
-                        hour,
-                        day_of_month,
-                        day_of_week,
-                        month,
-                        command: cronjob_line_from_to(l, 4),
-                    };
-                    jobs.push(CronJob::Specific(j));
                 }
             }
-            Ok(jobs)
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            '"' | '$' | '`' | ';' | '&' | '|' | '<' | '>' | '(' | ')' => return None,
+            c => word.get_or_insert_with(String::new).push(c),
         }
-        None => Err(CronError::ReadingError),
     }
+    words.extend(word);
+    Some(words)
 }
 
-pub enum CronDeletionError {
-    NoCronFile,
-}
+/// Disabled jobs are commented out using this prefix, so they can be told apart from comments.
+const DISABLED_PREFIX: &str = "#[disabled] ";
 
-/// Deletes the line of the nth occurrence of a valid specific CronJob from a crontab file given the index
-/// and targeted user.
-pub fn delete_specific_cronjob(target_index: u32) -> Result<(), CronDeletionError> {
-    debug!("Deleting specific cronjob.");
-    let mut lines;
-    match get_cron_lines() {
-        Some(v) => lines = v,
-        None => return Err(CronDeletionError::NoCronFile),
-    };
+/// A job in a crontab
+#[derive(Serialize)]
+pub struct CrontabEntry {
+    /// Derived from the line of the job, so it stays the same when other jobs are changed
+    pub id: String,
+    /// Disabled jobs are commented out
+    pub enabled: bool,
+    pub job: CronJob,
+    /// The user the job runs as, only known for crontabs with a user column
+    pub user: Option<String>,
+    /// Index of the line in the crontab file
+    #[serde(skip)]
+    line: usize,
+    /// The line of the job as written in the crontab, without the prefix of disabled jobs
+    #[serde(skip)]
+    job_line: String,
+}
 
-    let mut current_index = 0;
-    let mut file_line_index = 0;
-    let re = Regex::new(r"\s+").unwrap();
+/// The ID of a job, which is derived from its line and how often the same line occurred before.
+fn job_id(line: &str, occurrence: usize) -> String {
+    let digest = Sha256::digest(format!("{occurrence}:{line}").as_bytes());
+    hex::encode(&digest[..8])
+}
 
-    for l in lines.clone() {
-        let segments = re.split(l.trim()).collect::<Vec<&str>>();
+/// All jobs in the contents of a crontab file, including disabled jobs. Lines of crontabs with a
+/// user column name the user after the schedule.
+fn parse_crontab(contents: &str, with_user: bool) -> Vec<CrontabEntry> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let (enabled, job_line) = match line.strip_prefix(DISABLED_PREFIX.trim_end()) {
+            Some(job_line) => (false, job_line.trim()),
+            None => (true, line),
+        };
+        let Some((job, user)) = CronJob::parse(job_line, with_user) else {
+            continue;
+        };
+        let occurrence = occurrences.entry(job_line).or_insert(0);
+        entries.push(CrontabEntry {
+            id: job_id(job_line, *occurrence),
+            enabled,
+            job,
+            user,
+            line: index,
+            job_line: job_line.to_string(),
+        });
+        *occurrence += 1;
+    }
+    entries
+}
 
-        // Skip processing the line but acknowledge its existence if the line is a comment, interval,
-        // or invalid length.
+pub fn list_cronjobs(crontab: &Crontab) -> Result<Vec<CrontabEntry>, CronError> {
+    debug!("Listing cronjobs.");
+    match crontab.read()? {
+        Some(contents) => Ok(parse_crontab(&contents, crontab.source.has_user_column())),
+        None => Err(CronError::NoCronFile),
+    }
+}
 
-        // Ignores lines with less than six segments as they would not be correctly formed specific
-        // cronjobs, lines starting with @ as this signifies an interval and lines starting
-        // hashtags as those introduce a comment.
-        if segments.len() < 6 || l.starts_with("@") || l.starts_with("#") {
-            file_line_index += 1;
-            continue;
-        }
+/// The job with the given ID.
+pub fn find_cronjob(crontab: &Crontab, id: &str) -> Result<CrontabEntry, CronError> {
+    list_cronjobs(crontab)?
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or(CronError::NoSuchJob)
+}
 
-        if current_index == target_index {
-            lines.remove(file_line_index as usize);
+/// Replace the line of the job with the given ID in `contents`. Returns the changed contents and
+/// the index of the line, `None` removes the line.
+fn change_line(
+    contents: &str,
+    with_user: bool,
+    id: &str,
+    change: impl FnOnce(&CrontabEntry) -> Result<Option<String>, CronError>,
+) -> Result<(String, usize), CronError> {
+    let entry = parse_crontab(contents, with_user)
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or(CronError::NoSuchJob)?;
+    let mut lines = contents.lines().map(String::from).collect::<Vec<String>>();
+    match change(&entry)? {
+        Some(line) => lines[entry.line] = line,
+        None => {
+            lines.remove(entry.line);
         }
-        current_index += 1;
-        file_line_index += 1;
     }
 
-    let mut s = String::new();
-    for l in lines {
-        s = format!("{s}{l}\n");
+    let mut changed = lines.join("\n");
+    changed.push('\n');
+    Ok((changed, entry.line))
+}
+
+/// Apply a change to the job with the given ID and write the crontab. Returns the changed
+/// contents and the index of the changed line.
+fn modify_cronjob(
+    crontab: &Crontab,
+    id: &str,
+    change: impl FnOnce(&CrontabEntry) -> Result<Option<String>, CronError>,
+) -> Result<(String, usize), CronError> {
+    let contents = crontab.read()?.ok_or(CronError::NoCronFile)?;
+    let (changed, line) = change_line(&contents, crontab.source.has_user_column(), id, change)?;
+    crontab.write(changed.clone())?;
+    Ok((changed, line))
+}
+
+/// Replace the schedule and command of a job in place. A disabled job stays disabled and jobs of
+/// crontabs with a user column keep their user unless `user` is given.
+///
+/// Returns the new ID of the job, as IDs are derived from the line of the job.
+/// This function does not verify the command.
+pub fn edit_cronjob(
+    crontab: &Crontab,
+    id: &str,
+    job: CronJob,
+    user: Option<&str>,
+) -> Result<String, CronError> {
+    debug!("Editing cronjob.");
+    let (changed, line) = modify_cronjob(crontab, id, |entry| {
+        let line = crontab_line(&crontab.source, &job, user.or(entry.user.as_deref()))?;
+        Ok(Some(if entry.enabled {
+            line
+        } else {
+            format!("{DISABLED_PREFIX}{line}")
+        }))
+    })?;
+    parse_crontab(&changed, crontab.source.has_user_column())
+        .into_iter()
+        .find(|e| e.line == line)
+        .map(|e| e.id)
+        .ok_or(CronError::ReadingError)
+}
+
+/// The line of a job with the prefix of disabled jobs added or removed. The job itself is kept as
+/// written, so its ID does not change.
+fn toggled_line(entry: &CrontabEntry, enabled: bool) -> String {
+    if enabled {
+        entry.job_line.clone()
+    } else {
+        format!("{DISABLED_PREFIX}{}", entry.job_line)
     }
+}
 
-    let _ = write_cronfile(s);
+/// Disable a job by commenting it out or enable it again.
+pub fn set_cronjob_enabled(crontab: &Crontab, id: &str, enabled: bool) -> Result<(), CronError> {
+    debug!("Changing whether cronjob is enabled.");
+    modify_cronjob(crontab, id, |entry| Ok(Some(toggled_line(entry, enabled))))?;
+    Ok(())
+}
 
+/// Delete the line of the job with the given ID.
+pub fn delete_cronjob(crontab: &Crontab, id: &str) -> Result<(), CronError> {
+    debug!("Deleting cronjob.");
+    modify_cronjob(crontab, id, |_| Ok(None))?;
     Ok(())
 }
 
-/// Deletes the line of the nth occurrence of a valid interval CronJob from a crontab file given the index
-/// and targeted user.
-pub fn delete_interval_cronjob(target_index: u32) -> Result<(), CronDeletionError> {
-    debug!("Deleting interval cronjob.");
-    let mut lines;
-    match get_cron_lines() {
-        Some(v) => lines = v,
-        None => return Err(CronDeletionError::NoCronFile),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut current_index = 0;
-    let mut file_line_index = 0;
+    #[test]
+    fn parse_crontab_ids() {
+        let contents = "\
+# m h  dom mon dow   command
+SHELL=/bin/sh
+*/5 * * * * backup.sh
+#[disabled] 0 4 * * 1-5 report.sh --daily
+@daily cleanup.sh
+*/5 * * * * backup.sh
+61 * * * * invalid.sh
+";
+        let entries = parse_crontab(contents, false);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.line, e.enabled, e.job.to_string()))
+                .collect::<Vec<(usize, bool, String)>>(),
+            vec![
+                (2, true, "*/5 * * * * backup.sh".to_string()),
+                (3, false, "0 4 * * 1-5 report.sh --daily".to_string()),
+                (4, true, "@daily cleanup.sh".to_string()),
+                (5, true, "*/5 * * * * backup.sh".to_string()),
+            ]
+        );
+        // Identical lines get different IDs, which do not depend on other jobs
+        assert_ne!(entries[0].id, entries[3].id);
+        assert_eq!(
+            parse_crontab("@daily cleanup.sh", false)[0].id,
+            entries[2].id
+        );
 
-    let re = Regex::new(r"\s+").unwrap();
-    for l in lines.clone() {
-        let segments = re.split(l.trim()).collect::<Vec<&str>>();
+        let (disabled, line) = change_line(contents, false, &entries[0].id, |e| {
+            Ok(Some(toggled_line(e, false)))
+        })
+        .unwrap();
+        assert_eq!(line, 2);
+        let reparsed = parse_crontab(&disabled, false);
+        assert!(!reparsed[0].enabled);
+        assert_eq!(reparsed[0].id, entries[0].id);
 
-        // Skip processing the line but acknowledge its existence if the line is a comment, interval,
-        // or invalid length.
+        let (removed, _) = change_line(contents, false, &entries[2].id, |_| Ok(None)).unwrap();
+        assert!(!removed.contains("cleanup.sh"));
+        assert_eq!(parse_crontab(&removed, false)[2].id, entries[3].id);
+        assert!(change_line(contents, false, "unknown", |_| Ok(None)).is_err());
+    }
 
-        if segments.len() < 2
-            || !l.starts_with("@")
-            || !Interval::is(segments[0].trim())
-            || l.starts_with("#")
-        {
-            file_line_index += 1;
-            continue;
-        }
+    #[test]
+    fn toggle_keeps_line() {
+        let contents = "SHELL=/bin/sh\n0\t4\t*\tjan-jun\tmon-fri\troot\treport.sh --daily\n";
+        let id = parse_crontab(contents, true)[0].id.clone();
+
+        let (disabled, _) =
+            change_line(contents, true, &id, |e| Ok(Some(toggled_line(e, false)))).unwrap();
+        assert_eq!(
+            disabled,
+            "SHELL=/bin/sh\n#[disabled] 0\t4\t*\tjan-jun\tmon-fri\troot\treport.sh --daily\n"
+        );
+        let entry = &parse_crontab(&disabled, true)[0];
+        assert!(!entry.enabled);
+        assert_eq!(entry.id, id);
 
-        if current_index == target_index {
-            lines.remove(file_line_index as usize);
+        let (enabled, _) =
+            change_line(&disabled, true, &id, |e| Ok(Some(toggled_line(e, true)))).unwrap();
+        assert_eq!(enabled, contents);
+        assert_eq!(parse_crontab(&enabled, true)[0].id, id);
+    }
+
+    #[test]
+    fn parse_crontab_fixtures() {
+        let system = parse_crontab(include_str!("../tests/fixtures/cron/crontab"), true);
+        assert_eq!(system.len(), 4);
+        assert!(system.iter().all(|e| e.user.as_deref() == Some("root")));
+        assert_eq!(system[0].job.timing(), "17 * * * *");
+        assert_eq!(
+            system[0].job.command(),
+            "cd / && run-parts --report /etc/cron.hourly"
+        );
+        assert_eq!(
+            system[3].job.command(),
+            "test -x /usr/sbin/anacron || { cd / && run-parts --report /etc/cron.monthly; }"
+        );
+
+        let fragment = parse_crontab(include_str!("../tests/fixtures/cron/cron.d-certbot"), true);
+        // The job without a command is skipped
+        assert_eq!(fragment.len(), 2);
+        assert_eq!(fragment[0].job.timing(), "0 */12 * * *");
+        assert!(fragment[0].job.command().ends_with(
+            "perl -e 'sleep int(rand(43200))' && certbot -q renew --no-random-sleep-on-renew"
+        ));
+        assert!(!fragment[1].enabled);
+        assert_eq!(fragment[1].user.as_deref(), Some("backup"));
+        assert_eq!(
+            fragment[1].job_line,
+            "@reboot backup /usr/local/bin/backup --verify"
+        );
+
+        let user = parse_crontab(include_str!("../tests/fixtures/cron/crontab-user"), false);
+        assert_eq!(
+            user.iter()
+                .map(|e| e.job.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "30 2 * * * /usr/local/bin/backup.sh > /var/log/backup.log 2>&1",
+                "@reboot /usr/local/bin/zentrox",
+                "0 9-17 * jan-jun mon-fri echo \"office hours\"",
+            ]
+        );
+        assert!(user.iter().all(|e| e.user.is_none()));
+
+        // Editing a job of a crontab with a user column keeps the user
+        let contents = include_str!("../tests/fixtures/cron/cron.d-certbot");
+        let job = CronJob::parse("@daily certbot renew", false).unwrap().0;
+        let (changed, line) = change_line(contents, true, &fragment[0].id, |entry| {
+            crontab_line(
+                &CrontabSource::CronD("certbot".to_string()),
+                &job,
+                entry.user.as_deref(),
+            )
+            .map(Some)
+        })
+        .unwrap();
+        assert_eq!(changed.lines().nth(line), Some("@daily root certbot renew"));
+        assert!(matches!(
+            crontab_line(&CrontabSource::System, &job, None),
+            Err(CronError::MissingUser)
+        ));
+    }
+
+    #[test]
+    fn parse_crontab_sources() {
+        for source in [
+            "own",
+            "system",
+            "user:backup",
+            "cron.d:certbot",
+            "cron.d:e2scrub_all",
+        ] {
+            assert_eq!(source.parse::<CrontabSource>().unwrap().to_string(), source);
         }
-        current_index += 1;
-        file_line_index += 1;
+        for source in [
+            "",
+            "root",
+            "user:-r",
+            "user:Root",
+            "cron.d:certbot.dpkg-old",
+            "cron.d:../shadow",
+        ] {
+            assert!(source.parse::<CrontabSource>().is_err(), "{source}");
+        }
+        assert!(CrontabSource::System.has_user_column());
+        assert!(!CrontabSource::User("root".to_string()).has_user_column());
     }
 
-    let mut s = String::new();
-    for l in lines {
-        s = format!("{s}{l}\n");
+    #[test]
+    fn convert_recorded_commands() {
+        let command = "tar -czf /backup/home.tgz ~ 2>&1 | logger -t 'backup'";
+        let wrapped = RecordedCommand::wrap("/usr/bin/zentrox", "/srv/zen trox", "1f2e", command);
+        assert_eq!(
+            wrapped,
+            r"'/usr/bin/zentrox' --data-dir '/srv/zen trox' run-job 1f2e 'tar -czf /backup/home.tgz ~ 2>&1 | logger -t '\''backup'\'''"
+        );
+        assert_eq!(
+            RecordedCommand::parse(&wrapped),
+            Some(RecordedCommand {
+                key: "1f2e".to_string(),
+                command: command.to_string()
+            })
+        );
+        assert_eq!(
+            RecordedCommand::parse(r"zentrox run-job 1f2e backup\ now").map(|r| r.command),
+            Some("backup now".to_string())
+        );
+        assert_eq!(RecordedCommand::parse(command), None);
+        assert_eq!(RecordedCommand::parse("zentrox run-job 1f2e"), None);
+        assert_eq!(RecordedCommand::parse("echo run-job 1f2e \"x\""), None);
+
+        assert!(can_record(r"date +\%s"));
+        assert!(!can_record("date +%s"));
+        assert!(!can_record(r"echo \\%s"));
     }
 
-    let _ = write_cronfile(s);
+    #[test]
+    fn parse_schedules() {
+        let job = SpecificCronJob::new("0", "4", "*", "jan", "mon-fri", "report.sh".to_string());
+        assert_eq!(job.unwrap().to_string(), "0 4 * 1 mon-fri report.sh");
+        assert!(SpecificCronJob::new("60", "*", "*", "*", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("*", "5-2", "*", "*", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("*/0", "*", "*", "*", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("*", "*", "0", "*", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("1,,2", "*", "*", "*", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("*", "*", "*", "13", "*", String::new()).is_err());
+        assert!(SpecificCronJob::new("*", "*", "*", "*", "8", String::new()).is_err());
+        assert_eq!(
+            Digit::try_from("1,4").unwrap().to_string(),
+            "1,4",
+            "Lists are written without a trailing comma"
+        );
+    }
 
-    Ok(())
+    #[test]
+    fn convert_schedules_to_runs() {
+        let time = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let runs = |schedule: Schedule, after: &str, count: usize| {
+            schedule
+                .next_local(time(after), count)
+                .iter()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .collect::<Vec<String>>()
+        };
+
+        // 2024-01-15 is a Monday
+        assert_eq!(
+            runs(
+                Schedule::parse("*/20", "9-10", "*", "*", "mon").unwrap(),
+                "2024-01-15 10:20",
+                3
+            ),
+            vec!["2024-01-15 10:40", "2024-01-22 09:00", "2024-01-22 09:20"]
+        );
+        // Either day field matches if both are restricted
+        assert_eq!(
+            runs(
+                Schedule::parse("0", "0", "1", "*", "7").unwrap(),
+                "2024-01-01 00:00",
+                3
+            ),
+            vec!["2024-01-07 00:00", "2024-01-14 00:00", "2024-01-21 00:00"]
+        );
+        assert_eq!(
+            runs(
+                Schedule::parse("30", "12", "29", "feb", "*").unwrap(),
+                "2024-03-01 00:00",
+                1
+            ),
+            vec!["2028-02-29 12:30"]
+        );
+        assert!(
+            Schedule::parse("0", "0", "30", "2", "*")
+                .unwrap()
+                .next_local(time("2024-01-01 00:00"), 1)
+                .is_empty()
+        );
+        assert_eq!(
+            IntervalCronJob {
+                interval: Interval::Weekly,
+                command: String::new(),
+            }
+            .schedule()
+            .unwrap()
+            .next_local(time("2024-01-15 00:00"), 1),
+            vec![time("2024-01-21 00:00")]
+        );
+    }
 }
@@ -4,20 +4,100 @@ use diesel::{
     prelude::*,
     r2d2::{self, ConnectionManager, CustomizeConnection, Pool},
 };
-use std::{path::PathBuf, time::Duration};
+use std::{path::PathBuf, sync::OnceLock, time::Duration};
 
-/// Get the absolute path for the database by joining `~/.local/share` with `zentrox/database.db`.
+static DATA_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overrides the directory Zentrox stores its database, certificates and other data in.
+/// The directory can only be set once and has to be set before it is first read.
+///
+/// Returns the rejected path, if the directory has already been set or read.
+pub fn set_data_directory(path: PathBuf) -> Result<(), PathBuf> {
+    DATA_DIRECTORY.set(path)
+}
+
+/// Get the directory Zentrox stores its data in.
+/// This defaults to `~/.local/share/zentrox` unless it was overridden using
+/// [`set_data_directory`].
+pub fn data_directory() -> PathBuf {
+    DATA_DIRECTORY
+        .get_or_init(|| dirs::data_local_dir().unwrap().join("zentrox"))
+        .clone()
+}
+
+/// Get the absolute path for the database by joining the data directory with `database.db`.
 pub fn get_database_location() -> PathBuf {
-    dirs::data_local_dir()
-        .unwrap()
-        .join("zentrox")
-        .join("database.db")
+    data_directory().join("database.db")
 }
 
 pub fn base_database_setup() -> Result<(), Box<dyn std::error::Error>> {
     let connection = rusqlite::Connection::open(get_database_location().to_str().unwrap()).unwrap();
     let s = connection.execute_batch(include_str!("../../assets/setup.sql"));
     s?;
+    drop(connection);
+    upgrade_database()
+}
+
+/// Columns that were added to tables after they were first introduced in `setup.sql`.
+/// Every entry consists of the table name, the column name and the column definition that is
+/// used with `ALTER TABLE ... ADD COLUMN`.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    (
+        "Configuration",
+        "base_time_window",
+        "INTEGER NOT NULL DEFAULT 600",
+    ),
+    (
+        "Configuration",
+        "base_request_limit",
+        "INTEGER NOT NULL DEFAULT 5",
+    ),
+    (
+        "Configuration",
+        "large_time_window",
+        "INTEGER NOT NULL DEFAULT 1800",
+    ),
+    (
+        "Configuration",
+        "large_request_limit",
+        "INTEGER NOT NULL DEFAULT 15",
+    ),
+    (
+        "Configuration",
+        "account_time_window",
+        "INTEGER NOT NULL DEFAULT 3600",
+    ),
+    (
+        "Configuration",
+        "account_request_limit",
+        "INTEGER NOT NULL DEFAULT 60",
+    ),
+    // NULL if the private key is stored in the certificate file
+    ("Configuration", "tls_key", "TEXT"),
+];
+
+/// Brings the table structure of an existing database up to date.
+///
+/// Tables that were added after the initial setup are created from `upgrade.sql` and columns
+/// listed in [`ADDED_COLUMNS`] are appended to their table if they are missing.
+/// Running this function multiple times is safe.
+pub fn upgrade_database() -> Result<(), Box<dyn std::error::Error>> {
+    let connection = rusqlite::Connection::open(get_database_location())?;
+    connection.execute_batch(include_str!("../../assets/upgrade.sql"))?;
+
+    for (table, column, definition) in ADDED_COLUMNS {
+        let mut statement = connection.prepare(&format!("PRAGMA table_info({table})"))?;
+        let existing_columns = statement
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        if !existing_columns.iter().any(|c| c == column) {
+            connection.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN {column} {definition};"
+            ))?;
+        }
+    }
+
     Ok(())
 }
 
@@ -35,6 +115,9 @@ impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
     }
 }
 
+/// Time a connection waits for the database to be unlocked by another connection.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(1000);
+
 /// Directly connects to a database and creates on [`SqliteConnection`].
 pub fn establish_direct_connection() -> SqliteConnection {
     let db_url = get_database_location();
@@ -47,8 +130,23 @@ pub fn establish_direct_connection() -> SqliteConnection {
     .expect("Failed to establish database connection.")
 }
 
+/// Directly connects to the database like [`establish_direct_connection`], but returns an error
+/// instead of panicking. The connection has the same `busy_timeout` as the connections of the
+/// pool, as it is used next to the pool by other processes.
+pub fn try_establish_direct_connection() -> ConnectionResult<SqliteConnection> {
+    let db_url = get_database_location();
+    let mut connection = SqliteConnection::establish(&db_url.to_string_lossy())?;
+    connection
+        .batch_execute(&format!(
+            "PRAGMA busy_timeout={};",
+            BUSY_TIMEOUT.as_millis()
+        ))
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    Ok(connection)
+}
+
 /// Creates a new pool of [`SqliteConnection`]s to the database.
-/// 
+///
 /// The connections are customized to have a `busy_timeout` of 1000 milliseconds.
 /// The pool has an overall `connection_timeout` of 500 milliseconds.
 ///
@@ -63,7 +161,7 @@ pub fn create_connection_pool() -> Pool<ConnectionManager<SqliteConnection>> {
             .expect("Failed to retrieve database URL."),
     );
     let options = ConnectionOptions {
-        busy_timeout: Some(Duration::from_millis(1000)),
+        busy_timeout: Some(BUSY_TIMEOUT),
     };
     r2d2::Pool::builder()
         .connection_customizer(Box::new(options))
@@ -0,0 +1,387 @@
+//! Disk usage analyzer, similar to `ncdu`.
+//!
+//! A directory tree is scanned once and kept in memory, so it can be browsed afterwards without
+//! reading the disk again. Sizes are the space allocated on disk, which is what fills a
+//! filesystem. The apparent sizes, as shown by `ls`, are reported as well.
+//!
+//! Like `ncdu -x`, the scan stays on the filesystem of the scanned directory and does not follow
+//! symbolic links. Files with several hard links are only counted once.
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+struct File {
+    name: OsString,
+    size: u64,
+    apparent_size: u64,
+}
+
+struct Directory {
+    name: OsString,
+    /// Includes the directory itself and everything below it
+    size: u64,
+    apparent_size: u64,
+    /// Number of files and directories below this directory
+    items: u64,
+    /// Sorted by size, largest first
+    directories: Vec<Directory>,
+    /// Sorted by size, largest first
+    files: Vec<File>,
+}
+
+impl Directory {
+    fn new(name: OsString, metadata: &Metadata) -> Self {
+        Directory {
+            name,
+            size: allocated(metadata),
+            apparent_size: metadata.len(),
+            items: 0,
+            directories: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn child(&self, name: &std::ffi::OsStr) -> Option<&Directory> {
+        self.directories.iter().find(|d| d.name == name)
+    }
+}
+
+/// Bytes allocated on disk
+fn allocated(metadata: &Metadata) -> u64 {
+    // st_blocks is always counted in units of 512 bytes
+    metadata.blocks() * 512
+}
+
+struct Scanner {
+    device: u64,
+    /// Device and inode of files with several hard links that were already counted
+    hard_links: HashSet<(u64, u64)>,
+    errors: u64,
+}
+
+impl Scanner {
+    fn scan(&mut self, path: &Path, name: OsString, metadata: &Metadata) -> Directory {
+        let mut directory = Directory::new(name, metadata);
+        let Ok(entries) = fs::read_dir(path) else {
+            self.errors += 1;
+            return directory;
+        };
+
+        for entry in entries {
+            let Ok(entry) = entry else {
+                self.errors += 1;
+                continue;
+            };
+            // Does not follow symbolic links
+            let Ok(metadata) = entry.metadata() else {
+                self.errors += 1;
+                continue;
+            };
+
+            if metadata.is_dir() {
+                if metadata.dev() != self.device {
+                    continue;
+                }
+                let child = self.scan(&entry.path(), entry.file_name(), &metadata);
+                directory.size += child.size;
+                directory.apparent_size += child.apparent_size;
+                directory.items += child.items + 1;
+                directory.directories.push(child);
+            } else {
+                directory.items += 1;
+                if metadata.nlink() > 1 && !self.hard_links.insert((metadata.dev(), metadata.ino()))
+                {
+                    continue;
+                }
+                let file = File {
+                    name: entry.file_name(),
+                    size: allocated(&metadata),
+                    apparent_size: metadata.len(),
+                };
+                directory.size += file.size;
+                directory.apparent_size += file.apparent_size;
+                directory.files.push(file);
+            }
+        }
+
+        directory
+            .directories
+            .sort_unstable_by_key(|d| Reverse(d.size));
+        directory.files.sort_unstable_by_key(|f| Reverse(f.size));
+        directory
+    }
+}
+
+/// The result of scanning a directory tree
+pub struct DiskUsage {
+    root: PathBuf,
+    /// Seconds since the UNIX epoch
+    scanned_at: u64,
+    errors: u64,
+    tree: Directory,
+}
+
+/// A file or directory in a [`UsageView`]
+#[derive(Serialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageEntry {
+    pub name: String,
+    pub path: String,
+    /// Bytes allocated on disk
+    pub size: u64,
+    pub apparent_size: u64,
+    /// Number of files and directories below a directory, zero for files
+    pub items: u64,
+}
+
+/// A directory of a scanned tree
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageView {
+    pub path: String,
+    /// Bytes allocated on disk
+    pub size: u64,
+    pub apparent_size: u64,
+    pub items: u64,
+    /// The largest directories directly in this directory
+    pub directories: Vec<UsageEntry>,
+    /// The largest files directly in this directory
+    pub files: Vec<UsageEntry>,
+    /// The largest files anywhere below this directory
+    pub largest_files: Vec<UsageEntry>,
+}
+
+// The tree is left out, as it may contain millions of entries
+impl std::fmt::Debug for DiskUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiskUsage")
+            .field("root", &self.root)
+            .field("scanned_at", &self.scanned_at)
+            .field("errors", &self.errors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DiskUsage {
+    /// Scan the directory tree below `root`.
+    ///
+    /// This reads the metadata of every file in the tree and can take several minutes on large
+    /// filesystems. Entries that can not be read are skipped and counted in [`DiskUsage::errors`].
+    pub fn scan(root: &Path) -> io::Result<DiskUsage> {
+        let root = fs::canonicalize(root)?;
+        let metadata = fs::metadata(&root)?;
+        if !metadata.is_dir() {
+            return Err(io::Error::from(io::ErrorKind::NotADirectory));
+        }
+
+        let mut scanner = Scanner {
+            device: metadata.dev(),
+            hard_links: HashSet::new(),
+            errors: 0,
+        };
+        let tree = scanner.scan(&root, root.as_os_str().to_owned(), &metadata);
+
+        Ok(DiskUsage {
+            root,
+            scanned_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            errors: scanner.errors,
+            tree,
+        })
+    }
+
+    /// The canonical path of the scanned directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Seconds since the UNIX epoch
+    pub fn scanned_at(&self) -> u64 {
+        self.scanned_at
+    }
+
+    /// Number of directories and files that could not be read
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Check if `path` is the scanned directory or inside of it.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+
+    /// The largest entries of a directory in the scanned tree, at most `limit` of each kind.
+    ///
+    /// Returns `None` if `path` is not a directory of the scanned tree.
+    pub fn view(&self, path: &Path, limit: usize) -> Option<UsageView> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut directory = &self.tree;
+        let mut path = self.root.clone();
+        for component in relative.components() {
+            let Component::Normal(name) = component else {
+                return None;
+            };
+            directory = directory.child(name)?;
+            path.push(name);
+        }
+
+        let entry = |name: &OsString, size, apparent_size, items| UsageEntry {
+            name: name.to_string_lossy().to_string(),
+            path: path.join(name).to_string_lossy().to_string(),
+            size,
+            apparent_size,
+            items,
+        };
+
+        let mut largest = BinaryHeap::new();
+        largest_files(directory, &path, limit, &mut largest);
+        let largest_files = largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((size, apparent_size, path))| UsageEntry {
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                path: path.to_string_lossy().to_string(),
+                size,
+                apparent_size,
+                items: 0,
+            })
+            .collect();
+
+        Some(UsageView {
+            path: path.to_string_lossy().to_string(),
+            size: directory.size,
+            apparent_size: directory.apparent_size,
+            items: directory.items,
+            directories: directory
+                .directories
+                .iter()
+                .take(limit)
+                .map(|d| entry(&d.name, d.size, d.apparent_size, d.items))
+                .collect(),
+            files: directory
+                .files
+                .iter()
+                .take(limit)
+                .map(|f| entry(&f.name, f.size, f.apparent_size, 0))
+                .collect(),
+            largest_files,
+        })
+    }
+}
+
+/// Collect the `limit` largest files below `directory` into a min-heap.
+fn largest_files(
+    directory: &Directory,
+    path: &Path,
+    limit: usize,
+    largest: &mut BinaryHeap<Reverse<(u64, u64, PathBuf)>>,
+) {
+    if limit == 0 {
+        return;
+    }
+    for file in &directory.files {
+        if largest.len() == limit {
+            let Some(Reverse((smallest, _, _))) = largest.peek() else {
+                break;
+            };
+            // The files are sorted, so none of the remaining ones is larger
+            if file.size <= *smallest {
+                break;
+            }
+            largest.pop();
+        }
+        largest.push(Reverse((
+            file.size,
+            file.apparent_size,
+            path.join(&file.name),
+        )));
+    }
+    for child in &directory.directories {
+        largest_files(child, &path.join(&child.name), limit, largest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn analyze_tree() {
+        let root = std::env::temp_dir().join(format!("zentrox_analyzer_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("videos/2024")).unwrap();
+        fs::create_dir_all(root.join("documents")).unwrap();
+        fs::write(root.join("videos/2024/holiday.mkv"), vec![1; 64 * 1024]).unwrap();
+        fs::write(root.join("videos/trailer.mp4"), vec![1; 32 * 1024]).unwrap();
+        fs::write(root.join("documents/notes.txt"), vec![1; 8 * 1024]).unwrap();
+        fs::write(root.join("backup.tar"), vec![1; 16 * 1024]).unwrap();
+        fs::hard_link(root.join("backup.tar"), root.join("documents/backup.tar")).unwrap();
+        symlink("/usr", root.join("usr")).unwrap();
+
+        let usage = DiskUsage::scan(&root.join("videos/..")).unwrap();
+        assert_eq!(usage.root(), fs::canonicalize(&root).unwrap());
+        assert_eq!(usage.errors(), 0);
+
+        let view = usage.view(usage.root(), 10).unwrap();
+        assert_eq!(view.path, usage.root().to_string_lossy());
+        // 3 directories, 5 files and the symbolic link
+        assert_eq!(view.items, 9);
+        // Directories count with their own size, the hard link only once
+        assert_eq!(view.apparent_size - (64 + 32 + 8 + 16) * 1024, {
+            let directories = ["", "videos", "videos/2024", "documents"];
+            directories
+                .iter()
+                .map(|d| fs::metadata(root.join(d)).unwrap().len())
+                .sum::<u64>()
+                + fs::symlink_metadata(root.join("usr")).unwrap().len()
+        });
+        let directories: Vec<&str> = view.directories.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(directories, vec!["videos", "documents"]);
+        assert_eq!(view.directories[0].items, 3);
+        let documents = usage.view(&usage.root().join("documents"), 10).unwrap();
+        assert_eq!(view.files.len() + documents.files.len(), 3);
+
+        let largest: Vec<&str> = view.largest_files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(largest[..3], ["holiday.mkv", "trailer.mp4", "backup.tar"]);
+        assert_eq!(
+            view.largest_files[0].path,
+            usage
+                .root()
+                .join("videos/2024/holiday.mkv")
+                .to_string_lossy()
+        );
+
+        let videos = usage.view(&usage.root().join("videos"), 1).unwrap();
+        assert_eq!(videos.directories.len(), 1);
+        assert_eq!(videos.files[0].name, "trailer.mp4");
+        assert_eq!(videos.largest_files.len(), 1);
+        assert_eq!(videos.largest_files[0].name, "holiday.mkv");
+
+        assert!(usage.view(&usage.root().join("usr"), 10).is_none());
+        assert!(
+            usage
+                .view(&usage.root().join("videos/../documents"), 10)
+                .is_none()
+        );
+        assert!(usage.view(Path::new("/usr"), 10).is_none());
+        assert!(usage.contains(&usage.root().join("documents")));
+        assert!(DiskUsage::scan(&root.join("backup.tar")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,400 @@
+//! Reading and editing `/etc/fstab`.
+//!
+//! Comments, blank lines and the order of entries are kept when the file is written. Before the
+//! file is replaced, the new contents are checked using `mount --all --fake` and the previous
+//! file is kept as `/etc/fstab.zentrox-backup`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use super::mounts::MountError;
+use crate::sudo::{SudoCommand, SudoCredential, write_system_file};
+
+pub const FSTAB: &str = "/etc/fstab";
+const BACKUP: &str = "/etc/fstab.zentrox-backup";
+
+/// Decode the octal escapes used in `/etc/fstab` and `/proc/self/mounts`, like `\040` for a
+/// space.
+pub fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(code) = field
+                .get(i + 1..i + 4)
+                .and_then(|c| u8::from_str_radix(c, 8).ok())
+        {
+            decoded.push(code);
+            i += 4;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escape whitespace and backslashes, which would split or corrupt a field.
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A line of `/etc/fstab` describing a filesystem
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FstabEntry {
+    /// Device path, `UUID=…`, `LABEL=…`, `//server/share` or `server:/export`
+    pub source: String,
+    /// `none` for swap
+    pub mountpoint: String,
+    /// Like `ext4`, `nfs`, `cifs` or `swap`
+    pub filesystem: String,
+    pub options: Vec<String>,
+    /// Whether the filesystem is backed up by `dump`, usually 0
+    #[serde(default)]
+    pub dump: u8,
+    /// Order in which filesystems are checked at boot, 1 for the root filesystem, 2 for others
+    /// and 0 to skip the check
+    #[serde(default)]
+    pub pass: u8,
+}
+
+impl FstabEntry {
+    /// Parse a line with at least the source, mountpoint and filesystem. Missing options default
+    /// to `defaults`, like mount does.
+    fn parse(line: &str) -> Option<FstabEntry> {
+        let line = line.trim();
+        if line.starts_with('#') {
+            return None;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if !(3..=6).contains(&fields.len()) {
+            return None;
+        }
+        Some(FstabEntry {
+            source: unescape(fields[0]),
+            mountpoint: unescape(fields[1]),
+            filesystem: fields[2].to_string(),
+            options: fields
+                .get(3)
+                .unwrap_or(&"defaults")
+                .split(',')
+                .map(String::from)
+                .collect(),
+            dump: fields.get(4).and_then(|d| d.parse().ok()).unwrap_or(0),
+            pass: fields.get(5).and_then(|p| p.parse().ok()).unwrap_or(0),
+        })
+    }
+
+    fn is_swap(&self) -> bool {
+        self.filesystem == "swap"
+    }
+
+    pub fn validate(&self) -> Result<(), MountError> {
+        let invalid = |message: &str| Err(MountError::Invalid(message.to_string()));
+        if self.source.trim().is_empty() || self.source.chars().any(char::is_control) {
+            return invalid("The source is empty or contains control characters.");
+        }
+        if self.is_swap() {
+            if self.mountpoint != "none" && self.mountpoint != "swap" {
+                return invalid("The mountpoint of swap has to be none.");
+            }
+        } else if !super::mounts::is_valid_path(&self.mountpoint) {
+            return invalid("The mountpoint has to be an absolute path.");
+        }
+        if !super::mounts::is_valid_filesystem(&self.filesystem) {
+            return invalid("The filesystem type is invalid.");
+        }
+        if self.options.is_empty()
+            || !self
+                .options
+                .iter()
+                .all(|o| super::mounts::is_valid_option(o))
+        {
+            return invalid(
+                "Options may not be empty or contain whitespace, commas or control characters.",
+            );
+        }
+        if self.dump > 1 || self.pass > 2 {
+            return invalid("dump has to be 0 or 1 and pass 0, 1 or 2.");
+        }
+        Ok(())
+    }
+}
+
+impl Display for FstabEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape(&self.source),
+            escape(&self.mountpoint),
+            self.filesystem,
+            self.options.join(","),
+            self.dump,
+            self.pass
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+enum FstabLine {
+    Entry(FstabEntry),
+    /// Comments, blank lines and lines that can not be parsed are kept as they are
+    Other(String),
+}
+
+/// The contents of `/etc/fstab`. Entries are addressed by their position among the entries.
+#[derive(Clone, Debug)]
+pub struct Fstab {
+    lines: Vec<FstabLine>,
+}
+
+impl Fstab {
+    pub fn parse(contents: &str) -> Fstab {
+        Fstab {
+            lines: contents
+                .lines()
+                .map(|line| match FstabEntry::parse(line) {
+                    Some(entry) => FstabLine::Entry(entry),
+                    None => FstabLine::Other(line.to_string()),
+                })
+                .collect(),
+        }
+    }
+
+    /// Read `/etc/fstab`, which is readable by everyone.
+    pub fn read() -> Result<Fstab, MountError> {
+        match fs::read_to_string(FSTAB) {
+            Ok(contents) => Ok(Fstab::parse(&contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Fstab::parse("")),
+            Err(e) => Err(MountError::Failed(format!(
+                "{FSTAB} could not be read: {e}"
+            ))),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<FstabEntry> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                FstabLine::Entry(entry) => Some(entry.clone()),
+                FstabLine::Other(_) => None,
+            })
+            .collect()
+    }
+
+    fn entry_mut(&mut self, index: usize) -> Option<&mut FstabLine> {
+        self.lines
+            .iter_mut()
+            .filter(|line| matches!(line, FstabLine::Entry(_)))
+            .nth(index)
+    }
+
+    /// Validate an entry and make sure no other entry uses its mountpoint.
+    fn check_entry(&self, entry: &FstabEntry, replaced: Option<usize>) -> Result<(), MountError> {
+        entry.validate()?;
+        let taken = self.entries().iter().enumerate().any(|(index, other)| {
+            Some(index) != replaced
+                && !entry.is_swap()
+                && other.mountpoint.trim_end_matches('/') == entry.mountpoint.trim_end_matches('/')
+        });
+        if taken {
+            return Err(MountError::Invalid(format!(
+                "{} is used by another entry.",
+                entry.mountpoint
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, entry: FstabEntry) -> Result<(), MountError> {
+        self.check_entry(&entry, None)?;
+        self.lines.push(FstabLine::Entry(entry));
+        Ok(())
+    }
+
+    pub fn replace(&mut self, index: usize, entry: FstabEntry) -> Result<(), MountError> {
+        self.check_entry(&entry, Some(index))?;
+        let line = self.entry_mut(index).ok_or(MountError::NoSuchEntry)?;
+        *line = FstabLine::Entry(entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<FstabEntry, MountError> {
+        let position = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches!(line, FstabLine::Entry(_)))
+            .nth(index)
+            .map(|(position, _)| position)
+            .ok_or(MountError::NoSuchEntry)?;
+        match self.lines.remove(position) {
+            FstabLine::Entry(entry) => Ok(entry),
+            FstabLine::Other(_) => Err(MountError::NoSuchEntry),
+        }
+    }
+
+    /// Check the contents using `mount --all --fake`, which resolves the sources and checks the
+    /// mountpoints and options of all entries without mounting them.
+    pub fn check(&self, credential: &SudoCredential) -> Result<(), MountError> {
+        let staged = crate::database::data_directory()
+            .join(format!("fstab-{}.staged", uuid::Uuid::new_v4()));
+        fs::write(&staged, self.to_string())
+            .map_err(|e| MountError::Failed(format!("The fstab could not be staged: {e}")))?;
+        let output = SudoCommand::with_credential(credential.clone(), "mount")
+            .args(vec!["--all", "--fake", "--verbose", "--fstab"])
+            .arg(staged.display())
+            .output();
+        let _ = fs::remove_file(&staged);
+        let output = output?;
+        match output.status {
+            Some(0) => Ok(()),
+            _ => Err(MountError::CheckFailed(
+                output
+                    .stderr
+                    .replace(&staged.display().to_string(), FSTAB)
+                    .trim()
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Check the contents and replace `/etc/fstab`, keeping the previous file as a backup.
+    pub fn write(&self, credential: &SudoCredential) -> Result<(), MountError> {
+        self.check(credential)?;
+        if Path::new(FSTAB).exists() {
+            let backup = SudoCommand::with_credential(credential.clone(), "cp")
+                .args(vec!["-p", FSTAB, BACKUP])
+                .output()?;
+            if backup.status != Some(0) {
+                return Err(MountError::Failed(backup.stderr.trim().to_string()));
+            }
+        }
+        let output = write_system_file(credential.clone(), Path::new(FSTAB), &self.to_string())?;
+        match output.status {
+            Some(0) => Ok(()),
+            _ => Err(MountError::Failed(output.stderr.trim().to_string())),
+        }
+    }
+}
+
+impl Display for Fstab {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                FstabLine::Entry(entry) => writeln!(f, "{entry}")?,
+                FstabLine::Other(line) => writeln!(f, "{line}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FSTAB_CONTENTS: &str = "# /etc/fstab: static file system information.
+UUID=0a3407de-014b-458b-b5c1-848e92a327a3 /               ext4    errors=remount-ro 0       1
+UUID=6F1C-2A3B  /boot/efi       vfat    umask=0077      0       1
+/swapfile                                 none            swap    sw              0       0
+
+//nas/media\\040files /mnt/media\\040files cifs credentials=/etc/zentrox/cifs/nas_media,_netdev 0 0
+nas:/export/backup /mnt/backup nfs
+malformed
+";
+
+    fn entry(source: &str, mountpoint: &str, filesystem: &str) -> FstabEntry {
+        FstabEntry {
+            source: source.to_string(),
+            mountpoint: mountpoint.to_string(),
+            filesystem: filesystem.to_string(),
+            options: vec!["defaults".to_string()],
+            dump: 0,
+            pass: 0,
+        }
+    }
+
+    #[test]
+    fn parse_fstab() {
+        let fstab = Fstab::parse(FSTAB_CONTENTS);
+        let entries = fstab.entries();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].mountpoint, "/");
+        assert_eq!(entries[0].options, vec!["errors=remount-ro"]);
+        assert_eq!(entries[0].pass, 1);
+        assert_eq!(entries[2].filesystem, "swap");
+        assert_eq!(entries[3].source, "//nas/media files");
+        assert_eq!(entries[3].mountpoint, "/mnt/media files");
+        assert_eq!(
+            entries[3].options,
+            vec!["credentials=/etc/zentrox/cifs/nas_media", "_netdev"]
+        );
+        assert_eq!(entries[4].options, vec!["defaults"]);
+        assert!(entries.iter().all(|e| e.validate().is_ok()));
+
+        // Unchanged lines are kept, changed entries are escaped again
+        let written = fstab.to_string();
+        assert!(written.starts_with("# /etc/fstab: static file system information.\n"));
+        assert!(written.contains("//nas/media\\040files\t/mnt/media\\040files\tcifs"));
+        assert!(written.ends_with("malformed\n"));
+        assert_eq!(Fstab::parse(&written).entries(), entries);
+    }
+
+    #[test]
+    fn edit_fstab() {
+        let mut fstab = Fstab::parse(FSTAB_CONTENTS);
+        fstab.add(entry("LABEL=data", "/mnt/data", "xfs")).unwrap();
+        assert!(matches!(
+            fstab.add(entry("LABEL=other", "/mnt/data/", "xfs")),
+            Err(MountError::Invalid(_))
+        ));
+        assert!(matches!(
+            fstab.add(entry("/dev/sdb1", "relative", "ext4")),
+            Err(MountError::Invalid(_))
+        ));
+        assert!(matches!(
+            fstab.add(entry("/dev/sdb1", "/mnt/x", "ext 4")),
+            Err(MountError::Invalid(_))
+        ));
+        let mut bad_options = entry("/dev/sdb1", "/mnt/x", "ext4");
+        bad_options.options = vec!["rw,exec".to_string()];
+        assert!(matches!(
+            fstab.add(bad_options),
+            Err(MountError::Invalid(_))
+        ));
+
+        // The mountpoint of the replaced entry may be kept
+        fstab
+            .replace(4, entry("nas:/export/backup", "/mnt/backup", "nfs4"))
+            .unwrap();
+        assert_eq!(fstab.entries()[4].filesystem, "nfs4");
+        assert!(matches!(
+            fstab.replace(9, entry("/dev/sdb1", "/mnt/x", "ext4")),
+            Err(MountError::NoSuchEntry)
+        ));
+
+        let removed = fstab.remove(2).unwrap();
+        assert_eq!(removed.source, "/swapfile");
+        assert_eq!(fstab.entries().len(), 5);
+        assert!(
+            fstab
+                .to_string()
+                .contains("LABEL=data\t/mnt/data\txfs\tdefaults\t0\t0\n")
+        );
+        assert!(matches!(fstab.remove(5), Err(MountError::NoSuchEntry)));
+    }
+}
@@ -3,6 +3,13 @@ use serde::Deserialize;
 use std::process::Command;
 use utoipa::ToSchema;
 
+pub mod analyzer;
+pub mod fstab;
+pub mod mounts;
+pub mod partitions;
+pub mod smart;
+pub mod usage;
+
 #[derive(Debug)]
 pub enum DriveError {
     CommandError,
@@ -0,0 +1,503 @@
+//! Mounting and unmounting filesystems, including CIFS and NFS network shares.
+//!
+//! Everything runs using sudo. The credentials of CIFS shares are stored in files below
+//! [CIFS_CREDENTIALS], which are only readable by root, so passwords do not end up in the
+//! options of a mount or in `/etc/fstab`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::fstab::{Fstab, FstabEntry, unescape};
+use crate::sudo::{SudoCredential, SudoError, run_checked, write_secret_system_file};
+
+/// Directory of the credentials files of CIFS shares
+pub const CIFS_CREDENTIALS: &str = "/etc/zentrox/cifs";
+
+/// Mountpoints that are part of the operating system and may not be mounted over or unmounted
+const PROTECTED_MOUNTPOINTS: [&str; 17] = [
+    "/",
+    "/bin",
+    "/boot",
+    "/boot/efi",
+    "/efi",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/root",
+    "/run",
+    "/sbin",
+    "/srv",
+    "/tmp",
+    "/usr",
+    "/var",
+];
+/// Trees of virtual filesystems managed by the kernel and systemd
+const PROTECTED_TREES: [&str; 3] = ["/proc", "/sys", "/dev"];
+
+#[derive(Debug, Error)]
+pub enum MountError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Nothing is mounted there.")]
+    NotMounted,
+    #[error("The fstab entry does not exist.")]
+    NoSuchEntry,
+    /// `mount --all --fake` rejected the new fstab
+    #[error("{0}")]
+    CheckFailed(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for MountError {
+    fn from(error: SudoError) -> Self {
+        MountError::Sudo(error)
+    }
+}
+
+/// Absolute paths without `.` and `..`, which could be used to escape the checks below.
+pub fn is_valid_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.chars().any(char::is_control)
+        && !path
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+}
+
+/// Filesystem types like `ext4`, `nfs4` or `fuse.sshfs`.
+pub fn is_valid_filesystem(filesystem: &str) -> bool {
+    !filesystem.is_empty()
+        && filesystem
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '_')
+}
+
+/// Options are joined using commas, so they may not contain commas themselves.
+pub fn is_valid_option(option: &str) -> bool {
+    !option.is_empty()
+        && !option
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == ',')
+}
+
+pub(crate) fn is_protected(mountpoint: &str) -> bool {
+    let mountpoint = match mountpoint.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    PROTECTED_MOUNTPOINTS.contains(&mountpoint)
+        || PROTECTED_TREES
+            .iter()
+            .any(|tree| Path::new(mountpoint).starts_with(tree))
+}
+
+/// A mounted filesystem as listed in `/proc/self/mounts`
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Mount {
+    pub source: String,
+    pub mountpoint: String,
+    pub filesystem: String,
+    pub options: Vec<String>,
+}
+
+fn parse_mounts(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some(Mount {
+                source: unescape(fields.first()?),
+                mountpoint: unescape(fields.get(1)?),
+                filesystem: fields.get(2)?.to_string(),
+                options: fields.get(3)?.split(',').map(String::from).collect(),
+            })
+        })
+        .collect()
+}
+
+/// The mounted filesystems, including virtual ones like `proc`.
+pub fn list_mounts() -> Result<Vec<Mount>, MountError> {
+    fs::read_to_string("/proc/self/mounts")
+        .map(|contents| parse_mounts(&contents))
+        .map_err(|e| MountError::Failed(format!("The mounts could not be read: {e}")))
+}
+
+/// What to mount
+#[derive(Deserialize, ToSchema, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MountSource {
+    /// A block device, like `/dev/sdb1`
+    Device {
+        path: String,
+    },
+    Uuid {
+        uuid: String,
+    },
+    Label {
+        label: String,
+    },
+    /// A CIFS (SMB) share like `//server/share`. Without a username, the share is mounted as a
+    /// guest.
+    Cifs {
+        share: String,
+        username: Option<String>,
+        password: Option<String>,
+        domain: Option<String>,
+    },
+    /// An NFS export like `server:/export`
+    Nfs {
+        export: String,
+    },
+}
+
+impl MountSource {
+    /// The source as understood by mount and in `/etc/fstab`.
+    fn spec(&self) -> Result<String, MountError> {
+        let invalid = |message: &str| Err(MountError::Invalid(message.to_string()));
+        match self {
+            MountSource::Device { path } => {
+                if !path.starts_with("/dev/") || !is_valid_path(path) || !Path::new(path).exists() {
+                    return invalid("The device does not exist.");
+                }
+                Ok(path.clone())
+            }
+            MountSource::Uuid { uuid } => {
+                if uuid.is_empty() || !uuid.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+                    return invalid("The UUID is malformed.");
+                }
+                Ok(format!("UUID={uuid}"))
+            }
+            MountSource::Label { label } => {
+                if label.is_empty() || label.chars().any(|c| c.is_control() || c == '/') {
+                    return invalid(
+                        "The label is empty or contains slashes or control characters.",
+                    );
+                }
+                Ok(format!("LABEL={label}"))
+            }
+            MountSource::Cifs { share, .. } => {
+                let valid = share
+                    .strip_prefix("//")
+                    .and_then(|s| s.split_once('/'))
+                    .is_some_and(|(server, path)| !server.is_empty() && !path.is_empty())
+                    && !share.chars().any(char::is_control);
+                if !valid {
+                    return invalid("The share has to look like //server/share.");
+                }
+                Ok(share.clone())
+            }
+            MountSource::Nfs { export } => {
+                let valid = export.split_once(':').is_some_and(|(server, path)| {
+                    !server.is_empty() && !server.contains('/') && path.starts_with('/')
+                }) && !export.chars().any(|c| c.is_control() || c.is_whitespace());
+                if !valid {
+                    return invalid("The export has to look like server:/export.");
+                }
+                Ok(export.clone())
+            }
+        }
+    }
+
+    fn default_filesystem(&self) -> Option<&'static str> {
+        match self {
+            MountSource::Cifs { .. } => Some("cifs"),
+            MountSource::Nfs { .. } => Some("nfs"),
+            _ => None,
+        }
+    }
+}
+
+/// The credentials file of a CIFS share, named after the share, like `nas_media` for
+/// `//nas/media`.
+pub fn cifs_credentials_path(share: &str) -> PathBuf {
+    let name: String = share
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    Path::new(CIFS_CREDENTIALS).join(name.trim_matches('_'))
+}
+
+fn cifs_credentials(
+    username: &str,
+    password: Option<&str>,
+    domain: Option<&str>,
+) -> Result<String, MountError> {
+    let values = [Some(username), password, domain];
+    if values.iter().flatten().any(|v| v.contains(['\n', '\r'])) {
+        return Err(MountError::Invalid(
+            "The credentials may not contain line breaks.".to_string(),
+        ));
+    }
+    let mut contents = format!("username={username}\npassword={}\n", password.unwrap_or(""));
+    if let Some(domain) = domain {
+        contents.push_str(&format!("domain={domain}\n"));
+    }
+    Ok(contents)
+}
+
+/// A filesystem to mount
+#[derive(Deserialize, ToSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MountRequest {
+    pub source: MountSource,
+    /// Created if it does not exist
+    pub mountpoint: String,
+    /// Detected by mount if missing, `cifs` and `nfs` for network shares
+    pub filesystem: Option<String>,
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Also add the filesystem to `/etc/fstab`, so it is mounted at boot
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn run(credential: &SudoCredential, program: &str, args: Vec<String>) -> Result<(), MountError> {
+    run_checked(credential, program, args, MountError::Failed).map(|_| ())
+}
+
+/// Create a mountpoint if it does not exist yet.
+pub fn create_mountpoint(mountpoint: &str, credential: &SudoCredential) -> Result<(), MountError> {
+    if Path::new(mountpoint).is_dir() {
+        return Ok(());
+    }
+    run(
+        credential,
+        "mkdir",
+        vec!["-p".to_string(), "--".to_string(), mountpoint.to_string()],
+    )
+}
+
+/// Mount a filesystem and optionally add it to `/etc/fstab`.
+pub fn mount(request: MountRequest, credential: &SudoCredential) -> Result<(), MountError> {
+    let spec = request.source.spec()?;
+    if !is_valid_path(&request.mountpoint) || is_protected(&request.mountpoint) {
+        return Err(MountError::Invalid(format!(
+            "Nothing can be mounted on {}.",
+            request.mountpoint
+        )));
+    }
+    let filesystem = request
+        .filesystem
+        .clone()
+        .or(request.source.default_filesystem().map(String::from));
+    if filesystem
+        .as_deref()
+        .is_some_and(|f| !is_valid_filesystem(f))
+    {
+        return Err(MountError::Invalid(
+            "The filesystem type is invalid.".to_string(),
+        ));
+    }
+    if !request.options.iter().all(|o| is_valid_option(o)) {
+        return Err(MountError::Invalid(
+            "Options may not contain whitespace, commas or control characters.".to_string(),
+        ));
+    }
+    let mountpoint = request.mountpoint.trim_end_matches('/');
+    if list_mounts()?.iter().any(|m| m.mountpoint == mountpoint) {
+        return Err(MountError::Invalid(format!(
+            "Something is already mounted on {mountpoint}."
+        )));
+    }
+
+    let mut options = request.options.clone();
+    if let MountSource::Cifs {
+        share,
+        username,
+        password,
+        domain,
+    } = &request.source
+    {
+        match username {
+            Some(username) => {
+                let path = cifs_credentials_path(share);
+                let contents = cifs_credentials(username, password.as_deref(), domain.as_deref())?;
+                let output = write_secret_system_file(credential.clone(), &path, &contents)?;
+                if output.status != Some(0) {
+                    return Err(MountError::Failed(output.stderr.trim().to_string()));
+                }
+                options.push(format!("credentials={}", path.display()));
+            }
+            None => options.push("guest".to_string()),
+        }
+    }
+
+    create_mountpoint(mountpoint, credential)?;
+    // The entry is checked before mounting, so a rejected entry does not leave the filesystem
+    // mounted
+    let fstab = if request.persist {
+        let mut fstab_options = options.clone();
+        // Network shares can only be mounted once the network is up
+        if matches!(
+            request.source,
+            MountSource::Cifs { .. } | MountSource::Nfs { .. }
+        ) {
+            fstab_options.push("_netdev".to_string());
+        }
+        if fstab_options.is_empty() {
+            fstab_options.push("defaults".to_string());
+        }
+        let mut fstab = Fstab::read()?;
+        fstab.add(FstabEntry {
+            source: spec.clone(),
+            mountpoint: mountpoint.to_string(),
+            filesystem: filesystem.clone().unwrap_or_else(|| "auto".to_string()),
+            options: fstab_options,
+            dump: 0,
+            pass: 0,
+        })?;
+        fstab.check(credential)?;
+        Some(fstab)
+    } else {
+        None
+    };
+
+    let mut args = Vec::new();
+    if let Some(filesystem) = &filesystem {
+        args.extend(["-t".to_string(), filesystem.clone()]);
+    }
+    if !options.is_empty() {
+        args.extend(["-o".to_string(), options.join(",")]);
+    }
+    args.extend(["--".to_string(), spec, mountpoint.to_string()]);
+    run(credential, "mount", args)?;
+
+    if let Some(fstab) = fstab
+        && let Err(e) = fstab.write(credential)
+    {
+        // Only keep the filesystem mounted if the request succeeds as a whole
+        let _ = run(
+            credential,
+            "umount",
+            vec!["--".to_string(), mountpoint.to_string()],
+        );
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Unmount the filesystem mounted on `target` or the filesystem of the device `target`. Lazy
+/// unmounting detaches the filesystem immediately and cleans up once it is no longer busy.
+pub fn unmount(target: &str, lazy: bool, credential: &SudoCredential) -> Result<(), MountError> {
+    let mounts = list_mounts()?;
+    let target = match target.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    // The latest mount on a mountpoint hides the earlier ones
+    let mount = mounts
+        .iter()
+        .rev()
+        .find(|m| m.mountpoint == target)
+        .or_else(|| mounts.iter().rev().find(|m| m.source == target))
+        .ok_or(MountError::NotMounted)?;
+    if is_protected(&mount.mountpoint) {
+        return Err(MountError::Invalid(format!(
+            "{} can not be unmounted.",
+            mount.mountpoint
+        )));
+    }
+
+    let mut args = Vec::new();
+    if lazy {
+        args.push("--lazy".to_string());
+    }
+    args.extend(["--".to_string(), mount.mountpoint.clone()]);
+    run(credential, "umount", args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_mounts() {
+        assert!(is_valid_path("/mnt/usb disk"));
+        assert!(!is_valid_path("mnt/usb"));
+        assert!(!is_valid_path("/mnt/../etc"));
+        assert!(!is_valid_path("/mnt/./usb"));
+        assert!(is_valid_filesystem("fuse.sshfs"));
+        assert!(!is_valid_filesystem("ext4 -o"));
+        assert!(is_valid_option("uid=1000"));
+        assert!(!is_valid_option("ro,exec"));
+        assert!(!is_valid_option(""));
+
+        assert!(is_protected("/"));
+        assert!(is_protected("/boot/efi/"));
+        assert!(is_protected("/dev/shm"));
+        assert!(!is_protected("/mnt/usb"));
+        assert!(!is_protected("/run/media/user/USB"));
+        assert!(!is_protected("/device"));
+
+        let spec = |source: MountSource| source.spec().ok();
+        assert_eq!(
+            spec(MountSource::Uuid {
+                uuid: "6F1C-2A3B".to_string()
+            }),
+            Some("UUID=6F1C-2A3B".to_string())
+        );
+        assert_eq!(
+            spec(MountSource::Uuid {
+                uuid: "x; rm".to_string()
+            }),
+            None
+        );
+        assert_eq!(
+            spec(MountSource::Label {
+                label: "../x".to_string()
+            }),
+            None
+        );
+        assert_eq!(
+            spec(MountSource::Nfs {
+                export: "nas:/export/backup".to_string()
+            }),
+            Some("nas:/export/backup".to_string())
+        );
+        assert_eq!(
+            spec(MountSource::Nfs {
+                export: "nas:export".to_string()
+            }),
+            None
+        );
+        let cifs = |share: &str| MountSource::Cifs {
+            share: share.to_string(),
+            username: None,
+            password: None,
+            domain: None,
+        };
+        assert_eq!(spec(cifs("//nas/media")), Some("//nas/media".to_string()));
+        assert_eq!(spec(cifs("//nas")), None);
+        assert_eq!(spec(cifs("nas/media")), None);
+
+        assert_eq!(
+            cifs_credentials_path("//nas/media files"),
+            Path::new("/etc/zentrox/cifs/nas_media_files")
+        );
+        assert_eq!(
+            cifs_credentials("user", Some("secret"), Some("WORKGROUP")).unwrap(),
+            "username=user\npassword=secret\ndomain=WORKGROUP\n"
+        );
+        assert!(cifs_credentials("user", Some("secret\nusername=root"), None).is_err());
+    }
+
+    #[test]
+    fn parse_proc_mounts() {
+        let mounts = parse_mounts(
+            "proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sdb1 /media/usb\\040disk vfat rw,relatime,fmask=0022 0 0
+",
+        );
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[1].mountpoint, "/media/usb disk");
+        assert_eq!(mounts[1].options, vec!["rw", "relatime", "fmask=0022"]);
+    }
+}
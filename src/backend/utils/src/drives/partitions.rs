@@ -0,0 +1,886 @@
+//! Partitioning disks and creating filesystems.
+//!
+//! Partition tables are always GPT and are changed using `parted`. Everything runs using sudo and
+//! refuses to touch disks that are in use: Nothing on the disk may be mounted, used as swap or be
+//! part of an LVM volume group, RAID array or encrypted volume that is active. Members of ZFS pools
+//! and RAID arrays are refused even if inactive, as are devices of a mounted btrfs filesystem
+//! spanning several devices. Disks holding a directory of the operating system, like `/` or
+//! `/boot`, can not be changed at all.
+//!
+//! Destructive steps need to be confirmed by passing the path of the device as confirmation, so a
+//! typo in the path can not destroy the wrong disk.
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::mounts::{self, Mount, is_protected};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, run_checked};
+
+/// Partitions start and end at multiples of 1 MiB, which suits all kinds of disks.
+const ALIGNMENT: u64 = 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum PartitionError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("{0} is not a block device.")]
+    NoSuchDevice(String),
+    #[error("Type the path of the device to confirm.")]
+    Unconfirmed,
+    #[error("{0}")]
+    Invalid(String),
+    /// The device is in use or holds the operating system
+    #[error("{0}")]
+    Refused(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for PartitionError {
+    fn from(error: SudoError) -> Self {
+        PartitionError::Sudo(error)
+    }
+}
+
+/// Filesystems that can be created
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Filesystem {
+    Ext4,
+    Xfs,
+    Btrfs,
+    /// Readable by Windows and macOS, suited for external drives
+    Exfat,
+    /// FAT32, used for EFI system partitions
+    Vfat,
+}
+
+impl Filesystem {
+    /// The name used by lsblk and blkid
+    fn name(&self) -> &'static str {
+        match self {
+            Filesystem::Ext4 => "ext4",
+            Filesystem::Xfs => "xfs",
+            Filesystem::Btrfs => "btrfs",
+            Filesystem::Exfat => "exfat",
+            Filesystem::Vfat => "vfat",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Filesystem::Ext4,
+            Filesystem::Xfs,
+            Filesystem::Btrfs,
+            Filesystem::Exfat,
+            Filesystem::Vfat,
+        ]
+        .into_iter()
+        .find(|f| f.name() == name)
+    }
+
+    /// Longest label the filesystem can store, in bytes
+    fn max_label_length(&self) -> usize {
+        match self {
+            Filesystem::Ext4 => 16,
+            Filesystem::Xfs => 12,
+            Filesystem::Btrfs => 255,
+            Filesystem::Exfat => 15,
+            Filesystem::Vfat => 11,
+        }
+    }
+
+    /// The program and arguments creating the filesystem, without the device
+    fn mkfs(&self, label: Option<&str>) -> (&'static str, Vec<String>) {
+        let (program, mut args, label_flag) = match self {
+            Filesystem::Ext4 => ("mkfs.ext4", vec!["-F"], "-L"),
+            Filesystem::Xfs => ("mkfs.xfs", vec!["-f"], "-L"),
+            Filesystem::Btrfs => ("mkfs.btrfs", vec!["-f"], "-L"),
+            Filesystem::Exfat => ("mkfs.exfat", vec![], "-L"),
+            // -I allows using a whole disk without a partition table
+            Filesystem::Vfat => ("mkfs.vfat", vec!["-I"], "-n"),
+        };
+        if let Some(label) = label {
+            args.extend([label_flag, label]);
+        }
+        (program, args.into_iter().map(String::from).collect())
+    }
+
+    /// The program and arguments changing the label of an existing filesystem on `device`
+    fn relabel(&self, device: &str, label: &str) -> (&'static str, Vec<String>) {
+        let (program, args) = match self {
+            Filesystem::Ext4 => ("e2label", vec![device, label]),
+            Filesystem::Xfs => ("xfs_admin", vec!["-L", label, device]),
+            Filesystem::Btrfs => ("btrfs", vec!["filesystem", "label", device, label]),
+            Filesystem::Exfat => ("exfatlabel", vec![device, label]),
+            Filesystem::Vfat => ("fatlabel", vec![device, label]),
+        };
+        (program, args.into_iter().map(String::from).collect())
+    }
+}
+
+fn validate_label(label: &str, filesystem: Filesystem) -> Result<(), PartitionError> {
+    if label.is_empty() || label.chars().any(char::is_control) {
+        return Err(PartitionError::Invalid(
+            "The label may not be empty or contain control characters.".to_string(),
+        ));
+    }
+    if label.len() > filesystem.max_label_length() {
+        return Err(PartitionError::Invalid(format!(
+            "Labels of {} filesystems may be at most {} bytes long.",
+            filesystem.name(),
+            filesystem.max_label_length()
+        )));
+    }
+    Ok(())
+}
+
+/// Kinds of GPT partitions
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PartitionKind {
+    /// Linux filesystem data, also used for exFAT and FAT drives
+    #[default]
+    Data,
+    /// EFI system partition
+    Efi,
+    Swap,
+}
+
+/// A partition to create in the free space of a disk
+#[derive(Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPartition {
+    /// Up to 36 letters, digits, `.`, `_` or `-`, defaults to `data`
+    pub name: Option<String>,
+    /// Size in bytes, rounded up to a multiple of 1 MiB. The partition fills the first free space
+    /// if missing.
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub kind: PartitionKind,
+}
+
+/// Partition names are passed to parted, which splits its arguments at spaces.
+fn is_valid_partition_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 36
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// A block device as listed by `lsblk`
+#[derive(Deserialize, Debug)]
+struct BlockDevice {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    fstype: Option<String>,
+    /// The UUID of the filesystem, which is shared by all devices of a btrfs filesystem
+    uuid: Option<String>,
+    mountpoint: Option<String>,
+    #[serde(default)]
+    children: Vec<BlockDevice>,
+}
+
+impl BlockDevice {
+    fn find(&self, path: &str) -> Option<&BlockDevice> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(path))
+    }
+
+    /// This device and everything on it
+    fn descendants(&self) -> Vec<&BlockDevice> {
+        let mut devices = vec![self];
+        for child in &self.children {
+            devices.extend(child.descendants());
+        }
+        devices
+    }
+
+    /// Where the device is mounted, according to lsblk and `/proc/self/mounts`. Active swap is
+    /// listed as `[SWAP]`.
+    fn mountpoints<'a>(&'a self, mounts: &'a [Mount]) -> Vec<&'a str> {
+        let mut mountpoints: Vec<&str> = self.mountpoint.iter().map(String::as_str).collect();
+        mountpoints.extend(
+            mounts
+                .iter()
+                .filter(|m| m.source == self.path)
+                .map(|m| m.mountpoint.as_str()),
+        );
+        mountpoints
+    }
+}
+
+#[derive(Deserialize)]
+struct Lsblk {
+    blockdevices: Vec<BlockDevice>,
+}
+
+fn block_devices() -> Result<Vec<BlockDevice>, PartitionError> {
+    let output = Command::new("lsblk")
+        .args(["--json", "-o", "PATH,TYPE,FSTYPE,UUID,MOUNTPOINT"])
+        .output()
+        .map_err(|e| PartitionError::Failed(format!("lsblk could not be run: {e}")))?;
+    serde_json::from_slice::<Lsblk>(&output.stdout)
+        .map(|l| l.blockdevices)
+        .map_err(|e| PartitionError::Failed(format!("The output of lsblk is malformed: {e}")))
+}
+
+/// What is changed on a device
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Change {
+    /// The partition table of a whole disk
+    Table,
+    /// The filesystem on a disk or partition
+    Filesystem,
+}
+
+/// Make sure that `path` can be changed and return it with the disk it is on.
+fn check<'a>(
+    devices: &'a [BlockDevice],
+    mounts: &[Mount],
+    path: &str,
+    change: Change,
+) -> Result<(&'a BlockDevice, &'a BlockDevice), PartitionError> {
+    let (disk, device) = devices
+        .iter()
+        .find_map(|disk| Some((disk, disk.find(path)?)))
+        .ok_or_else(|| PartitionError::NoSuchDevice(path.to_string()))?;
+
+    if !matches!(disk.kind.as_str(), "disk" | "loop") {
+        return Err(PartitionError::Invalid(format!("{path} is not a disk.")));
+    }
+    match change {
+        Change::Table if device.path != disk.path => {
+            return Err(PartitionError::Invalid(format!(
+                "{path} is not a whole disk."
+            )));
+        }
+        Change::Filesystem if !matches!(device.kind.as_str(), "disk" | "loop" | "part") => {
+            return Err(PartitionError::Invalid(format!(
+                "{path} is not a disk or partition."
+            )));
+        }
+        _ => {}
+    }
+
+    for other in disk.descendants() {
+        for mountpoint in other.mountpoints(mounts) {
+            if mountpoint == "[SWAP]" || is_protected(mountpoint) {
+                return Err(PartitionError::Refused(format!(
+                    "{} holds the system, {} is used as {mountpoint}.",
+                    disk.path, other.path
+                )));
+            }
+        }
+    }
+
+    for inner in device.descendants() {
+        if let Some(mountpoint) = inner.mountpoints(mounts).first() {
+            return Err(PartitionError::Refused(format!(
+                "{} is mounted on {mountpoint}.",
+                inner.path
+            )));
+        }
+        // The datasets of a pool are mounted by their name and arrays may be inactive
+        match inner.fstype.as_deref() {
+            Some("zfs_member") => {
+                return Err(PartitionError::Refused(format!(
+                    "{} is part of a ZFS pool.",
+                    inner.path
+                )));
+            }
+            Some("linux_raid_member") => {
+                return Err(PartitionError::Refused(format!(
+                    "{} is part of a RAID array.",
+                    inner.path
+                )));
+            }
+            _ => {}
+        }
+        // Only one device of a btrfs filesystem spanning several devices is listed as mounted
+        if inner.fstype.as_deref() == Some("btrfs")
+            && let Some(uuid) = &inner.uuid
+            && let Some((other, mountpoint)) = devices
+                .iter()
+                .flat_map(BlockDevice::descendants)
+                .filter(|d| d.fstype.as_deref() == Some("btrfs") && d.uuid.as_ref() == Some(uuid))
+                .find_map(|d| Some((d, *d.mountpoints(mounts).first()?)))
+        {
+            return Err(PartitionError::Refused(format!(
+                "{} is part of the btrfs filesystem of {}, which is mounted on {mountpoint}.",
+                inner.path, other.path
+            )));
+        }
+        if inner.kind != "part" && inner.path != device.path {
+            return Err(PartitionError::Refused(format!(
+                "{} is in use by {}.",
+                device.path, inner.path
+            )));
+        }
+    }
+
+    Ok((disk, device))
+}
+
+fn check_device(path: &str, change: Change) -> Result<Option<Filesystem>, PartitionError> {
+    let devices = block_devices()?;
+    let mounts = mounts::list_mounts().map_err(|e| PartitionError::Failed(e.to_string()))?;
+    let (_, device) = check(&devices, &mounts, path, change)?;
+    Ok(device.fstype.as_deref().and_then(Filesystem::from_name))
+}
+
+/// Make sure that the partition table of a whole disk can be changed.
+pub fn check_disk(path: &str) -> Result<(), PartitionError> {
+    check_device(path, Change::Table).map(|_| ())
+}
+
+/// Make sure that the filesystem on a disk or partition can be changed.
+pub fn check_filesystem(path: &str) -> Result<(), PartitionError> {
+    check_device(path, Change::Filesystem).map(|_| ())
+}
+
+/// Make sure that `confirmation` is the path of the device.
+pub fn confirm(path: &str, confirmation: &str) -> Result<(), PartitionError> {
+    if path == confirmation {
+        Ok(())
+    } else {
+        Err(PartitionError::Unconfirmed)
+    }
+}
+
+/// Run a program using sudo and fail with its error output if it does not succeed.
+fn run(
+    credential: &SudoCredential,
+    program: &str,
+    args: Vec<String>,
+) -> Result<String, PartitionError> {
+    run_checked(credential, program, args, PartitionError::Failed)
+}
+
+/// A partition in a [DiskLayout]
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Partition {
+    pub number: u32,
+    pub path: String,
+    /// Offset of the first byte
+    pub start: u64,
+    /// Offset of the last byte
+    pub end: u64,
+    pub size: u64,
+    pub filesystem: Option<String>,
+    pub name: Option<String>,
+    pub flags: Vec<String>,
+}
+
+/// Unpartitioned space in a [DiskLayout]
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpace {
+    pub start: u64,
+    pub end: u64,
+    pub size: u64,
+}
+
+/// The partition table of a disk as reported by parted
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskLayout {
+    pub device: String,
+    pub size: u64,
+    pub logical_sector_size: u64,
+    /// Like `gpt` or `msdos`, missing if the disk is not partitioned
+    pub table: Option<String>,
+    pub partitions: Vec<Partition>,
+    pub free: Vec<FreeSpace>,
+}
+
+/// The path of a partition, like `/dev/sda1` or `/dev/nvme0n1p1`.
+fn partition_path(disk: &str, number: u32) -> String {
+    if disk.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{disk}p{number}")
+    } else {
+        format!("{disk}{number}")
+    }
+}
+
+fn bytes(field: &str) -> Option<u64> {
+    field.strip_suffix('B')?.parse().ok()
+}
+
+/// Parse the output of `parted --machine <disk> unit B print free`.
+fn parse_layout(output: &str) -> Option<DiskLayout> {
+    let mut lines = output
+        .lines()
+        .map(|l| l.trim().trim_end_matches(';'))
+        .filter(|l| !l.is_empty() && *l != "BYT");
+
+    let disk: Vec<&str> = lines.next()?.split(':').collect();
+    let device = disk.first()?.to_string();
+    let mut layout = DiskLayout {
+        size: bytes(disk.get(1)?)?,
+        logical_sector_size: disk.get(3)?.parse().ok()?,
+        table: disk
+            .get(5)
+            .filter(|t| !matches!(**t, "unknown" | "loop"))
+            .map(|t| t.to_string()),
+        device,
+        partitions: Vec::new(),
+        free: Vec::new(),
+    };
+
+    for line in lines {
+        let fields: Vec<&str> = line.split(':').collect();
+        let (Some(start), Some(end), Some(size)) = (
+            fields.get(1).and_then(|f| bytes(f)),
+            fields.get(2).and_then(|f| bytes(f)),
+            fields.get(3).and_then(|f| bytes(f)),
+        ) else {
+            continue;
+        };
+        if fields.get(4) == Some(&"free") {
+            layout.free.push(FreeSpace { start, end, size });
+            continue;
+        }
+        let Some(number) = fields.first().and_then(|n| n.parse().ok()) else {
+            continue;
+        };
+        let non_empty = |f: &str| (!f.is_empty()).then(|| f.to_string());
+        // Names may contain colons, the flags are always the last field
+        let (name, flags) = match fields.len() {
+            0..=5 => (String::new(), ""),
+            6 => (fields[5].to_string(), ""),
+            n => (fields[5..n - 1].join(":"), fields[n - 1]),
+        };
+        layout.partitions.push(Partition {
+            number,
+            path: partition_path(&layout.device, number),
+            start,
+            end,
+            size,
+            filesystem: fields.get(4).and_then(|f| non_empty(f)),
+            name: non_empty(&name),
+            flags: flags
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect(),
+        });
+    }
+
+    Some(layout)
+}
+
+/// Read the partitions and free space of a disk.
+pub fn layout(disk: &str, credential: &SudoCredential) -> Result<DiskLayout, PartitionError> {
+    let devices = block_devices()?;
+    if !devices
+        .iter()
+        .any(|d| d.path == disk && matches!(d.kind.as_str(), "disk" | "loop"))
+    {
+        return Err(PartitionError::NoSuchDevice(disk.to_string()));
+    }
+
+    // parted fails on disks without a partition table, but still prints the disk
+    let output = SudoCommand::with_credential(credential.clone(), "parted")
+        .args(vec![
+            "--script",
+            "--machine",
+            disk,
+            "unit",
+            "B",
+            "print",
+            "free",
+        ])
+        .output()?;
+    parse_layout(&output.stdout).ok_or_else(|| {
+        PartitionError::Failed(if output.stderr.trim().is_empty() {
+            "parted failed. Is it installed?".to_string()
+        } else {
+            output.stderr.trim().to_string()
+        })
+    })
+}
+
+/// Find the first free space `size` bytes fit into and return the first and last byte of the
+/// new partition.
+fn place(free: &[FreeSpace], size: Option<u64>) -> Option<(u64, u64)> {
+    free.iter().find_map(|space| {
+        let start = space.start.div_ceil(ALIGNMENT) * ALIGNMENT;
+        let end = match size {
+            Some(size) => start + size.div_ceil(ALIGNMENT) * ALIGNMENT - 1,
+            None => (space.end + 1) / ALIGNMENT * ALIGNMENT - 1,
+        };
+        (end > start && end <= space.end).then_some((start, end))
+    })
+}
+
+/// Wipe a disk and create an empty GPT partition table on it.
+///
+/// `confirmation` has to be the path of the disk.
+pub fn create_table(
+    disk: &str,
+    confirmation: &str,
+    credential: &SudoCredential,
+) -> Result<(), PartitionError> {
+    confirm(disk, confirmation)?;
+    check_device(disk, Change::Table)?;
+    // Removes signatures of filesystems and partition tables that would confuse blkid
+    run(
+        credential,
+        "wipefs",
+        vec!["--all".to_string(), disk.to_string()],
+    )?;
+    run(
+        credential,
+        "parted",
+        ["--script", disk, "mklabel", "gpt"]
+            .map(String::from)
+            .to_vec(),
+    )?;
+    Ok(())
+}
+
+/// Create a partition in the free space of a disk and return its path.
+pub fn create_partition(
+    disk: &str,
+    partition: &NewPartition,
+    credential: &SudoCredential,
+) -> Result<String, PartitionError> {
+    let name = partition.name.as_deref().unwrap_or("data");
+    if !is_valid_partition_name(name) {
+        return Err(PartitionError::Invalid(
+            "Partition names may only contain up to 36 letters, digits, ., _ or -.".to_string(),
+        ));
+    }
+    check_device(disk, Change::Table)?;
+
+    let current = layout(disk, credential)?;
+    if current.table.as_deref() != Some("gpt") {
+        return Err(PartitionError::Invalid(format!(
+            "{disk} does not have a GPT partition table."
+        )));
+    }
+    let (start, end) = place(&current.free, partition.size).ok_or_else(|| {
+        PartitionError::Invalid(format!("There is not enough free space on {disk}."))
+    })?;
+
+    let mut args = vec![
+        "--script", "--align", "optimal", disk, "unit", "B", "mkpart", name,
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect::<Vec<String>>();
+    if partition.kind == PartitionKind::Swap {
+        args.push("linux-swap".to_string());
+    }
+    args.extend([format!("{start}B"), format!("{end}B")]);
+    run(credential, "parted", args)?;
+
+    let created = layout(disk, credential)?
+        .partitions
+        .into_iter()
+        .find(|p| p.start == start)
+        .ok_or_else(|| {
+            PartitionError::Failed(format!("parted did not create the partition on {disk}."))
+        })?;
+
+    if partition.kind == PartitionKind::Efi {
+        run(
+            credential,
+            "parted",
+            vec![
+                "--script".to_string(),
+                disk.to_string(),
+                "set".to_string(),
+                created.number.to_string(),
+                "esp".to_string(),
+                "on".to_string(),
+            ],
+        )?;
+    }
+
+    Ok(created.path)
+}
+
+/// Create a filesystem on a disk or partition, destroying all data on it.
+///
+/// `confirmation` has to be the path of the device.
+pub fn format(
+    device: &str,
+    filesystem: Filesystem,
+    label: Option<&str>,
+    confirmation: &str,
+    credential: &SudoCredential,
+) -> Result<(), PartitionError> {
+    if let Some(label) = label {
+        validate_label(label, filesystem)?;
+    }
+    confirm(device, confirmation)?;
+    check_device(device, Change::Filesystem)?;
+
+    run(
+        credential,
+        "wipefs",
+        vec!["--all".to_string(), device.to_string()],
+    )?;
+    let (program, mut args) = filesystem.mkfs(label);
+    args.push(device.to_string());
+    run(credential, program, args)?;
+    Ok(())
+}
+
+/// Change the label of the filesystem on a disk or partition.
+pub fn set_label(
+    device: &str,
+    label: &str,
+    credential: &SudoCredential,
+) -> Result<(), PartitionError> {
+    let filesystem = check_device(device, Change::Filesystem)?.ok_or_else(|| {
+        PartitionError::Invalid(format!(
+            "{device} does not contain an ext4, xfs, btrfs, exFAT or FAT filesystem."
+        ))
+    })?;
+    validate_label(label, filesystem)?;
+
+    let (program, args) = filesystem.relabel(device, label);
+    run(credential, program, args)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<BlockDevice> {
+        serde_json::from_str::<Lsblk>(include_str!("../../tests/fixtures/partitions/lsblk.json"))
+            .unwrap()
+            .blockdevices
+    }
+
+    #[test]
+    fn check_devices() {
+        let devices = devices();
+        let mounts = vec![Mount {
+            source: "/dev/sde2".to_string(),
+            mountpoint: "/media/backup".to_string(),
+            filesystem: "ext4".to_string(),
+            options: vec!["rw".to_string()],
+        }];
+        let check = |path, change| check(&devices, &mounts, path, change).map(|(_, d)| &d.path);
+
+        assert_eq!(check("/dev/sdc", Change::Table).unwrap(), "/dev/sdc");
+        assert_eq!(check("/dev/loop0", Change::Table).unwrap(), "/dev/loop0");
+        assert_eq!(check("/dev/sdc", Change::Filesystem).unwrap(), "/dev/sdc");
+        assert_eq!(check("/dev/sde1", Change::Filesystem).unwrap(), "/dev/sde1");
+
+        assert!(matches!(
+            check("/dev/sdz", Change::Table),
+            Err(PartitionError::NoSuchDevice(_))
+        ));
+        assert!(matches!(
+            check("/dev/sr0", Change::Filesystem),
+            Err(PartitionError::Invalid(_))
+        ));
+        assert!(matches!(
+            check("/dev/sde1", Change::Table),
+            Err(PartitionError::Invalid(_))
+        ));
+        // Unmounted, but on the system disk
+        assert!(matches!(
+            check("/dev/sda3", Change::Filesystem),
+            Err(PartitionError::Refused(_))
+        ));
+        // Another partition is used as swap
+        assert!(matches!(
+            check("/dev/sdd1", Change::Filesystem),
+            Err(PartitionError::Refused(_))
+        ));
+        // Mounted according to /proc/self/mounts
+        assert!(matches!(
+            check("/dev/sde2", Change::Filesystem),
+            Err(PartitionError::Refused(_))
+        ));
+        assert!(matches!(
+            check("/dev/sde", Change::Table),
+            Err(PartitionError::Refused(_))
+        ));
+        // Part of an LVM volume group
+        assert!(matches!(
+            check("/dev/sdb1", Change::Filesystem),
+            Err(PartitionError::Refused(_))
+        ));
+        assert!(matches!(
+            check("/dev/mapper/data-media", Change::Filesystem),
+            Err(PartitionError::Invalid(_))
+        ));
+        // Members of a ZFS pool and an inactive RAID array
+        for path in ["/dev/sdf1", "/dev/sdf", "/dev/sdg1", "/dev/sdg"] {
+            let change = if path.ends_with('1') {
+                Change::Filesystem
+            } else {
+                Change::Table
+            };
+            assert!(matches!(
+                check(path, change),
+                Err(PartitionError::Refused(_))
+            ));
+        }
+        // Second device of a btrfs filesystem mounted from /dev/sdh1
+        assert!(matches!(
+            check("/dev/sdi1", Change::Filesystem),
+            Err(PartitionError::Refused(_))
+        ));
+        assert!(matches!(
+            check("/dev/sdi", Change::Table),
+            Err(PartitionError::Refused(_))
+        ));
+        assert_eq!(check("/dev/sdi2", Change::Filesystem).unwrap(), "/dev/sdi2");
+
+        assert!(confirm("/dev/sdc", "/dev/sdc").is_ok());
+        assert!(matches!(
+            confirm("/dev/sdc", "/dev/sdc1"),
+            Err(PartitionError::Unconfirmed)
+        ));
+    }
+
+    #[test]
+    fn parse_parted_layout() {
+        let layout = parse_layout(include_str!(
+            "../../tests/fixtures/partitions/parted-gpt.txt"
+        ))
+        .unwrap();
+        assert_eq!(layout.device, "/dev/sde");
+        assert_eq!(layout.size, 16_000_900_661_248);
+        assert_eq!(layout.logical_sector_size, 512);
+        assert_eq!(layout.table.as_deref(), Some("gpt"));
+        assert_eq!(
+            layout.partitions[0],
+            Partition {
+                number: 1,
+                path: "/dev/sde1".to_string(),
+                start: 1_048_576,
+                end: 537_919_487,
+                size: 536_870_912,
+                filesystem: Some("fat32".to_string()),
+                name: Some("EFI system partition".to_string()),
+                flags: vec!["boot".to_string(), "esp".to_string()],
+            }
+        );
+        assert_eq!(layout.partitions[1].name.as_deref(), Some("data"));
+        assert!(layout.partitions[1].flags.is_empty());
+        assert_eq!(layout.free.len(), 2);
+
+        // The 1 MiB before the first partition are too small for another partition
+        assert_eq!(
+            place(&layout.free, Some(4 * ALIGNMENT)),
+            Some((10_737_418_240, 10_741_612_543))
+        );
+        assert_eq!(
+            place(&layout.free, Some(1)),
+            Some((10_737_418_240, 10_738_466_815))
+        );
+        assert_eq!(
+            place(&layout.free, None),
+            Some((10_737_418_240, 16_000_899_612_671))
+        );
+        assert_eq!(place(&layout.free, Some(layout.size)), None);
+
+        let unpartitioned = parse_layout(include_str!(
+            "../../tests/fixtures/partitions/parted-unknown.txt"
+        ))
+        .unwrap();
+        assert_eq!(unpartitioned.table, None);
+        assert!(unpartitioned.partitions.is_empty());
+        assert!(parse_layout("Error: Could not stat device /dev/sdz").is_none());
+    }
+
+    #[test]
+    fn validate_names_and_labels() {
+        assert_eq!(partition_path("/dev/sdb", 2), "/dev/sdb2");
+        assert_eq!(partition_path("/dev/nvme0n1", 1), "/dev/nvme0n1p1");
+        assert_eq!(partition_path("/dev/loop3", 1), "/dev/loop3p1");
+
+        assert!(is_valid_partition_name("media-2024"));
+        assert!(!is_valid_partition_name("EFI system partition"));
+        assert!(!is_valid_partition_name(""));
+
+        assert!(validate_label("BACKUP", Filesystem::Vfat).is_ok());
+        assert!(validate_label("BACKUP-DRIVE", Filesystem::Vfat).is_err());
+        assert!(validate_label("backup drive", Filesystem::Ext4).is_ok());
+        assert!(validate_label("", Filesystem::Btrfs).is_err());
+        assert!(validate_label("media\n", Filesystem::Btrfs).is_err());
+
+        assert_eq!(
+            Filesystem::Vfat.mkfs(Some("EFI")),
+            ("mkfs.vfat", vec!["-I".into(), "-n".into(), "EFI".into()])
+        );
+        assert_eq!(Filesystem::Exfat.mkfs(None), ("mkfs.exfat", vec![]));
+        assert_eq!(Filesystem::from_name("xfs"), Some(Filesystem::Xfs));
+        assert_eq!(Filesystem::from_name("ntfs"), None);
+    }
+
+    #[test]
+    #[ignore = "needs root, parted, mkfs.ext4 and a free loop device"]
+    fn partition_loop_device() {
+        let image = std::env::temp_dir().join("zentrox_partitions.img");
+        std::fs::File::create(&image)
+            .unwrap()
+            .set_len(64 * ALIGNMENT)
+            .unwrap();
+        let output = Command::new("losetup")
+            .args(["--find", "--show", "--partscan"])
+            .arg(&image)
+            .output()
+            .unwrap();
+        let disk = String::from_utf8(output.stdout).unwrap().trim().to_string();
+        let credential = SudoCredential::Passwordless;
+
+        assert!(matches!(
+            create_table(&disk, "/dev/sda", &credential),
+            Err(PartitionError::Unconfirmed)
+        ));
+        create_table(&disk, &disk, &credential).unwrap();
+        let partition = NewPartition {
+            name: Some("media".to_string()),
+            size: Some(16 * ALIGNMENT),
+            kind: PartitionKind::Data,
+        };
+        let path = create_partition(&disk, &partition, &credential).unwrap();
+        assert_eq!(path, partition_path(&disk, 1));
+        let rest = NewPartition {
+            name: None,
+            size: None,
+            kind: PartitionKind::Efi,
+        };
+        assert_eq!(
+            create_partition(&disk, &rest, &credential).unwrap(),
+            partition_path(&disk, 2)
+        );
+
+        let layout = layout(&disk, &credential).unwrap();
+        assert_eq!(layout.partitions[0].size, 16 * ALIGNMENT);
+        assert!(layout.partitions[1].flags.contains(&"esp".to_string()));
+
+        format(&path, Filesystem::Ext4, Some("media"), &path, &credential).unwrap();
+        set_label(&path, "archive", &credential).unwrap();
+        let label = Command::new("blkid")
+            .args(["-s", "LABEL", "-o", "value", &path])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&label.stdout).trim(), "archive");
+
+        Command::new("losetup")
+            .args(["-d", &disk])
+            .status()
+            .unwrap();
+        std::fs::remove_file(&image).unwrap();
+    }
+}
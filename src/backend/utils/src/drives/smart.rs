@@ -0,0 +1,379 @@
+//! Drive health using [smartmontools](https://www.smartmontools.org/).
+//!
+//! `smartctl --json` is run using sudo, as reading SMART data requires root. The report of
+//! `smartctl -a` contains the overall health, the attributes of ATA drives, the health log of
+//! NVMe drives and the self-test log, so it is also used to poll running self-tests.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::sudo::{SudoCommand, SudoCredential, SudoError};
+
+/// smartctl sets these bits of its exit status if the command line could not be parsed or the
+/// device could not be opened. The other bits describe the health of the drive.
+const FATAL_EXIT_BITS: i32 = 0b11;
+
+#[derive(Debug, Error)]
+pub enum SmartError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("{0} is not a disk.")]
+    InvalidDevice(String),
+    /// smartctl could not read SMART data from the device, e.g. because a USB bridge hides it
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for SmartError {
+    fn from(error: SudoError) -> Self {
+        SmartError::Sudo(error)
+    }
+}
+
+/// Self-tests run by the drive in the background
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SelfTestKind {
+    /// Takes a few minutes
+    Short,
+    /// Reads the whole surface, which can take hours
+    Long,
+}
+
+/// An attribute of an ATA drive. The normalized values range from 1 (worst) to 253 and the
+/// attribute fails if its value drops to the threshold.
+#[derive(Serialize, ToSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartAttribute {
+    pub id: u64,
+    pub name: String,
+    pub value: Option<u64>,
+    pub worst: Option<u64>,
+    pub threshold: Option<u64>,
+    pub raw: Option<u64>,
+    /// Empty if the attribute never failed, otherwise `FAILING_NOW` or `In_the_past`
+    pub when_failed: String,
+}
+
+/// Progress of a running self-test
+#[derive(Serialize, ToSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestProgress {
+    pub running: bool,
+    pub remaining_percent: Option<u64>,
+    pub description: String,
+}
+
+/// An entry of the self-test log
+#[derive(Serialize, ToSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestResult {
+    /// Like `Short offline` or `Extended offline`
+    pub kind: String,
+    pub passed: bool,
+    pub status: String,
+    /// Power-on hours of the drive when the test ended
+    pub lifetime_hours: Option<u64>,
+}
+
+/// Health of a drive as reported by `smartctl -a`. Values that the drive does not report are
+/// missing.
+#[derive(Serialize, ToSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartReport {
+    pub device: String,
+    /// `ATA`, `NVMe` or `SCSI`
+    pub protocol: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub smart_enabled: bool,
+    /// Overall health assessment of the drive
+    pub passed: Option<bool>,
+    pub temperature_celsius: Option<i64>,
+    pub power_on_hours: Option<u64>,
+    /// Sectors of ATA drives that were replaced by spare sectors
+    pub reallocated_sectors: Option<u64>,
+    /// Sectors of ATA drives that could not be read and wait to be reallocated
+    pub pending_sectors: Option<u64>,
+    /// Estimated share of the endurance of an NVMe drive that has been used, may exceed 100
+    pub percentage_used: Option<u64>,
+    /// Remaining spare capacity of an NVMe drive in percent
+    pub available_spare: Option<u64>,
+    pub media_errors: Option<u64>,
+    pub attributes: Vec<SmartAttribute>,
+    pub self_test: Option<SelfTestProgress>,
+    /// Latest tests first
+    pub self_tests: Vec<SelfTestResult>,
+    /// Warnings and errors of smartctl
+    pub messages: Vec<String>,
+}
+
+fn messages(output: &Value) -> Vec<String> {
+    output["smartctl"]["messages"]
+        .as_array()
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(|m| m["string"].as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse the JSON output of `smartctl --json` and check its exit status.
+fn parse_output(device: &str, stdout: &str) -> Result<Value, SmartError> {
+    let output: Value = serde_json::from_str(stdout).map_err(|e| {
+        SmartError::Failed(format!("The output of smartctl could not be parsed: {e}"))
+    })?;
+    let status = output["smartctl"]["exit_status"].as_i64().unwrap_or(0) as i32;
+    if status & FATAL_EXIT_BITS != 0 {
+        let messages = messages(&output);
+        return Err(SmartError::Unavailable(if messages.is_empty() {
+            format!("smartctl could not read {device}.")
+        } else {
+            messages.join(" ")
+        }));
+    }
+    Ok(output)
+}
+
+/// Build a report from the JSON output of `smartctl --json -a`.
+fn parse_report(device: &str, output: &Value) -> SmartReport {
+    let attributes: Vec<SmartAttribute> = output["ata_smart_attributes"]["table"]
+        .as_array()
+        .map(|table| {
+            table
+                .iter()
+                .filter_map(|a| {
+                    Some(SmartAttribute {
+                        id: a["id"].as_u64()?,
+                        name: a["name"].as_str().unwrap_or_default().to_string(),
+                        value: a["value"].as_u64(),
+                        worst: a["worst"].as_u64(),
+                        threshold: a["thresh"].as_u64(),
+                        raw: a["raw"]["value"].as_u64(),
+                        when_failed: a["when_failed"].as_str().unwrap_or_default().to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let raw_attribute = |id: u64| attributes.iter().find(|a| a.id == id).and_then(|a| a.raw);
+    let nvme = &output["nvme_smart_health_information_log"];
+
+    let self_test = match (
+        &output["ata_smart_data"]["self_test"]["status"],
+        &output["nvme_self_test_log"],
+    ) {
+        (ata, _) if ata.is_object() => Some(SelfTestProgress {
+            // The upper four bits of the status are 15 while a test is running
+            running: ata["value"].as_u64().is_some_and(|v| v >> 4 == 15),
+            remaining_percent: ata["remaining_percent"].as_u64(),
+            description: ata["string"].as_str().unwrap_or_default().to_string(),
+        }),
+        (_, nvme_log) if nvme_log.is_object() => {
+            let operation = &nvme_log["current_self_test_operation"];
+            Some(SelfTestProgress {
+                running: operation["value"].as_u64().is_some_and(|v| v != 0),
+                remaining_percent: nvme_log["current_self_test_completion_percent"]
+                    .as_u64()
+                    .map(|done| 100_u64.saturating_sub(done)),
+                description: operation["string"].as_str().unwrap_or_default().to_string(),
+            })
+        }
+        _ => None,
+    };
+
+    let ata_tests = output["ata_smart_self_test_log"]["standard"]["table"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| SelfTestResult {
+            kind: t["type"]["string"].as_str().unwrap_or_default().to_string(),
+            passed: t["status"]["passed"].as_bool().unwrap_or(false),
+            status: t["status"]["string"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            lifetime_hours: t["lifetime_hours"].as_u64(),
+        });
+    let nvme_tests = output["nvme_self_test_log"]["table"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| SelfTestResult {
+            kind: t["self_test_code"]["string"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            passed: t["self_test_result"]["value"].as_u64() == Some(0),
+            status: t["self_test_result"]["string"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            lifetime_hours: t["power_on_hours"].as_u64(),
+        });
+
+    SmartReport {
+        device: device.to_string(),
+        protocol: output["device"]["protocol"].as_str().map(String::from),
+        model: output["model_name"].as_str().map(String::from),
+        serial: output["serial_number"].as_str().map(String::from),
+        smart_enabled: output["smart_support"]["enabled"]
+            .as_bool()
+            .unwrap_or(false),
+        passed: output["smart_status"]["passed"].as_bool(),
+        temperature_celsius: output["temperature"]["current"].as_i64(),
+        power_on_hours: output["power_on_time"]["hours"].as_u64(),
+        reallocated_sectors: raw_attribute(5),
+        pending_sectors: raw_attribute(197),
+        percentage_used: nvme["percentage_used"].as_u64(),
+        available_spare: nvme["available_spare"].as_u64(),
+        media_errors: nvme["media_errors"].as_u64(),
+        self_test,
+        self_tests: ata_tests.chain(nvme_tests).collect(),
+        attributes,
+        messages: messages(output),
+    }
+}
+
+/// Only whole disks listed by lsblk are passed to smartctl.
+fn check_device(device: &str) -> Result<(), SmartError> {
+    let disks = super::list().map_err(|e| SmartError::Failed(format!("{e:?}")))?;
+    if disks.iter().any(|d| d.path.as_deref() == Some(device)) {
+        Ok(())
+    } else {
+        Err(SmartError::InvalidDevice(device.to_string()))
+    }
+}
+
+fn smartctl(
+    device: &str,
+    args: Vec<&str>,
+    credential: &SudoCredential,
+) -> Result<Value, SmartError> {
+    check_device(device)?;
+    let output = SudoCommand::with_credential(credential.clone(), "smartctl")
+        .arg("--json")
+        .args(args)
+        .arg(device)
+        .output()?;
+    if output.stdout.trim().is_empty() {
+        return Err(SmartError::Failed(if output.stderr.trim().is_empty() {
+            "smartctl could not be run. Is smartmontools installed?".to_string()
+        } else {
+            output.stderr.trim().to_string()
+        }));
+    }
+    parse_output(device, &output.stdout)
+}
+
+/// Read the health of a disk, like `/dev/sda`.
+pub fn report(device: &str, credential: &SudoCredential) -> Result<SmartReport, SmartError> {
+    let output = smartctl(device, vec!["-a"], credential)?;
+    Ok(parse_report(device, &output))
+}
+
+/// Start a self-test, whose progress and result are part of the [report].
+pub fn start_self_test(
+    device: &str,
+    kind: SelfTestKind,
+    credential: &SudoCredential,
+) -> Result<(), SmartError> {
+    let kind = match kind {
+        SelfTestKind::Short => "short",
+        SelfTestKind::Long => "long",
+    };
+    smartctl(device, vec!["-t", kind], credential)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(device: &str, contents: &str) -> Result<SmartReport, SmartError> {
+        parse_output(device, contents).map(|output| parse_report(device, &output))
+    }
+
+    #[test]
+    fn parse_ata_report() {
+        let report = fixture(
+            "/dev/sda",
+            include_str!("../../tests/fixtures/smart/ata.json"),
+        )
+        .unwrap();
+        assert_eq!(report.protocol.as_deref(), Some("ATA"));
+        assert_eq!(report.model.as_deref(), Some("WDC WD40EFRX-68N32N0"));
+        assert!(report.smart_enabled);
+        assert_eq!(report.passed, Some(true));
+        assert_eq!(report.temperature_celsius, Some(36));
+        assert_eq!(report.power_on_hours, Some(28541));
+        assert_eq!(report.reallocated_sectors, Some(8));
+        assert_eq!(report.pending_sectors, Some(2));
+        assert_eq!(report.percentage_used, None);
+        assert_eq!(report.attributes.len(), 7);
+        assert_eq!(report.attributes[2].threshold, Some(140));
+
+        let progress = report.self_test.unwrap();
+        assert!(progress.running);
+        assert_eq!(progress.remaining_percent, Some(90));
+        assert_eq!(report.self_tests.len(), 2);
+        assert!(report.self_tests[0].passed);
+        assert_eq!(report.self_tests[1].kind, "Extended offline");
+        assert!(!report.self_tests[1].passed);
+        assert_eq!(report.self_tests[1].lifetime_hours, Some(27800));
+    }
+
+    #[test]
+    fn parse_nvme_report() {
+        let report = fixture(
+            "/dev/nvme0n1",
+            include_str!("../../tests/fixtures/smart/nvme.json"),
+        )
+        .unwrap();
+        assert_eq!(report.protocol.as_deref(), Some("NVMe"));
+        assert_eq!(report.passed, Some(true));
+        assert_eq!(report.temperature_celsius, Some(41));
+        assert_eq!(report.power_on_hours, Some(9120));
+        assert_eq!(report.percentage_used, Some(7));
+        assert_eq!(report.available_spare, Some(100));
+        assert_eq!(report.media_errors, Some(0));
+        assert_eq!(report.reallocated_sectors, None);
+        assert!(report.attributes.is_empty());
+        assert!(!report.self_test.unwrap().running);
+        assert_eq!(report.self_tests.len(), 1);
+        assert_eq!(report.self_tests[0].kind, "Short");
+        assert!(report.self_tests[0].passed);
+    }
+
+    #[test]
+    fn parse_failing_and_unsupported_drives() {
+        let report = fixture(
+            "/dev/sdb",
+            include_str!("../../tests/fixtures/smart/failing.json"),
+        )
+        .unwrap();
+        assert_eq!(report.passed, Some(false));
+        assert_eq!(report.reallocated_sectors, Some(3952));
+        assert_eq!(report.attributes[0].when_failed, "FAILING_NOW");
+        assert_eq!(report.messages.len(), 1);
+        assert!(!report.self_test.unwrap().running);
+
+        match fixture(
+            "/dev/sdc",
+            include_str!("../../tests/fixtures/smart/usb-unsupported.json"),
+        ) {
+            Err(SmartError::Unavailable(message)) => assert!(message.contains("USB bridge")),
+            other => panic!("Unexpected result: {other:?}"),
+        }
+        assert!(matches!(
+            fixture("/dev/sdc", "smartctl: command not found"),
+            Err(SmartError::Failed(_))
+        ));
+    }
+}
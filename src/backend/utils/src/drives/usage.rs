@@ -0,0 +1,203 @@
+//! Usage of mounted filesystems, similar to `df`.
+//!
+//! The numbers are read using `statvfs` and calculated the way `df` does: The space reserved for
+//! root is neither counted as used nor as available, so the used percentage is relative to the
+//! space that can actually be used by unprivileged users.
+
+use serde::Serialize;
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use super::mounts::{self, MountError};
+
+/// Raw numbers as reported by `statvfs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemStats {
+    /// Size of a block in bytes
+    pub block_size: u64,
+    pub blocks: u64,
+    /// Free blocks, including the ones reserved for root
+    pub blocks_free: u64,
+    /// Free blocks available to unprivileged users
+    pub blocks_available: u64,
+    pub inodes: u64,
+    pub inodes_free: u64,
+}
+
+/// Read the numbers of the filesystem `path` is on.
+pub fn statvfs(path: &Path) -> io::Result<FilesystemStats> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "The path contains NUL."))?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid NUL-terminated string and `stats` is only read if `statvfs`
+    // succeeded and initialized it.
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stats.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(FilesystemStats {
+        block_size: stats.f_frsize as u64,
+        blocks: stats.f_blocks as u64,
+        blocks_free: stats.f_bfree as u64,
+        blocks_available: stats.f_bavail as u64,
+        inodes: stats.f_files as u64,
+        inodes_free: stats.f_ffree as u64,
+    })
+}
+
+/// Usage of a mounted filesystem
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesystemUsage {
+    pub source: String,
+    pub mountpoint: String,
+    pub filesystem: String,
+    /// Size in bytes
+    pub total: u64,
+    pub used: u64,
+    /// Bytes available to unprivileged users
+    pub available: u64,
+    /// Free bytes, including the ones reserved for root
+    pub free: u64,
+    /// Used percentage of the space available to unprivileged users, rounded up
+    pub used_percent: u8,
+    /// Missing if the filesystem does not have a fixed number of inodes, like btrfs
+    pub inodes: Option<InodeUsage>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InodeUsage {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    /// Rounded up
+    pub used_percent: u8,
+}
+
+/// `part` as percentage of `whole`, rounded up like `df` does.
+fn percent(part: u64, whole: u64) -> u8 {
+    if whole == 0 {
+        return 0;
+    }
+    (part as u128 * 100).div_ceil(whole as u128).min(100) as u8
+}
+
+impl FilesystemUsage {
+    pub fn new(source: &str, mountpoint: &str, filesystem: &str, stats: FilesystemStats) -> Self {
+        let used_blocks = stats.blocks.saturating_sub(stats.blocks_free);
+        let inodes = (stats.inodes > 0).then(|| {
+            let used = stats.inodes.saturating_sub(stats.inodes_free);
+            InodeUsage {
+                total: stats.inodes,
+                used,
+                free: stats.inodes_free,
+                used_percent: percent(used, stats.inodes),
+            }
+        });
+
+        FilesystemUsage {
+            source: source.to_string(),
+            mountpoint: mountpoint.to_string(),
+            filesystem: filesystem.to_string(),
+            total: stats.blocks * stats.block_size,
+            used: used_blocks * stats.block_size,
+            available: stats.blocks_available * stats.block_size,
+            free: stats.blocks_free * stats.block_size,
+            used_percent: percent(used_blocks, used_blocks + stats.blocks_available),
+            inodes,
+        }
+    }
+}
+
+/// Usage of all mounted filesystems.
+///
+/// Like `df`, pseudo filesystems without any blocks, like `proc` or `sysfs`, are left out, as are
+/// filesystems that can not be read, like FUSE mounts of other users.
+pub fn filesystems() -> Result<Vec<FilesystemUsage>, MountError> {
+    Ok(mounts::list_mounts()?
+        .into_iter()
+        .filter_map(|mount| {
+            let stats = statvfs(Path::new(&mount.mountpoint)).ok()?;
+            (stats.blocks > 0).then(|| {
+                FilesystemUsage::new(&mount.source, &mount.mountpoint, &mount.filesystem, stats)
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calculate_usage() {
+        // Numbers of an ext4 filesystem with 5% reserved blocks
+        let stats = FilesystemStats {
+            block_size: 4096,
+            blocks: 1000,
+            blocks_free: 300,
+            blocks_available: 250,
+            inodes: 640,
+            inodes_free: 600,
+        };
+        let usage = FilesystemUsage::new("/dev/sda1", "/", "ext4", stats);
+
+        assert_eq!(usage.total, 4_096_000);
+        assert_eq!(usage.used, 2_867_200);
+        assert_eq!(usage.available, 1_024_000);
+        assert_eq!(usage.free, 1_228_800);
+        // 700 / 950 is 73.7%
+        assert_eq!(usage.used_percent, 74);
+        assert_eq!(
+            usage.inodes,
+            Some(InodeUsage {
+                total: 640,
+                used: 40,
+                free: 600,
+                used_percent: 7,
+            })
+        );
+
+        let btrfs = FilesystemUsage::new(
+            "/dev/sdb",
+            "/srv",
+            "btrfs",
+            FilesystemStats {
+                inodes: 0,
+                inodes_free: 0,
+                ..stats
+            },
+        );
+        assert_eq!(btrfs.inodes, None);
+
+        let full = FilesystemUsage::new(
+            "/dev/sdc1",
+            "/mnt",
+            "ext4",
+            FilesystemStats {
+                blocks_free: 10,
+                blocks_available: 0,
+                ..stats
+            },
+        );
+        assert_eq!(full.used_percent, 100);
+        assert_eq!(percent(0, 0), 0);
+    }
+
+    #[test]
+    fn read_root_filesystem() {
+        let stats = statvfs(Path::new("/")).unwrap();
+        assert!(stats.block_size > 0);
+        assert!(stats.blocks >= stats.blocks_free);
+        assert!(statvfs(Path::new("/does/not/exist")).is_err());
+    }
+}
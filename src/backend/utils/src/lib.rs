@@ -8,11 +8,16 @@ pub mod models;
 pub mod net_data;
 pub mod otp;
 pub mod packages;
+pub mod processes;
 pub mod sanitize;
 pub mod schema;
+pub mod sockets;
 pub mod status_com;
+pub mod storage;
 pub mod sudo;
+pub mod sudoers;
 pub mod time;
+pub mod timers;
 pub mod ufw;
 pub mod uptime;
 pub mod users;
@@ -26,6 +26,62 @@ pub struct Configurations {
     pub vault_enabled: bool,
     pub tls_cert: String,
     pub id: i32,
+    pub tls_key: Option<String>,
+}
+
+/// Thresholds used to detect brute-force attempts during login.
+/// Time windows are expressed in seconds.
+#[derive(
+    Queryable, Selectable, AsChangeset, Deserialize, Serialize, ToSchema, Clone, Copy, Debug,
+)]
+#[diesel(table_name = crate::schema::Configuration)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[serde(rename_all = "camelCase")]
+pub struct LoginLimits {
+    /// Surpassing `base_request_limit` failed requests from one peer in this window leads to a
+    /// temporary block.
+    pub base_time_window: i64,
+    pub base_request_limit: i64,
+    /// Surpassing `large_request_limit` failed requests from one peer in this window leads to a
+    /// permanent IP block.
+    pub large_time_window: i64,
+    pub large_request_limit: i64,
+    /// Surpassing `account_request_limit` failed requests in this window leads to the affected
+    /// account being locked down for any IP.
+    pub account_time_window: i64,
+    pub account_request_limit: i64,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        LoginLimits {
+            base_time_window: 10 * 60,
+            base_request_limit: 5,
+            large_time_window: 30 * 60,
+            large_request_limit: 15,
+            account_time_window: 60 * 60,
+            account_request_limit: 60,
+        }
+    }
+}
+
+impl LoginLimits {
+    /// Checks that every window and limit is positive and that the windows are ordered from
+    /// smallest to largest.
+    pub fn is_valid(&self) -> bool {
+        [
+            self.base_time_window,
+            self.base_request_limit,
+            self.large_time_window,
+            self.large_request_limit,
+            self.account_time_window,
+            self.account_request_limit,
+        ]
+        .iter()
+        .all(|v| *v > 0)
+            && self.base_time_window <= self.large_time_window
+            && self.base_request_limit <= self.large_request_limit
+    }
 }
 
 #[derive(Queryable, Selectable, Insertable)]
@@ -109,6 +165,111 @@ pub struct BlockedIp {
     pub ip: String,
 }
 
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, ToSchema)]
+#[diesel(table_name = crate::schema::TrustedNetworks)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct TrustedNetwork {
+    pub since: i64,
+    pub network: String,
+}
+
+/// A client certificate issued by the private CA of Zentrox.
+/// Timestamps are expressed in seconds since the Unix epoch.
+#[derive(Queryable, Selectable, Insertable, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = crate::schema::ClientCertificates)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[serde(rename_all = "camelCase")]
+pub struct ClientCertificate {
+    /// Serial number as colon separated hex
+    pub serial: String,
+    pub name: String,
+    /// Account the certificate belongs to
+    pub username: String,
+    /// SHA-256 fingerprint as colon separated hex
+    pub fingerprint: String,
+    pub issued_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+/// Stored form of [`crate::packages::unattended::UpdatePolicy`].
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone, Debug)]
+#[diesel(table_name = crate::schema::UpdatePolicy)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UpdatePolicyEntry {
+    pub enabled: bool,
+    pub kind: String,
+    pub days: String,
+    pub window_start: String,
+    pub window_duration: i32,
+    pub reboot: bool,
+    pub id: i32,
+}
+
+/// A run of unattended package updates.
+/// Timestamps are expressed in seconds since the Unix epoch.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = crate::schema::UpdateRuns)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRun {
+    pub id: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// `scheduled` or `manual`
+    pub triggered_by: String,
+    /// `security` or `all`
+    pub kind: String,
+    /// `running`, `success` or `failed`
+    pub status: String,
+    /// Unknown if it could not be determined
+    pub reboot_required: Option<bool>,
+    pub rebooted: bool,
+    /// Output of the hooks and package managers
+    pub output: String,
+}
+
+/// A package operation started using Zentrox.
+/// Timestamps are expressed in seconds since the Unix epoch.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = crate::schema::PackageJobs)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[serde(rename_all = "camelCase")]
+pub struct PackageJob {
+    pub id: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// The Zentrox account that started the job
+    pub username: Option<String>,
+    /// `install`, `remove`, `update`, `updateAll`, `removeOrphaned`, `undo` or `downgrade`
+    pub action: String,
+    /// Missing if every package manager was used
+    pub package_manager: Option<String>,
+    pub package: Option<String>,
+    /// `running`, `success` or `failed`
+    pub status: String,
+    /// Output of the package managers
+    pub output: String,
+}
+
+/// The packages known to a package manager when the package index was last refreshed.
+/// Package names are separated by newlines, `refreshed_at` is expressed in seconds since the Unix
+/// epoch.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone, Debug)]
+#[diesel(table_name = crate::schema::PackageIndex)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct PackageIndexEntry {
+    pub package_manager: String,
+    pub installed: String,
+    pub available: String,
+    /// Missing if the updates could not be listed
+    pub updates: Option<String>,
+    /// Installed packages that do not belong to any repository
+    pub foreign_packages: String,
+    pub refreshed_at: i64,
+}
+
 #[derive(Queryable, Selectable, Insertable, AsChangeset, serde::Serialize, Debug)]
 #[diesel(table_name = crate::schema::Encryption)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -116,3 +277,74 @@ pub struct Secrets {
     pub argon2_salt: String,
     pub id: i32,
 }
+
+/// A run of a cronjob whose runs are recorded.
+/// Timestamps are expressed in seconds since the Unix epoch.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, ToSchema, Clone, Debug)]
+#[diesel(table_name = crate::schema::CronjobRuns)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(treat_none_as_null = true)]
+#[serde(rename_all = "camelCase")]
+pub struct CronjobRun {
+    pub id: String,
+    /// History key of the cronjob
+    pub job: String,
+    /// `cron` or `manual`
+    pub triggered_by: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// `running`, `success` or `failed`
+    pub status: String,
+    /// Missing if the command was killed by a signal or could not be started
+    pub exit_code: Option<i32>,
+    /// The end of the output if it was too long
+    pub stdout: String,
+    /// The end of the output if it was too long
+    pub stderr: String,
+}
+
+/// Stored form of [`crate::storage::SnapshotSchedule`].
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone, Debug)]
+#[diesel(table_name = crate::schema::SnapshotSchedules)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct SnapshotScheduleEntry {
+    pub kind: String,
+    pub volume: String,
+    pub hourly: i32,
+    pub daily: i32,
+    pub weekly: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_login_limits() {
+        assert!(LoginLimits::default().is_valid());
+
+        let changes: [fn(&mut LoginLimits); 8] = [
+            |l| l.base_time_window = 0,
+            |l| l.base_request_limit = -1,
+            |l| l.large_time_window = 0,
+            |l| l.large_request_limit = 0,
+            |l| l.account_time_window = -60,
+            |l| l.account_request_limit = 0,
+            // The large window may not be smaller than the base window
+            |l| l.large_time_window = l.base_time_window - 1,
+            |l| l.large_request_limit = l.base_request_limit - 1,
+        ];
+        for change in changes {
+            let mut limits = LoginLimits::default();
+            change(&mut limits);
+            assert!(!limits.is_valid());
+        }
+
+        let equal = LoginLimits {
+            large_time_window: 10 * 60,
+            large_request_limit: 5,
+            ..LoginLimits::default()
+        };
+        assert!(equal.is_valid());
+    }
+}
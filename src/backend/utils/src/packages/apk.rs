@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::repositories::{
+    NewRepository, RepositoryError, RepositoryFile, RepositoryFormat, check, write_system_file,
+};
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    lines, nothing_to_do, parse_size, run_command_line, run_with_output, strip_version_constraint,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+const UPDATE_DATABASE: CommandLine = &["apk", "update"];
+const UPDATE_ALL: CommandLine = &["apk", "upgrade"];
+const REPOSITORIES_PATH: &str = "/etc/apk/repositories";
+
+/// Alpine Linux
+pub struct Apk;
+
+/// Split a package like `curl-8.5.0-r0` into its name and version.
+///
+/// Versions always consist of the version itself and a release like `r0`, so the name ends
+/// before the second to last `-`.
+fn split_name_version(package: &str) -> Option<(&str, &str)> {
+    let (rest, _release) = package.rsplit_once('-')?;
+    let (name, _) = rest.rsplit_once('-')?;
+    Some((name, &package[name.len() + 1..]))
+}
+
+/// Package names from `apk list`, which prints lines like
+/// `curl-8.5.0-r0 x86_64 {curl} (curl) [installed]`.
+fn package_names(output: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    output
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .filter_map(split_name_version)
+        .map(|(name, _)| name.to_string())
+        .filter(|name| seen.insert(name.clone()))
+        .collect()
+}
+
+impl PackageBackend for Apk {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Apk
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apk").arg("info"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(lines(&command.stdout))
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apk").arg("list").arg("--available"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        let installed = self.list_installed()?.into_iter().collect::<HashSet<_>>();
+        Ok(package_names(&command.stdout)
+            .into_iter()
+            .filter(|name| !installed.contains(name))
+            .collect())
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apk").arg("list").arg("--upgradable"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(package_names(&command.stdout))
+    }
+
+    /// apk only keeps packages that were explicitly installed or are required by one, so there
+    /// are no orphaned packages.
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        // Without wildcards, apk only matches the start of package names
+        let command = run_with_output(
+            Command::new("apk")
+                .arg("search")
+                .arg("-v")
+                .arg("--description")
+                .arg(format!("*{query}*")),
+        );
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        let installed = self.list_installed()?.into_iter().collect();
+        Ok(parse_search(&command.stdout, &installed))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let command = run_with_output(Command::new("apk").arg("info").arg("-a").arg(name));
+        let mut info = parse_info(&command.stdout).ok_or(PackageManagerError::UnknownPackage)?;
+
+        let installed = run_with_output(Command::new("apk").arg("info").arg("-e").arg(name));
+        if installed.success {
+            info.installed_version = Some(info.version.clone());
+        }
+
+        let policy = run_with_output(Command::new("apk").arg("policy").arg(name));
+        info.repository = policy
+            .stdout
+            .lines()
+            .map(str::trim)
+            .find(|l| l.contains("://"))
+            .map(String::from);
+        Ok(info)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apk")
+            .arg("add")
+            .arg(name)
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apk")
+            .arg("del")
+            .arg(name)
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apk")
+            .arg("add")
+            .arg("--upgrade")
+            .arg(name)
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL]
+    }
+
+    /// apk removes packages that are no longer required automatically, so there is nothing to
+    /// do.
+    fn remove_orphaned(&self, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        vec![RepositoryFile::new(
+            REPOSITORIES_PATH,
+            RepositoryFormat::ApkList,
+        )]
+    }
+
+    /// The signing key is the RSA public key of the repository, which is stored in
+    /// `/etc/apk/keys`.
+    fn add_repository(
+        &self,
+        repository: &NewRepository,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        repository.validate()?;
+        let key_path = PathBuf::from(format!("/etc/apk/keys/{}.rsa.pub", repository.name));
+        if key_path.exists() {
+            return Err(RepositoryError::InvalidRepository(
+                "A repository with this name exists already.".to_string(),
+            ));
+        }
+        if let Some(key) = &repository.signing_key {
+            check(write_system_file(credential.clone(), &key_path, key)?)?;
+        }
+
+        let mut content = fs::read_to_string(REPOSITORIES_PATH).unwrap_or_default();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("{}\n", repository.uri));
+        check(write_system_file(
+            credential,
+            Path::new(REPOSITORIES_PATH),
+            &content,
+        )?)
+    }
+}
+
+fn parse_search(output: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    output
+        .lines()
+        .filter_map(|l| l.split_once(" - "))
+        .filter_map(|(package, description)| {
+            let (name, version) = split_name_version(package.trim())?;
+            Some(PackageSummary {
+                installed: installed.contains(name),
+                name: name.to_string(),
+                version: Some(version.to_string()),
+                description: description.trim().to_string(),
+                package_manager: PackageManager::Apk,
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of `apk info -a`, which consists of sections like
+/// `curl-8.5.0-r0 description:` followed by their values and an empty line.
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    let mut package = None;
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+
+    for line in info.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_suffix(':')
+            && let Some((name_version, section)) = header.split_once(' ')
+            && split_name_version(name_version).is_some()
+        {
+            package.get_or_insert_with(|| name_version.to_string());
+            sections.push((section.to_string(), Vec::new()));
+        } else if let Some((_, values)) = sections.last_mut() {
+            values.push(line.to_string());
+        }
+    }
+
+    let package = package?;
+    let (name, version) = split_name_version(&package)?;
+    let section = |key: &str| {
+        sections
+            .iter()
+            .find(|(section, _)| section == key)
+            .map(|(_, values)| values.clone())
+            .unwrap_or_default()
+    };
+
+    Some(PackageInfo {
+        name: name.to_string(),
+        version: version.to_string(),
+        description: section("description").join("\n"),
+        installed_size: section("installed size")
+            .first()
+            .and_then(|s| parse_size(s)),
+        dependencies: section("depends on")
+            .iter()
+            .map(|d| strip_version_constraint(d))
+            .collect(),
+        reverse_dependencies: section("is required by")
+            .iter()
+            .filter_map(|p| split_name_version(p))
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        ..PackageInfo::new(PackageManager::Apk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let info = "curl-8.5.0-r0 description:
+URL retrival utility and library
+
+curl-8.5.0-r0 webpage:
+https://curl.se/
+
+curl-8.5.0-r0 installed size:
+252 KiB
+
+curl-8.5.0-r0 depends on:
+ca-certificates
+so:libc.musl-x86_64.so.1
+so:libcurl.so.4>=4
+
+curl-8.5.0-r0 is required by:
+git-2.43.0-r0
+
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.name, "curl");
+        assert_eq!(info.version, "8.5.0-r0");
+        assert_eq!(info.description, "URL retrival utility and library");
+        assert_eq!(info.installed_size, Some(252 * 1024));
+        assert_eq!(
+            info.dependencies,
+            vec![
+                "ca-certificates",
+                "so:libc.musl-x86_64.so.1",
+                "so:libcurl.so.4"
+            ]
+        );
+        assert_eq!(info.reverse_dependencies, vec!["git"]);
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let installed = HashSet::from(["curl".to_string()]);
+        let results = parse_search(
+            "curl-8.5.0-r0 - URL retrival utility and library\npy3-pycurl-7.45.2-r1 - Python interface to libcurl\n",
+            &installed,
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(results[1].name, "py3-pycurl");
+        assert_eq!(results[1].version.as_deref(), Some("7.45.2-r1"));
+
+        assert_eq!(
+            package_names("curl-8.6.0-r0 x86_64 {curl} (curl) [upgradable from: curl-8.5.0-r0]"),
+            vec!["curl"]
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use super::history::{ChangeAction, PackageChange, Transaction, local_timestamp};
+use super::repositories::{
+    self, NewRepository, RepositoryError, RepositoryFile, RepositoryFormat, check,
+    write_system_file,
+};
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    escape_regex, field, lines, parse_fields, run_command_line, run_with_output,
+    strip_version_constraint,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// Debian, Ubuntu and their derivatives
+pub struct Apt;
+
+/// The history of apt, oldest first. Older logs are compressed by logrotate and not read.
+const UPDATE_DATABASE: CommandLine = &["apt", "update", "-y"];
+const UPDATE_ALL: CommandLine = &["apt", "upgrade", "-y", "-q"];
+const UPDATE_SECURITY: CommandLine = &["unattended-upgrade"];
+
+const HISTORY_LOGS: [&str; 2] = ["/var/log/apt/history.log.1", "/var/log/apt/history.log"];
+
+const SOURCES_DIRECTORY: &str = "/etc/apt/sources.list.d";
+
+impl PackageBackend for Apt {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Apt
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apt").arg("list").arg("--installed"));
+        Ok(command
+            .stdout
+            .lines()
+            .filter_map(|e| {
+                let collection = e.split("/").collect::<Vec<&str>>();
+                if collection.len() != 2 {
+                    None
+                } else {
+                    Some(collection[0].to_string())
+                }
+            })
+            .collect::<Vec<String>>())
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apt").arg("list"));
+
+        let lines = &command.stdout.lines().filter(|x| !x.is_empty());
+        let mut installed = Vec::new();
+        let mut names = Vec::new();
+        lines.clone().for_each(|l| {
+            let line_s = l.split("/").collect::<Vec<&str>>();
+            if line_s.len() != 2 {
+                return;
+            };
+            if line_s[1].contains("[installed") {
+                installed.push(line_s[0]);
+            } else {
+                names.push(line_s[0])
+            }
+        });
+
+        let mut vector = Vec::new();
+
+        installed.sort();
+
+        for e in names {
+            if installed.binary_search(&e).is_err() {
+                vector.push(e.to_string());
+            }
+        }
+
+        Ok(vector
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<String>>())
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apt").arg("list").arg("--upgradable"));
+        Ok(command
+            .stdout
+            .lines()
+            .filter_map(|e| {
+                let collection = e.split("/").collect::<Vec<&str>>();
+                if collection.len() != 2 {
+                    None
+                } else {
+                    Some(collection[0].to_string())
+                }
+            })
+            .collect::<Vec<String>>())
+    }
+
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apt").arg("autoremove").arg("--dry-run"));
+        Ok(command
+            .stdout
+            .lines()
+            .map(|e| {
+                let collection = e.split(" - ").collect::<Vec<&str>>();
+                if collection.len() != 2 {
+                    String::from("")
+                } else {
+                    collection[0].to_string()
+                }
+            })
+            .filter(|lines| lines.starts_with("Remv"))
+            .collect::<Vec<String>>())
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        let command = run_with_output(
+            Command::new("apt-cache")
+                .arg("search")
+                .arg(escape_regex(query)),
+        );
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        let installed = self.list_installed()?.into_iter().collect();
+        Ok(parse_search(&command.stdout, &installed))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let show = run_with_output(
+            Command::new("apt-cache")
+                .arg("show")
+                .arg("--no-all-versions")
+                .arg(name),
+        );
+        let mut info = parse_show(&show.stdout).ok_or(PackageManagerError::UnknownPackage)?;
+
+        let policy = run_with_output(Command::new("apt-cache").arg("policy").arg(name));
+        (info.installed_version, info.repository) = parse_policy(&policy.stdout);
+
+        let rdepends = run_with_output(
+            Command::new("apt-cache")
+                .arg("rdepends")
+                .arg("--installed")
+                .arg(name),
+        );
+        info.reverse_dependencies = parse_rdepends(&rdepends.stdout);
+        Ok(info)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt")
+            .args(vec!["install", name, "-y", "-q"])
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt")
+            .args(vec!["remove", name, "-y", "-q"])
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt")
+            .args(vec!["--only-upgrade", "install", name, "-y", "-q"])
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    /// Security updates are installed by unattended-upgrades, which only considers the security
+    /// archive in its default configuration.
+    fn supports_security_updates(&self) -> bool {
+        Command::new("unattended-upgrade")
+            .arg("--help")
+            .output()
+            .is_ok()
+    }
+
+    fn update_security(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_SECURITY)
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL, UPDATE_SECURITY]
+    }
+
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt")
+            .args(vec!["autoremove", "-y", "-q"])
+            .output()
+    }
+
+    fn history(&self) -> Result<Vec<Transaction>, PackageManagerError> {
+        let mut transactions = HISTORY_LOGS
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|log| parse_history(&log))
+            .collect::<Vec<Transaction>>();
+        transactions.reverse();
+        Ok(transactions)
+    }
+
+    /// apt can only downgrade to versions that are still available from a repository or the
+    /// package cache.
+    fn supports_downgrade(&self) -> bool {
+        true
+    }
+
+    fn downgrade(
+        &self,
+        name: &str,
+        version: &str,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt")
+            .args(vec![
+                "install",
+                &format!("{name}={version}"),
+                "--allow-downgrades",
+                "-y",
+                "-q",
+            ])
+            .output()
+    }
+
+    fn supports_holds(&self) -> bool {
+        true
+    }
+
+    fn list_holds(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("apt-mark").arg("showhold"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(lines(&command.stdout))
+    }
+
+    fn hold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt-mark")
+            .args(vec!["hold", name])
+            .output()
+    }
+
+    fn unhold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "apt-mark")
+            .args(vec!["unhold", name])
+            .output()
+    }
+
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        let mut files = vec![RepositoryFile::new(
+            "/etc/apt/sources.list",
+            RepositoryFormat::AptList,
+        )];
+        files.extend(RepositoryFile::in_directory(
+            SOURCES_DIRECTORY,
+            "list",
+            RepositoryFormat::AptList,
+        ));
+        files.extend(RepositoryFile::in_directory(
+            SOURCES_DIRECTORY,
+            "sources",
+            RepositoryFormat::Deb822,
+        ));
+        files
+    }
+
+    /// The repository is written to its own file in the deb822 format and only trusts its own
+    /// key, which is stored in `/etc/apt/keyrings`.
+    fn add_repository(
+        &self,
+        repository: &NewRepository,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        repository.validate()?;
+        let Some(suite) = &repository.suite else {
+            return Err(RepositoryError::InvalidRepository(
+                "apt requires a suite.".to_string(),
+            ));
+        };
+        if repositories::exists(&self.repository_files(), repository) {
+            return Err(RepositoryError::InvalidRepository(
+                "A repository with this name exists already.".to_string(),
+            ));
+        }
+
+        let mut sources = vec![
+            "Types: deb".to_string(),
+            format!("URIs: {}", repository.uri),
+            format!("Suites: {suite}"),
+        ];
+        if !repository.components.is_empty() {
+            sources.push(format!("Components: {}", repository.components.join(" ")));
+        }
+        if let Some(key) = &repository.signing_key {
+            let key_path = PathBuf::from(format!("/etc/apt/keyrings/{}.asc", repository.name));
+            check(write_system_file(credential.clone(), &key_path, key)?)?;
+            sources.push(format!("Signed-By: {}", key_path.display()));
+        }
+
+        let path = PathBuf::from(format!("{SOURCES_DIRECTORY}/{}.sources", repository.name));
+        check(write_system_file(
+            credential,
+            &path,
+            &format!("{}\n", sources.join("\n")),
+        )?)
+    }
+}
+
+/// Parse the entries of `/var/log/apt/history.log`.
+fn parse_history(log: &str) -> Vec<Transaction> {
+    let mut transactions = Vec::new();
+
+    for entry in log.split("\n\n") {
+        let fields = parse_fields(entry);
+        let Some(started_at) =
+            field(&fields, &["Start-Date"]).and_then(|d| local_timestamp(d, "%Y-%m-%d %H:%M:%S"))
+        else {
+            continue;
+        };
+
+        let mut transaction =
+            Transaction::new(PackageManager::Apt, &started_at.to_string(), started_at);
+        transaction.command = field(&fields, &["Commandline"]).map(String::from);
+        // Requested-By: john (1000)
+        transaction.user = field(&fields, &["Requested-By"])
+            .and_then(|u| u.split_whitespace().next())
+            .map(String::from);
+
+        for (key, packages) in &fields {
+            let action = match key.as_str() {
+                "Install" => ChangeAction::Install,
+                "Upgrade" => ChangeAction::Upgrade,
+                "Downgrade" => ChangeAction::Downgrade,
+                "Reinstall" => ChangeAction::Reinstall,
+                "Remove" | "Purge" => ChangeAction::Remove,
+                _ => continue,
+            };
+            transaction
+                .changes
+                .extend(parse_history_packages(packages, action));
+        }
+        transactions.push(transaction);
+    }
+
+    transactions
+}
+
+/// Parse a list of packages like `curl:amd64 (7.88.1-10, 7.88.1-11), libc6:amd64 (2.36-9)`.
+fn parse_history_packages(packages: &str, action: ChangeAction) -> Vec<PackageChange> {
+    packages
+        .split("), ")
+        .filter_map(|p| {
+            let (name, versions) = p.trim().trim_end_matches(')').split_once(" (")?;
+            let name = name.split(':').next().unwrap_or(name);
+            let versions = versions.split(", ").collect::<Vec<&str>>();
+            let mut change = match action {
+                ChangeAction::Install => PackageChange::new(name, action, None, Some(versions[0])),
+                ChangeAction::Remove => PackageChange::new(name, action, Some(versions[0]), None),
+                ChangeAction::Reinstall => {
+                    PackageChange::new(name, action, Some(versions[0]), Some(versions[0]))
+                }
+                ChangeAction::Upgrade | ChangeAction::Downgrade => {
+                    PackageChange::new(name, action, Some(versions[0]), versions.get(1).copied())
+                }
+            };
+            change.revertible = action == ChangeAction::Upgrade;
+            Some(change)
+        })
+        .collect()
+}
+
+fn parse_search(output: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    output
+        .lines()
+        .filter_map(|l| l.split_once(" - "))
+        .map(|(name, description)| PackageSummary {
+            installed: installed.contains(name),
+            name: name.to_string(),
+            version: None,
+            description: description.to_string(),
+            package_manager: PackageManager::Apt,
+        })
+        .collect()
+}
+
+fn parse_show(show: &str) -> Option<PackageInfo> {
+    let fields = parse_fields(show);
+    let dependencies = [
+        field(&fields, &["Pre-Depends"]),
+        field(&fields, &["Depends"]),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|d| d.split(','))
+    .map(strip_version_constraint)
+    .filter(|d| !d.is_empty())
+    .collect();
+
+    Some(PackageInfo {
+        name: field(&fields, &["Package"])?.to_string(),
+        version: field(&fields, &["Version"])?.to_string(),
+        description: field(&fields, &["Description", "Description-en"])
+            .unwrap_or_default()
+            .to_string(),
+        installed_size: field(&fields, &["Installed-Size"])
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(|kib| kib * 1024),
+        download_size: field(&fields, &["Size"]).and_then(|s| s.parse().ok()),
+        dependencies,
+        ..PackageInfo::new(PackageManager::Apt)
+    })
+}
+
+/// Parse the installed version and the origin of the candidate version from `apt-cache policy`.
+fn parse_policy(policy: &str) -> (Option<String>, Option<String>) {
+    let mut installed = None;
+    let mut candidate = None;
+    let mut repository = None;
+    let mut in_candidate = false;
+
+    for line in policy.lines() {
+        let trimmed = line.trim();
+        if let Some(version) = trimmed.strip_prefix("Installed:") {
+            let version = version.trim();
+            if version != "(none)" {
+                installed = Some(version.to_string());
+            }
+        } else if let Some(version) = trimmed.strip_prefix("Candidate:") {
+            candidate = Some(version.trim().to_string());
+        } else if let Some(candidate) = &candidate {
+            let entry = trimmed.trim_start_matches("***").trim();
+            if entry.split_whitespace().next() == Some(candidate) {
+                in_candidate = true;
+            } else if in_candidate {
+                let origin = entry.split_whitespace().collect::<Vec<&str>>();
+                // Origins are listed as "<priority> <uri> <suite/component> <arch> Packages",
+                // the dpkg status file is not a repository
+                if origin.len() >= 3 {
+                    repository = Some(format!("{} {}", origin[1], origin[2]));
+                }
+                break;
+            }
+        }
+    }
+
+    (installed, repository)
+}
+
+fn parse_rdepends(rdepends: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    for line in rdepends
+        .lines()
+        .skip_while(|l| !l.starts_with("Reverse Depends:"))
+        .skip(1)
+    {
+        let name = line.trim().trim_start_matches('|').to_string();
+        if !name.is_empty() && !packages.contains(&name) {
+            packages.push(name);
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let show = "Package: curl
+Version: 7.88.1-10+deb12u5
+Priority: optional
+Section: web
+Installed-Size: 500
+Depends: libc6 (>= 2.34), libcurl4 (= 7.88.1-10+deb12u5), zlib1g (>= 1:1.1.4) | zlib-ng
+Description: command line tool for transferring data with URL syntax
+ curl is a command line tool for transferring data with URL syntax.
+ .
+ It supports many protocols.
+Size: 315360
+
+";
+        let info = parse_show(show).unwrap();
+        assert_eq!(info.name, "curl");
+        assert_eq!(info.installed_size, Some(500 * 1024));
+        assert_eq!(info.download_size, Some(315360));
+        assert_eq!(
+            info.dependencies,
+            vec!["libc6", "libcurl4", "zlib1g | zlib-ng"]
+        );
+        assert_eq!(
+            info.description,
+            "command line tool for transferring data with URL syntax\ncurl is a command line tool for transferring data with URL syntax.\n\nIt supports many protocols."
+        );
+
+        let policy = "curl:
+  Installed: 7.88.1-10+deb12u4
+  Candidate: 7.88.1-10+deb12u5
+  Version table:
+     7.88.1-10+deb12u5 500
+        500 http://deb.debian.org/debian bookworm/main amd64 Packages
+ *** 7.88.1-10+deb12u4 100
+        100 /var/lib/dpkg/status
+";
+        assert_eq!(
+            parse_policy(policy),
+            (
+                Some("7.88.1-10+deb12u4".to_string()),
+                Some("http://deb.debian.org/debian bookworm/main".to_string())
+            )
+        );
+
+        let rdepends = "curl
+Reverse Depends:
+  git
+ |wget2
+  git
+";
+        assert_eq!(parse_rdepends(rdepends), vec!["git", "wget2"]);
+    }
+
+    #[test]
+    fn parse_history_log() {
+        let log = "
+Start-Date: 2024-01-15  10:23:45
+Commandline: apt upgrade -y
+Requested-By: john (1000)
+Upgrade: curl:amd64 (7.88.1-10+deb12u4, 7.88.1-10+deb12u5), libcurl4:amd64 (7.88.1-10+deb12u4, 7.88.1-10+deb12u5)
+Install: linux-image-6.1.0-18-amd64:amd64 (6.1.76-1, automatic)
+End-Date: 2024-01-15  10:23:50
+
+Start-Date: 2024-01-16  08:00:00
+Commandline: apt purge xterm
+Purge: xterm:amd64 (379-1)
+End-Date: 2024-01-16  08:00:02
+";
+        let transactions = parse_history(log);
+        assert_eq!(transactions.len(), 2);
+
+        let upgrade = &transactions[0];
+        assert_eq!(
+            Some(upgrade.started_at),
+            local_timestamp("2024-01-15 10:23:45", "%Y-%m-%d %H:%M:%S")
+        );
+        assert_eq!(upgrade.user.as_deref(), Some("john"));
+        assert_eq!(upgrade.command.as_deref(), Some("apt upgrade -y"));
+        assert_eq!(upgrade.changes.len(), 3);
+        assert_eq!(
+            upgrade.changes[0],
+            PackageChange {
+                name: "curl".to_string(),
+                action: ChangeAction::Upgrade,
+                old_version: Some("7.88.1-10+deb12u4".to_string()),
+                new_version: Some("7.88.1-10+deb12u5".to_string()),
+                revertible: true,
+            }
+        );
+        assert_eq!(upgrade.changes[2].action, ChangeAction::Install);
+        assert_eq!(upgrade.changes[2].new_version.as_deref(), Some("6.1.76-1"));
+
+        let purge = &transactions[1];
+        assert_eq!(purge.user, None);
+        assert_eq!(
+            purge.changes,
+            vec![PackageChange::new(
+                "xterm",
+                ChangeAction::Remove,
+                Some("379-1"),
+                None
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let installed = HashSet::from(["curl".to_string()]);
+        let results = parse_search(
+            "curl - command line tool for transferring data with URL syntax\nlibcurl4 - easy-to-use client-side URL transfer library\n",
+            &installed,
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert!(!results[1].installed);
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::{BTreeSet, HashSet};
+use std::process::Command;
+
+use super::history::{ChangeAction, PackageChange, Transaction, local_timestamp};
+use super::repositories::{
+    NewRepository, RepositoryError, RepositoryFile, RepositoryFormat, add_rpm_repository,
+};
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    field, parse_fields, parse_size, run_command_line, run_with_output,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// Fedora, RHEL and their derivatives
+pub struct Dnf;
+
+/// Every transaction is read using a separate call of `dnf history info`, so only the latest
+/// transactions are listed.
+const UPDATE_DATABASE: CommandLine = &["dnf", "makecache", "-y"];
+const UPDATE_ALL: CommandLine = &["dnf", "update", "-y", "-q"];
+const UPDATE_SECURITY: CommandLine = &["dnf", "upgrade", "--security", "-y", "-q"];
+
+const HISTORY_LIMIT: usize = 25;
+
+/// Extract the package names from lines like `curl.x86_64  8.2.1-4.fc39  updates`.
+fn package_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|e| {
+            let collection = e.split(".").collect::<Vec<&str>>();
+            if collection.len() != 2 {
+                None
+            } else {
+                Some(collection[0].to_string())
+            }
+        })
+        .collect::<Vec<String>>()
+}
+
+impl Dnf {
+    /// Names of the packages returned by `dnf repoquery` with the given arguments.
+    fn repoquery(&self, args: &[&str], name: &str) -> Vec<String> {
+        run_with_output(
+            Command::new("dnf")
+                .arg("repoquery")
+                .arg("-q")
+                .args(args)
+                .arg("--qf")
+                .arg("%{name}\n")
+                .arg(name),
+        )
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+    }
+}
+
+impl PackageBackend for Dnf {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Dnf
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("dnf").arg("list").arg("installed"));
+        Ok(package_names(&command.stdout))
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("dnf").arg("list").arg("available"));
+        Ok(package_names(&command.stdout))
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("dnf").arg("list-update"));
+        Ok(package_names(&command.stdout))
+    }
+
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("dnf").arg("repoquery").arg("--unneeded"));
+        Ok(package_names(&command.stdout))
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        // dnf exits with an error if nothing matches
+        let command = run_with_output(Command::new("dnf").arg("search").arg("-q").arg(query));
+        let installed = self.list_installed()?.into_iter().collect();
+        Ok(parse_search(&command.stdout, &installed))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let dnf_info = run_with_output(Command::new("dnf").arg("info").arg("-q").arg(name));
+        let mut info = parse_info(&dnf_info.stdout).ok_or(PackageManagerError::UnknownPackage)?;
+
+        info.dependencies = self.repoquery(&["--requires", "--resolve"], name);
+        info.reverse_dependencies = self.repoquery(&["--installed", "--whatrequires"], name);
+        Ok(info)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["install", name, "-y", "-q"])
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["remove", name, "-y", "-q"])
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["update", name, "-y", "-q"])
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    fn supports_security_updates(&self) -> bool {
+        true
+    }
+
+    fn update_security(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_SECURITY)
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL, UPDATE_SECURITY]
+    }
+
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["autoremove", "-y", "-q"])
+            .output()
+    }
+
+    fn history(&self) -> Result<Vec<Transaction>, PackageManagerError> {
+        let list = run_with_output(
+            Command::new("dnf")
+                .env("LC_ALL", "C")
+                .arg("history")
+                .arg("list"),
+        );
+        if !list.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+
+        let mut transactions = parse_history_list(&list.stdout);
+        transactions.truncate(HISTORY_LIMIT);
+        for transaction in transactions.iter_mut() {
+            let info = run_with_output(
+                Command::new("dnf")
+                    .env("LC_ALL", "C")
+                    .arg("history")
+                    .arg("info")
+                    .arg(transaction.native_id()),
+            );
+            parse_history_info(&info.stdout, transaction);
+        }
+        Ok(transactions)
+    }
+
+    fn undo(
+        &self,
+        transaction: &Transaction,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["history", "undo", transaction.native_id(), "-y", "-q"])
+            .output()
+    }
+
+    /// dnf can only downgrade to versions that are still available from a repository.
+    fn supports_downgrade(&self) -> bool {
+        true
+    }
+
+    fn downgrade(
+        &self,
+        name: &str,
+        version: &str,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["downgrade", &format!("{name}-{version}"), "-y", "-q"])
+            .output()
+    }
+
+    /// Holds require the versionlock plugin, which is part of dnf 5 and packaged as
+    /// `python3-dnf-plugin-versionlock` for dnf 4.
+    fn supports_holds(&self) -> bool {
+        run_with_output(Command::new("dnf").arg("versionlock").arg("list")).success
+    }
+
+    fn list_holds(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("dnf").arg("versionlock").arg("list"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(parse_versionlock(&command.stdout))
+    }
+
+    fn hold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["versionlock", "add", name, "-q"])
+            .output()
+    }
+
+    fn unhold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "dnf")
+            .args(vec!["versionlock", "delete", name, "-q"])
+            .output()
+    }
+
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        RepositoryFile::in_directory(REPOSITORY_DIRECTORY, "repo", RepositoryFormat::Ini)
+    }
+
+    fn add_repository(
+        &self,
+        repository: &NewRepository,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        add_rpm_repository(
+            &self.repository_files(),
+            REPOSITORY_DIRECTORY,
+            repository,
+            &[],
+            credential,
+        )
+    }
+}
+
+const REPOSITORY_DIRECTORY: &str = "/etc/yum.repos.d";
+
+/// Parse the packages locked by `dnf versionlock list`.
+///
+/// dnf 4 lists patterns like `curl-0:8.2.1-1.fc39.*`, dnf 5 lists `Package name: curl`.
+fn parse_versionlock(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter_map(|l| {
+            if let Some(name) = l.strip_prefix("Package name:") {
+                return Some(name.trim().to_string());
+            }
+            let (name_version, _) = l.strip_suffix(".*")?.rsplit_once('-')?;
+            let (name, _) = name_version.rsplit_once('-')?;
+            Some(name.to_string())
+        })
+        .collect()
+}
+
+/// Parse the table printed by `dnf history list`, which looks like
+/// `    12 | upgrade -y | 2024-01-15 10:23 | Upgrade |    5`.
+fn parse_history_list(output: &str) -> Vec<Transaction> {
+    output
+        .lines()
+        .filter_map(|l| {
+            let columns = l.split('|').map(str::trim).collect::<Vec<&str>>();
+            if columns.len() < 4 || columns[0].parse::<u64>().is_err() {
+                return None;
+            }
+            let started_at = local_timestamp(columns[2], "%Y-%m-%d %H:%M")?;
+            let mut transaction = Transaction::new(PackageManager::Dnf, columns[0], started_at);
+            transaction.command = Some(columns[1].to_string()).filter(|c| !c.is_empty());
+            Some(transaction)
+        })
+        .collect()
+}
+
+/// Add the user and the changed packages from `dnf history info` to a transaction.
+fn parse_history_info(output: &str, transaction: &mut Transaction) {
+    let mut changes: Vec<PackageChange> = Vec::new();
+    let mut altered = false;
+
+    for line in output.lines() {
+        if line.starts_with("Packages Altered") {
+            altered = true;
+            continue;
+        }
+        if !altered {
+            // User           : John Doe <john>
+            if let Some((key, value)) = line.split_once(':')
+                && key.trim() == "User"
+            {
+                transaction.user = value
+                    .rsplit_once('<')
+                    .map(|(_, u)| u.trim_end_matches('>'))
+                    .or(Some(value))
+                    .map(|u| u.trim().to_string());
+            }
+            continue;
+        }
+        if !line.starts_with(' ') {
+            break;
+        }
+
+        // Problems are marked using `**` in front of the action
+        let mut words = line.split_whitespace().skip_while(|w| w.starts_with("**"));
+        let (Some(action), Some(package)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let Some((name, version)) = split_nevra(package) else {
+            continue;
+        };
+        let existing = changes.iter().position(|c| c.name == name);
+
+        match action {
+            "Install" | "Dep-Install" => {
+                changes.push(PackageChange::new(
+                    name,
+                    ChangeAction::Install,
+                    None,
+                    Some(version),
+                ));
+            }
+            "Erase" | "Removed" | "Obsoleted" => {
+                changes.push(PackageChange::new(
+                    name,
+                    ChangeAction::Remove,
+                    Some(version),
+                    None,
+                ));
+            }
+            "Reinstall" => {
+                changes.push(PackageChange::new(
+                    name,
+                    ChangeAction::Reinstall,
+                    Some(version),
+                    Some(version),
+                ));
+            }
+            // The new version is listed next to the version it replaced
+            "Upgrade" | "Downgrade" | "Upgraded" | "Downgraded" => {
+                let change_action = if action.starts_with("Upgrade") {
+                    ChangeAction::Upgrade
+                } else {
+                    ChangeAction::Downgrade
+                };
+                let index = existing.unwrap_or_else(|| {
+                    changes.push(PackageChange::new(name, change_action, None, None));
+                    changes.len() - 1
+                });
+                let change = &mut changes[index];
+                if action.ends_with('d') {
+                    change.old_version = Some(version.to_string());
+                } else {
+                    change.new_version = Some(version.to_string());
+                }
+                change.revertible = change.action == ChangeAction::Upgrade;
+            }
+            _ => {}
+        }
+    }
+
+    transaction.undoable = !changes.is_empty();
+    transaction.changes = changes;
+}
+
+/// Split a package like `curl-8.2.1-1.fc39.x86_64` into its name and `version-release`.
+fn split_nevra(package: &str) -> Option<(&str, &str)> {
+    let (without_arch, _) = package.rsplit_once('.')?;
+    let (name_version, _) = without_arch.rsplit_once('-')?;
+    let (name, _) = name_version.rsplit_once('-')?;
+    Some((name, &without_arch[name.len() + 1..]))
+}
+
+fn parse_search(output: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    let mut results: Vec<PackageSummary> = Vec::new();
+    for line in output.lines() {
+        // dnf 4 separates the package from its summary using " : ", dnf 5 uses a tab
+        let Some((package, description)) = line
+            .split_once(" : ")
+            .or_else(|| line.trim_start().split_once('\t'))
+        else {
+            continue;
+        };
+        let package = package.trim();
+        if package.contains(' ') {
+            continue;
+        }
+        let name = package
+            .rsplit_once('.')
+            .map_or(package, |(name, _)| name)
+            .to_string();
+        if results.iter().any(|r| r.name == name) {
+            continue;
+        }
+        results.push(PackageSummary {
+            installed: installed.contains(&name),
+            name,
+            version: None,
+            description: description.trim().to_string(),
+            package_manager: PackageManager::Dnf,
+        });
+    }
+    results
+}
+
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    // Skip headers like "Installed Packages" preceding the first entry
+    let entry = info
+        .lines()
+        .skip_while(|l| !l.starts_with("Name"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let fields = parse_fields(&entry);
+
+    let version = match field(&fields, &["Release"]) {
+        Some(release) => format!("{}-{release}", field(&fields, &["Version"])?),
+        None => field(&fields, &["Version"])?.to_string(),
+    };
+    let repository = field(&fields, &["Repository"])?;
+    let installed = repository == "@System";
+    let origin = field(&fields, &["From repository", "From repo"]).or(if installed {
+        None
+    } else {
+        Some(repository)
+    });
+
+    Some(PackageInfo {
+        name: field(&fields, &["Name"])?.to_string(),
+        installed_version: installed.then(|| version.clone()),
+        version,
+        description: field(&fields, &["Description"])
+            .or(field(&fields, &["Summary"]))
+            .unwrap_or_default()
+            .to_string(),
+        installed_size: field(&fields, &["Installed size"])
+            .or(if installed {
+                field(&fields, &["Size"])
+            } else {
+                None
+            })
+            .and_then(parse_size),
+        download_size: field(&fields, &["Package size", "Download size"])
+            .or(if installed {
+                None
+            } else {
+                field(&fields, &["Size"])
+            })
+            .and_then(parse_size),
+        repository: origin.map(String::from),
+        ..PackageInfo::new(PackageManager::Dnf)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let info = "Installed Packages
+Name         : curl
+Version      : 8.2.1
+Release      : 4.fc39
+Architecture : x86_64
+Size         : 776 k
+Source       : curl-8.2.1-4.fc39.src.rpm
+Repository   : @System
+From repo    : updates
+Summary      : A utility for getting files from remote servers (FTP, HTTP, and others)
+URL          : https://curl.se/
+License      : curl
+Description  : curl is a command line tool for transferring data with URL syntax.
+             : It supports many protocols.
+
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.name, "curl");
+        assert_eq!(info.version, "8.2.1-4.fc39");
+        assert_eq!(info.installed_version.as_deref(), Some("8.2.1-4.fc39"));
+        assert_eq!(info.installed_size, Some(776 * 1024));
+        assert_eq!(info.download_size, None);
+        assert_eq!(info.repository.as_deref(), Some("updates"));
+        assert_eq!(
+            info.description,
+            "curl is a command line tool for transferring data with URL syntax.\nIt supports many protocols."
+        );
+    }
+
+    #[test]
+    fn parse_transaction_history() {
+        let list = "ID     | Command line             | Date and time    | Action(s)      | Altered
+-------------------------------------------------------------------------------
+    12 | upgrade -y               | 2024-01-15 10:23 | Upgrade        |    2   
+    11 |                          | 2024-01-14 08:00 | Install        |    1 EE
+";
+        let mut transactions = parse_history_list(list);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].id, "dnf-12");
+        assert_eq!(transactions[0].command.as_deref(), Some("upgrade -y"));
+        assert_eq!(transactions[1].command, None);
+        assert_eq!(
+            Some(transactions[1].started_at),
+            local_timestamp("2024-01-14 08:00", "%Y-%m-%d %H:%M")
+        );
+
+        let info = "Transaction ID : 12
+Begin time     : Mon 15 Jan 2024 10:23:45 AM CET
+User           : John Doe <john>
+Return-Code    : Success
+Command Line   : upgrade -y
+Comment        :
+Packages Altered:
+    Upgrade  curl-8.2.1-1.fc39.x86_64          @updates
+    Upgraded curl-8.0.1-1.fc39.x86_64          @@System
+    Install  kernel-core-6.7.4-200.fc39.x86_64 @updates
+    Erase    xterm-389-1.fc39.x86_64           @@System
+Scriptlet output:
+   1 warning: something
+";
+        parse_history_info(info, &mut transactions[0]);
+        let transaction = &transactions[0];
+        assert_eq!(transaction.user.as_deref(), Some("john"));
+        assert!(transaction.undoable);
+        assert_eq!(
+            transaction.changes,
+            vec![
+                PackageChange {
+                    name: "curl".to_string(),
+                    action: ChangeAction::Upgrade,
+                    old_version: Some("8.0.1-1.fc39".to_string()),
+                    new_version: Some("8.2.1-1.fc39".to_string()),
+                    revertible: true,
+                },
+                PackageChange::new(
+                    "kernel-core",
+                    ChangeAction::Install,
+                    None,
+                    Some("6.7.4-200.fc39")
+                ),
+                PackageChange::new("xterm", ChangeAction::Remove, Some("389-1.fc39"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_locked_packages() {
+        let dnf4 = "Last metadata expiration check: 0:10:00 ago on Mon 15 Jan 2024.
+curl-0:8.2.1-1.fc39.*
+kernel-core-0:6.7.4-200.fc39.*
+";
+        assert_eq!(parse_versionlock(dnf4), vec!["curl", "kernel-core"]);
+
+        let dnf5 = "# Added by 'versionlock add' command on 2024-01-15 10:00:00
+Package name: curl
+evr = 8.2.1-1.fc39
+";
+        assert_eq!(parse_versionlock(dnf5), vec!["curl"]);
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let search = "Last metadata expiration check: 0:10:01 ago.
+======================== Name Exactly Matched: curl ========================
+curl.x86_64 : A utility for getting files from remote servers
+======================== Name & Summary Matched: curl ======================
+curlpp.x86_64 : A C++ wrapper for libcURL
+curlpp.i686 : A C++ wrapper for libcURL
+";
+        let installed = HashSet::from(["curl".to_string()]);
+        let results = parse_search(search, &installed);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(results[1].name, "curlpp");
+        assert_eq!(results[1].description, "A C++ wrapper for libcURL");
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::process::Command;
+
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    lines, parse_size, run_command_line, run_with_output,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// Flatpak applications from all configured remotes, which are installed system-wide
+pub struct Flatpak;
+
+impl Flatpak {
+    /// Run a flatpak command listing application IDs, one per line.
+    fn applications(args: &[&str]) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(
+            Command::new("flatpak")
+                .args(args)
+                .arg("--app")
+                .arg("--columns=application"),
+        );
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(lines(&command.stdout))
+    }
+}
+
+const UPDATE_DATABASE: CommandLine = &[
+    "flatpak",
+    "update",
+    "--system",
+    "--appstream",
+    "--noninteractive",
+];
+const UPDATE_ALL: CommandLine = &["flatpak", "update", "--system", "--noninteractive", "-y"];
+
+impl PackageBackend for Flatpak {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Flatpak
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        Flatpak::applications(&["list"])
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let installed = self.list_installed()?.into_iter().collect::<HashSet<_>>();
+        Ok(Flatpak::applications(&["remote-ls"])?
+            .into_iter()
+            .filter(|a| !installed.contains(a))
+            .collect())
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        Flatpak::applications(&["remote-ls", "--updates"])
+    }
+
+    /// Runtimes that are no longer used by any application can not be listed without removing
+    /// them, see [`Flatpak::remove_orphaned`].
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        let command = run_with_output(
+            Command::new("flatpak")
+                .arg("search")
+                .arg("--columns=application,version,description")
+                .arg(query),
+        );
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        let installed = self.list_installed()?.into_iter().collect();
+        Ok(parse_search(&command.stdout, &installed))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let local = run_with_output(Command::new("flatpak").arg("info").arg(name));
+        if local.success
+            && let Some(mut info) = parse_info(&local.stdout)
+        {
+            info.installed_version = Some(info.version.clone());
+            return Ok(info);
+        }
+
+        // Applications that are not installed have to be looked up in every remote
+        let remotes = run_with_output(Command::new("flatpak").arg("remotes").arg("--columns=name"));
+        for remote in lines(&remotes.stdout) {
+            let remote_info = run_with_output(
+                Command::new("flatpak")
+                    .arg("remote-info")
+                    .arg(&remote)
+                    .arg(name),
+            );
+            if remote_info.success
+                && let Some(mut info) = parse_info(&remote_info.stdout)
+            {
+                info.repository = Some(remote);
+                return Ok(info);
+            }
+        }
+
+        Err(PackageManagerError::UnknownPackage)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "flatpak")
+            .args(vec!["install", "--system", "--noninteractive", "-y", name])
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "flatpak")
+            .args(vec![
+                "uninstall",
+                "--system",
+                "--noninteractive",
+                "-y",
+                name,
+            ])
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "flatpak")
+            .args(vec!["update", "--system", "--noninteractive", "-y", name])
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL]
+    }
+
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "flatpak")
+            .args(vec![
+                "uninstall",
+                "--system",
+                "--unused",
+                "--noninteractive",
+                "-y",
+            ])
+            .output()
+    }
+}
+
+fn parse_search(output: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    output
+        .lines()
+        .filter_map(|l| {
+            let mut columns = l.split('\t');
+            let name = columns.next()?.trim();
+            // "No matches found" is printed if nothing matches
+            if name.is_empty() || name.contains(' ') {
+                return None;
+            }
+            Some(PackageSummary {
+                installed: installed.contains(name),
+                name: name.to_string(),
+                version: columns
+                    .next()
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(String::from),
+                description: columns.next().unwrap_or_default().trim().to_string(),
+                package_manager: PackageManager::Flatpak,
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of `flatpak info` or `flatpak remote-info`.
+///
+/// Both print the name and summary of the application followed by fields that are right-aligned
+/// at the colon.
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    let mut description = String::new();
+    let mut fields: Vec<(&str, &str)> = Vec::new();
+
+    for line in info.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match line.split_once(": ") {
+            Some((key, value)) if !key.contains(' ') => fields.push((key, value.trim())),
+            _ if fields.is_empty() => {
+                description = line
+                    .split_once(" - ")
+                    .map_or(line, |(_, summary)| summary)
+                    .to_string();
+            }
+            _ => {}
+        }
+    }
+
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| *v)
+    };
+
+    Some(PackageInfo {
+        name: field("ID")?.to_string(),
+        version: field("Version")
+            .or(field("Commit"))
+            .unwrap_or_default()
+            .to_string(),
+        description,
+        installed_size: field("Installed").and_then(parse_size),
+        download_size: field("Download").and_then(parse_size),
+        dependencies: [field("Runtime"), field("Sdk")]
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+        repository: field("Origin").map(String::from),
+        ..PackageInfo::new(PackageManager::Flatpak)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let info = "
+GNOME Text Editor - Edit text files
+
+          ID: org.gnome.TextEditor
+         Ref: app/org.gnome.TextEditor/x86_64/stable
+        Arch: x86_64
+      Branch: stable
+     Version: 45.1
+      Origin: flathub
+Installation: system
+   Installed: 3.1 MB
+     Runtime: org.gnome.Platform/x86_64/45
+         Sdk: org.gnome.Sdk/x86_64/45
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.name, "org.gnome.TextEditor");
+        assert_eq!(info.version, "45.1");
+        assert_eq!(info.description, "Edit text files");
+        assert_eq!(info.installed_size, Some(3_100_000));
+        assert_eq!(info.repository.as_deref(), Some("flathub"));
+        assert_eq!(
+            info.dependencies,
+            vec!["org.gnome.Platform/x86_64/45", "org.gnome.Sdk/x86_64/45"]
+        );
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let installed = HashSet::from(["org.gnome.TextEditor".to_string()]);
+        let results = parse_search(
+            "org.gnome.TextEditor\t45.1\tEdit text files\norg.gnome.gedit\t46.2\tEdit text files\n",
+            &installed,
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(results[1].version.as_deref(), Some("46.2"));
+        assert!(parse_search("No matches found\n", &installed).is_empty());
+    }
+}
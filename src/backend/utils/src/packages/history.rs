@@ -0,0 +1,110 @@
+//! Past package transactions as recorded by the package managers.
+//!
+//! apt and pacman keep a log in `/var/log`, dnf keeps a database that is read using
+//! `dnf history`. The other package managers do not keep a history that could be parsed.
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::PackageManager;
+
+/// What happened to a package in a transaction
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeAction {
+    Install,
+    Remove,
+    Upgrade,
+    Downgrade,
+    Reinstall,
+}
+
+/// A single package changed by a transaction
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageChange {
+    pub name: String,
+    pub action: ChangeAction,
+    /// The version before the transaction, missing for installed packages
+    pub old_version: Option<String>,
+    /// The version after the transaction, missing for removed packages
+    pub new_version: Option<String>,
+    /// Whether the package can be downgraded to `old_version` using
+    /// [`super::PackageBackend::downgrade`]
+    pub revertible: bool,
+}
+
+impl PackageChange {
+    pub(super) fn new(
+        name: &str,
+        action: ChangeAction,
+        old_version: Option<&str>,
+        new_version: Option<&str>,
+    ) -> Self {
+        PackageChange {
+            name: name.to_string(),
+            action,
+            old_version: old_version.map(String::from),
+            new_version: new_version.map(String::from),
+            revertible: false,
+        }
+    }
+}
+
+/// A set of changes made to the installed packages at once
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    /// Unique among all transactions, e.g. `dnf-12` or `apt-1705310625`
+    pub id: String,
+    /// Missing for Zentrox jobs that used every package manager
+    pub package_manager: Option<PackageManager>,
+    /// Seconds since the UNIX epoch
+    pub started_at: i64,
+    /// The Zentrox account or the system user that started the transaction if it is known
+    pub user: Option<String>,
+    /// The command line used to start the transaction
+    pub command: Option<String>,
+    pub changes: Vec<PackageChange>,
+    /// Whether the whole transaction can be reverted using [`super::PackageBackend::undo`]
+    pub undoable: bool,
+    /// ID of the Zentrox job that made the transaction
+    pub job: Option<String>,
+}
+
+impl Transaction {
+    pub(super) fn new(package_manager: PackageManager, id: &str, started_at: i64) -> Self {
+        Transaction {
+            id: format!("{package_manager}-{id}"),
+            package_manager: Some(package_manager),
+            started_at,
+            user: None,
+            command: None,
+            changes: Vec::new(),
+            undoable: false,
+            job: None,
+        }
+    }
+
+    /// The ID without the name of the package manager, i.e. the ID used by the package manager.
+    pub fn native_id(&self) -> &str {
+        match self.package_manager {
+            Some(manager) => self
+                .id
+                .strip_prefix(&format!("{manager}-"))
+                .unwrap_or(&self.id),
+            None => &self.id,
+        }
+    }
+}
+
+/// Convert a time in the local time zone like `2024-01-15 10:23:45` to seconds since the UNIX
+/// epoch.
+pub(super) fn local_timestamp(time: &str, format: &str) -> Option<i64> {
+    let time = NaiveDateTime::parse_from_str(time.trim(), format).ok()?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.timestamp())
+}
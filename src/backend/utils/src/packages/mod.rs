@@ -0,0 +1,714 @@
+//! Package management through the package managers installed on the system.
+//!
+//! Every package manager is implemented as a [`PackageBackend`]. A host has one system package
+//! manager (apt, dnf, pacman, zypper or apk), which is returned by [`primary_backend`], and may
+//! additionally use Flatpak and Snap as secondary sources. [`backends`] returns all of them.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+use std::{
+    fmt::Display,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+mod apk;
+mod apt;
+mod dnf;
+mod flatpak;
+pub mod history;
+mod pacman;
+pub mod repositories;
+mod snap;
+pub mod unattended;
+mod zypper;
+
+pub use apk::Apk;
+pub use apt::Apt;
+pub use dnf::Dnf;
+pub use flatpak::Flatpak;
+use history::Transaction;
+pub use pacman::Pacman;
+use repositories::{NewRepository, Repository, RepositoryError, RepositoryFile};
+pub use snap::Snap;
+pub use zypper::Zypper;
+
+struct CommandOutputStringified {
+    stdout: String,
+    stderr: String,
+    success: bool,
+    code: Option<i32>,
+}
+
+/// Run a command capturing it standard output and error, returning those values as Strings.
+fn run_with_output(c: &mut Command) -> CommandOutputStringified {
+    let x = c
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdin(Stdio::null())
+        .spawn()
+        .unwrap()
+        .wait_with_output()
+        .unwrap();
+
+    CommandOutputStringified {
+        stdout: stdout_to_string(x.stdout),
+        stderr: stdout_to_string(x.stderr),
+        success: x.status.success(),
+        code: x.status.code(),
+    }
+}
+
+/// Convert a Vec<u8> (vector of bytes) to a lossy String with UTF-8 encoding.
+#[doc(hidden)]
+fn stdout_to_string(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+#[derive(Debug)]
+pub enum PackageManagerError {
+    /// While executing a command with sudo, an error occurred
+    SudoError,
+
+    /// Executing a command failed
+    ExecutionError,
+
+    /// The package manager that was detected on the system is not supported
+    UnsupportedPackageManager,
+
+    /// The package is not known to the package manager
+    UnknownPackage,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Zypper,
+    Apk,
+    Flatpak,
+    Snap,
+}
+
+impl PackageManager {
+    /// System package managers in the order they are detected in.
+    const SYSTEM: [PackageManager; 5] = [
+        PackageManager::Apt,
+        PackageManager::Dnf,
+        PackageManager::Pacman,
+        PackageManager::Zypper,
+        PackageManager::Apk,
+    ];
+
+    /// Package managers that install applications next to the system package manager.
+    const SECONDARY: [PackageManager; 2] = [PackageManager::Flatpak, PackageManager::Snap];
+
+    /// Flatpak and Snap are secondary sources, which can not replace the system package manager.
+    pub fn is_secondary(&self) -> bool {
+        PackageManager::SECONDARY.contains(self)
+    }
+
+    /// Get the backend implementing this package manager.
+    pub fn backend(&self) -> Box<dyn PackageBackend> {
+        match self {
+            PackageManager::Apt => Box::new(Apt),
+            PackageManager::Dnf => Box::new(Dnf),
+            PackageManager::Pacman => Box::new(Pacman),
+            PackageManager::Zypper => Box::new(Zypper),
+            PackageManager::Apk => Box::new(Apk),
+            PackageManager::Flatpak => Box::new(Flatpak),
+            PackageManager::Snap => Box::new(Snap),
+        }
+    }
+
+    /// Check if the package manager is installed by trying to run it.
+    fn is_present(&self) -> bool {
+        try_command(&self.to_string())
+    }
+}
+
+impl Display for PackageManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageManager::Apt => f.write_str("apt"),
+            PackageManager::Dnf => f.write_str("dnf"),
+            PackageManager::Pacman => f.write_str("pacman"),
+            PackageManager::Zypper => f.write_str("zypper"),
+            PackageManager::Apk => f.write_str("apk"),
+            PackageManager::Flatpak => f.write_str("flatpak"),
+            PackageManager::Snap => f.write_str("snap"),
+        }
+    }
+}
+
+impl FromStr for PackageManager {
+    type Err = PackageManagerError;
+
+    /// Parse the name of a package manager as written by [`Display`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PackageManager::SYSTEM
+            .into_iter()
+            .chain(PackageManager::SECONDARY)
+            .find(|m| m.to_string() == s)
+            .ok_or(PackageManagerError::UnsupportedPackageManager)
+    }
+}
+
+/// A package found by [`PackageBackend::search`].
+#[derive(Serialize, Debug, ToSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageSummary {
+    pub name: String,
+    /// Not reported by apt and zypper
+    pub version: Option<String>,
+    pub description: String,
+    pub installed: bool,
+    pub package_manager: PackageManager,
+}
+
+/// Details on a single package as returned by [`PackageBackend::info`].
+#[derive(Serialize, Debug, ToSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageInfo {
+    pub name: String,
+    /// The version available from the repositories or the installed version if the package is
+    /// not available from any repository
+    pub version: String,
+    pub installed_version: Option<String>,
+    pub description: String,
+    /// Size of the installed package in bytes
+    pub installed_size: Option<u64>,
+    /// Size of the package archive in bytes
+    pub download_size: Option<u64>,
+    /// Names of the packages this package depends on, alternatives are separated by ` | `
+    pub dependencies: Vec<String>,
+    /// Names of installed packages that depend on this package
+    pub reverse_dependencies: Vec<String>,
+    pub repository: Option<String>,
+    pub package_manager: PackageManager,
+}
+
+impl PackageInfo {
+    fn new(package_manager: PackageManager) -> Self {
+        PackageInfo {
+            name: String::new(),
+            version: String::new(),
+            installed_version: None,
+            description: String::new(),
+            installed_size: None,
+            download_size: None,
+            dependencies: Vec::new(),
+            reverse_dependencies: Vec::new(),
+            repository: None,
+            package_manager,
+        }
+    }
+}
+
+/// A package manager that Zentrox can list, search, install, remove and update packages with.
+///
+/// Listing and searching run as the current user, while changes to the system are run using sudo
+/// with the password of the user or a passwordless sudoers rule, see [`unattended`].
+pub trait PackageBackend: Send + Sync {
+    fn package_manager(&self) -> PackageManager;
+
+    /// List the names of all installed packages.
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError>;
+
+    /// List the names of all packages that are available, but not installed.
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError>;
+
+    /// List the names of all installed packages that could be updated.
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError>;
+
+    /// List the names of all packages that count as orphaned according to the package manager.
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError>;
+
+    /// List the names of all installed packages that do not belong to any repository, like
+    /// packages from the AUR. They can not be updated through the package manager.
+    fn list_foreign(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    /// Search the names and descriptions of all packages known to the package manager.
+    ///
+    /// * `query` - Text to search for, which is not interpreted as a regular expression
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError>;
+
+    /// Show details on a package including its dependencies.
+    ///
+    /// * `name` - Exact name of the package
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError>;
+
+    /// Install a package.
+    ///
+    /// * `name` - Name of the package to install
+    /// * `credential` - Used to run sudo for root privileges
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// Remove a package.
+    ///
+    /// * `name` - Name of the package to remove
+    /// * `credential` - Used to run sudo for root privileges
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// Update a single package.
+    ///
+    /// * `name` - Name of the package to update
+    /// * `credential` - Used to run sudo for root privileges
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// Update all packages.
+    ///
+    /// * `credential` - Used to run sudo for root privileges
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// Whether the package manager can tell security updates apart from other updates.
+    fn supports_security_updates(&self) -> bool {
+        false
+    }
+
+    /// Only install updates that fix security issues. This does nothing unless
+    /// [`PackageBackend::supports_security_updates`] is true.
+    ///
+    /// * `credential` - Used to run sudo for root privileges
+    fn update_security(&self, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    /// Refresh the package managers repositories or database.
+    /// This is useful for detecting possible updates.
+    ///
+    /// * `credential` - Used to run sudo for root privileges
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// The commands run by [`PackageBackend::update_database`], [`PackageBackend::update_all`]
+    /// and [`PackageBackend::update_security`]. The sudoers rule of unattended updates allows
+    /// exactly these command lines.
+    fn update_commands(&self) -> Vec<CommandLine>;
+
+    /// Remove all packages that are detected by the package manager to be orphaned.
+    ///
+    /// * `credential` - Used to run sudo for root privileges
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError>;
+
+    /// List the transactions recorded by the package manager, starting with the latest.
+    /// Package managers that do not keep a history return no transactions.
+    fn history(&self) -> Result<Vec<Transaction>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    /// Revert all changes of a transaction returned by [`PackageBackend::history`]. This does
+    /// nothing unless the transaction is marked as undoable.
+    ///
+    /// * `transaction` - The transaction to revert
+    /// * `credential` - Used to run sudo for root privileges
+    fn undo(
+        &self,
+        _transaction: &Transaction,
+        _credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    /// Whether the package manager can install an older version of a package.
+    fn supports_downgrade(&self) -> bool {
+        false
+    }
+
+    /// Replace an installed package with an older version. This does nothing unless
+    /// [`PackageBackend::supports_downgrade`] is true.
+    ///
+    /// * `name` - Name of the package to downgrade
+    /// * `version` - The version to install, as listed in the history
+    /// * `credential` - Used to run sudo for root privileges
+    fn downgrade(
+        &self,
+        _name: &str,
+        _version: &str,
+        _credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    /// Whether packages can be held back, so they are not updated.
+    fn supports_holds(&self) -> bool {
+        false
+    }
+
+    /// List the names of all packages that are held back.
+    fn list_holds(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    /// Hold a package back, so it is neither updated by [`PackageBackend::update_all`] nor by
+    /// unattended updates. This does nothing unless [`PackageBackend::supports_holds`] is true.
+    ///
+    /// * `name` - Name of the package to hold
+    /// * `credential` - Used to run sudo for root privileges
+    fn hold(&self, _name: &str, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    /// Allow a package that was held back to be updated again.
+    ///
+    /// * `name` - Name of the package to release
+    /// * `credential` - Used to run sudo for root privileges
+    fn unhold(&self, _name: &str, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    /// The files repositories are configured in. Package managers without repositories return no
+    /// files.
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        Vec::new()
+    }
+
+    /// List the repositories configured in [`PackageBackend::repository_files`].
+    fn list_repositories(&self) -> Vec<Repository> {
+        repositories::list(self.package_manager(), &self.repository_files())
+    }
+
+    /// Enable or disable a repository.
+    ///
+    /// * `id` - ID of the repository as returned by [`PackageBackend::list_repositories`]
+    /// * `credential` - Used to run sudo for root privileges
+    fn set_repository_enabled(
+        &self,
+        id: &str,
+        enabled: bool,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        repositories::set_enabled(&self.repository_files(), id, enabled, credential)
+    }
+
+    /// Add a repository and import its signing key.
+    ///
+    /// * `credential` - Used to run sudo for root privileges
+    fn add_repository(
+        &self,
+        _repository: &NewRepository,
+        _credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        Err(RepositoryError::Unsupported)
+    }
+
+    /// Remove a repository. Imported signing keys are kept.
+    ///
+    /// * `id` - ID of the repository as returned by [`PackageBackend::list_repositories`]
+    /// * `credential` - Used to run sudo for root privileges
+    fn remove_repository(
+        &self,
+        id: &str,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        repositories::remove(&self.repository_files(), id, credential)
+    }
+}
+
+/// Try to run a command and return if it succeeded or failed
+#[doc(hidden)]
+fn try_command(c: &str) -> bool {
+    Command::new(c).output().is_ok()
+}
+
+/// Detect the system package manager by trying to run apt, dnf, pacman, zypper and apk.
+pub fn get_package_manager() -> Result<PackageManager, PackageManagerError> {
+    PackageManager::SYSTEM
+        .into_iter()
+        .find(PackageManager::is_present)
+        .ok_or(PackageManagerError::UnsupportedPackageManager)
+}
+
+/// The backend of the system package manager.
+pub fn primary_backend() -> Result<Box<dyn PackageBackend>, PackageManagerError> {
+    get_package_manager().map(|m| m.backend())
+}
+
+/// Detect all package managers on the system. The system package manager comes first and is
+/// followed by Flatpak and Snap if they are installed.
+pub fn detect_package_managers() -> Vec<PackageManager> {
+    get_package_manager()
+        .into_iter()
+        .chain(
+            PackageManager::SECONDARY
+                .into_iter()
+                .filter(PackageManager::is_present),
+        )
+        .collect()
+}
+
+/// The backends of all package managers on the system as detected by
+/// [`detect_package_managers`].
+pub fn backends() -> Vec<Box<dyn PackageBackend>> {
+    detect_package_managers()
+        .iter()
+        .map(PackageManager::backend)
+        .collect()
+}
+
+/// Whether `name` can be passed to a package manager or written to its configuration as the name
+/// of a single package. It must not be read as an option or split into several names.
+pub fn is_valid_package_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && !name.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// A command run as root, the program followed by its arguments
+pub type CommandLine = &'static [&'static str];
+
+/// Run a [`CommandLine`] using sudo.
+fn run_command_line(
+    credential: SudoCredential,
+    command: CommandLine,
+) -> Result<SudoOutput, SudoError> {
+    let (program, args) = command
+        .split_first()
+        .expect("A command line starts with the program");
+    SudoCommand::with_credential(credential, program)
+        .args(args.to_vec())
+        .output()
+}
+
+/// Result of an action that does not apply to a package manager and therefore always succeeds.
+fn nothing_to_do() -> Result<SudoOutput, SudoError> {
+    Ok(SudoOutput {
+        stdout: String::new(),
+        stderr: String::new(),
+        status: Some(0),
+    })
+}
+
+/// Parse the fields of the first entry in the output of `apt-cache show`, `dnf info` or
+/// `pacman -Si`.
+///
+/// All three use a `Key: Value` format, which may be aligned using spaces. Indented lines continue
+/// the value of the previous field and are joined using a new line. Under apt, a line only
+/// containing ` .` is an empty line and dnf prefixes continuation lines with `:`.
+fn parse_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if fields.is_empty() {
+                continue;
+            }
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                let continued = line.trim();
+                let continued = continued.strip_prefix(':').unwrap_or(continued).trim();
+                value.push('\n');
+                if continued != "." {
+                    value.push_str(continued);
+                }
+            }
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    fields
+}
+
+/// The value of the first field matching any of the keys.
+fn field<'a>(fields: &'a [(String, String)], keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|k| {
+        fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(k))
+            .map(|(_, value)| value.as_str())
+    })
+}
+
+/// Convert a size like `1.5 MiB`, `520 k`, `45.2 MB` or `12 B` to bytes.
+///
+/// Units ending in `iB` and single letter units are binary, units like `MB` are decimal.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let unit = unit.trim();
+
+    let base: f64 = if unit.len() == 2 && unit.ends_with(['B', 'b']) {
+        1000.0
+    } else {
+        1024.0
+    };
+
+    let exponent = match unit.chars().next().map(|c| c.to_ascii_lowercase()) {
+        None | Some('b') => 0,
+        Some('k') => 1,
+        Some('m') => 2,
+        Some('g') => 3,
+        Some('t') => 4,
+        Some(_) => return None,
+    };
+
+    Some((number * base.powi(exponent)).round() as u64)
+}
+
+/// Strip version constraints like `(>= 2.34)` or `>=3.0` from a dependency.
+fn strip_version_constraint(dependency: &str) -> String {
+    dependency
+        .split('|')
+        .map(|alternative| {
+            alternative
+                .split(['(', '<', '>', '='])
+                .next()
+                .unwrap_or_default()
+                .trim()
+        })
+        .filter(|a| !a.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" | ")
+}
+
+/// Escape characters that apt and pacman would interpret as part of a regular expression.
+fn escape_regex(query: &str) -> String {
+    query
+        .chars()
+        .flat_map(|c| {
+            if "\\.^$|?*+()[]{}".contains(c) {
+                vec!['\\', c]
+            } else {
+                vec![c]
+            }
+        })
+        .collect()
+}
+
+/// Split the output of a command into its non-empty, trimmed lines.
+fn lines(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parse the rows of a table printed by zypper, which separates columns using `|`.
+///
+/// The header row and the separator below it are skipped.
+fn parse_table(output: &str) -> Vec<Vec<String>> {
+    output
+        .lines()
+        .skip_while(|l| !l.contains("-+-"))
+        .skip(1)
+        .filter(|l| l.contains('|'))
+        .map(|l| l.split('|').map(|c| c.trim().to_string()).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_all_installed_packages() {
+        primary_backend()
+            .unwrap()
+            .list_installed()
+            .expect("Failed to list installed packages.");
+    }
+
+    #[test]
+    fn list_all_available_packages() {
+        primary_backend()
+            .unwrap()
+            .list_available()
+            .expect("Failed to list available packages.");
+    }
+
+    #[test]
+    fn list_all_orphaned_packages() {
+        primary_backend()
+            .unwrap()
+            .list_orphaned()
+            .expect("Failed to list orphaned packages.");
+    }
+
+    #[test]
+    fn list_all_outdated_packages() {
+        primary_backend()
+            .unwrap()
+            .list_updates()
+            .expect("Failed to list outdated packages.");
+    }
+
+    #[test]
+    fn determine_package_manager() {
+        get_package_manager().expect("Failed to list available packages.");
+    }
+
+    #[test]
+    fn install_xterm() {
+        let password =
+            std::env::var("TEST_PASSWORD").expect("Requires TEST_PASSWORD environment variable");
+        primary_backend()
+            .unwrap()
+            .install("xterm", password.into())
+            .expect("Failed to install xterm package");
+    }
+
+    #[test]
+    fn remove_xterm() {
+        let password =
+            std::env::var("TEST_PASSWORD").expect("Requires TEST_PASSWORD environment variable");
+        primary_backend()
+            .unwrap()
+            .remove("xterm", password.into())
+            .expect("Failed to install xterm package");
+    }
+
+    #[test]
+    fn validate_package_names() {
+        assert!(is_valid_package_name("xterm"));
+        assert!(is_valid_package_name("python3.12-dev"));
+        assert!(!is_valid_package_name(""));
+        assert!(!is_valid_package_name("-Syu"));
+        assert!(!is_valid_package_name("xterm vim"));
+        assert!(!is_valid_package_name("xterm\n[custom]"));
+        assert!(!is_valid_package_name("xterm\t"));
+    }
+
+    #[test]
+    fn convert_sizes() {
+        assert_eq!(parse_size("12 B"), Some(12));
+        assert_eq!(parse_size("520 k"), Some(520 * 1024));
+        assert_eq!(parse_size("1.5 MiB"), Some(1572864));
+        assert_eq!(parse_size("2 G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("45.2 MB"), Some(45_200_000));
+        assert_eq!(parse_size("unknown"), None);
+        assert_eq!(escape_regex("c++"), "c\\+\\+");
+    }
+
+    #[test]
+    fn parse_zypper_table() {
+        let output = "Loading repository data...
+Reading installed packages...
+
+S  | Name     | Summary                  | Type
+---+----------+--------------------------+--------
+i+ | curl     | A Tool for Transferring  | package
+   | curlftpfs | FUSE based filesystem   | package
+";
+        let rows = parse_table(output);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], "i+");
+        assert_eq!(rows[1][1], "curlftpfs");
+    }
+}
@@ -0,0 +1,605 @@
+use chrono::DateTime;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::history::{ChangeAction, PackageChange, Transaction, local_timestamp};
+use super::repositories::{
+    NewRepository, RepositoryError, RepositoryFile, RepositoryFormat, check, exists,
+    pacman_ignored, set_pacman_ignored, write_system_file,
+};
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    escape_regex, field, is_valid_package_name, lines, parse_fields, parse_size, run_command_line,
+    run_with_output, strip_version_constraint,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// Arch Linux and its derivatives
+pub struct Pacman;
+
+const UPDATE_DATABASE: CommandLine = &["pacman", "-Syy", "--noconfirm"];
+const UPDATE_ALL: CommandLine = &["pacman", "--noconfirm", "-Su"];
+const LOG_PATH: &str = "/var/log/pacman.log";
+const CONFIG_PATH: &str = "/etc/pacman.conf";
+const CACHE_DIRECTORY: &str = "/var/cache/pacman/pkg";
+
+/// The archive of a version of a package kept in the package cache.
+fn cached_package(name: &str, version: &str) -> Option<PathBuf> {
+    let prefix = format!("{name}-{version}-");
+    fs::read_dir(CACHE_DIRECTORY)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_name()
+                .and_then(|f| f.to_str())
+                .is_some_and(|f| f.starts_with(&prefix) && !f.ends_with(".sig"))
+        })
+}
+
+impl PackageBackend for Pacman {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Pacman
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("pacman").arg("--noconfirm").arg("-Qq"));
+        Ok(lines(&command.stdout))
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("pacman").arg("--noconfirm").arg("-Sl"));
+        Ok(command
+            .stdout
+            .lines()
+            .filter(|entry| !entry.contains("[installed"))
+            .filter_map(|entry| entry.split(" ").nth(1))
+            .map(String::from)
+            .collect::<Vec<String>>())
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        // checkupdates from pacman-contrib syncs a temporary copy of the database, so the result
+        // does not depend on when the database was last updated
+        if let Ok(output) = Command::new("checkupdates").output() {
+            return match output.status.code() {
+                Some(0) => Ok(parse_updates(&String::from_utf8_lossy(&output.stdout))),
+                // Exit code 2 means that there are no updates
+                Some(2) => Ok(Vec::new()),
+                _ => Err(PackageManagerError::ExecutionError),
+            };
+        }
+
+        // Without checkupdates, the system database is compared to the installed packages
+        let command = run_with_output(Command::new("pacman").arg("-Qu"));
+        match command.code {
+            Some(0) => Ok(parse_updates(&command.stdout)),
+            // pacman exits with 1 if no package is outdated
+            Some(1) if command.stderr.trim().is_empty() => Ok(Vec::new()),
+            _ => Err(PackageManagerError::ExecutionError),
+        }
+    }
+
+    fn list_foreign(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("pacman").arg("-Qmq"));
+        match command.code {
+            Some(0) => Ok(lines(&command.stdout)),
+            // pacman exits with 1 if there are no foreign packages
+            Some(1) if command.stderr.trim().is_empty() => Ok(Vec::new()),
+            _ => Err(PackageManagerError::ExecutionError),
+        }
+    }
+
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("pacman").arg("--noconfirm").arg("-Qdtq"));
+        Ok(lines(&command.stdout))
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        let command = run_with_output(Command::new("pacman").arg("-Ss").arg(escape_regex(query)));
+        Ok(parse_search(&command.stdout))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        // Only the local database knows which packages require an installed package
+        let local = run_with_output(Command::new("pacman").arg("-Qi").arg(name));
+        let sync = run_with_output(Command::new("pacman").arg("-Sii").arg(name));
+
+        let local_info = local.success.then(|| parse_info(&local.stdout)).flatten();
+        let sync_info = sync.success.then(|| parse_info(&sync.stdout)).flatten();
+
+        match (sync_info, local_info) {
+            (Some(mut info), Some(local_info)) => {
+                info.installed_version = Some(local_info.version);
+                info.installed_size = local_info.installed_size;
+                info.reverse_dependencies = local_info.reverse_dependencies;
+                Ok(info)
+            }
+            (Some(info), None) => Ok(info),
+            // Packages that were not installed from a repository, e.g. from the AUR
+            (None, Some(mut info)) => {
+                info.installed_version = Some(info.version.clone());
+                Ok(info)
+            }
+            (None, None) => Err(PackageManagerError::UnknownPackage),
+        }
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "pacman")
+            .args(vec!["--noconfirm", "-Sy", name])
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "pacman")
+            .args(vec!["--noconfirm", "-R", name])
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "pacman")
+            .args(vec!["--noconfirm", "-S", name])
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL]
+    }
+
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        let packages = self.list_orphaned().unwrap_or_default();
+        SudoCommand::with_credential(credential, "pacman")
+            .args(vec!["--noconfirm".to_string(), "-Rc".to_string()])
+            .args(packages)
+            .output()
+    }
+
+    fn history(&self) -> Result<Vec<Transaction>, PackageManagerError> {
+        let log = fs::read_to_string(LOG_PATH).map_err(|_| PackageManagerError::ExecutionError)?;
+        let mut transactions = parse_log(&log);
+        for change in transactions.iter_mut().flat_map(|t| t.changes.iter_mut()) {
+            change.revertible = change.action == ChangeAction::Upgrade
+                && change
+                    .old_version
+                    .as_ref()
+                    .is_some_and(|v| cached_package(&change.name, v).is_some());
+        }
+        Ok(transactions)
+    }
+
+    /// Packages are downgraded using the archives in the package cache, which are removed by
+    /// `paccache` or `pacman -Sc`.
+    fn supports_downgrade(&self) -> bool {
+        true
+    }
+
+    fn downgrade(
+        &self,
+        name: &str,
+        version: &str,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, SudoError> {
+        let Some(archive) = cached_package(name, version) else {
+            return Ok(SudoOutput {
+                stdout: String::new(),
+                stderr: format!("{name} {version} is not in the package cache."),
+                status: Some(1),
+            });
+        };
+        SudoCommand::with_credential(credential, "pacman")
+            .arg("--noconfirm")
+            .arg("-U")
+            .arg(archive.to_string_lossy())
+            .output()
+    }
+
+    /// Packages are held using `IgnorePkg` in `/etc/pacman.conf`.
+    fn supports_holds(&self) -> bool {
+        true
+    }
+
+    fn list_holds(&self) -> Result<Vec<String>, PackageManagerError> {
+        let config =
+            fs::read_to_string(CONFIG_PATH).map_err(|_| PackageManagerError::ExecutionError)?;
+        Ok(pacman_ignored(&config))
+    }
+
+    fn hold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        set_ignored(name, true, credential)
+    }
+
+    fn unhold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        set_ignored(name, false, credential)
+    }
+
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        vec![RepositoryFile::new(
+            CONFIG_PATH,
+            RepositoryFormat::PacmanConf,
+        )]
+    }
+
+    /// The repository is appended to `/etc/pacman.conf`. Its key is added to the keyring of
+    /// pacman and signed locally, so packages signed with it are trusted.
+    fn add_repository(
+        &self,
+        repository: &NewRepository,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        repository.validate()?;
+        if exists(&self.repository_files(), repository) {
+            return Err(RepositoryError::InvalidRepository(
+                "A repository with this name exists already.".to_string(),
+            ));
+        }
+        if let Some(key) = &repository.signing_key {
+            import_key(key, credential.clone())?;
+        }
+
+        let mut config = fs::read_to_string(CONFIG_PATH).unwrap_or_default();
+        if !config.ends_with('\n') {
+            config.push('\n');
+        }
+        config.push_str(&format!(
+            "\n[{}]\nServer = {}\n",
+            repository.name, repository.uri
+        ));
+        check(write_system_file(
+            credential,
+            Path::new(CONFIG_PATH),
+            &config,
+        )?)
+    }
+}
+
+fn set_ignored(
+    name: &str,
+    ignored: bool,
+    credential: SudoCredential,
+) -> Result<SudoOutput, SudoError> {
+    // The name is written to the configuration
+    if !is_valid_package_name(name) {
+        return Err(SudoError::BadParameters);
+    }
+    let config = fs::read_to_string(CONFIG_PATH).unwrap_or_default();
+    write_system_file(
+        credential,
+        Path::new(CONFIG_PATH),
+        &set_pacman_ignored(&config, name, ignored),
+    )
+}
+
+/// Add an ASCII armored key to the keyring of pacman and sign it locally.
+fn import_key(key: &str, credential: SudoCredential) -> Result<(), RepositoryError> {
+    let staged = crate::database::data_directory().join("repository-key.staged");
+    fs::write(&staged, key).map_err(|e| RepositoryError::Failed(e.to_string()))?;
+
+    let result = (|| {
+        // The fingerprint is read without importing the key into a keyring
+        let show = run_with_output(
+            Command::new("gpg")
+                .args(["--with-colons", "--import-options", "show-only", "--import"])
+                .arg(&staged),
+        );
+        let fingerprints = parse_fingerprints(&show.stdout);
+        if fingerprints.is_empty() {
+            return Err(RepositoryError::InvalidRepository(
+                "The signing key could not be read.".to_string(),
+            ));
+        }
+
+        check(
+            SudoCommand::with_credential(credential.clone(), "pacman-key")
+                .arg("--add")
+                .arg(staged.display())
+                .output()?,
+        )?;
+        for fingerprint in fingerprints {
+            check(
+                SudoCommand::with_credential(credential.clone(), "pacman-key")
+                    .arg("--lsign-key")
+                    .arg(fingerprint)
+                    .output()?,
+            )?;
+        }
+        Ok(())
+    })();
+
+    let _ = fs::remove_file(&staged);
+    result
+}
+
+/// Fingerprints of primary keys in the colon separated output of gpg.
+fn parse_fingerprints(output: &str) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    let mut primary = false;
+    for line in output.lines() {
+        let fields = line.split(':').collect::<Vec<&str>>();
+        match fields.first() {
+            Some(&"pub") => primary = true,
+            Some(&"sub") => primary = false,
+            Some(&"fpr") if primary => {
+                if let Some(fingerprint) = fields.get(9).filter(|f| !f.is_empty()) {
+                    fingerprints.push(fingerprint.to_string());
+                }
+                primary = false;
+            }
+            _ => {}
+        }
+    }
+    fingerprints
+}
+
+/// Parse the transactions in `/var/log/pacman.log`, starting with the latest.
+///
+/// Lines look like `[2024-01-15T10:23:46+0100] [ALPM] upgraded curl (8.5.0-1 -> 8.6.0-1)`, older
+/// versions of pacman logged the time as `[2019-01-01 10:00]`. The ID of a transaction is the
+/// line it started at.
+fn parse_log(log: &str) -> Vec<Transaction> {
+    let mut transactions = Vec::new();
+    let mut command = None;
+    let mut current: Option<Transaction> = None;
+
+    for (number, line) in log.lines().enumerate() {
+        let Some((time, rest)) = line.strip_prefix('[').and_then(|l| l.split_once("] ")) else {
+            continue;
+        };
+        let Some((source, message)) = rest.strip_prefix('[').and_then(|l| l.split_once("] "))
+        else {
+            continue;
+        };
+
+        match (source, message) {
+            ("PACMAN", message) if message.starts_with("Running '") => {
+                command = Some(
+                    message
+                        .trim_start_matches("Running '")
+                        .trim_end_matches('\'')
+                        .to_string(),
+                );
+            }
+            ("ALPM", "transaction started") => {
+                let started_at = DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%z")
+                    .map(|t| t.timestamp())
+                    .ok()
+                    .or_else(|| local_timestamp(time, "%Y-%m-%d %H:%M"));
+                current = started_at.map(|started_at| {
+                    let mut transaction = Transaction::new(
+                        PackageManager::Pacman,
+                        &(number + 1).to_string(),
+                        started_at,
+                    );
+                    transaction.command = command.take();
+                    transaction
+                });
+            }
+            ("ALPM", "transaction completed") => transactions.extend(current.take()),
+            ("ALPM", "transaction failed" | "transaction interrupted") => current = None,
+            ("ALPM", message) => {
+                if let Some(transaction) = current.as_mut()
+                    && let Some(change) = parse_change(message)
+                {
+                    transaction.changes.push(change);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    transactions.reverse();
+    transactions
+}
+
+/// Parse a change like `upgraded curl (8.5.0-1 -> 8.6.0-1)` or `installed git (2.43.0-1)`.
+fn parse_change(message: &str) -> Option<PackageChange> {
+    let (verb, package) = message.split_once(' ')?;
+    let (name, versions) = package.trim_end_matches(')').split_once(" (")?;
+    let (old, new) = match versions.split_once(" -> ") {
+        Some((old, new)) => (Some(old), Some(new)),
+        None => (Some(versions), Some(versions)),
+    };
+
+    Some(match verb {
+        "installed" => PackageChange::new(name, ChangeAction::Install, None, new),
+        "removed" => PackageChange::new(name, ChangeAction::Remove, old, None),
+        "upgraded" => PackageChange::new(name, ChangeAction::Upgrade, old, new),
+        "downgraded" => PackageChange::new(name, ChangeAction::Downgrade, old, new),
+        "reinstalled" => PackageChange::new(name, ChangeAction::Reinstall, old, new),
+        _ => return None,
+    })
+}
+
+/// Parse updates listed as `linux 6.7.1.arch1-1 -> 6.7.2.arch1-1`.
+///
+/// Packages in `IgnorePkg` are marked with `[ignored]` and skipped, as they are not updated.
+fn parse_updates(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|l| !l.trim_end().ends_with("[ignored]"))
+        .filter_map(|l| l.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_search(output: &str) -> Vec<PackageSummary> {
+    let mut results: Vec<PackageSummary> = Vec::new();
+    for line in output.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some(last) = results.last_mut() {
+                if !last.description.is_empty() {
+                    last.description.push(' ');
+                }
+                last.description.push_str(line.trim());
+            }
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let Some(name) = parts
+            .next()
+            .and_then(|p| p.split_once('/'))
+            .map(|(_, name)| name.to_string())
+        else {
+            continue;
+        };
+        results.push(PackageSummary {
+            name,
+            version: parts.next().map(String::from),
+            description: String::new(),
+            installed: line.contains("[installed"),
+            package_manager: PackageManager::Pacman,
+        });
+    }
+    results
+}
+
+/// Split a list of packages as printed by pacman, which prints `None` for empty lists.
+fn package_list(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|d| *d != "None")
+        .map(strip_version_constraint)
+        .collect()
+}
+
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    let fields = parse_fields(info);
+
+    Some(PackageInfo {
+        name: field(&fields, &["Name"])?.to_string(),
+        version: field(&fields, &["Version"])?.to_string(),
+        description: field(&fields, &["Description"])
+            .unwrap_or_default()
+            .to_string(),
+        installed_size: field(&fields, &["Installed Size"]).and_then(parse_size),
+        download_size: field(&fields, &["Download Size"]).and_then(parse_size),
+        dependencies: package_list(field(&fields, &["Depends On"])),
+        reverse_dependencies: package_list(field(&fields, &["Required By"])),
+        repository: field(&fields, &["Repository"]).map(String::from),
+        ..PackageInfo::new(PackageManager::Pacman)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_update_list() {
+        let output = "linux 6.7.1.arch1-1 -> 6.7.2.arch1-1
+curl 8.5.0-1 -> 8.6.0-1 [ignored]
+git 2.43.0-1 -> 2.43.1-1
+";
+        assert_eq!(parse_updates(output), vec!["linux", "git"]);
+        assert!(parse_updates("").is_empty());
+    }
+
+    #[test]
+    fn parse_transaction_log() {
+        let log = "[2024-01-15T10:23:40+0100] [PACMAN] Running 'pacman -Syu'
+[2024-01-15T10:23:40+0100] [PACMAN] synchronizing package lists
+[2024-01-15T10:23:45+0100] [ALPM] transaction started
+[2024-01-15T10:23:46+0100] [ALPM] upgraded curl (8.5.0-1 -> 8.6.0-1)
+[2024-01-15T10:23:46+0100] [ALPM] installed git (2.43.0-1)
+[2024-01-15T10:23:47+0100] [ALPM-SCRIPTLET] ==> Running hook
+[2024-01-15T10:23:47+0100] [ALPM] transaction completed
+[2024-01-16T09:00:00+0100] [PACMAN] Running 'pacman -R xterm'
+[2024-01-16T09:00:01+0100] [ALPM] transaction started
+[2024-01-16T09:00:01+0100] [ALPM] removed xterm (390-1)
+[2024-01-16T09:00:01+0100] [ALPM] transaction failed
+[2019-01-01 10:00] [ALPM] transaction started
+[2019-01-01 10:00] [ALPM] downgraded vim (8.1-2 -> 8.1-1)
+[2019-01-01 10:00] [ALPM] transaction completed
+";
+        let transactions = parse_log(log);
+        assert_eq!(transactions.len(), 2);
+
+        assert_eq!(transactions[0].id, "pacman-12");
+        assert_eq!(transactions[0].native_id(), "12");
+        assert_eq!(
+            transactions[0].changes,
+            vec![PackageChange::new(
+                "vim",
+                ChangeAction::Downgrade,
+                Some("8.1-2"),
+                Some("8.1-1")
+            )]
+        );
+
+        let upgrade = &transactions[1];
+        assert_eq!(upgrade.started_at, 1705310625);
+        assert_eq!(upgrade.command.as_deref(), Some("pacman -Syu"));
+        assert_eq!(
+            upgrade.changes,
+            vec![
+                PackageChange::new(
+                    "curl",
+                    ChangeAction::Upgrade,
+                    Some("8.5.0-1"),
+                    Some("8.6.0-1")
+                ),
+                PackageChange::new("git", ChangeAction::Install, None, Some("2.43.0-1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_key_fingerprints() {
+        let output = "pub:-:4096:1:786C63F330D7CB92:1609459200:::-:::scSC::::::23::0:
+fpr:::::::::D8AFDDA07A5B6EDFA7D8CCDAD6D055F927843F1C:
+uid:-::::1609459200::1A2B::Example Repository <repo@example.com>::::::::::0:
+sub:-:4096:1:0123456789ABCDEF:1609459200::::::e::::::23:
+fpr:::::::::0000000000000000000000000123456789ABCDEF:
+";
+        assert_eq!(
+            parse_fingerprints(output),
+            vec!["D8AFDDA07A5B6EDFA7D8CCDAD6D055F927843F1C"]
+        );
+    }
+
+    #[test]
+    fn parse_details() {
+        let info = "Repository      : core
+Name            : curl
+Version         : 8.5.0-1
+Description     : command line tool and library for transferring data with URLs
+Depends On      : ca-certificates  brotli  krb5  libidn2  libnghttp2  libpsl  libssh2
+                  openssl>=3  zlib  zstd
+Required By     : None
+Download Size   : 1.16 MiB
+Installed Size  : 1.88 MiB
+
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.repository.as_deref(), Some("core"));
+        assert_eq!(info.dependencies.len(), 10);
+        assert_eq!(info.dependencies[7], "openssl");
+        assert!(info.reverse_dependencies.is_empty());
+        assert_eq!(info.download_size, Some(1216348));
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let search = "core/curl 8.5.0-1 [installed]
+    command line tool and library for transferring data with URLs
+extra/curlftpfs 0.9.2-10
+    A filesystem for acessing FTP hosts based on FUSE and libcurl
+";
+        let results = parse_search(search);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(results[1].version.as_deref(), Some("0.9.2-10"));
+        assert!(!results[1].installed);
+    }
+}
@@ -0,0 +1,847 @@
+//! Repositories configured for the package managers.
+//!
+//! Repositories are read from and written to the configuration files of the package managers,
+//! so no package manager specific tooling is required:
+//! - apt: `/etc/apt/sources.list` and `/etc/apt/sources.list.d`, in the one-line and the deb822
+//!   format
+//! - dnf and zypper: `.repo` files in `/etc/yum.repos.d` and `/etc/zypp/repos.d`
+//! - pacman: sections of `/etc/pacman.conf`
+//! - apk: `/etc/apk/repositories`
+//!
+//! Files are written by staging them in the data directory and copying them into place using
+//! `sudo install`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::PackageManager;
+pub(super) use crate::sudo::write_system_file;
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// A repository as configured in a file
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Repository {
+    /// The file and the position of the repository in it, e.g.
+    /// `/etc/apt/sources.list.d/debian.sources:1`
+    pub id: String,
+    /// The section name under dnf, zypper and pacman or the suites and components under apt
+    pub name: String,
+    pub uris: Vec<String>,
+    pub enabled: bool,
+    pub file: String,
+    pub package_manager: PackageManager,
+}
+
+/// A third-party repository that is added to the package manager
+#[derive(Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NewRepository {
+    /// Used to name the files of the repository, only letters, digits, `.`, `_` and `-` are
+    /// allowed
+    pub name: String,
+    pub uri: String,
+    /// Required under apt, e.g. `bookworm`
+    pub suite: Option<String>,
+    /// Only used by apt, e.g. `main`
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// ASCII armored GPG key the repository is signed with. Under Alpine, this is the RSA public
+    /// key of the repository.
+    pub signing_key: Option<String>,
+}
+
+impl NewRepository {
+    /// Check that the repository can be written to a configuration file.
+    pub fn validate(&self) -> Result<(), RepositoryError> {
+        let invalid = |message: &str| Err(RepositoryError::InvalidRepository(message.to_string()));
+
+        if self.name.is_empty()
+            || self.name.starts_with('.')
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        {
+            return invalid("The name may only contain letters, digits, '.', '_' and '-'.");
+        }
+        if !["http://", "https://", "ftp://", "file:/"]
+            .iter()
+            .any(|s| self.uri.starts_with(s))
+            || self.uri.chars().any(|c| c.is_whitespace() || c == '#')
+        {
+            return invalid("The URI has to be an HTTP, FTP or file URI.");
+        }
+        if self
+            .suite
+            .iter()
+            .chain(self.components.iter())
+            .any(|s| s.is_empty() || s.chars().any(|c| c.is_whitespace() || c == '#'))
+        {
+            return invalid("Suites and components may not contain whitespace.");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RepositoryError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("The repository does not exist.")]
+    UnknownRepository,
+    #[error("The package manager does not support managing repositories.")]
+    Unsupported,
+    #[error("{0}")]
+    InvalidRepository(String),
+    #[error("Changing the repository failed: {0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for RepositoryError {
+    fn from(error: SudoError) -> Self {
+        RepositoryError::Sudo(error)
+    }
+}
+
+/// Format of a file configuring repositories
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RepositoryFormat {
+    /// `deb [options] uri suite components` per line
+    AptList,
+    /// Stanzas of `Key: value` fields separated by empty lines
+    Deb822,
+    /// INI sections as used by dnf and zypper
+    Ini,
+    /// Sections of `/etc/pacman.conf`
+    PacmanConf,
+    /// One URI per line
+    ApkList,
+}
+
+/// A file configuring repositories
+pub struct RepositoryFile {
+    pub path: PathBuf,
+    pub format: RepositoryFormat,
+}
+
+impl RepositoryFile {
+    pub fn new<P: Into<PathBuf>>(path: P, format: RepositoryFormat) -> Self {
+        RepositoryFile {
+            path: path.into(),
+            format,
+        }
+    }
+
+    /// Files in `directory` with the `extension`, sorted by name.
+    pub(super) fn in_directory(
+        directory: &str,
+        extension: &str,
+        format: RepositoryFormat,
+    ) -> Vec<Self> {
+        let mut paths = fs::read_dir(directory)
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|e| e == extension))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|p| RepositoryFile::new(p, format))
+            .collect()
+    }
+}
+
+/// A repository in a file and the lines it spans
+#[derive(PartialEq, Debug)]
+struct Entry {
+    key: String,
+    name: String,
+    uris: Vec<String>,
+    enabled: bool,
+    lines: Range<usize>,
+}
+
+/// Strip a comment marker from a line, returning whether the line was commented.
+fn uncomment(line: &str) -> (bool, &str) {
+    match line.trim_start().strip_prefix('#') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line.trim()),
+    }
+}
+
+impl RepositoryFormat {
+    fn parse(&self, content: &str) -> Vec<Entry> {
+        match self {
+            RepositoryFormat::AptList => parse_lines(content, parse_apt_line),
+            RepositoryFormat::ApkList => parse_lines(content, |line| {
+                // Repositories may be tagged like `@edge https://...`
+                let uri = line.split_whitespace().last()?;
+                (uri.contains("://") || uri.starts_with('/'))
+                    .then(|| (uri.to_string(), line.to_string()))
+            }),
+            RepositoryFormat::Deb822 => parse_deb822(content),
+            RepositoryFormat::Ini => parse_ini(content),
+            RepositoryFormat::PacmanConf => parse_pacman_conf(content),
+        }
+    }
+
+    /// Change the file so the repository is enabled or disabled.
+    fn set_enabled(&self, content: &str, entry: &Entry, enabled: bool) -> String {
+        let mut lines = content.lines().map(String::from).collect::<Vec<String>>();
+
+        match self {
+            RepositoryFormat::AptList | RepositoryFormat::ApkList => {
+                let line = uncomment(&lines[entry.lines.start]).1.to_string();
+                lines[entry.lines.start] = if enabled { line } else { format!("# {line}") };
+            }
+            RepositoryFormat::Deb822 => {
+                let value = if enabled { "yes" } else { "no" };
+                match entry
+                    .lines
+                    .clone()
+                    .find(|&i| field_name(&lines[i]) == Some("Enabled"))
+                {
+                    Some(i) => lines[i] = format!("Enabled: {value}"),
+                    None => lines.insert(entry.lines.end, format!("Enabled: {value}")),
+                }
+            }
+            RepositoryFormat::Ini => {
+                let value = if enabled { 1 } else { 0 };
+                match entry.lines.clone().skip(1).find(|&i| {
+                    lines[i]
+                        .split_once('=')
+                        .is_some_and(|(k, _)| k.trim() == "enabled")
+                }) {
+                    Some(i) => lines[i] = format!("enabled={value}"),
+                    None => lines.insert(entry.lines.start + 1, format!("enabled={value}")),
+                }
+            }
+            RepositoryFormat::PacmanConf => {
+                for i in entry.lines.clone() {
+                    let line = uncomment(&lines[i]).1.to_string();
+                    lines[i] = if enabled { line } else { format!("#{line}") };
+                }
+            }
+        }
+
+        join_lines(lines)
+    }
+}
+
+fn join_lines(lines: Vec<String>) -> String {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+/// The name of a `Key: value` field.
+fn field_name(line: &str) -> Option<&str> {
+    if line.starts_with([' ', '\t', '#']) {
+        return None;
+    }
+    line.split_once(':').map(|(k, _)| k.trim())
+}
+
+/// Parse files with one repository per line. `parse` returns the URI and the name of a line
+/// with the comment marker removed.
+fn parse_lines(content: &str, parse: impl Fn(&str) -> Option<(String, String)>) -> Vec<Entry> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let (commented, line) = uncomment(line);
+            let (uri, name) = parse(line)?;
+            Some(Entry {
+                key: (i + 1).to_string(),
+                name,
+                uris: vec![uri],
+                enabled: !commented,
+                lines: i..i + 1,
+            })
+        })
+        .collect()
+}
+
+/// Parse a line like `deb [signed-by=/etc/apt/keyrings/docker.asc] https://download.docker.com/linux/debian bookworm stable`.
+fn parse_apt_line(line: &str) -> Option<(String, String)> {
+    let rest = line
+        .strip_prefix("deb-src ")
+        .or_else(|| line.strip_prefix("deb "))?
+        .trim_start();
+    let rest = match rest.strip_prefix('[') {
+        Some(options) => options.split_once(']')?.1,
+        None => rest,
+    };
+    let mut words = rest.split_whitespace();
+    let uri = words.next().filter(|u| !u.starts_with("cdrom:"))?;
+    Some((uri.to_string(), words.collect::<Vec<&str>>().join(" ")))
+}
+
+fn parse_deb822(content: &str) -> Vec<Entry> {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let mut entries = Vec::new();
+    let mut start = 0;
+
+    while start < lines.len() {
+        let end = (start..lines.len())
+            .find(|&i| lines[i].trim().is_empty())
+            .unwrap_or(lines.len());
+
+        let value = |key: &str| {
+            (start..end)
+                .find(|&i| field_name(lines[i]) == Some(key))
+                .and_then(|i| lines[i].split_once(':'))
+                .map(|(_, v)| v.trim().to_string())
+        };
+        if value("Types").is_some() {
+            entries.push(Entry {
+                key: (entries.len() + 1).to_string(),
+                name: [value("Suites"), value("Components")]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                uris: value("URIs")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+                enabled: value("Enabled").is_none_or(|v| v != "no"),
+                lines: start..end,
+            });
+        }
+        start = end + 1;
+    }
+
+    entries
+}
+
+fn parse_ini(content: &str) -> Vec<Entry> {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let headers = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| {
+            let section = l.trim().strip_prefix('[')?.strip_suffix(']')?;
+            Some((i, section.trim().to_string()))
+        })
+        .collect::<Vec<(usize, String)>>();
+
+    headers
+        .iter()
+        .enumerate()
+        .map(|(n, (start, section))| {
+            let end = headers.get(n + 1).map(|h| h.0).unwrap_or(lines.len());
+            let mut entry = Entry {
+                key: section.clone(),
+                name: section.clone(),
+                uris: Vec::new(),
+                enabled: true,
+                lines: *start..end,
+            };
+            let mut in_uris = false;
+            for line in &lines[start + 1..end] {
+                if line.trim().starts_with('#') {
+                    continue;
+                }
+                // Multiple base URLs may be listed on indented lines
+                if line.starts_with([' ', '\t']) && in_uris {
+                    entry.uris.push(line.trim().to_string());
+                    continue;
+                }
+                in_uris = false;
+                let Some((key, value)) = line.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim();
+                match key.trim() {
+                    "name" => entry.name = value.to_string(),
+                    "enabled" => entry.enabled = value != "0",
+                    "baseurl" | "mirrorlist" | "metalink" => {
+                        in_uris = true;
+                        entry
+                            .uris
+                            .extend(value.split_whitespace().map(String::from));
+                    }
+                    _ => {}
+                }
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Keys of a repository section in `/etc/pacman.conf`
+const PACMAN_REPOSITORY_KEYS: [&str; 4] = ["Server", "Include", "SigLevel", "Usage"];
+
+/// Parse the repository sections of `/etc/pacman.conf`.
+///
+/// Disabled repositories are commented out including their header, like `#[core-testing]`. A
+/// section ends at the first line that does not belong to it, which is usually an empty line.
+fn parse_pacman_conf(content: &str) -> Vec<Entry> {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let mut entries = Vec::new();
+
+    for (start, line) in lines.iter().enumerate() {
+        let (commented, line) = uncomment(line);
+        let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) else {
+            continue;
+        };
+        if section == "options" || section.contains(' ') {
+            continue;
+        }
+
+        let mut entry = Entry {
+            key: section.to_string(),
+            name: section.to_string(),
+            uris: Vec::new(),
+            enabled: !commented,
+            lines: start..start + 1,
+        };
+        for line in &lines[start + 1..] {
+            let (line_commented, line) = uncomment(line);
+            let Some((key, value)) = line.split_once('=') else {
+                break;
+            };
+            let key = key.trim();
+            if line_commented != commented || !PACMAN_REPOSITORY_KEYS.contains(&key) {
+                break;
+            }
+            if key == "Server" || key == "Include" {
+                entry.uris.push(value.trim().to_string());
+            }
+            entry.lines.end += 1;
+        }
+        entries.push(entry);
+    }
+
+    entries
+}
+
+/// Packages listed in `IgnorePkg` in the `[options]` section of `/etc/pacman.conf`.
+pub(super) fn pacman_ignored(content: &str) -> Vec<String> {
+    pacman_options(content)
+        .filter_map(|(_, line)| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "IgnorePkg").then_some(value)
+        })
+        .flat_map(|value| value.split_whitespace().map(String::from))
+        .collect()
+}
+
+/// Lines of the `[options]` section with their index.
+fn pacman_options(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .skip_while(|(_, l)| l.trim() != "[options]")
+        .skip(1)
+        .take_while(|(_, l)| !l.trim().starts_with('['))
+}
+
+/// Add or remove a package from `IgnorePkg`.
+pub(super) fn set_pacman_ignored(content: &str, name: &str, ignored: bool) -> String {
+    let mut packages = pacman_ignored(content);
+    packages.retain(|p| p != name);
+    if ignored {
+        packages.push(name.to_string());
+    }
+    let setting = if packages.is_empty() {
+        "#IgnorePkg   =".to_string()
+    } else {
+        format!("IgnorePkg = {}", packages.join(" "))
+    };
+
+    let mut lines = content.lines().map(String::from).collect::<Vec<String>>();
+    let settings = pacman_options(content)
+        .filter(|(_, l)| {
+            let (_, line) = uncomment(l);
+            line.split_once('=')
+                .is_some_and(|(k, _)| k.trim() == "IgnorePkg")
+        })
+        .map(|(i, l)| (i, l.trim_start().starts_with('#')))
+        .collect::<Vec<(usize, bool)>>();
+
+    // Replace the first active setting, or the commented default if there is none
+    let replaced = settings
+        .iter()
+        .find(|(_, commented)| !commented)
+        .or(settings.first())
+        .map(|(i, _)| *i);
+    for (i, commented) in settings.iter().rev() {
+        if Some(*i) != replaced && !commented {
+            lines.remove(*i);
+        }
+    }
+    match replaced {
+        Some(i) => lines[i] = setting,
+        None => {
+            let options = lines
+                .iter()
+                .position(|l| l.trim() == "[options]")
+                .map(|i| i + 1);
+            match options {
+                Some(i) => lines.insert(i, setting),
+                None => {
+                    lines.insert(0, setting);
+                    lines.insert(0, "[options]".to_string());
+                }
+            }
+        }
+    }
+
+    join_lines(lines)
+}
+
+/// Turn an output with a non-zero exit status into an error.
+pub(super) fn check(output: SudoOutput) -> Result<SudoOutput, RepositoryError> {
+    output.checked().map_err(RepositoryError::Failed)
+}
+
+/// The repositories configured in `files`.
+pub(super) fn list(package_manager: PackageManager, files: &[RepositoryFile]) -> Vec<Repository> {
+    files
+        .iter()
+        .flat_map(|file| {
+            let content = fs::read_to_string(&file.path).unwrap_or_default();
+            file.format
+                .parse(&content)
+                .into_iter()
+                .map(|entry| Repository {
+                    id: format!("{}:{}", file.path.display(), entry.key),
+                    name: entry.name,
+                    uris: entry.uris,
+                    enabled: entry.enabled,
+                    file: file.path.display().to_string(),
+                    package_manager,
+                })
+        })
+        .collect()
+}
+
+/// The file containing a repository, its content and the entry of the repository.
+fn locate<'a>(
+    files: &'a [RepositoryFile],
+    id: &str,
+) -> Result<(&'a RepositoryFile, String, Entry), RepositoryError> {
+    let (path, key) = id
+        .rsplit_once(':')
+        .ok_or(RepositoryError::UnknownRepository)?;
+    let file = files
+        .iter()
+        .find(|f| f.path == Path::new(path))
+        .ok_or(RepositoryError::UnknownRepository)?;
+    let content = fs::read_to_string(&file.path).map_err(|_| RepositoryError::UnknownRepository)?;
+    let entry = file
+        .format
+        .parse(&content)
+        .into_iter()
+        .find(|e| e.key == key)
+        .ok_or(RepositoryError::UnknownRepository)?;
+    Ok((file, content, entry))
+}
+
+/// Enable or disable the repository with the ID returned by [`list`].
+pub(super) fn set_enabled(
+    files: &[RepositoryFile],
+    id: &str,
+    enabled: bool,
+    credential: SudoCredential,
+) -> Result<SudoOutput, RepositoryError> {
+    let (file, content, entry) = locate(files, id)?;
+    let changed = file.format.set_enabled(&content, &entry, enabled);
+    check(write_system_file(credential, &file.path, &changed)?)
+}
+
+/// Remove the repository with the ID returned by [`list`]. Files that do not configure any other
+/// repository are removed, except for `/etc/pacman.conf`.
+pub(super) fn remove(
+    files: &[RepositoryFile],
+    id: &str,
+    credential: SudoCredential,
+) -> Result<SudoOutput, RepositoryError> {
+    let (file, content, entry) = locate(files, id)?;
+    let mut lines = content.lines().map(String::from).collect::<Vec<String>>();
+    lines.drain(entry.lines.clone());
+    let changed = join_lines(lines);
+
+    if file.format != RepositoryFormat::PacmanConf && file.format.parse(&changed).is_empty() {
+        return check(
+            SudoCommand::with_credential(credential, "rm")
+                .arg("-f")
+                .arg(file.path.display())
+                .output()?,
+        );
+    }
+    check(write_system_file(credential, &file.path, &changed)?)
+}
+
+/// A `.repo` file for dnf and zypper, which checks signatures if the repository has a key.
+///
+/// * `key_path` - Where the key of the repository is installed to
+/// * `extra` - Additional settings like `type=rpm-md`
+pub(super) fn ini_repository(
+    repository: &NewRepository,
+    key_path: Option<&Path>,
+    extra: &[&str],
+) -> String {
+    let mut lines = vec![
+        format!("[{}]", repository.name),
+        format!("name={}", repository.name),
+        format!("baseurl={}", repository.uri),
+        "enabled=1".to_string(),
+    ];
+    match key_path {
+        Some(path) => {
+            lines.push("gpgcheck=1".to_string());
+            lines.push(format!("gpgkey=file://{}", path.display()));
+        }
+        None => lines.push("gpgcheck=0".to_string()),
+    }
+    lines.extend(extra.iter().map(|l| l.to_string()));
+    join_lines(lines)
+}
+
+/// Add a repository to dnf or zypper, which both use `.repo` files and import keys into the RPM
+/// database.
+pub(super) fn add_rpm_repository(
+    files: &[RepositoryFile],
+    directory: &str,
+    repository: &NewRepository,
+    extra: &[&str],
+    credential: SudoCredential,
+) -> Result<SudoOutput, RepositoryError> {
+    repository.validate()?;
+    if exists(files, repository) {
+        return Err(RepositoryError::InvalidRepository(
+            "A repository with this name exists already.".to_string(),
+        ));
+    }
+
+    let key_path = match &repository.signing_key {
+        Some(key) => {
+            let path = PathBuf::from(format!("/etc/pki/rpm-gpg/RPM-GPG-KEY-{}", repository.name));
+            check(write_system_file(credential.clone(), &path, key)?)?;
+            check(
+                SudoCommand::with_credential(credential.clone(), "rpm")
+                    .arg("--import")
+                    .arg(path.display())
+                    .output()?,
+            )?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    let path = PathBuf::from(format!("{directory}/{}.repo", repository.name));
+    check(write_system_file(
+        credential,
+        &path,
+        &ini_repository(repository, key_path.as_deref(), extra),
+    )?)
+}
+
+/// Whether any of the files configures a repository with the name used by `repository`.
+pub(super) fn exists(files: &[RepositoryFile], repository: &NewRepository) -> bool {
+    files.iter().any(|f| {
+        f.path
+            .file_stem()
+            .is_some_and(|s| s == repository.name.as_str())
+            || f.format
+                .parse(&fs::read_to_string(&f.path).unwrap_or_default())
+                .iter()
+                .any(|e| {
+                    matches!(
+                        f.format,
+                        RepositoryFormat::Ini | RepositoryFormat::PacmanConf
+                    ) && e.key == repository.name
+                })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_apt_sources() {
+        let list = "# deb cdrom:[Debian GNU/Linux]/ bookworm main
+deb http://deb.debian.org/debian bookworm main contrib
+# deb-src http://deb.debian.org/debian bookworm main
+deb [arch=amd64 signed-by=/etc/apt/keyrings/docker.asc] https://download.docker.com/linux/debian bookworm stable
+";
+        let entries = RepositoryFormat::AptList.parse(list);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].key, "2");
+        assert_eq!(entries[0].name, "bookworm main contrib");
+        assert!(!entries[1].enabled);
+        assert_eq!(
+            entries[2].uris,
+            vec!["https://download.docker.com/linux/debian"]
+        );
+
+        let disabled = RepositoryFormat::AptList.set_enabled(list, &entries[0], false);
+        assert!(disabled.contains("\n# deb http://deb.debian.org/debian bookworm main contrib\n"));
+        let enabled = RepositoryFormat::AptList.set_enabled(list, &entries[1], true);
+        assert!(enabled.contains("\ndeb-src http://deb.debian.org/debian bookworm main\n"));
+
+        let sources = "Types: deb
+URIs: http://deb.debian.org/debian
+Suites: bookworm bookworm-updates
+Components: main
+Signed-By: /usr/share/keyrings/debian-archive-keyring.gpg
+
+Types: deb
+URIs: http://deb.debian.org/debian-security
+Suites: bookworm-security
+Components: main
+Enabled: no
+";
+        let entries = RepositoryFormat::Deb822.parse(sources);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "bookworm bookworm-updates main");
+        assert!(entries[0].enabled);
+        assert!(!entries[1].enabled);
+
+        let changed = RepositoryFormat::Deb822.set_enabled(sources, &entries[0], false);
+        assert!(changed.contains("debian-archive-keyring.gpg\nEnabled: no\n\nTypes: deb"));
+        let changed = RepositoryFormat::Deb822.set_enabled(sources, &entries[1], true);
+        assert!(changed.ends_with("Components: main\nEnabled: yes\n"));
+    }
+
+    #[test]
+    fn edit_repo_files() {
+        let repo = "[fedora]
+name=Fedora $releasever - $basearch
+#baseurl=http://download.example/pub/fedora/linux/releases/$releasever/Everything/$basearch/os/
+metalink=https://mirrors.fedoraproject.org/metalink?repo=fedora-$releasever&arch=$basearch
+enabled=1
+gpgcheck=1
+
+[fedora-debuginfo]
+name=Fedora $releasever - $basearch - Debug
+baseurl=https://example.com/one
+  https://example.com/two
+enabled=0
+";
+        let entries = RepositoryFormat::Ini.parse(repo);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Fedora $releasever - $basearch");
+        assert_eq!(entries[0].uris.len(), 1);
+        assert_eq!(
+            entries[1].uris,
+            vec!["https://example.com/one", "https://example.com/two"]
+        );
+        assert!(!entries[1].enabled);
+
+        let changed = RepositoryFormat::Ini.set_enabled(repo, &entries[0], false);
+        assert!(changed.contains("&arch=$basearch\nenabled=0\ngpgcheck=1"));
+
+        let new = NewRepository {
+            name: "example".to_string(),
+            uri: "https://example.com/repo".to_string(),
+            suite: None,
+            components: Vec::new(),
+            signing_key: None,
+        };
+        assert!(new.validate().is_ok());
+        let written = ini_repository(&new, None, &["type=rpm-md"]);
+        assert_eq!(
+            RepositoryFormat::Ini.parse(&written),
+            vec![Entry {
+                key: "example".to_string(),
+                name: "example".to_string(),
+                uris: vec!["https://example.com/repo".to_string()],
+                enabled: true,
+                lines: 0..6,
+            }]
+        );
+
+        let invalid = NewRepository {
+            name: "../example".to_string(),
+            ..new.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = NewRepository {
+            uri: "https://example.com/repo\n[evil]".to_string(),
+            ..new
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn edit_pacman_conf() {
+        let conf = "[options]
+HoldPkg     = pacman glibc
+#IgnorePkg   =
+Architecture = auto
+
+# The testing repositories are disabled by default.
+
+#[core-testing]
+#Include = /etc/pacman.d/mirrorlist
+
+[core]
+Include = /etc/pacman.d/mirrorlist
+
+[custom]
+SigLevel = Optional TrustAll
+Server = file:///home/custompkgs
+";
+        let entries = RepositoryFormat::PacmanConf.parse(conf);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.key.as_str(), e.enabled, e.lines.clone()))
+                .collect::<Vec<(&str, bool, Range<usize>)>>(),
+            vec![
+                ("core-testing", false, 7..9),
+                ("core", true, 10..12),
+                ("custom", true, 13..16)
+            ]
+        );
+        assert_eq!(entries[2].uris, vec!["file:///home/custompkgs"]);
+
+        let changed = RepositoryFormat::PacmanConf.set_enabled(conf, &entries[0], true);
+        assert!(changed.contains("\n[core-testing]\nInclude = /etc/pacman.d/mirrorlist\n"));
+        let changed = RepositoryFormat::PacmanConf.set_enabled(conf, &entries[1], false);
+        assert!(changed.contains("\n#[core]\n#Include = /etc/pacman.d/mirrorlist\n"));
+
+        assert!(pacman_ignored(conf).is_empty());
+        let held = set_pacman_ignored(conf, "linux", true);
+        assert!(held.contains("\nIgnorePkg = linux\nArchitecture"));
+        let held = set_pacman_ignored(&held, "nvidia", true);
+        assert_eq!(pacman_ignored(&held), vec!["linux", "nvidia"]);
+        let unheld =
+            set_pacman_ignored(&set_pacman_ignored(&held, "linux", false), "nvidia", false);
+        assert_eq!(unheld, conf);
+    }
+
+    #[test]
+    fn edit_apk_repositories() {
+        let repositories = "https://dl-cdn.alpinelinux.org/alpine/v3.19/main
+#https://dl-cdn.alpinelinux.org/alpine/v3.19/community
+@edge https://dl-cdn.alpinelinux.org/alpine/edge/testing
+";
+        let entries = RepositoryFormat::ApkList.parse(repositories);
+        assert_eq!(entries.len(), 3);
+        assert!(!entries[1].enabled);
+        assert_eq!(
+            entries[2].uris,
+            vec!["https://dl-cdn.alpinelinux.org/alpine/edge/testing"]
+        );
+        assert!(
+            RepositoryFormat::ApkList
+                .set_enabled(repositories, &entries[1], true)
+                .contains("\nhttps://dl-cdn.alpinelinux.org/alpine/v3.19/community\n")
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+use std::process::Command;
+
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    field, nothing_to_do, parse_fields, parse_size, run_command_line, run_with_output,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+/// Snaps from the Snap Store
+pub struct Snap;
+
+/// The first column of a table printed by snap, skipping the header.
+fn first_column(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|l| l.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+const UPDATE_ALL: CommandLine = &["snap", "refresh"];
+
+impl PackageBackend for Snap {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Snap
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        let command = run_with_output(Command::new("snap").arg("list"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(first_column(&command.stdout))
+    }
+
+    /// The Snap Store can only be searched, not listed.
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        // "All snaps up to date." is printed to the standard error
+        let command = run_with_output(Command::new("snap").arg("refresh").arg("--list"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(first_column(&command.stdout))
+    }
+
+    /// Old revisions are removed by snapd automatically.
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Vec::new())
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        let command = run_with_output(Command::new("snap").arg("find").arg(query));
+        // snap exits with an error if nothing matches
+        if !command.success && !command.stderr.contains("No matching snaps") {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        let installed = self.list_installed()?.into_iter().collect();
+        Ok(parse_search(&command.stdout, &installed))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let command = run_with_output(Command::new("snap").arg("info").arg(name));
+        if !command.success {
+            return Err(PackageManagerError::UnknownPackage);
+        }
+        parse_info(&command.stdout).ok_or(PackageManagerError::UnknownPackage)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "snap")
+            .arg("install")
+            .arg(name)
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "snap")
+            .arg("remove")
+            .arg(name)
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "snap")
+            .arg("refresh")
+            .arg(name)
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_ALL)
+    }
+
+    /// snapd queries the Snap Store directly, so there is no local database to refresh.
+    fn update_database(&self, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_ALL]
+    }
+
+    fn remove_orphaned(&self, _credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        nothing_to_do()
+    }
+}
+
+/// Parse the output of `snap find`, which prints a table with the columns name, version,
+/// publisher, notes and summary.
+fn parse_search(output: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|l| {
+            let columns = l.split_whitespace().collect::<Vec<&str>>();
+            if columns.len() < 4 {
+                return None;
+            }
+            Some(PackageSummary {
+                installed: installed.contains(columns[0]),
+                name: columns[0].to_string(),
+                version: Some(columns[1].to_string()),
+                description: columns[4..].join(" "),
+                package_manager: PackageManager::Snap,
+            })
+        })
+        .collect()
+}
+
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    let fields = parse_fields(info);
+
+    // Installed snaps are listed as "installed: 2.10 (42) 65kB -"
+    let installed = field(&fields, &["installed"])
+        .map(|i| i.split_whitespace().collect::<Vec<&str>>())
+        .filter(|i| !i.is_empty());
+
+    // Channels are listed as "latest/stable: 2.10 2019-04-17 (42) 65kB -", closed channels use
+    // "^" or "--" instead of a version
+    let tracking = field(&fields, &["tracking"]).unwrap_or("latest/stable");
+    let channels = field(&fields, &["channels"])
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .map(|(channel, rest)| {
+            (
+                channel.trim(),
+                rest.split_whitespace().collect::<Vec<&str>>(),
+            )
+        })
+        .filter(|(_, columns)| columns.len() >= 4 && columns[0] != "^" && columns[0] != "--")
+        .collect::<Vec<(&str, Vec<&str>)>>();
+    let channel = channels
+        .iter()
+        .find(|(channel, _)| *channel == tracking)
+        .or(channels.first());
+
+    let version = installed
+        .as_ref()
+        .map(|i| i[0])
+        .or(channel.map(|(_, columns)| columns[0]))?;
+
+    let description = field(&fields, &["description"]).unwrap_or_default();
+    let description = description.strip_prefix('|').unwrap_or(description).trim();
+
+    Some(PackageInfo {
+        name: field(&fields, &["name"])?.to_string(),
+        version: version.to_string(),
+        installed_version: installed.as_ref().map(|i| i[0].to_string()),
+        description: description.to_string(),
+        installed_size: installed
+            .as_ref()
+            .and_then(|i| i.get(2))
+            .and_then(|s| parse_size(s)),
+        download_size: channel.and_then(|(_, columns)| parse_size(columns[3])),
+        repository: channel.map(|(channel, _)| channel.to_string()),
+        ..PackageInfo::new(PackageManager::Snap)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let info = "name:      hello
+summary:   GNU Hello, the \"hello world\" snap
+publisher: Canonical✓
+store-url: https://snapcraft.io/hello
+license:   GPL-3.0
+description: |
+  GNU hello prints a friendly greeting.
+  This is part of the snapcraft tour.
+snap-id:      buPKUD3TKqCOgLEjjHx5kSiCpIs5cMuQ
+tracking:     latest/stable
+refresh-date: today at 10:00 UTC
+channels:
+  latest/stable:    2.10 2019-04-17 (42) 65kB -
+  latest/candidate: ^
+  latest/beta:      2.10 2019-04-17 (42) 65kB -
+installed:          2.10            (38) 64kB -
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.name, "hello");
+        assert_eq!(info.version, "2.10");
+        assert_eq!(info.installed_version.as_deref(), Some("2.10"));
+        assert_eq!(info.installed_size, Some(64_000));
+        assert_eq!(info.download_size, Some(65_000));
+        assert_eq!(info.repository.as_deref(), Some("latest/stable"));
+        assert_eq!(
+            info.description,
+            "GNU hello prints a friendly greeting.\nThis is part of the snapcraft tour."
+        );
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let output = "Name   Version  Publisher   Notes  Summary
+hello  2.10     canonical✓  -      GNU Hello, the \"hello world\" snap
+hello-world  6.4  canonical✓  -  The 'hello-world' of snaps
+";
+        let installed = HashSet::from(["hello".to_string()]);
+        let results = parse_search(output, &installed);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(
+            results[0].description,
+            "GNU Hello, the \"hello world\" snap"
+        );
+        assert_eq!(results[1].name, "hello-world");
+    }
+}
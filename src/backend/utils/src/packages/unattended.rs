@@ -0,0 +1,458 @@
+//! Unattended package updates.
+//!
+//! Updates that run without a human typing the sudo password rely on a sudoers rule at
+//! [`SUDOERS_RULE_PATH`]. It only allows the exact commands that update the package managers on
+//! the system and `reboot` to be run as root without a password, see [`crate::sudoers`]. The rule
+//! is installed once using the sudo password of the user and can be removed again at any time.
+//!
+//! When updates are run is described by an [`UpdatePolicy`] and its [`MaintenanceWindow`].
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, TimeDelta, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::{CommandLine, PackageManager, backends, get_package_manager, primary_backend};
+use crate::models::UpdatePolicyEntry;
+use crate::sudo::SudoError;
+use crate::sudoers::{self, PrivilegedCommand, SudoersError};
+
+/// Location of the sudoers rule that allows unattended updates.
+pub const SUDOERS_RULE_PATH: &str = "/etc/sudoers.d/zentrox";
+
+#[derive(Debug, Error)]
+pub enum UnattendedError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("No supported package manager was found.")]
+    NoPackageManager,
+    #[error("{0}")]
+    Rule(SudoersError),
+}
+
+impl From<SudoersError> for UnattendedError {
+    fn from(error: SudoersError) -> Self {
+        match error {
+            SudoersError::Sudo(error) => UnattendedError::Sudo(error),
+            error => UnattendedError::Rule(error),
+        }
+    }
+}
+
+/// Command used to reboot after updates.
+const REBOOT: CommandLine = &["reboot"];
+
+/// Commands that the sudoers rule allows to be run without a password.
+///
+/// These are the update commands of the package managers on the system, as returned by
+/// [`super::PackageBackend::update_commands`], and `reboot`.
+pub fn privileged_commands() -> Vec<PrivilegedCommand> {
+    backends()
+        .iter()
+        .flat_map(|b| b.update_commands())
+        .chain([REBOOT])
+        .filter_map(PrivilegedCommand::resolve)
+        .collect()
+}
+
+/// Install the sudoers rule for the user running Zentrox.
+///
+/// * `password` - Sudo password of the user
+pub fn authorize(password: String) -> Result<(), UnattendedError> {
+    if get_package_manager().is_err() {
+        return Err(UnattendedError::NoPackageManager);
+    }
+    sudoers::install(
+        SUDOERS_RULE_PATH,
+        "run unattended package updates",
+        &privileged_commands(),
+        &password,
+    )?;
+
+    if !is_authorized() {
+        return Err(SudoersError::NotIncluded.into());
+    }
+    Ok(())
+}
+
+/// Remove the sudoers rule, so updates require the sudo password again.
+///
+/// * `password` - Sudo password of the user
+pub fn revoke(password: String) -> Result<(), UnattendedError> {
+    sudoers::remove(SUDOERS_RULE_PATH, &password)?;
+    Ok(())
+}
+
+/// Whether the system package manager can update its database as root without a password.
+pub fn is_authorized() -> bool {
+    primary_backend()
+        .ok()
+        .and_then(|b| b.update_commands().into_iter().next())
+        .and_then(PrivilegedCommand::resolve)
+        .is_some_and(|command| sudoers::is_allowed(&command))
+}
+
+/// Whether the system has to be rebooted to complete an update, if this can be determined.
+pub fn reboot_required() -> Option<bool> {
+    // Debian and Ubuntu
+    if Path::new("/var/run/reboot-required").exists() {
+        return Some(true);
+    }
+
+    // Fedora and RHEL with dnf-utils, which exits with 1 if a reboot is required
+    if let Ok(output) = Command::new("needs-restarting").arg("-r").output() {
+        match output.status.code() {
+            Some(0) => return Some(false),
+            Some(1) => return Some(true),
+            _ => {}
+        }
+    }
+
+    // openSUSE, which exits with 102 if a reboot is required
+    if get_package_manager().is_ok_and(|m| m == PackageManager::Zypper)
+        && let Ok(output) = Command::new("zypper").arg("needs-rebooting").output()
+    {
+        match output.status.code() {
+            Some(0) => return Some(false),
+            Some(102) => return Some(true),
+            _ => {}
+        }
+    }
+
+    // Distributions like Arch Linux remove the modules of the running kernel once it is updated
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let modules = ["/usr/lib/modules", "/lib/modules"]
+        .into_iter()
+        .map(Path::new)
+        .find(|p| p.is_dir())?;
+    Some(!modules.join(release.trim()).exists())
+}
+
+/// Which updates are installed unattended
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateKind {
+    /// Only updates that fix security issues. This requires apt with unattended-upgrades, dnf or
+    /// zypper.
+    #[default]
+    Security,
+    /// All updates of all package managers
+    All,
+}
+
+impl Display for UpdateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateKind::Security => f.write_str("security"),
+            UpdateKind::All => f.write_str("all"),
+        }
+    }
+}
+
+impl FromStr for UpdateKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "security" => Ok(UpdateKind::Security),
+            "all" => Ok(UpdateKind::All),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Day of the week
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Day {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Day {
+    const ALL: [Day; 7] = [
+        Day::Monday,
+        Day::Tuesday,
+        Day::Wednesday,
+        Day::Thursday,
+        Day::Friday,
+        Day::Saturday,
+        Day::Sunday,
+    ];
+
+    fn weekday(self) -> Weekday {
+        match self {
+            Day::Monday => Weekday::Mon,
+            Day::Tuesday => Weekday::Tue,
+            Day::Wednesday => Weekday::Wed,
+            Day::Thursday => Weekday::Thu,
+            Day::Friday => Weekday::Fri,
+            Day::Saturday => Weekday::Sat,
+            Day::Sunday => Weekday::Sun,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Day::Monday => "monday",
+            Day::Tuesday => "tuesday",
+            Day::Wednesday => "wednesday",
+            Day::Thursday => "thursday",
+            Day::Friday => "friday",
+            Day::Saturday => "saturday",
+            Day::Sunday => "sunday",
+        }
+    }
+}
+
+/// When and which updates are installed unattended
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePolicy {
+    pub enabled: bool,
+    pub kind: UpdateKind,
+    /// Days the maintenance window opens on
+    pub days: Vec<Day>,
+    /// Local time the maintenance window opens at, formatted as `HH:MM`
+    pub window_start: String,
+    /// Length of the maintenance window in minutes
+    pub window_duration: u32,
+    /// Reboot automatically if an update requires it. Reboots only happen within the maintenance
+    /// window.
+    pub reboot: bool,
+}
+
+impl Default for UpdatePolicy {
+    fn default() -> Self {
+        UpdatePolicy {
+            enabled: false,
+            kind: UpdateKind::Security,
+            days: Day::ALL.to_vec(),
+            window_start: "03:00".to_string(),
+            window_duration: 120,
+            reboot: false,
+        }
+    }
+}
+
+impl UpdatePolicy {
+    /// Check the policy and parse its maintenance window.
+    pub fn window(&self) -> Result<MaintenanceWindow, String> {
+        let start = NaiveTime::parse_from_str(&self.window_start, "%H:%M")
+            .map_err(|_| "The window start has to be formatted as HH:MM.".to_string())?;
+        if self.window_duration == 0 || self.window_duration > 24 * 60 {
+            return Err("The window has to last between one minute and one day.".to_string());
+        }
+        if self.enabled && self.days.is_empty() {
+            return Err("The window has to open on at least one day.".to_string());
+        }
+        Ok(MaintenanceWindow {
+            days: self.days.iter().map(|d| d.weekday()).collect(),
+            start,
+            duration: TimeDelta::minutes(self.window_duration as i64),
+        })
+    }
+
+    /// Whether a scheduled run is due now.
+    ///
+    /// * `last_run` - Start of the last scheduled run in seconds since the UNIX epoch
+    pub fn is_due(&self, last_run: Option<i64>) -> bool {
+        self.enabled
+            && self
+                .window()
+                .is_ok_and(|w| w.is_due(&Local::now(), last_run))
+    }
+
+    /// Whether the maintenance window is currently open.
+    pub fn in_window(&self) -> bool {
+        self.window()
+            .is_ok_and(|w| w.containing(&Local::now()).is_some())
+    }
+
+    /// The next time the maintenance window opens in seconds since the UNIX epoch, if the policy
+    /// is enabled.
+    pub fn next_window(&self) -> Option<i64> {
+        if !self.enabled {
+            return None;
+        }
+        self.window()
+            .ok()?
+            .next_start(&Local::now())
+            .map(|start| start.timestamp())
+    }
+}
+
+impl From<&UpdatePolicy> for UpdatePolicyEntry {
+    fn from(policy: &UpdatePolicy) -> Self {
+        UpdatePolicyEntry {
+            id: 0,
+            enabled: policy.enabled,
+            kind: policy.kind.to_string(),
+            days: policy
+                .days
+                .iter()
+                .map(|d| d.name())
+                .collect::<Vec<&str>>()
+                .join(","),
+            window_start: policy.window_start.clone(),
+            window_duration: policy.window_duration as i32,
+            reboot: policy.reboot,
+        }
+    }
+}
+
+impl From<UpdatePolicyEntry> for UpdatePolicy {
+    fn from(entry: UpdatePolicyEntry) -> Self {
+        UpdatePolicy {
+            enabled: entry.enabled,
+            kind: entry.kind.parse().unwrap_or_default(),
+            days: Day::ALL
+                .into_iter()
+                .filter(|d| entry.days.split(',').any(|e| e == d.name()))
+                .collect(),
+            window_start: entry.window_start,
+            window_duration: entry.window_duration.max(0) as u32,
+            reboot: entry.reboot,
+        }
+    }
+}
+
+/// A recurring time span in which updates may be installed
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    duration: TimeDelta,
+}
+
+impl MaintenanceWindow {
+    /// The time the window opened at on `date`, if it opens on that day.
+    fn start_on<Tz: TimeZone>(&self, date: chrono::NaiveDate, tz: &Tz) -> Option<DateTime<Tz>> {
+        if !self.days.contains(&date.weekday()) {
+            return None;
+        }
+        // Times skipped by daylight saving time do not exist, repeated ones use the first
+        tz.from_local_datetime(&date.and_time(self.start))
+            .earliest()
+    }
+
+    /// The start of the window `now` lies in.
+    ///
+    /// Windows may last until the following day, so the window of the previous day is considered
+    /// as well.
+    pub fn containing<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let today = now.date_naive();
+        [today.checked_sub_days(Days::new(1)), Some(today)]
+            .into_iter()
+            .flatten()
+            .filter_map(|date| self.start_on(date, &now.timezone()))
+            .filter(|start| start <= now && *now < start.clone() + self.duration)
+            .last()
+    }
+
+    /// Whether a scheduled run is due at `now`, which is the case once per window.
+    ///
+    /// * `last_run` - Start of the last scheduled run in seconds since the UNIX epoch
+    pub fn is_due<Tz: TimeZone>(&self, now: &DateTime<Tz>, last_run: Option<i64>) -> bool {
+        self.containing(now)
+            .is_some_and(|start| last_run.is_none_or(|last| last < start.timestamp()))
+    }
+
+    /// The next time the window opens after `now`.
+    pub fn next_start<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let today = now.date_naive();
+        (0..=7)
+            .filter_map(|offset| today.checked_add_days(Days::new(offset)))
+            .filter_map(|date| self.start_on(date, &now.timezone()))
+            .find(|start| start > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn maintenance_window() {
+        let policy = UpdatePolicy {
+            enabled: true,
+            days: vec![Day::Saturday],
+            window_start: "23:00".to_string(),
+            window_duration: 180,
+            ..UpdatePolicy::default()
+        };
+        let window = policy.window().unwrap();
+
+        // 2024-06-01 is a Saturday, the window lasts until 02:00 on Sunday
+        assert_eq!(
+            window.containing(&at("2024-06-01T23:30:00Z")),
+            Some(at("2024-06-01T23:00:00Z"))
+        );
+        assert_eq!(
+            window.containing(&at("2024-06-02T01:59:00Z")),
+            Some(at("2024-06-01T23:00:00Z"))
+        );
+        assert_eq!(window.containing(&at("2024-06-02T02:00:00Z")), None);
+        assert_eq!(window.containing(&at("2024-06-01T22:59:00Z")), None);
+
+        let now = at("2024-06-02T00:30:00Z");
+        assert!(window.is_due(&now, None));
+        assert!(window.is_due(&now, Some(at("2024-05-25T23:10:00Z").timestamp())));
+        assert!(!window.is_due(&now, Some(at("2024-06-01T23:10:00Z").timestamp())));
+
+        assert_eq!(
+            window.next_start(&at("2024-06-01T23:30:00Z")),
+            Some(at("2024-06-08T23:00:00Z"))
+        );
+        assert_eq!(
+            window.next_start(&at("2024-06-03T12:00:00Z")),
+            Some(at("2024-06-08T23:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn validate_policy() {
+        assert!(UpdatePolicy::default().window().is_ok());
+        for policy in [
+            UpdatePolicy {
+                window_start: "25:00".to_string(),
+                ..UpdatePolicy::default()
+            },
+            UpdatePolicy {
+                window_duration: 0,
+                ..UpdatePolicy::default()
+            },
+            UpdatePolicy {
+                enabled: true,
+                days: vec![],
+                ..UpdatePolicy::default()
+            },
+        ] {
+            assert!(policy.window().is_err());
+        }
+
+        let policy = UpdatePolicy {
+            kind: UpdateKind::All,
+            days: vec![Day::Monday, Day::Friday],
+            ..UpdatePolicy::default()
+        };
+        let entry = UpdatePolicyEntry::from(&policy);
+        assert_eq!(entry.days, "monday,friday");
+        assert_eq!(UpdatePolicy::from(entry), policy);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use std::process::Command;
+
+use super::repositories::{
+    NewRepository, RepositoryError, RepositoryFile, RepositoryFormat, add_rpm_repository,
+};
+use super::{
+    CommandLine, PackageBackend, PackageInfo, PackageManager, PackageManagerError, PackageSummary,
+    field, lines, parse_fields, parse_size, parse_table, run_command_line, run_with_output,
+    strip_version_constraint,
+};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput};
+
+const UPDATE_DATABASE: CommandLine = &["zypper", "--non-interactive", "refresh"];
+const UPDATE_ALL: CommandLine = &["zypper", "--non-interactive", "update"];
+const UPDATE_SECURITY: CommandLine = &[
+    "zypper",
+    "--non-interactive",
+    "patch",
+    "--category",
+    "security",
+];
+const REPOSITORY_DIRECTORY: &str = "/etc/zypp/repos.d";
+
+/// openSUSE and SUSE Linux Enterprise
+pub struct Zypper;
+
+impl Zypper {
+    /// Run zypper without interaction and progress output as the current user.
+    fn query(args: &[&str]) -> Result<String, PackageManagerError> {
+        let command = run_with_output(
+            Command::new("zypper")
+                .arg("--non-interactive")
+                .arg("--quiet")
+                .args(args),
+        );
+        // Exit code 104 means that no package matched
+        if !command.success && command.code != Some(104) {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(command.stdout)
+    }
+
+    /// Names in the column at `index` of a table without duplicates, which are caused by
+    /// packages being available for multiple architectures or from multiple repositories.
+    fn column(output: &str, index: usize) -> Vec<String> {
+        let mut seen = HashSet::new();
+        parse_table(output)
+            .into_iter()
+            .filter_map(|row| row.get(index).cloned())
+            .filter(|name| seen.insert(name.clone()))
+            .collect()
+    }
+}
+
+impl PackageBackend for Zypper {
+    fn package_manager(&self) -> PackageManager {
+        PackageManager::Zypper
+    }
+
+    fn list_installed(&self) -> Result<Vec<String>, PackageManagerError> {
+        // The RPM database is much faster to query than zypper
+        let command = run_with_output(Command::new("rpm").arg("-qa").arg("--qf").arg("%{NAME}\n"));
+        if !command.success {
+            return Err(PackageManagerError::ExecutionError);
+        }
+        Ok(lines(&command.stdout))
+    }
+
+    fn list_available(&self) -> Result<Vec<String>, PackageManagerError> {
+        let output = Zypper::query(&["search", "--uninstalled-only", "--type", "package"])?;
+        Ok(Zypper::column(&output, 1))
+    }
+
+    fn list_updates(&self) -> Result<Vec<String>, PackageManagerError> {
+        let output = Zypper::query(&["list-updates"])?;
+        Ok(Zypper::column(&output, 2))
+    }
+
+    fn list_orphaned(&self) -> Result<Vec<String>, PackageManagerError> {
+        let output = Zypper::query(&["packages", "--unneeded"])?;
+        Ok(Zypper::column(&output, 2))
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PackageSummary>, PackageManagerError> {
+        let output = Zypper::query(&[
+            "search",
+            "--search-descriptions",
+            "--type",
+            "package",
+            "--",
+            query,
+        ])?;
+        Ok(parse_search(&output))
+    }
+
+    fn info(&self, name: &str) -> Result<PackageInfo, PackageManagerError> {
+        let output = Zypper::query(&["info", "--requires", "--", name])?;
+        let mut info = parse_info(&output).ok_or(PackageManagerError::UnknownPackage)?;
+
+        let rdepends = run_with_output(
+            Command::new("rpm")
+                .arg("-q")
+                .arg("--whatrequires")
+                .arg("--qf")
+                .arg("%{NAME}\n")
+                .arg(name),
+        );
+        // rpm exits with an error if no package requires the package
+        if rdepends.success {
+            info.reverse_dependencies = lines(&rdepends.stdout);
+        }
+        Ok(info)
+    }
+
+    fn install(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive", "install", name])
+            .output()
+    }
+
+    fn remove(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive", "remove", name])
+            .output()
+    }
+
+    fn update(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive", "update", name])
+            .output()
+    }
+
+    fn update_all(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        informational(run_command_line(credential, UPDATE_ALL))
+    }
+
+    fn supports_security_updates(&self) -> bool {
+        true
+    }
+
+    fn update_security(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        informational(run_command_line(credential, UPDATE_SECURITY))
+    }
+
+    fn update_database(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        run_command_line(credential, UPDATE_DATABASE)
+    }
+
+    fn update_commands(&self) -> Vec<CommandLine> {
+        vec![UPDATE_DATABASE, UPDATE_ALL, UPDATE_SECURITY]
+    }
+
+    fn remove_orphaned(&self, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        let packages = self.list_orphaned().unwrap_or_default();
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive".to_string(), "remove".to_string()])
+            .args(packages)
+            .output()
+    }
+
+    fn supports_holds(&self) -> bool {
+        true
+    }
+
+    /// Package locks, which may also contain patterns
+    fn list_holds(&self) -> Result<Vec<String>, PackageManagerError> {
+        Ok(Zypper::column(&Zypper::query(&["locks"])?, 1))
+    }
+
+    fn hold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive", "addlock", name])
+            .output()
+    }
+
+    fn unhold(&self, name: &str, credential: SudoCredential) -> Result<SudoOutput, SudoError> {
+        SudoCommand::with_credential(credential, "zypper")
+            .args(vec!["--non-interactive", "removelock", name])
+            .output()
+    }
+
+    fn repository_files(&self) -> Vec<RepositoryFile> {
+        RepositoryFile::in_directory(REPOSITORY_DIRECTORY, "repo", RepositoryFormat::Ini)
+    }
+
+    fn add_repository(
+        &self,
+        repository: &NewRepository,
+        credential: SudoCredential,
+    ) -> Result<SudoOutput, RepositoryError> {
+        add_rpm_repository(
+            &self.repository_files(),
+            REPOSITORY_DIRECTORY,
+            repository,
+            &["autorefresh=1", "type=rpm-md"],
+            credential,
+        )
+    }
+}
+
+/// zypper uses the exit codes 100 to 103 to report that it succeeded, but e.g. a reboot is
+/// required. These are reported as 0, as the reboot is detected separately.
+fn informational(output: Result<SudoOutput, SudoError>) -> Result<SudoOutput, SudoError> {
+    output.map(|o| match o.status {
+        Some(100..=103) => SudoOutput {
+            status: Some(0),
+            ..o
+        },
+        _ => o,
+    })
+}
+
+fn parse_search(output: &str) -> Vec<PackageSummary> {
+    parse_table(output)
+        .into_iter()
+        .filter(|row| row.len() >= 3)
+        .map(|row| PackageSummary {
+            installed: row[0].starts_with('i'),
+            name: row[1].clone(),
+            version: None,
+            description: row[2].clone(),
+            package_manager: PackageManager::Zypper,
+        })
+        .collect()
+}
+
+fn parse_info(info: &str) -> Option<PackageInfo> {
+    // Skip the "Information for package" header
+    let entry = info
+        .lines()
+        .skip_while(|l| !l.starts_with("Repository") && !l.starts_with("Name"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let fields = parse_fields(&entry);
+
+    let version = field(&fields, &["Version"])?.to_string();
+    let installed_version = if field(&fields, &["Installed"]) == Some("Yes") {
+        // An outdated package has the status "out-of-date (version 8.5.0-1.1 installed)"
+        field(&fields, &["Status"])
+            .and_then(|s| s.split_once("(version "))
+            .and_then(|(_, v)| v.split_whitespace().next())
+            .map(String::from)
+            .or(Some(version.clone()))
+    } else {
+        None
+    };
+
+    let dependencies = field(&fields, &["Requires"])
+        .unwrap_or_default()
+        .lines()
+        // The first line only contains the number of requirements
+        .skip(1)
+        .map(strip_version_constraint)
+        .filter(|d| !d.is_empty())
+        .collect();
+
+    Some(PackageInfo {
+        name: field(&fields, &["Name"])?.to_string(),
+        version,
+        installed_version,
+        description: field(&fields, &["Description"])
+            .or(field(&fields, &["Summary"]))
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        installed_size: field(&fields, &["Installed Size"]).and_then(parse_size),
+        dependencies,
+        repository: field(&fields, &["Repository"])
+            .filter(|r| *r != "@System")
+            .map(String::from),
+        ..PackageInfo::new(PackageManager::Zypper)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_details() {
+        let info = "Information for package curl:
+-----------------------------
+Repository     : Main Update Repository
+Name           : curl
+Version        : 8.6.0-1.1
+Arch           : x86_64
+Vendor         : openSUSE
+Installed Size : 323.9 KiB
+Installed      : Yes
+Status         : out-of-date (version 8.5.0-2.1 installed)
+Summary        : A Tool for Transferring Data from URLs
+Description    :
+    Curl is a client to get documents and files from or send documents to a server.
+Requires       : [3]
+    libcurl4 = 8.6.0
+    libc.so.6()(64bit)
+    /bin/sh
+";
+        let info = parse_info(info).unwrap();
+        assert_eq!(info.name, "curl");
+        assert_eq!(info.version, "8.6.0-1.1");
+        assert_eq!(info.installed_version.as_deref(), Some("8.5.0-2.1"));
+        assert_eq!(info.installed_size, Some(331674));
+        assert_eq!(info.dependencies, vec!["libcurl4", "libc.so.6", "/bin/sh"]);
+        assert_eq!(info.repository.as_deref(), Some("Main Update Repository"));
+        assert!(info.description.starts_with("Curl is a client"));
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let output = "S  | Name      | Summary                                | Type
+---+-----------+----------------------------------------+--------
+i+ | curl      | A Tool for Transferring Data from URLs | package
+   | curlftpfs | FUSE based filesystem for FTP hosts    | package
+";
+        let results = parse_search(output);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].installed);
+        assert_eq!(results[1].name, "curlftpfs");
+        assert!(!results[1].installed);
+
+        let updates = "S | Repository | Name | Current Version | Available Version | Arch
+--+------------+------+-----------------+-------------------+-------
+v | Main       | curl | 8.5.0-2.1       | 8.6.0-1.1         | x86_64
+v | Main       | curl | 8.5.0-2.1       | 8.6.0-1.1         | i586
+";
+        assert_eq!(Zypper::column(updates, 2), vec!["curl"]);
+    }
+}
@@ -0,0 +1,881 @@
+//! Controlling processes by sending signals and changing their priorities and CPU affinity,
+//! inspecting them using `/proc/<pid>` and arranging processes in a tree.
+//!
+//! Changes are made using `kill`, `renice`, `ionice` and `taskset`. If the user running Zentrox
+//! is not permitted to make a change, e.g. because the process belongs to another user or the
+//! priority is raised, the change is retried using sudo.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::sockets::{Socket, read_sockets, socket_inode};
+use crate::sudo::{SudoCommand, SudoCredential, SudoError};
+
+/// Signals that can be sent to processes
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Signal {
+    /// Ask the process to terminate
+    Term,
+    /// Usually makes daemons reload their configuration
+    Hup,
+    /// Like pressing Ctrl+C in a terminal
+    Int,
+    /// Terminate the process immediately
+    Kill,
+    /// Pause the process
+    Stop,
+    /// Continue a paused process
+    Cont,
+    Usr1,
+    Usr2,
+}
+
+impl Signal {
+    /// The name of the signal as understood by `kill -s`.
+    fn name(self) -> &'static str {
+        match self {
+            Signal::Term => "TERM",
+            Signal::Hup => "HUP",
+            Signal::Int => "INT",
+            Signal::Kill => "KILL",
+            Signal::Stop => "STOP",
+            Signal::Cont => "CONT",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+        }
+    }
+}
+
+/// I/O scheduling class as used by `ionice`
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum IoClass {
+    /// Derived from the CPU priority
+    None,
+    /// Served first, setting it requires root
+    Realtime,
+    /// The default class
+    BestEffort,
+    /// Only served if no other process uses the disk
+    Idle,
+}
+
+impl IoClass {
+    fn number(self) -> u8 {
+        match self {
+            IoClass::None => 0,
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+}
+
+/// I/O scheduling class and priority of a process
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IoPriority {
+    pub class: IoClass,
+    /// 0 (highest) to 7 (lowest), only used by the realtime and best-effort classes
+    pub level: Option<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum ProcessError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("The process does not exist.")]
+    NoSuchProcess,
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for ProcessError {
+    fn from(error: SudoError) -> Self {
+        ProcessError::Sudo(error)
+    }
+}
+
+/// Classify the error message of `kill`, `renice`, `ionice` or `taskset`.
+fn command_error(stderr: &str) -> ProcessError {
+    if stderr.contains("No such process") {
+        ProcessError::NoSuchProcess
+    } else {
+        ProcessError::Failed(stderr.trim().to_string())
+    }
+}
+
+/// Run a command and retry it using sudo if it is not permitted.
+fn run(program: &str, args: Vec<String>, credential: &SudoCredential) -> Result<(), ProcessError> {
+    let output = Command::new(program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| ProcessError::Failed(format!("{program} could not be started: {e}")))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.contains("Operation not permitted") && !stderr.contains("Permission denied") {
+        return Err(command_error(&stderr));
+    }
+    let output = SudoCommand::with_credential(credential.clone(), program)
+        .args(args)
+        .output()?;
+    match output.status {
+        Some(0) => Ok(()),
+        _ => Err(command_error(&output.stderr)),
+    }
+}
+
+/// PID 0 would address the process group of the command instead of a process. PIDs above
+/// `i32::MAX` are negative for `kill`, which e.g. sends signals to every process for -1.
+fn check_pid(pid: u32) -> Result<(), ProcessError> {
+    match pid {
+        0 => Err(ProcessError::NoSuchProcess),
+        p if p > i32::MAX as u32 => Err(ProcessError::NoSuchProcess),
+        _ => Ok(()),
+    }
+}
+
+/// Send a signal to a process.
+pub fn send_signal(
+    pid: u32,
+    signal: Signal,
+    credential: &SudoCredential,
+) -> Result<(), ProcessError> {
+    check_pid(pid)?;
+    run(
+        "kill",
+        vec![
+            "-s".to_string(),
+            signal.name().to_string(),
+            "--".to_string(),
+            pid.to_string(),
+        ],
+        credential,
+    )
+}
+
+/// Change the nice value of a process, which ranges from -20 (highest priority) to 19.
+pub fn renice(pid: u32, nice: i32, credential: &SudoCredential) -> Result<(), ProcessError> {
+    check_pid(pid)?;
+    if !(-20..=19).contains(&nice) {
+        return Err(ProcessError::InvalidValue(
+            "The nice value has to be between -20 and 19.".to_string(),
+        ));
+    }
+    run(
+        "renice",
+        vec![
+            "-n".to_string(),
+            nice.to_string(),
+            "-p".to_string(),
+            pid.to_string(),
+        ],
+        credential,
+    )
+}
+
+/// Change the I/O scheduling class and priority of a process.
+pub fn set_io_priority(
+    pid: u32,
+    priority: IoPriority,
+    credential: &SudoCredential,
+) -> Result<(), ProcessError> {
+    check_pid(pid)?;
+    let mut args = vec!["-c".to_string(), priority.class.number().to_string()];
+    match (priority.class, priority.level) {
+        (IoClass::Realtime | IoClass::BestEffort, Some(level)) if level <= 7 => {
+            args.extend(["-n".to_string(), level.to_string()]);
+        }
+        (IoClass::Realtime | IoClass::BestEffort, Some(_)) => {
+            return Err(ProcessError::InvalidValue(
+                "The I/O priority has to be between 0 and 7.".to_string(),
+            ));
+        }
+        (IoClass::None | IoClass::Idle, Some(_)) => {
+            return Err(ProcessError::InvalidValue(
+                "Only the realtime and best-effort classes have a priority.".to_string(),
+            ));
+        }
+        (_, None) => {}
+    }
+    args.extend(["-p".to_string(), pid.to_string()]);
+    run("ionice", args, credential)
+}
+
+/// Parse a list of CPUs like `0-3,6` and return it in a normalized form. Every CPU has to be
+/// lower than `cpu_count`.
+pub fn parse_cpu_list(list: &str, cpu_count: usize) -> Result<String, ProcessError> {
+    let invalid = || ProcessError::InvalidValue(format!("{list} is not a valid list of CPUs."));
+    let mut cpus = Vec::new();
+    for part in list.split(',').map(str::trim) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first, last),
+            None => (part, part),
+        };
+        let first = first.trim().parse::<usize>().map_err(|_| invalid())?;
+        let last = last.trim().parse::<usize>().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        if last >= cpu_count {
+            return Err(ProcessError::InvalidValue(format!(
+                "The system only has the CPUs 0 to {}.",
+                cpu_count.saturating_sub(1)
+            )));
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+
+    // Join consecutive CPUs to ranges again
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    Ok(ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{first}-{last}")
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(","))
+}
+
+/// Restrict all threads of a process to a list of CPUs like `0-3,6`.
+pub fn set_affinity(pid: u32, cpus: &str, credential: &SudoCredential) -> Result<(), ProcessError> {
+    check_pid(pid)?;
+    let cpu_count = std::thread::available_parallelism().map_or(1, |c| c.get());
+    // Processes may be restricted to fewer CPUs than the system has
+    let cpu_count = cpu_count.max(
+        fs::read_to_string("/sys/devices/system/cpu/possible")
+            .ok()
+            .and_then(|possible| {
+                possible
+                    .trim()
+                    .rsplit(['-', ','])
+                    .next()?
+                    .parse::<usize>()
+                    .ok()
+            })
+            .map_or(0, |last| last + 1),
+    );
+    let cpus = parse_cpu_list(cpus, cpu_count)?;
+    run(
+        "taskset",
+        vec![
+            "-a".to_string(),
+            "-p".to_string(),
+            "-c".to_string(),
+            cpus,
+            pid.to_string(),
+        ],
+        credential,
+    )
+}
+
+fn proc_file(pid: u32, name: &str) -> Option<String> {
+    fs::read_to_string(PathBuf::from("/proc").join(pid.to_string()).join(name)).ok()
+}
+
+/// The nice value of a process.
+pub fn nice(pid: u32) -> Option<i32> {
+    parse_nice(&proc_file(pid, "stat")?)
+}
+
+/// Read the nice value from `/proc/<pid>/stat`. The name of the process may contain spaces and
+/// parentheses, so the fields are counted from the end of the name.
+fn parse_nice(stat: &str) -> Option<i32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    // The nice value is the 19th field, the fields after the name start with the 3rd
+    fields.split_whitespace().nth(16)?.parse().ok()
+}
+
+/// The CPUs a process may run on, like `0-3,6`.
+pub fn affinity(pid: u32) -> Option<String> {
+    proc_file(pid, "status")?
+        .lines()
+        .find_map(|l| l.strip_prefix("Cpus_allowed_list:"))
+        .map(|l| l.trim().to_string())
+}
+
+/// The I/O scheduling class and priority of a process.
+pub fn io_priority(pid: u32) -> Option<IoPriority> {
+    let output = Command::new("ionice")
+        .arg("-p")
+        .arg(pid.to_string())
+        .stdin(Stdio::null())
+        .env("LC_ALL", "C")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_ionice(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the output of `ionice -p`, like `best-effort: prio 4` or `idle`.
+fn parse_ionice(output: &str) -> Option<IoPriority> {
+    let (class, level) = match output.trim().split_once(':') {
+        Some((class, level)) => (class.trim(), level.trim().strip_prefix("prio ")),
+        None => (output.trim(), None),
+    };
+    let class = match class {
+        "none" => IoClass::None,
+        "realtime" => IoClass::Realtime,
+        "best-effort" => IoClass::BestEffort,
+        "idle" => IoClass::Idle,
+        _ => return None,
+    };
+    Some(IoPriority {
+        class,
+        level: level.and_then(|l| l.trim().parse().ok()),
+    })
+}
+
+/// The systemd unit a process belongs to, like `nginx.service` or `session-2.scope`.
+pub fn systemd_unit(pid: u32) -> Option<String> {
+    parse_cgroup(&proc_file(pid, "cgroup")?)
+}
+
+/// Find the innermost service or scope in `/proc/<pid>/cgroup`. Only the unified hierarchy and
+/// the hierarchy of systemd are organized by units.
+fn parse_cgroup(cgroup: &str) -> Option<String> {
+    cgroup.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        if !controllers.is_empty() && controllers != "name=systemd" {
+            return None;
+        }
+        path.rsplit('/')
+            .find(|unit| unit.ends_with(".service") || unit.ends_with(".scope"))
+            .map(String::from)
+    })
+}
+
+/// A file descriptor of a process
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenFile {
+    pub fd: u32,
+    /// Path of the file, or a description like `socket:[12345]` or `pipe:[6789]`
+    pub target: String,
+}
+
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// Membership of a process in a cgroup hierarchy
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CgroupMembership {
+    /// Empty for the unified hierarchy of cgroup v2
+    pub controllers: Vec<String>,
+    pub path: String,
+}
+
+/// A mapped region of the memory of a process
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryMap {
+    /// Hexadecimal address
+    pub start: String,
+    /// Hexadecimal address
+    pub end: String,
+    pub size_bytes: u64,
+    /// Like `r-xp`, `p` stands for private and `s` for shared memory
+    pub permissions: String,
+    pub offset: u64,
+    pub device: String,
+    pub inode: u64,
+    /// The mapped file or a pseudo path like `[heap]`, missing for anonymous memory
+    pub path: Option<String>,
+}
+
+/// Details about a process read from `/proc/<pid>`. Parts that are missing could not be read,
+/// usually because the process belongs to another user.
+#[derive(Serialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessInspection {
+    pub pid: u32,
+    /// Whether `/proc/<pid>` was read using sudo
+    pub privileged: bool,
+    /// Working directory
+    pub cwd: Option<String>,
+    pub open_files: Option<Vec<OpenFile>>,
+    /// Sockets among the open files
+    pub sockets: Option<Vec<Socket>>,
+    pub environment: Option<Vec<EnvironmentVariable>>,
+    pub cgroups: Vec<CgroupMembership>,
+    /// The systemd unit the process belongs to
+    pub unit: Option<String>,
+    pub memory_maps: Option<Vec<MemoryMap>>,
+}
+
+/// Reads files in `/proc/<pid>` directly or using sudo.
+enum ProcReader {
+    Direct,
+    Sudo(SudoCredential),
+}
+
+impl ProcReader {
+    /// Run a command using sudo and return its output. Files of processes that exited vanish.
+    fn sudo(
+        credential: &SudoCredential,
+        program: &str,
+        args: Vec<String>,
+    ) -> Result<String, ProcessError> {
+        let output = SudoCommand::with_credential(credential.clone(), program)
+            .args(args)
+            .output()?;
+        match output.status {
+            Some(0) => Ok(output.stdout),
+            _ if output.stderr.contains("No such file or directory") => {
+                Err(ProcessError::NoSuchProcess)
+            }
+            _ => Err(ProcessError::Failed(output.stderr.trim().to_string())),
+        }
+    }
+
+    /// Errors other than a vanished process make the file count as unreadable.
+    fn direct<T>(result: std::io::Result<T>) -> Result<Option<T>, ProcessError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ProcessError::NoSuchProcess),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Option<String>, ProcessError> {
+        match self {
+            ProcReader::Direct => Self::direct(fs::read(path))
+                .map(|c| c.map(|c| String::from_utf8_lossy(&c).into_owned())),
+            ProcReader::Sudo(credential) => {
+                Self::sudo(credential, "cat", vec![path.display().to_string()]).map(Some)
+            }
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> Result<Option<String>, ProcessError> {
+        match self {
+            ProcReader::Direct => Self::direct(fs::read_link(path))
+                .map(|l| l.map(|l| l.to_string_lossy().into_owned())),
+            ProcReader::Sudo(credential) => {
+                Self::sudo(credential, "readlink", vec![path.display().to_string()])
+                    .map(|l| Some(l.trim_end_matches('\n').to_string()))
+            }
+        }
+    }
+
+    fn open_files(&self, directory: &Path) -> Result<Option<Vec<OpenFile>>, ProcessError> {
+        match self {
+            ProcReader::Direct => {
+                let Some(entries) = Self::direct(fs::read_dir(directory))? else {
+                    return Ok(None);
+                };
+                let mut files = Vec::new();
+                for entry in entries.flatten() {
+                    // File descriptors may be closed while they are listed
+                    let (Some(fd), Ok(target)) = (
+                        entry.file_name().to_str().and_then(|n| n.parse().ok()),
+                        fs::read_link(entry.path()),
+                    ) else {
+                        continue;
+                    };
+                    files.push(OpenFile {
+                        fd,
+                        target: target.to_string_lossy().into_owned(),
+                    });
+                }
+                files.sort_by_key(|f| f.fd);
+                Ok(Some(files))
+            }
+            ProcReader::Sudo(credential) => Self::sudo(
+                credential,
+                "find",
+                vec![
+                    directory.display().to_string(),
+                    "-mindepth".to_string(),
+                    "1".to_string(),
+                    "-maxdepth".to_string(),
+                    "1".to_string(),
+                    "-printf".to_string(),
+                    "%f\\t%l\\n".to_string(),
+                ],
+            )
+            .map(|listing| Some(parse_fd_listing(&listing))),
+        }
+    }
+}
+
+/// Split a line into `count` fields separated by spaces and the rest of the line, which may
+/// contain spaces itself.
+fn split_fields(line: &str, count: usize) -> (Vec<&str>, &str) {
+    let mut fields = Vec::new();
+    let mut rest = line;
+    for _ in 0..count {
+        rest = rest.trim_start();
+        let end = rest.find(' ').unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    (fields, rest.trim())
+}
+
+/// Parse the output of `find /proc/<pid>/fd -printf '%f\t%l\n'`.
+fn parse_fd_listing(listing: &str) -> Vec<OpenFile> {
+    let mut files: Vec<OpenFile> = listing
+        .lines()
+        .filter_map(|line| {
+            let (fd, target) = line.split_once('\t')?;
+            Some(OpenFile {
+                fd: fd.parse().ok()?,
+                target: target.to_string(),
+            })
+        })
+        .collect();
+    files.sort_by_key(|f| f.fd);
+    files
+}
+
+/// Parse `/proc/<pid>/environ`, in which the variables are separated by null bytes.
+fn parse_environ(environ: &str) -> Vec<EnvironmentVariable> {
+    environ
+        .split('\0')
+        .filter(|v| !v.is_empty())
+        .map(|variable| {
+            let (name, value) = variable.split_once('=').unwrap_or((variable, ""));
+            EnvironmentVariable {
+                name: name.to_string(),
+                value: value.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Parse `/proc/<pid>/cgroup`.
+fn parse_cgroups(cgroup: &str) -> Vec<CgroupMembership> {
+    cgroup
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+            Some(CgroupMembership {
+                controllers: controllers
+                    .split(',')
+                    .filter(|c| !c.is_empty())
+                    .map(String::from)
+                    .collect(),
+                path: path.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `/proc/<pid>/maps`.
+fn parse_maps(maps: &str) -> Vec<MemoryMap> {
+    maps.lines()
+        .filter_map(|line| {
+            let (fields, path) = split_fields(line, 5);
+            let (start, end) = fields[0].split_once('-')?;
+            let size_bytes = u64::from_str_radix(end, 16)
+                .ok()?
+                .checked_sub(u64::from_str_radix(start, 16).ok()?)?;
+            Some(MemoryMap {
+                start: start.to_string(),
+                end: end.to_string(),
+                size_bytes,
+                permissions: fields[1].to_string(),
+                offset: u64::from_str_radix(fields[2], 16).ok()?,
+                device: fields[3].to_string(),
+                inode: fields[4].parse().ok()?,
+                path: Some(path).filter(|p| !p.is_empty()).map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Inspect the open files, sockets, working directory, environment, cgroups and memory maps of
+/// a process. Files of processes of other users are only readable if a credential for sudo is
+/// passed.
+pub fn inspect(
+    pid: u32,
+    credential: Option<SudoCredential>,
+) -> Result<ProcessInspection, ProcessError> {
+    check_pid(pid)?;
+    let directory = PathBuf::from("/proc").join(pid.to_string());
+    if !directory.is_dir() {
+        return Err(ProcessError::NoSuchProcess);
+    }
+    let privileged = credential.is_some();
+    let reader = match credential {
+        Some(credential) => ProcReader::Sudo(credential),
+        None => ProcReader::Direct,
+    };
+
+    let open_files = reader.open_files(&directory.join("fd"))?;
+    // The sockets are listed in the network namespace of the process
+    let sockets = open_files.as_ref().map(|files| {
+        let inodes: HashSet<u64> = files
+            .iter()
+            .filter_map(|f| socket_inode(&f.target))
+            .collect();
+        if inodes.is_empty() {
+            return Vec::new();
+        }
+        read_sockets(&directory)
+            .into_iter()
+            .filter(|s| inodes.contains(&s.inode))
+            .collect()
+    });
+    let cgroup = fs::read_to_string(directory.join("cgroup")).unwrap_or_default();
+
+    Ok(ProcessInspection {
+        pid,
+        privileged,
+        cwd: reader.read_link(&directory.join("cwd"))?,
+        open_files,
+        sockets,
+        environment: reader
+            .read(&directory.join("environ"))?
+            .map(|e| parse_environ(&e)),
+        cgroups: parse_cgroups(&cgroup),
+        unit: parse_cgroup(&cgroup),
+        memory_maps: reader
+            .read(&directory.join("maps"))?
+            .map(|m| parse_maps(&m)),
+    })
+}
+
+/// A process and the processes it started
+#[derive(Serialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessNode {
+    pub pid: u32,
+    /// Missing for processes started by the kernel
+    pub parent: Option<u32>,
+    pub name: String,
+    pub username: Option<String>,
+    /// The systemd unit the process belongs to
+    pub unit: Option<String>,
+    /// Share of one CPU, e.g. `0.5` for half of a CPU
+    pub cpu_usage: f32,
+    pub memory_usage_bytes: u64,
+    #[schema(no_recursion)]
+    pub children: Vec<ProcessNode>,
+}
+
+/// Arrange processes in a tree using their parents. Processes whose parent is not listed are
+/// roots. Children are sorted by their PID.
+pub fn build_tree(processes: Vec<ProcessNode>) -> Vec<ProcessNode> {
+    let pids: HashSet<u32> = processes.iter().map(|p| p.pid).collect();
+    let mut children: HashMap<u32, Vec<ProcessNode>> = HashMap::new();
+    let mut roots = Vec::new();
+    for process in processes {
+        match process.parent {
+            Some(parent) if parent != process.pid && pids.contains(&parent) => {
+                children.entry(parent).or_default().push(process)
+            }
+            _ => roots.push(process),
+        }
+    }
+
+    fn attach(node: &mut ProcessNode, children: &mut HashMap<u32, Vec<ProcessNode>>) {
+        let mut own = children.remove(&node.pid).unwrap_or_default();
+        own.sort_by_key(|c| c.pid);
+        for child in &mut own {
+            attach(child, children);
+        }
+        node.children = own;
+    }
+
+    roots.sort_by_key(|r| r.pid);
+    for root in &mut roots {
+        attach(root, &mut children);
+    }
+    // Processes in a cycle of parents are not reachable from a root
+    let mut unreachable: Vec<ProcessNode> = children.into_values().flatten().collect();
+    unreachable.sort_by_key(|p| p.pid);
+    roots.extend(unreachable);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_files() {
+        let stat = "1234 (my (weird) proc) S 1 1234 1234 0 -1 4194560 231 0 0 0 3 1 0 0 20 -5 2 0 \
+                    5126 12345 321 18446744073709551615";
+        assert_eq!(parse_nice(stat), Some(-5));
+
+        assert_eq!(
+            parse_cgroup("0::/system.slice/nginx.service\n"),
+            Some("nginx.service".to_string())
+        );
+        assert_eq!(
+            parse_cgroup(
+                "12:cpu,cpuacct:/\n1:name=systemd:/user.slice/user-1000.slice/session-2.scope\n"
+            ),
+            Some("session-2.scope".to_string())
+        );
+        assert_eq!(
+            parse_cgroup(
+                "0::/user.slice/user-1000.slice/user@1000.service/app.slice/zentrox.service\n"
+            ),
+            Some("zentrox.service".to_string())
+        );
+        assert_eq!(
+            parse_cgroup("0::/init.scope/\n"),
+            Some("init.scope".to_string())
+        );
+        assert_eq!(parse_cgroup("0::/\n"), None);
+
+        assert_eq!(
+            parse_ionice("best-effort: prio 4\n"),
+            Some(IoPriority {
+                class: IoClass::BestEffort,
+                level: Some(4)
+            })
+        );
+        assert_eq!(
+            parse_ionice("idle\n"),
+            Some(IoPriority {
+                class: IoClass::Idle,
+                level: None
+            })
+        );
+        assert_eq!(parse_ionice("unknown: prio 0"), None);
+    }
+
+    #[test]
+    fn reject_pids_addressing_groups() {
+        assert!(matches!(check_pid(0), Err(ProcessError::NoSuchProcess)));
+        assert!(matches!(
+            check_pid(u32::MAX),
+            Err(ProcessError::NoSuchProcess)
+        ));
+        assert!(matches!(
+            check_pid(i32::MAX as u32 + 1),
+            Err(ProcessError::NoSuchProcess)
+        ));
+        assert!(check_pid(1).is_ok());
+        assert!(check_pid(i32::MAX as u32).is_ok());
+    }
+
+    #[test]
+    fn parse_cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,6", 8).unwrap(), "0-3,6");
+        assert_eq!(parse_cpu_list("3, 1,2,2,7", 8).unwrap(), "1-3,7");
+        assert_eq!(parse_cpu_list("5", 8).unwrap(), "5");
+        assert!(parse_cpu_list("8", 8).is_err());
+        assert!(parse_cpu_list("3-1", 8).is_err());
+        assert!(parse_cpu_list("", 8).is_err());
+        assert!(parse_cpu_list("0,,1", 8).is_err());
+        assert!(parse_cpu_list("a-b", 8).is_err());
+    }
+
+    #[test]
+    fn parse_inspected_files() {
+        assert_eq!(
+            parse_fd_listing("1\t/dev/null\n10\tsocket:[4242]\n2\tpipe:[77]\n"),
+            vec![
+                OpenFile {
+                    fd: 1,
+                    target: "/dev/null".to_string()
+                },
+                OpenFile {
+                    fd: 2,
+                    target: "pipe:[77]".to_string()
+                },
+                OpenFile {
+                    fd: 10,
+                    target: "socket:[4242]".to_string()
+                },
+            ]
+        );
+
+        let environment = parse_environ("HOME=/root\0EMPTY=\0EQUALS=a=b\0");
+        assert_eq!(environment.len(), 3);
+        assert_eq!(environment[1].name, "EMPTY");
+        assert_eq!(environment[1].value, "");
+        assert_eq!(environment[2].value, "a=b");
+
+        let cgroups = parse_cgroups("12:cpu,cpuacct:/\n0::/system.slice/nginx.service\n");
+        assert_eq!(cgroups[0].controllers, vec!["cpu", "cpuacct"]);
+        assert!(cgroups[1].controllers.is_empty());
+        assert_eq!(cgroups[1].path, "/system.slice/nginx.service");
+
+        let maps = parse_maps(
+            "55d0c8a00000-55d0c8a02000 r-xp 00001000 08:01 1234                       /usr/bin/my tool (deleted)
+7ffd1c1f0000-7ffd1c211000 rw-p 00000000 00:00 0                          [stack]
+7f0e5c000000-7f0e5c021000 rw-p 00000000 00:00 0 
+",
+        );
+        assert_eq!(maps.len(), 3);
+        assert_eq!(maps[0].size_bytes, 0x2000);
+        assert_eq!(maps[0].offset, 0x1000);
+        assert_eq!(maps[0].inode, 1234);
+        assert_eq!(maps[0].path.as_deref(), Some("/usr/bin/my tool (deleted)"));
+        assert_eq!(maps[1].path.as_deref(), Some("[stack]"));
+        assert_eq!(maps[2].path, None);
+    }
+
+    #[test]
+    fn build_process_tree() {
+        let process = |pid: u32, parent: Option<u32>| ProcessNode {
+            pid,
+            parent,
+            name: format!("p{pid}"),
+            username: None,
+            unit: None,
+            cpu_usage: 0.0,
+            memory_usage_bytes: 0,
+            children: Vec::new(),
+        };
+        let tree = build_tree(vec![
+            process(30, Some(10)),
+            process(2, None),
+            process(10, Some(1)),
+            process(1, None),
+            process(20, Some(10)),
+            process(40, Some(99)),
+            process(50, Some(51)),
+            process(51, Some(50)),
+        ]);
+
+        fn pids(nodes: &[ProcessNode]) -> Vec<u32> {
+            nodes.iter().map(|n| n.pid).collect()
+        }
+        // Processes with unknown parents are roots, cycles are appended
+        assert_eq!(pids(&tree), vec![1, 2, 40, 50, 51]);
+        assert_eq!(pids(&tree[0].children), vec![10]);
+        assert_eq!(pids(&tree[0].children[0].children), vec![20, 30]);
+        assert!(tree[1].children.is_empty());
+    }
+}
@@ -19,6 +19,27 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    #[allow(non_snake_case)]
+    TrustedNetworks (network) {
+        since -> BigInt,
+        network -> Text
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    ClientCertificates (serial) {
+        serial -> Text,
+        name -> Text,
+        username -> Text,
+        fingerprint -> Text,
+        issued_at -> BigInt,
+        expires_at -> BigInt,
+        revoked_at -> Nullable<BigInt>,
+    }
+}
+
 diesel::table! {
     #[allow(non_snake_case)]
     Configuration (id) {
@@ -27,6 +48,13 @@ diesel::table! {
         vault_enabled -> Bool,
         tls_cert -> Text,
         id -> Integer,
+        base_time_window -> BigInt,
+        base_request_limit -> BigInt,
+        large_time_window -> BigInt,
+        large_request_limit -> BigInt,
+        account_time_window -> BigInt,
+        account_request_limit -> BigInt,
+        tls_key -> Nullable<Text>,
     }
 }
 
@@ -96,6 +124,87 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    #[allow(non_snake_case)]
+    UpdatePolicy (id) {
+        enabled -> Bool,
+        kind -> Text,
+        days -> Text,
+        window_start -> Text,
+        window_duration -> Integer,
+        reboot -> Bool,
+        id -> Integer,
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    UpdateRuns (id) {
+        id -> Text,
+        started_at -> BigInt,
+        finished_at -> Nullable<BigInt>,
+        triggered_by -> Text,
+        kind -> Text,
+        status -> Text,
+        reboot_required -> Nullable<Bool>,
+        rebooted -> Bool,
+        output -> Text,
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    PackageJobs (id) {
+        id -> Text,
+        started_at -> BigInt,
+        finished_at -> Nullable<BigInt>,
+        username -> Nullable<Text>,
+        action -> Text,
+        package_manager -> Nullable<Text>,
+        package -> Nullable<Text>,
+        status -> Text,
+        output -> Text,
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    PackageIndex (package_manager) {
+        package_manager -> Text,
+        installed -> Text,
+        available -> Text,
+        updates -> Nullable<Text>,
+        foreign_packages -> Text,
+        refreshed_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    CronjobRuns (id) {
+        id -> Text,
+        job -> Text,
+        triggered_by -> Text,
+        started_at -> BigInt,
+        finished_at -> Nullable<BigInt>,
+        status -> Text,
+        exit_code -> Nullable<Integer>,
+        stdout -> Text,
+        stderr -> Text,
+    }
+}
+
+diesel::table! {
+    #[allow(non_snake_case)]
+    SnapshotSchedules (kind, volume) {
+        kind -> Text,
+        volume -> Text,
+        hourly -> Integer,
+        daily -> Integer,
+        weekly -> Integer,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     Users,
     Configuration,
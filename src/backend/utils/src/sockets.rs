@@ -0,0 +1,408 @@
+//! Sockets as listed by the kernel in `/proc/net/{tcp,tcp6,udp,udp6,unix}`.
+//!
+//! Sockets are identified by their inode, which is also the target of the file descriptors of
+//! the processes using them, e.g. `socket:[12345]`. This is used to find the processes using a
+//! socket.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::sudo::{SudoCommand, SudoCredential, SudoError};
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Tcp6,
+    Udp,
+    Udp6,
+    Unix,
+}
+
+impl Protocol {
+    /// The file in `/proc/net` listing the sockets of the protocol.
+    fn file_name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Tcp6 => "tcp6",
+            Protocol::Udp => "udp",
+            Protocol::Udp6 => "udp6",
+            Protocol::Unix => "unix",
+        }
+    }
+
+    pub const ALL: [Protocol; 5] = [
+        Protocol::Tcp,
+        Protocol::Tcp6,
+        Protocol::Udp,
+        Protocol::Udp6,
+        Protocol::Unix,
+    ];
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SocketState {
+    Established,
+    SynSent,
+    SynReceived,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Closed,
+    CloseWait,
+    LastAck,
+    /// Accepting connections
+    Listen,
+    Closing,
+    /// Bound UDP sockets and unconnected UNIX sockets
+    Unconnected,
+    Unknown,
+}
+
+impl SocketState {
+    /// TCP and UDP sockets share the states of TCP. UDP sockets that are not connected are in the
+    /// `TCP_CLOSE` state.
+    fn from_inet(code: u8, protocol: Protocol) -> SocketState {
+        match (code, protocol) {
+            (0x07, Protocol::Udp | Protocol::Udp6) => SocketState::Unconnected,
+            (0x01, _) => SocketState::Established,
+            (0x02, _) => SocketState::SynSent,
+            (0x03, _) => SocketState::SynReceived,
+            (0x04, _) => SocketState::FinWait1,
+            (0x05, _) => SocketState::FinWait2,
+            (0x06, _) => SocketState::TimeWait,
+            (0x07, _) => SocketState::Closed,
+            (0x08, _) => SocketState::CloseWait,
+            (0x09, _) => SocketState::LastAck,
+            (0x0A, _) => SocketState::Listen,
+            (0x0B, _) => SocketState::Closing,
+            (0x0C, _) => SocketState::SynReceived,
+            _ => SocketState::Unknown,
+        }
+    }
+}
+
+/// A TCP, UDP or UNIX socket
+#[derive(Serialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Socket {
+    pub protocol: Protocol,
+    pub state: SocketState,
+    /// Missing for UNIX sockets
+    #[schema(value_type = Option<String>)]
+    pub local_address: Option<IpAddr>,
+    pub local_port: Option<u16>,
+    /// Missing for UNIX sockets
+    #[schema(value_type = Option<String>)]
+    pub remote_address: Option<IpAddr>,
+    /// 0 if the socket is not connected
+    pub remote_port: Option<u16>,
+    /// Path of a UNIX socket, starting with `@` for abstract sockets
+    pub path: Option<String>,
+    /// Owner of TCP and UDP sockets
+    pub uid: Option<u32>,
+    pub inode: u64,
+}
+
+/// Parse an address like `0100007F:1F90`. The kernel prints the 32-bit words of the address in
+/// the byte order of the host.
+fn parse_inet_address(address: &str) -> Option<(IpAddr, u16)> {
+    let (ip, port) = address.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut bytes = Vec::new();
+    for i in (0..ip.len()).step_by(8) {
+        let word = u32::from_str_radix(ip.get(i..i + 8)?, 16).ok()?;
+        bytes.extend(word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?);
+            // IPv4 connections to sockets bound to `::`
+            match ip.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => IpAddr::V6(ip),
+            }
+        }
+        _ => return None,
+    };
+    Some((ip, port))
+}
+
+/// Parse `/proc/net/{tcp,tcp6,udp,udp6}`.
+pub fn parse_inet(contents: &str, protocol: Protocol) -> Vec<Socket> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (local_address, local_port) = parse_inet_address(fields.get(1)?)?;
+            let (remote_address, remote_port) = parse_inet_address(fields.get(2)?)?;
+            let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+            Some(Socket {
+                protocol,
+                state: SocketState::from_inet(state, protocol),
+                local_address: Some(local_address),
+                local_port: Some(local_port),
+                remote_address: Some(remote_address),
+                remote_port: Some(remote_port),
+                path: None,
+                uid: fields.get(7)?.parse().ok(),
+                inode: fields.get(9)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse `/proc/net/unix`. The path is missing for unnamed sockets.
+pub fn parse_unix(contents: &str) -> Vec<Socket> {
+    /// Set in the flags of listening sockets
+    const ACCEPTING_CONNECTIONS: u32 = 0x10000;
+
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // The inode is padded with spaces and the path may contain spaces
+            let mut fields = Vec::new();
+            let mut rest = line;
+            for _ in 0..7 {
+                rest = rest.trim_start();
+                let end = rest.find(' ').unwrap_or(rest.len());
+                fields.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+            fields.push(rest.trim_start());
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            let state = match u8::from_str_radix(fields.get(5)?, 16).ok()? {
+                _ if flags & ACCEPTING_CONNECTIONS != 0 => SocketState::Listen,
+                0x01 => SocketState::Unconnected,
+                0x02 => SocketState::SynSent,
+                0x03 => SocketState::Established,
+                0x04 => SocketState::Closing,
+                _ => SocketState::Unknown,
+            };
+            Some(Socket {
+                protocol: Protocol::Unix,
+                state,
+                local_address: None,
+                local_port: None,
+                remote_address: None,
+                remote_port: None,
+                path: Some(fields[7].trim_end())
+                    .filter(|p| !p.is_empty())
+                    .map(String::from),
+                uid: None,
+                inode: fields.get(6)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Read the sockets of all protocols from the `net` directory below `proc`, which is either
+/// `/proc` or `/proc/<pid>` for the network namespace of a process. Protocols that are not
+/// supported by the kernel are skipped.
+pub fn read_sockets(proc: &Path) -> Vec<Socket> {
+    let mut sockets = Vec::new();
+    for protocol in Protocol::ALL {
+        let Ok(contents) = fs::read_to_string(proc.join("net").join(protocol.file_name())) else {
+            continue;
+        };
+        match protocol {
+            Protocol::Unix => sockets.extend(parse_unix(&contents)),
+            _ => sockets.extend(parse_inet(&contents, protocol)),
+        }
+    }
+    sockets
+}
+
+/// The inode of the socket a file descriptor links to, like `socket:[12345]`.
+pub fn socket_inode(link: &str) -> Option<u64> {
+    link.strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// A process using a socket
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SocketOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// Parse the output of `find /proc -path '/proc/[0-9]*/fd/*' -printf '%h\t%l\n'`, like
+/// `/proc/123/fd\tsocket:[4567]`, into the PIDs using each socket.
+fn parse_socket_listing(listing: &str) -> HashMap<u64, Vec<u32>> {
+    let mut owners: HashMap<u64, Vec<u32>> = HashMap::new();
+    for line in listing.lines() {
+        let Some((directory, link)) = line.split_once('\t') else {
+            continue;
+        };
+        let (Some(pid), Some(inode)) = (
+            directory
+                .strip_prefix("/proc/")
+                .and_then(|d| d.strip_suffix("/fd"))
+                .and_then(|pid| pid.parse().ok()),
+            socket_inode(link),
+        ) else {
+            continue;
+        };
+        let pids = owners.entry(inode).or_default();
+        if !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    owners
+}
+
+/// Find the processes using each socket by its inode. Without a credential for sudo, only the
+/// file descriptors of processes of the user running Zentrox can be read.
+pub fn socket_owners(
+    credential: Option<SudoCredential>,
+) -> Result<HashMap<u64, Vec<SocketOwner>>, SudoError> {
+    let pids = match credential {
+        Some(credential) => {
+            // Processes may exit while they are listed, which makes find fail
+            let output = SudoCommand::with_credential(credential, "find")
+                .args(vec![
+                    "/proc",
+                    "-mindepth",
+                    "3",
+                    "-maxdepth",
+                    "3",
+                    "-path",
+                    "/proc/[0-9]*/fd/*",
+                    "-lname",
+                    "socket:*",
+                    "-printf",
+                    "%h\\t%l\\n",
+                ])
+                .output()?;
+            parse_socket_listing(&output.stdout)
+        }
+        None => {
+            let mut listing = String::new();
+            for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+                let name = entry.file_name();
+                let Some(pid) = name.to_str().filter(|n| n.parse::<u32>().is_ok()) else {
+                    continue;
+                };
+                for fd in fs::read_dir(entry.path().join("fd"))
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                {
+                    if let Ok(link) = fs::read_link(fd.path()) {
+                        listing.push_str(&format!("/proc/{pid}/fd\t{}\n", link.display()));
+                    }
+                }
+            }
+            parse_socket_listing(&listing)
+        }
+    };
+
+    let mut names: HashMap<u32, String> = HashMap::new();
+    Ok(pids
+        .into_iter()
+        .map(|(inode, pids)| {
+            let mut owners: Vec<SocketOwner> = pids
+                .into_iter()
+                .map(|pid| SocketOwner {
+                    pid,
+                    name: names
+                        .entry(pid)
+                        .or_insert_with(|| {
+                            fs::read_to_string(format!("/proc/{pid}/comm"))
+                                .map(|n| n.trim_end().to_string())
+                                .unwrap_or_default()
+                        })
+                        .clone(),
+                })
+                .collect();
+            owners.sort_by_key(|o| o.pid);
+            (inode, owners)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_net() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 31337 1 0000000000000000 100 0 0 10 0
+   1: 0F02000A:0016 0102000A:D431 01 00000000:00000000 02:00097B3A 00000000     0        0 4242 4 0000000000000000 20 4 29 10 -1
+";
+        let sockets = parse_inet(tcp, Protocol::Tcp);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[0].state, SocketState::Listen);
+        assert_eq!(
+            sockets[0].local_address,
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(sockets[0].local_port, Some(8080));
+        assert_eq!(sockets[0].uid, Some(1000));
+        assert_eq!(sockets[0].inode, 31337);
+        assert_eq!(sockets[1].state, SocketState::Established);
+        assert_eq!(
+            sockets[1].remote_address,
+            Some(IpAddr::V4(Ipv4Addr::new(10, 0, 2, 1)))
+        );
+        assert_eq!(sockets[1].remote_port, Some(54321));
+
+        let udp6 = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  10: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 999 2 0000000000000000 0
+  11: 0000000000000000FFFF00000100007F:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 1000 2 0000000000000000 0
+";
+        let sockets = parse_inet(udp6, Protocol::Udp6);
+        assert_eq!(sockets[0].state, SocketState::Unconnected);
+        assert_eq!(
+            sockets[0].local_address,
+            Some(IpAddr::V6(Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(sockets[0].local_port, Some(53));
+        assert_eq!(
+            sockets[1].local_address,
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+
+        let unix = "Num       RefCount Protocol Flags    Type St Inode Path
+0000000000000000: 00000002 00000000 00010000 0001 01 20768 /run/systemd/journal/stdout
+0000000000000000: 00000003 00000000 00000000 0001 03  2100
+0000000000000000: 00000002 00000000 00000000 0002 01 18000 @/tmp/.X11-unix/X0 with spaces
+";
+        let sockets = parse_unix(unix);
+        assert_eq!(sockets.len(), 3);
+        assert_eq!(sockets[0].state, SocketState::Listen);
+        assert_eq!(
+            sockets[0].path.as_deref(),
+            Some("/run/systemd/journal/stdout")
+        );
+        assert_eq!(sockets[1].state, SocketState::Established);
+        assert_eq!(sockets[1].path, None);
+        assert_eq!(sockets[1].inode, 2100);
+        assert_eq!(
+            sockets[2].path.as_deref(),
+            Some("@/tmp/.X11-unix/X0 with spaces")
+        );
+
+        assert_eq!(socket_inode("socket:[12345]"), Some(12345));
+        assert_eq!(socket_inode("/dev/null"), None);
+
+        let owners = parse_socket_listing(
+            "/proc/12/fd\tsocket:[100]\n/proc/13/fd\tsocket:[100]\n/proc/12/fd\tsocket:[100]\n/proc/14/fd\tsocket:[101]\n/proc/self/fd\tsocket:[102]\n",
+        );
+        assert_eq!(owners.len(), 2);
+        assert_eq!(owners[&100], vec![12, 13]);
+        assert_eq!(owners[&101], vec![14]);
+    }
+}
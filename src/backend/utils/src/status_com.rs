@@ -115,6 +115,10 @@ pub enum ErrorCode {
     /// While performing an action related to packages, the package manager encoutnered an error.
     /// It is likely, this was a permission based error or a wrong package name was provided.
     PackageManagerFailed,
+    /// The package is not known to the package manager
+    NoSuchPackage,
+    /// The requested package manager is not installed on the system
+    PackageManagerUnavailable,
     /// While processing a background task, an error without further specification was encountered
     /// and the task failed.
     TaskFailed,
@@ -156,6 +160,10 @@ pub enum ErrorCode {
     NoCronjobs,
     /// Creating a cronjob failed
     CronjobCreationFailed,
+    /// No cronjob has the requested ID
+    UnknownCronjob,
+    /// The schedule or command of a cronjob is invalid
+    InvalidCronjob(String),
     /// The server received a string that describes a variant of an enummeration agreed through the API,
     /// but the variant does not exist.
     NoSuchVariant,
@@ -177,4 +185,107 @@ pub enum ErrorCode {
     UfwError(String, String, Vec<String>),
     /// No such rule exists
     NoSuchRule,
+    /// The provided value is neither an IP address nor a network in CIDR notation
+    InvalidIpNetwork,
+    /// The network is not in the list of blocked networks
+    NoSuchBlockedNetwork,
+    /// The network is not in the list of trusted networks
+    NoSuchTrustedNetwork,
+    /// The network overlaps with a trusted network and can not be blocked
+    TrustedNetworkConflict,
+    /// Blocking the network would block the peer that sent the request
+    WouldBlockRequester,
+    /// The provided brute-force thresholds are not positive or not properly ordered
+    InvalidLoginLimits,
+    /// Certificates can not be obtained automatically as ACME is not configured
+    AcmeNotConfigured,
+    /// The certificate or key is malformed, expired or the two do not match
+    InvalidCertificate(String),
+    /// A certificate with the same name has already been uploaded
+    CertificateExists,
+    /// The certificate was stored, but could not be put into use
+    CertificateActivationFailed(String),
+    /// No client certificate with this serial number has been issued
+    NoSuchClientCertificate,
+    /// The connection did not present a valid client certificate that belongs to the account
+    ClientCertificateRequired,
+    /// Logging in with a client certificate is disabled
+    CertificateLoginDisabled,
+    /// Issuing a client certificate failed
+    ClientCertificateIssuingFailed(String),
+    /// The update policy is malformed
+    InvalidUpdatePolicy(String),
+    /// The system package manager can not tell security updates apart from other updates
+    SecurityUpdatesUnsupported,
+    /// Package updates can not run unattended, as the sudoers rule has not been installed
+    UnattendedUpdatesNotAuthorized,
+    /// Installing or removing the sudoers rule for unattended updates failed
+    UnattendedAuthorizationFailed(String),
+    /// Unattended updates are already being installed
+    UpdateRunInProgress,
+    /// The package transaction is not known to Zentrox or the package manager
+    UnknownTransaction,
+    /// The package manager can not revert the transaction
+    TransactionNotUndoable,
+    /// The package manager can not install older versions of packages
+    DowngradeUnsupported,
+    /// The package manager can not hold packages back
+    HoldsUnsupported,
+    /// The package name can not be passed to the package manager
+    InvalidPackageName,
+    /// Holding or releasing a package failed
+    PackageHoldFailed(String),
+    /// The package manager does not support managing repositories
+    RepositoriesUnsupported,
+    /// The repository is not configured
+    UnknownRepository,
+    /// The repository can not be added as provided
+    InvalidRepository(String),
+    /// Changing the repository configuration failed
+    RepositoryChangeFailed(String),
+    /// The systemd timer does not exist
+    UnknownTimer,
+    /// The systemd timer was not created using Zentrox and can not be changed
+    TimerNotManaged,
+    /// The systemd timer can not be created as provided
+    InvalidTimer(String),
+    /// Changing a systemd timer failed
+    TimerChangeFailed(String),
+    /// The signal, priority or CPU affinity can not be applied as provided
+    InvalidProcessChange(String),
+    /// Sending a signal or changing the priority or CPU affinity of a process failed
+    ProcessChangeFailed(String),
+    /// The device is not a disk listed by lsblk
+    NoSuchDisk,
+    /// smartctl can not read SMART data from the disk
+    SmartUnavailable(String),
+    /// Reading SMART data or starting a self-test failed
+    SmartFailed(String),
+    /// The mount, unmount or fstab entry is malformed or targets a system directory
+    InvalidMount(String),
+    /// Nothing is mounted on the mountpoint or device
+    NotMounted,
+    /// The fstab entry does not exist
+    NoSuchFstabEntry,
+    /// `mount --all --fake` rejected the changed fstab, which was not written
+    FstabCheckFailed(String),
+    /// Mounting, unmounting or writing the fstab failed
+    MountFailed(String),
+    /// The usage of the mounted filesystems could not be read
+    FilesystemUsageFailed(String),
+    /// No finished disk usage scan contains the requested directory
+    NotScanned,
+    /// The typed confirmation of a destructive operation is not the path of the device or the
+    /// name of the volume
+    ConfirmationMismatch,
+    /// The partition, filesystem or label is malformed or does not fit on the disk
+    InvalidPartitioning(String),
+    /// The disk is mounted, in use or holds the operating system
+    PartitioningRefused(String),
+    /// Partitioning, formatting or labeling failed
+    PartitioningFailed(String),
+    /// The volume, snapshot or snapshot schedule is malformed or can not be changed
+    InvalidStorageChange(String),
+    /// Listing or changing btrfs or ZFS storage failed
+    StorageFailed(String),
 }
@@ -0,0 +1,556 @@
+//! Btrfs filesystems, subvolumes, snapshots and scrubs using `btrfs`.
+//!
+//! Only mounted filesystems are known. Subvolume paths reported by btrfs are relative to the top
+//! level of the filesystem and are translated to paths using the `subvol=` option of the mounts.
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use log::warn;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::{Pool, ScrubState, ScrubStatus, Snapshot, StorageError, StorageKind, Volume, run};
+use crate::drives::mounts::{self, Mount, is_valid_path};
+use crate::drives::usage::{FilesystemUsage, statvfs};
+use crate::sudo::{SudoCommand, SudoCredential};
+
+/// Directory inside of a subvolume that holds its snapshots
+const SNAPSHOTS: &str = ".snapshots";
+
+fn btrfs_mounts() -> Result<Vec<Mount>, StorageError> {
+    Ok(mounts::list_mounts()
+        .map_err(|e| StorageError::Failed(e.to_string()))?
+        .into_iter()
+        .filter(|m| m.filesystem == "btrfs")
+        .collect())
+}
+
+/// Mounts grouped by the filesystem they belong to, shortest mountpoint first.
+fn filesystems(mounts: &[Mount]) -> BTreeMap<&str, Vec<&Mount>> {
+    let mut filesystems: BTreeMap<&str, Vec<&Mount>> = BTreeMap::new();
+    for mount in mounts {
+        filesystems.entry(&mount.source).or_default().push(mount);
+    }
+    for mounts in filesystems.values_mut() {
+        mounts.sort_by_key(|m| m.mountpoint.len());
+    }
+    filesystems
+}
+
+/// The subvolume shown by a mount relative to the top level, which is empty.
+fn mounted_subvolume(mount: &Mount) -> &str {
+    mount
+        .options
+        .iter()
+        .find_map(|o| o.strip_prefix("subvol="))
+        .unwrap_or("/")
+        .trim_matches('/')
+}
+
+/// Where a subvolume can be accessed through one of the mounts of its filesystem.
+fn accessible_path(subvolume: &str, mounts: &[&Mount]) -> Option<String> {
+    mounts
+        .iter()
+        .filter_map(|mount| {
+            let mounted = mounted_subvolume(mount);
+            let rest = if mounted.is_empty() {
+                subvolume
+            } else if subvolume == mounted {
+                ""
+            } else {
+                subvolume.strip_prefix(mounted)?.strip_prefix('/')?
+            };
+            let path = if rest.is_empty() {
+                PathBuf::from(&mount.mountpoint)
+            } else {
+                Path::new(&mount.mountpoint).join(rest)
+            };
+            Some((mounted.len(), path))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, path)| path.to_string_lossy().to_string())
+}
+
+pub(super) fn pools() -> Result<Vec<Pool>, StorageError> {
+    let mounts = btrfs_mounts()?;
+    Ok(filesystems(&mounts)
+        .into_iter()
+        .filter_map(|(source, mounts)| {
+            let mount = mounts.first()?;
+            let stats = statvfs(Path::new(&mount.mountpoint)).ok()?;
+            let usage = FilesystemUsage::new(source, &mount.mountpoint, "btrfs", stats);
+            Some(Pool {
+                kind: StorageKind::Btrfs,
+                name: mount.mountpoint.clone(),
+                devices: vec![source.to_string()],
+                size: usage.total,
+                used: usage.used,
+                available: usage.available,
+                health: None,
+            })
+        })
+        .collect())
+}
+
+/// Parse the paths of the output of `btrfs subvolume list`.
+fn parse_subvolumes(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .filter_map(|l| l.split_once(" path ").map(|(_, path)| path))
+        .collect()
+}
+
+fn is_snapshot_path(path: &str) -> bool {
+    path.split('/').any(|c| c == SNAPSHOTS)
+}
+
+pub(super) fn volumes(credential: &SudoCredential) -> Result<Vec<Volume>, StorageError> {
+    let mounts = btrfs_mounts()?;
+    let mut volumes = Vec::new();
+    for (_, mounts) in filesystems(&mounts) {
+        let pool = &mounts[0].mountpoint;
+        let output = run(credential, "btrfs", vec!["subvolume", "list", pool])?;
+        let subvolumes = std::iter::once("").chain(
+            parse_subvolumes(&output)
+                .into_iter()
+                .filter(|p| !is_snapshot_path(p)),
+        );
+        for subvolume in subvolumes {
+            let path = accessible_path(subvolume, &mounts);
+            // The top level is only of interest if it is mounted
+            if subvolume.is_empty() && path.is_none() {
+                continue;
+            }
+            volumes.push(Volume {
+                kind: StorageKind::Btrfs,
+                pool: pool.clone(),
+                name: format!("/{subvolume}"),
+                path,
+                used: None,
+                available: None,
+                referenced: None,
+            });
+        }
+    }
+    Ok(volumes)
+}
+
+fn check_volume(volume: &str) -> Result<(), StorageError> {
+    if is_valid_path(volume) {
+        Ok(())
+    } else {
+        Err(StorageError::Invalid(format!(
+            "{volume} is not an absolute path."
+        )))
+    }
+}
+
+fn snapshot_directory(volume: &str) -> String {
+    Path::new(volume)
+        .join(SNAPSHOTS)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn is_directory(path: &str, credential: &SudoCredential) -> Result<bool, StorageError> {
+    let output = SudoCommand::with_credential(credential.clone(), "test")
+        .args(vec!["-d", path])
+        .output()?;
+    Ok(output.status == Some(0))
+}
+
+/// Parse the output of `btrfs subvolume list -o -s` for a snapshot directory.
+fn parse_snapshots(output: &str, volume: &str) -> Vec<Snapshot> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (fields, path) = line.split_once(" path ")?;
+            let (directory, name) = path.rsplit_once('/')?;
+            if directory.rsplit('/').next() != Some(SNAPSHOTS) {
+                return None;
+            }
+            let (_, otime) = fields.split_once(" otime ")?;
+            let otime = NaiveDateTime::parse_from_str(otime.trim(), "%Y-%m-%d %H:%M:%S").ok()?;
+            Some(Snapshot {
+                volume: volume.to_string(),
+                name: name.to_string(),
+                created: Local.from_local_datetime(&otime).earliest()?.timestamp(),
+                used: None,
+            })
+        })
+        .collect()
+}
+
+fn list_snapshots_args(directory: &str) -> Vec<String> {
+    ["subvolume", "list", "-o", "-s", directory]
+        .map(String::from)
+        .to_vec()
+}
+
+fn create_directory_args(directory: &str) -> Vec<String> {
+    ["subvolume", "create", directory]
+        .map(String::from)
+        .to_vec()
+}
+
+fn create_snapshot_args(volume: &str, directory: &str, name: &str) -> Vec<String> {
+    vec![
+        "subvolume".to_string(),
+        "snapshot".to_string(),
+        "-r".to_string(),
+        volume.to_string(),
+        format!("{directory}/{name}"),
+    ]
+}
+
+fn delete_snapshot_args(directory: &str, name: &str) -> Vec<String> {
+    vec![
+        "subvolume".to_string(),
+        "delete".to_string(),
+        format!("{directory}/{name}"),
+    ]
+}
+
+pub(super) fn snapshots(
+    volume: &str,
+    credential: &SudoCredential,
+) -> Result<Vec<Snapshot>, StorageError> {
+    check_volume(volume)?;
+    let directory = snapshot_directory(volume);
+    if !is_directory(&directory, credential)? {
+        return Ok(Vec::new());
+    }
+    let output = run(credential, "btrfs", list_snapshots_args(&directory))?;
+    Ok(parse_snapshots(&output, volume))
+}
+
+pub(super) fn create_snapshot(
+    volume: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_volume(volume)?;
+    let directory = snapshot_directory(volume);
+    if !is_directory(&directory, credential)? {
+        // A subvolume, so the snapshots are not part of the snapshots of the volume
+        run(credential, "btrfs", create_directory_args(&directory))?;
+    }
+    run(
+        credential,
+        "btrfs",
+        create_snapshot_args(volume, &directory, name),
+    )?;
+    Ok(())
+}
+
+pub(super) fn delete_snapshot(
+    volume: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_volume(volume)?;
+    let directory = snapshot_directory(volume);
+    run(credential, "btrfs", delete_snapshot_args(&directory, name))?;
+    Ok(())
+}
+
+/// The command lines run by [snapshots], [create_snapshot] and [delete_snapshot] for snapshots
+/// named like one of `names`, including the checks for the snapshot directory.
+pub(super) fn snapshot_commands(
+    volume: &str,
+    names: &[String],
+) -> Result<Vec<Vec<String>>, StorageError> {
+    check_volume(volume)?;
+    let directory = snapshot_directory(volume);
+    let btrfs = |args: Vec<String>| std::iter::once("btrfs".to_string()).chain(args).collect();
+    let mut commands = vec![
+        ["test", "-d", &directory].map(String::from).to_vec(),
+        btrfs(list_snapshots_args(&directory)),
+        btrfs(create_directory_args(&directory)),
+    ];
+    for name in names {
+        commands.push(btrfs(create_snapshot_args(volume, &directory, name)));
+        commands.push(btrfs(delete_snapshot_args(&directory, name)));
+    }
+    Ok(commands)
+}
+
+/// Btrfs can not roll back a subvolume in place. Instead, a writable snapshot of the snapshot
+/// replaces the subvolume, the snapshots are moved over and the replaced subvolume is deleted.
+pub(super) fn rollback(
+    volume: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_volume(volume)?;
+    let volume = volume.trim_end_matches('/');
+    if btrfs_mounts()?.iter().any(|m| m.mountpoint == volume) {
+        return Err(StorageError::Invalid(format!(
+            "{volume} is mounted and can not be replaced. Unmount it and roll it back from the subvolume containing it."
+        )));
+    }
+    let (parent, base) = volume
+        .rsplit_once('/')
+        .ok_or_else(|| StorageError::Invalid(format!("{volume} is not a subvolume.")))?;
+    let snapshot = format!("{}/{name}", snapshot_directory(volume));
+    let restored = format!("{parent}/.{base}.zentrox-rollback");
+    let replaced = format!("{parent}/.{base}.zentrox-replaced");
+
+    run(
+        credential,
+        "btrfs",
+        vec!["subvolume", "snapshot", &snapshot, &restored],
+    )?;
+    if let Err(e) = run(credential, "mv", vec!["-T", volume, &replaced]) {
+        let _ = run(credential, "btrfs", vec!["subvolume", "delete", &restored]);
+        return Err(e);
+    }
+    if let Err(e) = run(credential, "mv", vec!["-T", &restored, volume]) {
+        let _ = run(credential, "mv", vec!["-T", &replaced, volume]);
+        return Err(e);
+    }
+
+    // The snapshot contains an empty directory in place of the nested snapshots subvolume
+    let snapshots = snapshot_directory(volume);
+    let replaced_snapshots = snapshot_directory(&replaced);
+    run(credential, "rmdir", vec![&snapshots])?;
+    run(
+        credential,
+        "mv",
+        vec!["-T", &replaced_snapshots, &snapshots],
+    )?;
+
+    if let Err(e) = run(credential, "btrfs", vec!["subvolume", "delete", &replaced]) {
+        // Nested subvolumes created by the user keep it from being deleted
+        warn!("The replaced subvolume {replaced} could not be deleted: {e}");
+    }
+    Ok(())
+}
+
+pub(super) fn start_scrub(pool: &str, credential: &SudoCredential) -> Result<(), StorageError> {
+    check_volume(pool)?;
+    run(credential, "btrfs", vec!["scrub", "start", pool])?;
+    Ok(())
+}
+
+/// Parse the output of `btrfs scrub status`.
+fn parse_scrub(output: &str) -> ScrubStatus {
+    let field = |key: &str| {
+        output.lines().find_map(|l| {
+            let (k, v) = l.split_once(':')?;
+            (k.trim() == key).then(|| v.trim())
+        })
+    };
+
+    let state = match field("Status") {
+        _ if output.contains("no stats available") => ScrubState::Never,
+        Some("running") => ScrubState::Running,
+        Some("finished") => ScrubState::Finished,
+        Some("aborted" | "interrupted" | "canceled") => ScrubState::Canceled,
+        _ => ScrubState::Never,
+    };
+    let progress = field("Bytes scrubbed")
+        .filter(|_| state == ScrubState::Running)
+        .and_then(|v| v.split_once('(')?.1.split_once('%')?.0.parse().ok());
+    let errors = field("Error summary").map(|v| {
+        v.split_whitespace()
+            .filter_map(|e| e.split_once('=')?.1.parse::<u64>().ok())
+            .sum()
+    });
+
+    ScrubStatus {
+        state,
+        progress,
+        errors,
+        summary: output.trim().to_string(),
+    }
+}
+
+pub(super) fn scrub_status(
+    pool: &str,
+    credential: &SudoCredential,
+) -> Result<ScrubStatus, StorageError> {
+    check_volume(pool)?;
+    let output = run(credential, "btrfs", vec!["scrub", "status", pool])?;
+    Ok(parse_scrub(&output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(source: &str, mountpoint: &str, subvolume: &str) -> Mount {
+        Mount {
+            source: source.to_string(),
+            mountpoint: mountpoint.to_string(),
+            filesystem: "btrfs".to_string(),
+            options: vec![
+                "rw".to_string(),
+                "compress=zstd:3".to_string(),
+                format!("subvol={subvolume}"),
+            ],
+        }
+    }
+
+    #[test]
+    fn map_subvolumes() {
+        let mounts = vec![
+            mount("/dev/sda2", "/home", "/@home"),
+            mount("/dev/sda2", "/", "/@"),
+            mount("/dev/sda2", "/srv/data", "/@data"),
+            mount("/dev/sdb", "/mnt/backup", "/"),
+        ];
+        let filesystems = filesystems(&mounts);
+        assert_eq!(filesystems.len(), 2);
+        let system = &filesystems["/dev/sda2"];
+        assert_eq!(system[0].mountpoint, "/");
+
+        let subvolumes = parse_subvolumes(include_str!(
+            "../../tests/fixtures/storage/btrfs-subvolumes.txt"
+        ));
+        assert_eq!(subvolumes.len(), 8);
+        assert_eq!(subvolumes[7], "@old root");
+        let paths: Vec<(&str, Option<String>)> = subvolumes
+            .into_iter()
+            .filter(|p| !is_snapshot_path(p))
+            .map(|p| (p, accessible_path(p, system)))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("@", Some("/".to_string())),
+                ("@home", Some("/home".to_string())),
+                ("@data", Some("/srv/data".to_string())),
+                ("@data/media", Some("/srv/data/media".to_string())),
+                ("@/var/lib/machines", Some("/var/lib/machines".to_string())),
+                ("@old root", None),
+            ]
+        );
+        // The top level is only accessible on the second filesystem
+        assert_eq!(accessible_path("", system), None);
+        assert_eq!(
+            accessible_path("", &filesystems["/dev/sdb"]),
+            Some("/mnt/backup".to_string())
+        );
+        assert_eq!(
+            accessible_path("@daily/2026", &filesystems["/dev/sdb"]),
+            Some("/mnt/backup/@daily/2026".to_string())
+        );
+
+        assert_eq!(snapshot_directory("/srv/data"), "/srv/data/.snapshots");
+        assert_eq!(snapshot_directory("/"), "/.snapshots");
+    }
+
+    #[test]
+    fn parse_btrfs_snapshots() {
+        let snapshots = parse_snapshots(
+            include_str!("../../tests/fixtures/storage/btrfs-snapshots.txt"),
+            "/srv/data",
+        );
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].name, "before-upgrade");
+        assert_eq!(snapshots[0].volume, "/srv/data");
+        assert_eq!(
+            snapshots[0].created,
+            Local
+                .with_ymd_and_hms(2026, 10, 18, 9, 30, 0)
+                .unwrap()
+                .timestamp()
+        );
+        assert_eq!(snapshots[1].name, "zentrox-hourly-20261019T090000Z");
+
+        // Snapshots outside of the snapshot directory are left out
+        let other = "ID 290 gen 1 cgen 1 top level 258 otime 2026-10-19 11:00:02 path @data/backup";
+        assert!(parse_snapshots(other, "/srv/data").is_empty());
+    }
+
+    #[test]
+    fn parse_btrfs_scrub() {
+        let finished = parse_scrub(include_str!(
+            "../../tests/fixtures/storage/btrfs-scrub-finished.txt"
+        ));
+        assert_eq!(finished.state, ScrubState::Finished);
+        assert_eq!(finished.progress, None);
+        assert_eq!(finished.errors, Some(0));
+
+        let running = parse_scrub(include_str!(
+            "../../tests/fixtures/storage/btrfs-scrub-running.txt"
+        ));
+        assert_eq!(running.state, ScrubState::Running);
+        assert_eq!(running.progress, Some(25.0));
+        assert_eq!(running.errors, Some(3));
+
+        let never = parse_scrub("UUID:             3c1a6d6e\n\tno stats available\n");
+        assert_eq!(never.state, ScrubState::Never);
+        assert_eq!(never.errors, None);
+    }
+
+    #[test]
+    #[ignore = "needs root, btrfs-progs and a free loop device"]
+    fn manage_loop_filesystem() {
+        use std::process::Command;
+
+        let image = std::env::temp_dir().join("zentrox_btrfs.img");
+        let mountpoint = std::env::temp_dir().join("zentrox_btrfs");
+        std::fs::File::create(&image)
+            .unwrap()
+            .set_len(256 * 1024 * 1024)
+            .unwrap();
+        std::fs::create_dir_all(&mountpoint).unwrap();
+        assert!(
+            Command::new("mkfs.btrfs")
+                .arg(&image)
+                .status()
+                .unwrap()
+                .success()
+        );
+        assert!(
+            Command::new("mount")
+                .args(["-o", "loop"])
+                .arg(&image)
+                .arg(&mountpoint)
+                .status()
+                .unwrap()
+                .success()
+        );
+        let credential = SudoCredential::Passwordless;
+        let pool = mountpoint.to_string_lossy().to_string();
+        let volume = format!("{pool}/data");
+        run(&credential, "btrfs", vec!["subvolume", "create", &volume]).unwrap();
+        std::fs::write(format!("{volume}/file"), "before").unwrap();
+
+        assert!(pools().unwrap().iter().any(|p| p.name == pool));
+        assert!(
+            volumes(&credential)
+                .unwrap()
+                .iter()
+                .any(|v| v.path.as_deref() == Some(volume.as_str()))
+        );
+
+        create_snapshot(&volume, "before", &credential).unwrap();
+        std::fs::write(format!("{volume}/file"), "after").unwrap();
+        create_snapshot(&volume, "after", &credential).unwrap();
+        let names: Vec<String> = snapshots(&volume, &credential)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["before", "after"]);
+
+        rollback(&volume, "before", &credential).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(format!("{volume}/file")).unwrap(),
+            "before"
+        );
+        assert_eq!(snapshots(&volume, &credential).unwrap().len(), 2);
+        delete_snapshot(&volume, "after", &credential).unwrap();
+
+        start_scrub(&pool, &credential).unwrap();
+        assert_ne!(
+            scrub_status(&pool, &credential).unwrap().state,
+            ScrubState::Never
+        );
+
+        Command::new("umount").arg(&mountpoint).status().unwrap();
+        std::fs::remove_file(&image).unwrap();
+    }
+}
@@ -0,0 +1,627 @@
+//! Btrfs and ZFS pools, filesystems, snapshots and scrubs.
+//!
+//! Volumes are btrfs subvolumes and ZFS datasets. They are identified by their path for btrfs and
+//! by their name, like `tank/media`, for ZFS. The snapshots of a btrfs subvolume are read-only
+//! subvolumes in its `.snapshots` directory, like snapper keeps them. Every command runs using
+//! sudo.
+//!
+//! Snapshots created by a [SnapshotSchedule] are named after their interval and creation time, so
+//! [plan] can tell them apart from snapshots made by hand, which are never deleted automatically.
+//! Schedules run without a password, which a sudoers rule at [SNAPSHOT_SUDOERS_RULE_PATH] allows
+//! for the commands of the scheduled volumes only.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fmt::Display;
+use std::str::FromStr;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::models::SnapshotScheduleEntry;
+use crate::sudo::{SudoCredential, SudoError, run_checked};
+use crate::sudoers::{self, PrivilegedCommand, SudoersError};
+
+mod btrfs;
+mod zfs;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for StorageError {
+    fn from(error: SudoError) -> Self {
+        StorageError::Sudo(error)
+    }
+}
+
+impl From<SudoersError> for StorageError {
+    fn from(error: SudoersError) -> Self {
+        match error {
+            SudoersError::Sudo(error) => StorageError::Sudo(error),
+            error => StorageError::Failed(error.to_string()),
+        }
+    }
+}
+
+/// Run a program using sudo and fail with its error output if it does not succeed.
+fn run<T: Display>(
+    credential: &SudoCredential,
+    program: &str,
+    args: Vec<T>,
+) -> Result<String, StorageError> {
+    run_checked(credential, program, args, StorageError::Failed)
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    Btrfs,
+    Zfs,
+}
+
+impl Display for StorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageKind::Btrfs => f.write_str("btrfs"),
+            StorageKind::Zfs => f.write_str("zfs"),
+        }
+    }
+}
+
+impl FromStr for StorageKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "btrfs" => Ok(StorageKind::Btrfs),
+            "zfs" => Ok(StorageKind::Zfs),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A btrfs filesystem or a ZFS pool
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Pool {
+    pub kind: StorageKind,
+    /// The mountpoint of a btrfs filesystem or the name of a ZFS pool
+    pub name: String,
+    /// The devices of a btrfs filesystem, empty for ZFS
+    pub devices: Vec<String>,
+    /// Sizes in bytes
+    pub size: u64,
+    pub used: u64,
+    pub available: u64,
+    /// Like `ONLINE` or `DEGRADED`, missing for btrfs
+    pub health: Option<String>,
+}
+
+/// A btrfs subvolume or a ZFS filesystem or volume
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Volume {
+    pub kind: StorageKind,
+    /// Name of the [Pool]
+    pub pool: String,
+    /// Path of a btrfs subvolume relative to the top level of the filesystem, or name of a ZFS
+    /// dataset
+    pub name: String,
+    /// Where the volume can be accessed. Btrfs subvolumes are identified by this path, they can
+    /// not be snapshotted if it is missing.
+    pub path: Option<String>,
+    /// Sizes in bytes, missing for btrfs, which only tracks them with quotas enabled
+    pub used: Option<u64>,
+    pub available: Option<u64>,
+    /// Bytes of data in the volume itself, without snapshots and children
+    pub referenced: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    pub volume: String,
+    pub name: String,
+    /// Seconds since the UNIX epoch
+    pub created: i64,
+    /// Bytes only referenced by the snapshot, missing for btrfs
+    pub used: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrubState {
+    /// The pool has never been scrubbed
+    Never,
+    Running,
+    Finished,
+    Canceled,
+}
+
+#[derive(Serialize, ToSchema, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrubStatus {
+    pub state: ScrubState,
+    /// Percentage of a running scrub
+    pub progress: Option<f64>,
+    /// Errors found by the last scrub
+    pub errors: Option<u64>,
+    /// The status as reported by `btrfs scrub status` or `zpool status`
+    pub summary: String,
+}
+
+/// Names of snapshots, like `before-upgrade`.
+pub fn is_valid_snapshot_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 200
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':'))
+}
+
+fn check_snapshot_name(name: &str) -> Result<(), StorageError> {
+    if is_valid_snapshot_name(name) {
+        Ok(())
+    } else {
+        Err(StorageError::Invalid(
+            "Snapshot names may only contain letters, digits, ., _, - or : and may not start with . or -."
+                .to_string(),
+        ))
+    }
+}
+
+/// Btrfs filesystems and ZFS pools on the system.
+///
+/// ZFS is left out if its kernel module is not loaded.
+pub fn pools(credential: &SudoCredential) -> Result<Vec<Pool>, StorageError> {
+    let mut pools = btrfs::pools()?;
+    if zfs::is_loaded() {
+        pools.extend(zfs::pools(credential)?);
+    }
+    Ok(pools)
+}
+
+/// Btrfs subvolumes and ZFS datasets on the system.
+pub fn volumes(credential: &SudoCredential) -> Result<Vec<Volume>, StorageError> {
+    let mut volumes = btrfs::volumes(credential)?;
+    if zfs::is_loaded() {
+        volumes.extend(zfs::volumes(credential)?);
+    }
+    Ok(volumes)
+}
+
+/// Snapshots of a volume, oldest first.
+pub fn snapshots(
+    kind: StorageKind,
+    volume: &str,
+    credential: &SudoCredential,
+) -> Result<Vec<Snapshot>, StorageError> {
+    let mut snapshots = match kind {
+        StorageKind::Btrfs => btrfs::snapshots(volume, credential),
+        StorageKind::Zfs => zfs::snapshots(volume, credential),
+    }?;
+    snapshots.sort_by_key(|s| s.created);
+    Ok(snapshots)
+}
+
+pub fn create_snapshot(
+    kind: StorageKind,
+    volume: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_snapshot_name(name)?;
+    match kind {
+        StorageKind::Btrfs => btrfs::create_snapshot(volume, name, credential),
+        StorageKind::Zfs => zfs::create_snapshot(volume, name, credential),
+    }
+}
+
+pub fn delete_snapshot(
+    kind: StorageKind,
+    volume: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_snapshot_name(name)?;
+    match kind {
+        StorageKind::Btrfs => btrfs::delete_snapshot(volume, name, credential),
+        StorageKind::Zfs => zfs::delete_snapshot(volume, name, credential),
+    }
+}
+
+/// Return a volume to the state of a snapshot, losing all changes made since.
+///
+/// ZFS can only roll back to the latest snapshot, unless `discard_newer` is set, which destroys
+/// all newer snapshots. The snapshots of btrfs subvolumes are independent of each other, so
+/// `discard_newer` has no effect on them.
+pub fn rollback(
+    kind: StorageKind,
+    volume: &str,
+    name: &str,
+    discard_newer: bool,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_snapshot_name(name)?;
+    match kind {
+        StorageKind::Btrfs => btrfs::rollback(volume, name, credential),
+        StorageKind::Zfs => zfs::rollback(volume, name, discard_newer, credential),
+    }
+}
+
+/// Start verifying the checksums of all data in a pool. The scrub runs in the background.
+pub fn start_scrub(
+    kind: StorageKind,
+    pool: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    match kind {
+        StorageKind::Btrfs => btrfs::start_scrub(pool, credential),
+        StorageKind::Zfs => zfs::start_scrub(pool, credential),
+    }
+}
+
+/// Progress or result of the latest scrub of a pool.
+pub fn scrub_status(
+    kind: StorageKind,
+    pool: &str,
+    credential: &SudoCredential,
+) -> Result<ScrubStatus, StorageError> {
+    match kind {
+        StorageKind::Btrfs => btrfs::scrub_status(pool, credential),
+        StorageKind::Zfs => zfs::scrub_status(pool, credential),
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+impl Interval {
+    const ALL: [Interval; 3] = [Interval::Hourly, Interval::Daily, Interval::Weekly];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Interval::Hourly => "hourly",
+            Interval::Daily => "daily",
+            Interval::Weekly => "weekly",
+        }
+    }
+
+    /// Snapshots of this interval taken at `a` and `b` belong to the same hour, day or week.
+    fn same_period<Tz: TimeZone>(&self, a: &DateTime<Tz>, b: &DateTime<Tz>) -> bool {
+        match self {
+            Interval::Hourly => a.date_naive() == b.date_naive() && a.hour() == b.hour(),
+            Interval::Daily => a.date_naive() == b.date_naive(),
+            Interval::Weekly => a.iso_week() == b.iso_week(),
+        }
+    }
+}
+
+/// Prefix of the names of scheduled snapshots
+const SCHEDULED_PREFIX: &str = "zentrox-";
+const SCHEDULED_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Name of a scheduled snapshot, like `zentrox-daily-20261019T000000Z`.
+fn scheduled_name(interval: Interval, time: &DateTime<Utc>) -> String {
+    format!(
+        "{SCHEDULED_PREFIX}{}-{}",
+        interval.name(),
+        time.format(SCHEDULED_TIME_FORMAT)
+    )
+}
+
+/// Pattern matching the names of scheduled snapshots of an interval in a sudoers rule.
+fn scheduled_name_pattern(interval: Interval) -> String {
+    let digits = |count| "[0-9]".repeat(count);
+    format!(
+        "{SCHEDULED_PREFIX}{}-{}T{}Z",
+        interval.name(),
+        digits(8),
+        digits(6)
+    )
+}
+
+fn parse_scheduled_name(name: &str) -> Option<(Interval, DateTime<Utc>)> {
+    let (interval, time) = name.strip_prefix(SCHEDULED_PREFIX)?.split_once('-')?;
+    let interval = Interval::ALL.into_iter().find(|i| i.name() == interval)?;
+    let time = NaiveDateTime::parse_from_str(time, SCHEDULED_TIME_FORMAT).ok()?;
+    Some((interval, time.and_utc()))
+}
+
+/// How many scheduled snapshots of a volume are kept. No snapshots are taken for an interval
+/// that keeps none.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Retention {
+    pub hourly: u32,
+    pub daily: u32,
+    pub weekly: u32,
+}
+
+impl Retention {
+    fn keep(&self, interval: Interval) -> usize {
+        let keep = match interval {
+            Interval::Hourly => self.hourly,
+            Interval::Daily => self.daily,
+            Interval::Weekly => self.weekly,
+        };
+        keep as usize
+    }
+}
+
+/// Snapshots of a volume that are taken and deleted automatically
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSchedule {
+    pub kind: StorageKind,
+    /// The path of a btrfs subvolume or the name of a ZFS dataset
+    pub volume: String,
+    #[serde(flatten)]
+    pub retention: Retention,
+}
+
+impl From<&SnapshotSchedule> for SnapshotScheduleEntry {
+    fn from(schedule: &SnapshotSchedule) -> Self {
+        SnapshotScheduleEntry {
+            kind: schedule.kind.to_string(),
+            volume: schedule.volume.clone(),
+            hourly: schedule.retention.hourly as i32,
+            daily: schedule.retention.daily as i32,
+            weekly: schedule.retention.weekly as i32,
+        }
+    }
+}
+
+impl TryFrom<SnapshotScheduleEntry> for SnapshotSchedule {
+    type Error = ();
+
+    fn try_from(entry: SnapshotScheduleEntry) -> Result<Self, Self::Error> {
+        Ok(SnapshotSchedule {
+            kind: entry.kind.parse()?,
+            volume: entry.volume,
+            retention: Retention {
+                hourly: entry.hourly.max(0) as u32,
+                daily: entry.daily.max(0) as u32,
+                weekly: entry.weekly.max(0) as u32,
+            },
+        })
+    }
+}
+
+/// Location of the sudoers rule that allows snapshot schedules to run.
+pub const SNAPSHOT_SUDOERS_RULE_PATH: &str = "/etc/sudoers.d/zentrox-snapshots";
+
+/// The commands a schedule runs to list, take and delete the snapshots of its volume.
+fn scheduled_commands(schedule: &SnapshotSchedule) -> Result<Vec<Vec<String>>, StorageError> {
+    let names = Interval::ALL
+        .into_iter()
+        .map(scheduled_name_pattern)
+        .collect::<Vec<String>>();
+    match schedule.kind {
+        StorageKind::Btrfs => btrfs::snapshot_commands(&schedule.volume, &names),
+        StorageKind::Zfs => zfs::snapshot_commands(&schedule.volume, &names),
+    }
+}
+
+/// Install the sudoers rule that allows exactly the commands of `schedules` to run without a
+/// password, replacing the rule for earlier schedules. The rule is removed if there are no
+/// schedules.
+///
+/// * `password` - Sudo password of the user
+pub fn authorize_schedules(
+    schedules: &[SnapshotSchedule],
+    password: &str,
+) -> Result<(), StorageError> {
+    if schedules.is_empty() {
+        sudoers::remove(SNAPSHOT_SUDOERS_RULE_PATH, password)?;
+        return Ok(());
+    }
+
+    let mut commands = Vec::new();
+    for schedule in schedules {
+        for command in scheduled_commands(schedule)? {
+            commands.push(PrivilegedCommand::resolve(&command).ok_or_else(|| {
+                StorageError::Invalid(format!(
+                    "{} can not be run without a password for {}.",
+                    command[0], schedule.volume
+                ))
+            })?);
+        }
+    }
+    sudoers::install(
+        SNAPSHOT_SUDOERS_RULE_PATH,
+        "take scheduled snapshots",
+        &commands,
+        password,
+    )?;
+    Ok(())
+}
+
+/// Snapshots to take and to delete to follow a [Retention]
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct SnapshotPlan {
+    pub create: Vec<String>,
+    pub delete: Vec<String>,
+}
+
+/// Decide which scheduled snapshots are due at `now` and which ones are no longer kept, given the
+/// names of the existing snapshots of a volume.
+///
+/// A snapshot is due if there is none of its interval in the current hour, day or ISO week in the
+/// time zone of `now`.
+pub fn plan<Tz: TimeZone>(
+    retention: &Retention,
+    existing: &[String],
+    now: &DateTime<Tz>,
+) -> SnapshotPlan {
+    let mut plan = SnapshotPlan::default();
+
+    for interval in Interval::ALL {
+        let mut taken: Vec<(DateTime<Utc>, &String)> = existing
+            .iter()
+            .filter_map(|name| match parse_scheduled_name(name)? {
+                (i, time) if i == interval => Some((time, name)),
+                _ => None,
+            })
+            .collect();
+        // Newest first
+        taken.sort_by_key(|(time, _)| Reverse(*time));
+
+        let keep = retention.keep(interval);
+        let due = keep > 0
+            && !taken.first().is_some_and(|(time, _)| {
+                interval.same_period(&time.with_timezone(&now.timezone()), now)
+            });
+        if due {
+            plan.create
+                .push(scheduled_name(interval, &now.with_timezone(&Utc)));
+        }
+
+        let kept_existing = if due { keep - 1 } else { keep };
+        plan.delete.extend(
+            taken
+                .into_iter()
+                .skip(kept_existing)
+                .map(|(_, n)| n.clone()),
+        );
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn plan_snapshots() {
+        let berlin = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = berlin.with_ymd_and_hms(2026, 10, 19, 12, 5, 0).unwrap();
+        let retention = Retention {
+            hourly: 2,
+            daily: 1,
+            weekly: 0,
+        };
+
+        let existing = names(&[
+            "before-upgrade",
+            "zentrox-hourly-20261019T090000Z",
+            "zentrox-hourly-20261019T080000Z",
+            "zentrox-hourly-20261019T070000Z",
+            // 00:00 in Berlin, so it belongs to today
+            "zentrox-daily-20261018T220000Z",
+            "zentrox-weekly-20261012T000000Z",
+            "zentrox-monthly-20261001T000000Z",
+        ]);
+        assert_eq!(
+            plan(&retention, &existing, &now),
+            SnapshotPlan {
+                create: names(&["zentrox-hourly-20261019T100500Z"]),
+                delete: names(&[
+                    "zentrox-hourly-20261019T080000Z",
+                    "zentrox-hourly-20261019T070000Z",
+                    "zentrox-weekly-20261012T000000Z",
+                ]),
+            }
+        );
+
+        // An hourly snapshot was already taken in this hour
+        let existing = names(&[
+            "zentrox-hourly-20261019T100000Z",
+            "zentrox-hourly-20261019T090000Z",
+            "zentrox-daily-20261018T100000Z",
+        ]);
+        assert_eq!(
+            plan(&retention, &existing, &now),
+            SnapshotPlan {
+                create: names(&["zentrox-daily-20261019T100500Z"]),
+                delete: names(&["zentrox-daily-20261018T100000Z"]),
+            }
+        );
+
+        assert_eq!(
+            plan(&Retention::default(), &names(&["manual"]), &now),
+            SnapshotPlan::default()
+        );
+
+        assert_eq!(
+            parse_scheduled_name("zentrox-weekly-20261012T000000Z"),
+            Some((
+                Interval::Weekly,
+                Utc.with_ymd_and_hms(2026, 10, 12, 0, 0, 0).unwrap()
+            ))
+        );
+        assert_eq!(parse_scheduled_name("zentrox-hourly-yesterday"), None);
+    }
+
+    #[test]
+    fn match_scheduled_names() {
+        let time = Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        for interval in Interval::ALL {
+            let name = scheduled_name(interval, &time);
+            let digits = name.replace(|c: char| c.is_ascii_digit(), "[0-9]");
+            assert_eq!(scheduled_name_pattern(interval), digits);
+        }
+
+        let schedule = |kind, volume: &str| SnapshotSchedule {
+            kind,
+            volume: volume.to_string(),
+            retention: Retention::default(),
+        };
+        let commands = scheduled_commands(&schedule(StorageKind::Zfs, "tank/media")).unwrap();
+        assert_eq!(commands.len(), 1 + 2 * Interval::ALL.len());
+        assert_eq!(
+            commands[1],
+            [
+                "zfs",
+                "snapshot",
+                "tank/media@zentrox-hourly-[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]T[0-9][0-9][0-9][0-9][0-9][0-9]Z"
+            ]
+        );
+        let commands = scheduled_commands(&schedule(StorageKind::Btrfs, "/home")).unwrap();
+        assert_eq!(commands[0], ["test", "-d", "/home/.snapshots"]);
+        assert_eq!(
+            commands[4],
+            [
+                "btrfs",
+                "subvolume",
+                "delete",
+                "/home/.snapshots/zentrox-hourly-[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]T[0-9][0-9][0-9][0-9][0-9][0-9]Z"
+            ]
+        );
+        assert!(scheduled_commands(&schedule(StorageKind::Zfs, "-o")).is_err());
+        assert!(scheduled_commands(&schedule(StorageKind::Btrfs, "home")).is_err());
+    }
+
+    #[test]
+    fn validate_snapshot_names() {
+        assert!(is_valid_snapshot_name("before-upgrade"));
+        assert!(is_valid_snapshot_name("zentrox-daily-20261019T000000Z"));
+        assert!(is_valid_snapshot_name("2026-10-19_12:00"));
+        assert!(!is_valid_snapshot_name(""));
+        assert!(!is_valid_snapshot_name(".snapshots"));
+        assert!(!is_valid_snapshot_name("-r"));
+        assert!(!is_valid_snapshot_name("../etc"));
+        assert!(!is_valid_snapshot_name("tank@snapshot"));
+        assert!(!is_valid_snapshot_name("with space"));
+    }
+}
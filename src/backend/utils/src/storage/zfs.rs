@@ -0,0 +1,409 @@
+//! ZFS pools, datasets, snapshots and scrubs using `zpool` and `zfs`.
+
+use std::path::Path;
+
+use super::{Pool, ScrubState, ScrubStatus, Snapshot, StorageError, StorageKind, Volume, run};
+use crate::sudo::SudoCredential;
+
+/// Check if the ZFS kernel module is loaded. Without it, `zpool` and `zfs` can not be used.
+pub(super) fn is_loaded() -> bool {
+    Path::new("/dev/zfs").exists()
+}
+
+/// Names of datasets, like `tank/media`. Pools are datasets without a parent.
+fn is_valid_dataset(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['-', '/'])
+        && !name.ends_with('/')
+        && !name.contains("//")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '_' | '-' | ':'))
+}
+
+fn check_dataset(name: &str) -> Result<(), StorageError> {
+    if is_valid_dataset(name) {
+        Ok(())
+    } else {
+        Err(StorageError::Invalid(format!(
+            "{name} is not a valid ZFS dataset."
+        )))
+    }
+}
+
+fn check_pool(name: &str) -> Result<(), StorageError> {
+    if is_valid_dataset(name) && !name.contains('/') {
+        Ok(())
+    } else {
+        Err(StorageError::Invalid(format!(
+            "{name} is not a valid ZFS pool."
+        )))
+    }
+}
+
+/// Parse the output of `zpool list -H -p -o name,size,allocated,free,health`.
+fn parse_pools(output: &str) -> Vec<Pool> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, size, allocated, free, health] = fields[..] else {
+                return None;
+            };
+            Some(Pool {
+                kind: StorageKind::Zfs,
+                name: name.to_string(),
+                devices: Vec::new(),
+                size: size.parse().ok()?,
+                used: allocated.parse().ok()?,
+                available: free.parse().ok()?,
+                health: Some(health.to_string()),
+            })
+        })
+        .collect()
+}
+
+pub(super) fn pools(credential: &SudoCredential) -> Result<Vec<Pool>, StorageError> {
+    let output = run(
+        credential,
+        "zpool",
+        vec!["list", "-H", "-p", "-o", "name,size,allocated,free,health"],
+    )?;
+    Ok(parse_pools(&output))
+}
+
+/// Parse the output of `zfs list -H -p -o name,used,available,referenced,mountpoint`.
+fn parse_volumes(output: &str) -> Vec<Volume> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, used, available, referenced, mountpoint] = fields[..] else {
+                return None;
+            };
+            Some(Volume {
+                kind: StorageKind::Zfs,
+                pool: name.split('/').next()?.to_string(),
+                name: name.to_string(),
+                // Volumes have no mountpoint and legacy ones are mounted using /etc/fstab
+                path: (!matches!(mountpoint, "-" | "none" | "legacy"))
+                    .then(|| mountpoint.to_string()),
+                used: used.parse().ok(),
+                available: available.parse().ok(),
+                referenced: referenced.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+pub(super) fn volumes(credential: &SudoCredential) -> Result<Vec<Volume>, StorageError> {
+    let output = run(
+        credential,
+        "zfs",
+        vec![
+            "list",
+            "-H",
+            "-p",
+            "-t",
+            "filesystem,volume",
+            "-o",
+            "name,used,available,referenced,mountpoint",
+        ],
+    )?;
+    Ok(parse_volumes(&output))
+}
+
+/// Parse the output of `zfs list -H -p -t snapshot -o name,used,creation`.
+fn parse_snapshots(output: &str) -> Vec<Snapshot> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, used, creation] = fields[..] else {
+                return None;
+            };
+            let (volume, name) = name.split_once('@')?;
+            Some(Snapshot {
+                volume: volume.to_string(),
+                name: name.to_string(),
+                created: creation.parse().ok()?,
+                used: used.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+fn list_snapshots_args(dataset: &str) -> Vec<String> {
+    [
+        "list",
+        "-H",
+        "-p",
+        "-t",
+        "snapshot",
+        "-o",
+        "name,used,creation",
+        "-d",
+        "1",
+        dataset,
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn create_snapshot_args(dataset: &str, name: &str) -> Vec<String> {
+    vec!["snapshot".to_string(), format!("{dataset}@{name}")]
+}
+
+fn delete_snapshot_args(dataset: &str, name: &str) -> Vec<String> {
+    vec!["destroy".to_string(), format!("{dataset}@{name}")]
+}
+
+pub(super) fn snapshots(
+    dataset: &str,
+    credential: &SudoCredential,
+) -> Result<Vec<Snapshot>, StorageError> {
+    check_dataset(dataset)?;
+    let output = run(credential, "zfs", list_snapshots_args(dataset))?;
+    Ok(parse_snapshots(&output))
+}
+
+pub(super) fn create_snapshot(
+    dataset: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_dataset(dataset)?;
+    run(credential, "zfs", create_snapshot_args(dataset, name))?;
+    Ok(())
+}
+
+pub(super) fn delete_snapshot(
+    dataset: &str,
+    name: &str,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_dataset(dataset)?;
+    run(credential, "zfs", delete_snapshot_args(dataset, name))?;
+    Ok(())
+}
+
+/// The command lines run by [snapshots], [create_snapshot] and [delete_snapshot] for snapshots
+/// named like one of `names`.
+pub(super) fn snapshot_commands(
+    dataset: &str,
+    names: &[String],
+) -> Result<Vec<Vec<String>>, StorageError> {
+    check_dataset(dataset)?;
+    let zfs = |args: Vec<String>| std::iter::once("zfs".to_string()).chain(args).collect();
+    let mut commands = vec![zfs(list_snapshots_args(dataset))];
+    for name in names {
+        commands.push(zfs(create_snapshot_args(dataset, name)));
+        commands.push(zfs(delete_snapshot_args(dataset, name)));
+    }
+    Ok(commands)
+}
+
+pub(super) fn rollback(
+    dataset: &str,
+    name: &str,
+    discard_newer: bool,
+    credential: &SudoCredential,
+) -> Result<(), StorageError> {
+    check_dataset(dataset)?;
+    let snapshot = format!("{dataset}@{name}");
+    let args = if discard_newer {
+        vec!["rollback", "-r", &snapshot]
+    } else {
+        vec!["rollback", &snapshot]
+    };
+    run(credential, "zfs", args)?;
+    Ok(())
+}
+
+pub(super) fn start_scrub(pool: &str, credential: &SudoCredential) -> Result<(), StorageError> {
+    check_pool(pool)?;
+    run(credential, "zpool", vec!["scrub", pool])?;
+    Ok(())
+}
+
+/// Parse the `scan` section of the output of `zpool status`.
+fn parse_scrub(output: &str) -> ScrubStatus {
+    let mut lines = output.lines();
+    let scan: Vec<&str> = lines
+        .by_ref()
+        .find_map(|l| l.trim_start().strip_prefix("scan:"))
+        .into_iter()
+        .chain(lines.take_while(|l| l.starts_with('\t')))
+        .map(str::trim)
+        .collect();
+    let first = scan.first().copied().unwrap_or_default();
+
+    let state = if first.starts_with("scrub in progress") {
+        ScrubState::Running
+    } else if first.starts_with("scrub canceled") {
+        ScrubState::Canceled
+    } else if first.starts_with("scrub repaired") {
+        ScrubState::Finished
+    } else {
+        // "none requested" or the status of a resilver
+        ScrubState::Never
+    };
+    let progress = scan
+        .iter()
+        .filter(|_| state == ScrubState::Running)
+        .find_map(|l| {
+            let (before, _) = l.split_once("% done")?;
+            before.rsplit(' ').next()?.parse().ok()
+        });
+    let errors = first
+        .split_once(" with ")
+        .filter(|_| state == ScrubState::Finished)
+        .and_then(|(_, rest)| rest.split(' ').next()?.parse().ok());
+
+    ScrubStatus {
+        state,
+        progress,
+        errors,
+        summary: scan.join("\n"),
+    }
+}
+
+pub(super) fn scrub_status(
+    pool: &str,
+    credential: &SudoCredential,
+) -> Result<ScrubStatus, StorageError> {
+    check_pool(pool)?;
+    let output = run(credential, "zpool", vec!["status", pool])?;
+    Ok(parse_scrub(&output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_zfs_lists() {
+        let pools = parse_pools(include_str!("../../tests/fixtures/storage/zpool-list.txt"));
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].name, "tank");
+        assert_eq!(pools[0].size, 3_985_729_650_688);
+        assert_eq!(pools[1].health.as_deref(), Some("DEGRADED"));
+
+        let volumes = parse_volumes(include_str!("../../tests/fixtures/storage/zfs-list.txt"));
+        let paths: Vec<(&str, &str, Option<&str>)> = volumes
+            .iter()
+            .map(|v| (v.pool.as_str(), v.name.as_str(), v.path.as_deref()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("tank", "tank", Some("/tank")),
+                ("tank", "tank/media", Some("/tank/media")),
+                ("tank", "tank/vm", None),
+                ("tank", "tank/vm/disk0", None),
+                ("backup", "backup", None),
+            ]
+        );
+        assert_eq!(volumes[1].referenced, Some(1_073_741_824_000));
+
+        let snapshots = parse_snapshots(include_str!(
+            "../../tests/fixtures/storage/zfs-snapshots.txt"
+        ));
+        assert_eq!(
+            snapshots[1],
+            Snapshot {
+                volume: "tank/media".to_string(),
+                name: "zentrox-daily-20261018T000000Z".to_string(),
+                created: 1_760_745_600,
+                used: Some(52_428_800),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_zpool_scrub() {
+        let finished = parse_scrub(include_str!(
+            "../../tests/fixtures/storage/zpool-status-finished.txt"
+        ));
+        assert_eq!(finished.state, ScrubState::Finished);
+        assert_eq!(finished.errors, Some(3));
+        assert_eq!(
+            finished.summary,
+            "scrub repaired 128K in 00:12:41 with 3 errors on Sun Oct 18 03:12:42 2026"
+        );
+
+        let running = parse_scrub(include_str!(
+            "../../tests/fixtures/storage/zpool-status-running.txt"
+        ));
+        assert_eq!(running.state, ScrubState::Running);
+        assert_eq!(running.progress, Some(24.42));
+        assert_eq!(running.errors, None);
+        assert_eq!(running.summary.lines().count(), 3);
+
+        let never = parse_scrub("  pool: tank\n state: ONLINE\n  scan: none requested\nconfig:\n");
+        assert_eq!(never.state, ScrubState::Never);
+        assert_eq!(never.summary, "none requested");
+    }
+
+    #[test]
+    fn validate_datasets() {
+        assert!(is_valid_dataset("tank"));
+        assert!(is_valid_dataset("tank/media.old"));
+        assert!(!is_valid_dataset(""));
+        assert!(!is_valid_dataset("-r"));
+        assert!(!is_valid_dataset("/tank"));
+        assert!(!is_valid_dataset("tank/"));
+        assert!(!is_valid_dataset("tank//media"));
+        assert!(!is_valid_dataset("tank@snapshot"));
+        assert!(check_pool("tank").is_ok());
+        assert!(check_pool("tank/media").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs root and ZFS"]
+    fn manage_file_pool() {
+        let image = std::env::temp_dir().join("zentrox_zfs.img");
+        std::fs::File::create(&image)
+            .unwrap()
+            .set_len(256 * 1024 * 1024)
+            .unwrap();
+        let credential = SudoCredential::Passwordless;
+        let pool = "zentrox-test";
+        run(
+            &credential,
+            "zpool",
+            vec!["create", pool, &image.to_string_lossy()],
+        )
+        .unwrap();
+        let dataset = format!("{pool}/data");
+        run(&credential, "zfs", vec!["create", &dataset]).unwrap();
+
+        assert!(pools(&credential).unwrap().iter().any(|p| p.name == pool));
+        assert!(
+            volumes(&credential)
+                .unwrap()
+                .iter()
+                .any(|v| v.name == dataset)
+        );
+
+        create_snapshot(&dataset, "before", &credential).unwrap();
+        std::fs::write(format!("/{dataset}/file"), "after").unwrap();
+        create_snapshot(&dataset, "after", &credential).unwrap();
+        assert_eq!(snapshots(&dataset, &credential).unwrap().len(), 2);
+
+        assert!(rollback(&dataset, "before", false, &credential).is_err());
+        rollback(&dataset, "before", true, &credential).unwrap();
+        assert!(!Path::new(&format!("/{dataset}/file")).exists());
+        assert_eq!(snapshots(&dataset, &credential).unwrap().len(), 1);
+        delete_snapshot(&dataset, "before", &credential).unwrap();
+
+        start_scrub(pool, &credential).unwrap();
+        assert_ne!(
+            scrub_status(pool, &credential).unwrap().state,
+            ScrubState::Never
+        );
+
+        run(&credential, "zpool", vec!["destroy", pool]).unwrap();
+        std::fs::remove_file(&image).unwrap();
+    }
+}
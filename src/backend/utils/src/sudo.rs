@@ -1,13 +1,46 @@
 use std::fmt::Display;
+use std::fs;
 use std::io::BufReader;
 use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 use std::process::Command;
 use std::process::Stdio;
 use std::thread;
 
+/// The way sudo authenticates a command
+#[derive(Debug, Clone)]
+pub enum SudoCredential {
+    /// Password of the user running Zentrox, which is passed to sudo on the standard input
+    Password(String),
+    /// sudo is not asked for a password and fails if one would be required.
+    /// This relies on a rule with the `NOPASSWD` tag in the sudoers file.
+    Passwordless,
+}
+
+impl From<String> for SudoCredential {
+    fn from(password: String) -> Self {
+        SudoCredential::Password(password)
+    }
+}
+
+impl From<&str> for SudoCredential {
+    fn from(password: &str) -> Self {
+        SudoCredential::Password(password.to_string())
+    }
+}
+
+/// Requests take an optional sudo password. Without one, sudo is used without a password and
+/// fails unless a command is allowed without one.
+impl From<Option<String>> for SudoCredential {
+    fn from(password: Option<String>) -> Self {
+        password.map_or(SudoCredential::Passwordless, SudoCredential::from)
+    }
+}
+
 #[derive(Debug)]
 pub struct SudoCommand {
-    password: String,
+    credential: SudoCredential,
     program: String,
     args: Vec<String>,
 }
@@ -19,6 +52,20 @@ pub struct SudoOutput {
     pub status: Option<i32>,
 }
 
+impl SudoOutput {
+    /// Turn an output with a non-zero exit status into an error message, which is the error
+    /// output of the command or its output if it printed no error.
+    pub fn checked(self) -> Result<SudoOutput, String> {
+        if self.status == Some(0) {
+            Ok(self)
+        } else if self.stderr.trim().is_empty() {
+            Err(self.stdout.trim().to_string())
+        } else {
+            Err(self.stderr.trim().to_string())
+        }
+    }
+}
+
 /// Errors while running sudo
 #[derive(Debug)]
 pub enum SudoError {
@@ -30,6 +77,8 @@ pub enum SudoError {
     NotInSudoers,
     /// The supplied information may not be passed to sudo
     BadParameters,
+    /// The command was run without a password, but sudo requires one.
+    PasswordRequired,
 }
 
 impl SudoCommand {
@@ -37,8 +86,15 @@ impl SudoCommand {
     /// * `password` - The password used for `sudo`
     /// * `program` - The command without arguments that will be launched
     pub fn new<A: Display, B: Display>(password: A, program: B) -> SudoCommand {
+        SudoCommand::with_credential(SudoCredential::Password(password.to_string()), program)
+    }
+
+    /// Create new SudoCommand that is not necessarily authenticated using a password
+    /// * `credential` - The way sudo authenticates the command
+    /// * `program` - The command without arguments that will be launched
+    pub fn with_credential<B: Display>(credential: SudoCredential, program: B) -> SudoCommand {
         SudoCommand {
-            password: password.to_string(),
+            credential,
             program: program.to_string(),
             args: vec![],
         }
@@ -72,24 +128,28 @@ impl SudoCommand {
     /// Spawns a SudoCommand and captures the contents of standard input & standard error.
     pub fn output(&self) -> Result<SudoOutput, SudoError> {
         let args = self.args.clone();
-        let password = self.password.clone();
+        let credential = self.credential.clone();
         let program = self.program.clone();
 
         let prohibited_program = &[' ', '\n', '\r', '\t'];
         let prohibited_password = &['\n', '\r'];
-        if program.chars().any(|x| prohibited_program.contains(&x))
-            || password.chars().any(|x| prohibited_password.contains(&x))
-        {
+        let bad_password = match &credential {
+            SudoCredential::Password(p) => p.chars().any(|x| prohibited_password.contains(&x)),
+            SudoCredential::Passwordless => false,
+        };
+        if program.chars().any(|x| prohibited_program.contains(&x)) || bad_password {
             return Err(SudoError::BadParameters);
         }
 
         let thread_handle = thread::spawn(move || {
-            let mut command_handle = Command::new("sudo")
-                .arg("-S")
-                .arg("-k")
+            let mut command = Command::new("sudo");
+            match &credential {
+                SudoCredential::Password(_) => command.arg("-S").arg("-k").stdin(Stdio::piped()),
+                SudoCredential::Passwordless => command.arg("-n").stdin(Stdio::null()),
+            };
+            let mut command_handle = command
                 .arg(program)
                 .args(args)
-                .stdin(Stdio::piped())
                 .stderr(Stdio::piped())
                 .stdout(Stdio::piped())
                 .spawn()
@@ -97,7 +157,6 @@ impl SudoCommand {
 
             // Capture output and errors
 
-            let mut stdin = command_handle.stdin.take().expect("Failed to open stdin");
             let stdout = command_handle
                 .stdout
                 .take()
@@ -107,10 +166,13 @@ impl SudoCommand {
                 .take()
                 .expect("Failed to capture stderr");
 
-            let _ = thread::spawn(move || {
-                writeln!(stdin, "{password}").expect("Failed to write password to stdin");
-                stdin.flush().expect("Failed to flush stdin");
-            });
+            if let SudoCredential::Password(password) = credential {
+                let mut stdin = command_handle.stdin.take().expect("Failed to open stdin");
+                let _ = thread::spawn(move || {
+                    writeln!(stdin, "{password}").expect("Failed to write password to stdin");
+                    stdin.flush().expect("Failed to flush stdin");
+                });
+            }
 
             let mut o_reader = BufReader::new(stdout);
             let mut stdout_content = String::new();
@@ -130,6 +192,9 @@ impl SudoCommand {
             if stderr_content.contains("is not in the sudoers file.") {
                 return Err(SudoError::NotInSudoers);
             }
+            if stderr_content.contains("a password is required") {
+                return Err(SudoError::PasswordRequired);
+            }
 
             // Return the output if everything succeeded
             Ok(SudoOutput {
@@ -145,6 +210,26 @@ impl SudoCommand {
     }
 }
 
+/// Run a program using sudo and return its output. If it does not succeed, the message of
+/// [SudoOutput::checked] is turned into an error using `failed`.
+pub fn run_checked<T: Display, E: From<SudoError>>(
+    credential: &SudoCredential,
+    program: &str,
+    args: Vec<T>,
+    failed: impl FnOnce(String) -> E,
+) -> Result<String, E> {
+    let output = SudoCommand::with_credential(credential.clone(), program)
+        .args(args)
+        .output()?;
+    match output.checked() {
+        Ok(output) => Ok(output.stdout),
+        Err(message) if message.is_empty() => {
+            Err(failed(format!("{program} failed. Is it installed?")))
+        }
+        Err(message) => Err(failed(message)),
+    }
+}
+
 pub fn verify_password(password: String) -> bool {
     let mut c = Command::new("sudo");
     c.args(["-S", "-k", "-v"]);
@@ -166,6 +251,59 @@ pub fn verify_password(password: String) -> bool {
     !stderr_content.contains("Sorry, try again.")
 }
 
+/// Copy `contents` to `path` as root, creating missing directories.
+///
+/// The file is staged in the data directory and copied into place using `sudo install`, so it is
+/// owned by root and readable by everyone.
+pub fn write_system_file(
+    credential: SudoCredential,
+    path: &Path,
+    contents: &str,
+) -> Result<SudoOutput, SudoError> {
+    install_file(credential, path, contents, "0644")
+}
+
+/// Like [write_system_file], but the file is only readable by root, e.g. for passwords.
+pub fn write_secret_system_file(
+    credential: SudoCredential,
+    path: &Path,
+    contents: &str,
+) -> Result<SudoOutput, SudoError> {
+    install_file(credential, path, contents, "0600")
+}
+
+fn install_file(
+    credential: SudoCredential,
+    path: &Path,
+    contents: &str,
+    mode: &str,
+) -> Result<SudoOutput, SudoError> {
+    let staged = crate::database::data_directory().join(format!("{}.staged", uuid::Uuid::new_v4()));
+    // The staged file is not readable by other users either
+    let staging = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&staged)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+    if let Err(e) = staging {
+        let _ = fs::remove_file(&staged);
+        return Ok(SudoOutput {
+            stdout: String::new(),
+            stderr: format!("The file could not be staged: {e}"),
+            status: Some(1),
+        });
+    }
+
+    let output = SudoCommand::with_credential(credential, "install")
+        .args(vec!["-D", "-m", mode, "-o", "root", "-g", "root"])
+        .arg(staged.display())
+        .arg(path.display())
+        .output();
+    let _ = fs::remove_file(&staged);
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,237 @@
+//! Sudoers rules that allow the user running Zentrox to run exact commands as root without a
+//! password.
+//!
+//! Features that run without a human typing the sudo password, like unattended updates and
+//! scheduled snapshots, install a rule of their own below `/etc/sudoers.d`. A rule only allows
+//! exact command lines, so no other arguments can be passed to the programs. Rules are installed
+//! and removed using the sudo password of the user, which is not stored by Zentrox.
+
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+use crate::sudo::{SudoCommand, SudoError};
+
+/// Directories searched for programs in addition to `PATH`, which often does not include the
+/// `sbin` directories for users other than root.
+const SBIN_DIRECTORIES: &[&str] = &["/usr/local/sbin", "/usr/sbin", "/sbin"];
+
+#[derive(Debug, Error)]
+pub enum SudoersError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("The user name {0} can not be used in a sudoers rule.")]
+    BadUser(String),
+    #[error("The sudoers rule could not be staged: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The sudoers rule was rejected: {0}")]
+    InvalidRule(String),
+    #[error("The sudoers rule could not be installed: {0}")]
+    InstallFailed(String),
+    #[error(
+        "The sudoers rule was installed, but is not used by sudo. Make sure /etc/sudoers includes /etc/sudoers.d."
+    )]
+    NotIncluded,
+}
+
+impl From<SudoError> for SudoersError {
+    fn from(error: SudoError) -> Self {
+        SudoersError::Sudo(error)
+    }
+}
+
+/// Absolute path of a program as sudo would find it.
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(SBIN_DIRECTORIES.iter().map(PathBuf::from))
+        .map(|directory| directory.join(name))
+        .find(|program| program.is_file())
+}
+
+/// A command line with the absolute path of its program, as written to a sudoers rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivilegedCommand {
+    pub program: PathBuf,
+    /// Arguments may contain `[...]` wildcards, which match one character each
+    pub args: Vec<String>,
+}
+
+impl PrivilegedCommand {
+    /// Resolve the program of a command line. Commands that could not be written to the sudoers
+    /// file without escaping are skipped.
+    pub fn resolve<S: AsRef<str>>(command: &[S]) -> Option<Self> {
+        let (name, args) = command.split_first()?;
+        let command = PrivilegedCommand {
+            program: find_program(name.as_ref())?,
+            args: args.iter().map(|a| a.as_ref().to_string()).collect(),
+        };
+        let is_plain = |s: &str, special: &str| {
+            s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "/._+-".contains(c) || special.contains(c))
+        };
+        (is_plain(&command.program.to_string_lossy(), "")
+            && command.args.iter().all(|a| is_plain(a, "@:,[]")))
+        .then_some(command)
+    }
+}
+
+impl Display for PrivilegedCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program.to_string_lossy())?;
+        if self.args.is_empty() {
+            // Without "", sudo would allow any arguments
+            return write!(f, " \"\"");
+        }
+        for arg in &self.args {
+            // : and , separate the parts of a rule
+            write!(f, " {}", arg.replace(':', "\\:").replace(',', "\\,"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sudoers rule that allows `user` to run exactly `commands` as root without a password.
+///
+/// * `purpose` - What the commands are used for, which is noted in a comment
+pub fn sudoers_rule(purpose: &str, user: &str, commands: &[PrivilegedCommand]) -> String {
+    let commands = commands
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!("# Managed by Zentrox to {purpose}\n{user} ALL=(root) NOPASSWD: {commands}\n")
+}
+
+/// Install a rule at `path` that allows the user running Zentrox to run `commands`, replacing the
+/// rule at `path` if there is one.
+///
+/// The rule is checked using `visudo` before it is installed, so a malformed rule can not lock
+/// the user out of sudo.
+///
+/// * `password` - Sudo password of the user
+pub fn install(
+    path: &str,
+    purpose: &str,
+    commands: &[PrivilegedCommand],
+    password: &str,
+) -> Result<(), SudoersError> {
+    let user = whoami::username();
+    if user.is_empty()
+        || !user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+    {
+        return Err(SudoersError::BadUser(user));
+    }
+
+    let staged =
+        crate::database::data_directory().join(format!("sudoers-{}.staged", uuid::Uuid::new_v4()));
+    fs::write(&staged, sudoers_rule(purpose, &user, commands))?;
+
+    let result = install_rule(password, &staged, path);
+    let _ = fs::remove_file(&staged);
+    result?;
+
+    if commands.first().is_some_and(|c| !is_allowed(c)) {
+        return Err(SudoersError::NotIncluded);
+    }
+    Ok(())
+}
+
+/// Check the staged rule and copy it to `path` with the permissions sudo expects.
+fn install_rule(password: &str, staged: &Path, path: &str) -> Result<(), SudoersError> {
+    let check = SudoCommand::new(password, "visudo")
+        .arg("-cf")
+        .arg(staged.display())
+        .output()?;
+    if check.status != Some(0) {
+        return Err(SudoersError::InvalidRule(
+            format!("{}{}", check.stdout, check.stderr)
+                .trim()
+                .to_string(),
+        ));
+    }
+
+    let install = SudoCommand::new(password, "install")
+        .args(vec!["-m", "0440", "-o", "root", "-g", "root"])
+        .arg(staged.display())
+        .arg(path)
+        .output()?;
+    if install.status != Some(0) {
+        return Err(SudoersError::InstallFailed(
+            install.stderr.trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Remove the rule at `path`, so its commands require the sudo password again.
+///
+/// * `password` - Sudo password of the user
+pub fn remove(path: &str, password: &str) -> Result<(), SudoersError> {
+    let output = SudoCommand::new(password, "rm")
+        .args(vec!["-f", path])
+        .output()?;
+    if output.status != Some(0) {
+        return Err(SudoersError::InstallFailed(
+            output.stderr.trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether sudo allows the user running Zentrox to run a command as root without a password.
+pub fn is_allowed(command: &PrivilegedCommand) -> bool {
+    Command::new("sudo")
+        .arg("-n")
+        .arg("-l")
+        .arg(&command.program)
+        .args(&command.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_sudoers_rule() {
+        assert_eq!(
+            sudoers_rule(
+                "run unattended package updates",
+                "zentrox",
+                &[
+                    PrivilegedCommand {
+                        program: PathBuf::from("/usr/bin/apt"),
+                        args: vec!["upgrade".to_string(), "-y".to_string(), "-q".to_string()]
+                    },
+                    PrivilegedCommand {
+                        program: PathBuf::from("/usr/sbin/reboot"),
+                        args: vec![]
+                    }
+                ]
+            ),
+            "# Managed by Zentrox to run unattended package updates\nzentrox ALL=(root) NOPASSWD: /usr/bin/apt upgrade -y -q, /usr/sbin/reboot \"\"\n"
+        );
+        assert_eq!(
+            PrivilegedCommand {
+                program: PathBuf::from("/usr/sbin/zfs"),
+                args: vec![
+                    "list".to_string(),
+                    "-o".to_string(),
+                    "name,used".to_string(),
+                    "tank/vm:disk".to_string()
+                ]
+            }
+            .to_string(),
+            "/usr/sbin/zfs list -o name\\,used tank/vm\\:disk"
+        );
+    }
+}
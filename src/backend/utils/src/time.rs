@@ -8,6 +8,14 @@ pub fn current_timestamp_unix() -> u128 {
         .as_millis()
 }
 
+/// Seconds since the UNIX epoch, as timestamps are stored in the database.
+pub fn current_timestamp_secs() -> i64 {
+    time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time moved backwards.")
+        .as_secs() as i64
+}
+
 pub fn time_to_unix(time: SystemTime) -> u128 {
     time.duration_since(UNIX_EPOCH)
         .expect("Time went backwards.")
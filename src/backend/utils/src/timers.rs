@@ -0,0 +1,819 @@
+//! systemd timers of the system and of the user running Zentrox, which are scheduled next to the
+//! cronjobs of [`crate::cron`].
+//!
+//! Timers created using Zentrox consist of a `.timer` and a `.service` unit of the same name that
+//! start with [`MANAGED_MARKER`]. Only those timers can be edited and deleted, other timers, like
+//! the ones shipped by the distribution, can only be enabled and disabled.
+//! System timers are stored in `/etc/systemd/system` and changed using sudo, user timers are
+//! stored in `~/.config/systemd/user` and changed using `systemctl --user`.
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::sudo::{SudoCommand, SudoCredential, SudoError, SudoOutput, write_system_file};
+
+/// First line of every unit written by Zentrox
+pub const MANAGED_MARKER: &str = "# Managed by Zentrox";
+
+/// Properties of timers read using `systemctl show`
+const TIMER_PROPERTIES: &str = "Id,Description,Triggers,TimersCalendar,TimersMonotonic,\
+UnitFileState,ActiveState,NextElapseUSecRealtime,LastTriggerUSec,Persistent,FragmentPath";
+
+/// The systemd instance a unit belongs to
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum UnitScope {
+    /// The system instance, changing its units requires sudo
+    System,
+    /// The instance of the user running Zentrox
+    User,
+}
+
+impl UnitScope {
+    /// The directory units created by the administrator are stored in.
+    fn directory(self) -> Option<PathBuf> {
+        match self {
+            UnitScope::System => Some(PathBuf::from("/etc/systemd/system")),
+            UnitScope::User => dirs::config_dir().map(|c| c.join("systemd").join("user")),
+        }
+    }
+
+    /// `systemctl` for reading units, which never requires sudo.
+    fn systemctl(self) -> Command {
+        let mut command = Command::new("systemctl");
+        if self == UnitScope::User {
+            command.arg("--user");
+        }
+        command.stdin(Stdio::null()).env("LC_ALL", "C");
+        command
+    }
+}
+
+/// A systemd timer and the service it starts
+#[derive(Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemdTimer {
+    /// Name of the timer unit without `.timer`
+    pub name: String,
+    pub scope: UnitScope,
+    pub description: String,
+    /// The unit started by the timer, usually the service of the same name
+    pub unit: String,
+    /// Command line of the started service
+    pub command: Option<String>,
+    /// Calendar expressions like `Mon *-*-* 04:00:00`
+    pub on_calendar: Vec<String>,
+    /// Triggers relative to other events like `OnBootSec=15min`
+    pub monotonic: Vec<String>,
+    /// Whether missed runs are made up for once the system is running again
+    pub persistent: bool,
+    /// Whether the timer is started on boot
+    pub enabled: bool,
+    /// Whether the timer is running and will start its unit
+    pub active: bool,
+    /// Seconds since the UNIX epoch
+    pub next_run: Option<i64>,
+    /// Seconds since the UNIX epoch
+    pub last_run: Option<i64>,
+    /// Whether the timer was created using Zentrox and can be edited
+    pub managed: bool,
+}
+
+/// A timer to be created or edited
+#[derive(Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TimerDefinition {
+    /// Name of the units without suffix, may only contain letters, digits, `-` and `_`
+    pub name: String,
+    pub scope: UnitScope,
+    pub description: String,
+    /// Shell command that is run using `/bin/sh -c`
+    pub command: String,
+    /// Calendar expressions as described in `systemd.time(7)`
+    pub on_calendar: Vec<String>,
+    /// Make up for runs that were missed while the system was off
+    #[serde(default)]
+    pub persistent: bool,
+}
+
+impl TimerDefinition {
+    /// Check the definition without consulting systemd. See [`validate_calendar`] for the calendar
+    /// expressions.
+    pub fn validate(&self) -> Result<(), String> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if !is_unit_name(&self.name) || !self.name.chars().all(allowed) {
+            return Err(
+                "The name may only contain letters, digits, dashes and underscores.".to_string(),
+            );
+        }
+        if self.command.trim().is_empty() {
+            return Err("The command is empty.".to_string());
+        }
+        if self.on_calendar.is_empty() {
+            return Err("At least one calendar expression is required.".to_string());
+        }
+        let single_lines = [&self.description, &self.command]
+            .into_iter()
+            .chain(self.on_calendar.iter());
+        for value in single_lines {
+            if value.contains(['\n', '\r']) {
+                return Err("Values may not span multiple lines.".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The `.service` unit, which runs the command once.
+    pub fn service_unit(&self) -> String {
+        format!(
+            "{MANAGED_MARKER}\n[Unit]\nDescription={}\n\n[Service]\nType=oneshot\nExecStart=/bin/sh -c \"{}\"\n",
+            escape_specifiers(&self.description),
+            escape_command(&self.command)
+        )
+    }
+
+    /// The `.timer` unit, which is started on boot once it is enabled.
+    pub fn timer_unit(&self) -> String {
+        let mut unit = format!(
+            "{MANAGED_MARKER}\n[Unit]\nDescription={}\n\n[Timer]\n",
+            escape_specifiers(&self.description)
+        );
+        for expression in &self.on_calendar {
+            unit.push_str(&format!("OnCalendar={}\n", expression.trim()));
+        }
+        if self.persistent {
+            unit.push_str("Persistent=true\n");
+        }
+        unit.push_str("\n[Install]\nWantedBy=timers.target\n");
+        unit
+    }
+}
+
+/// Errors while changing timers
+#[derive(Debug, Error)]
+pub enum TimerError {
+    #[error("Running sudo failed: {0:?}")]
+    Sudo(SudoError),
+    #[error("The timer does not exist.")]
+    UnknownTimer,
+    #[error("The timer was not created using Zentrox and can only be enabled or disabled.")]
+    Unmanaged,
+    #[error("A unit with this name exists already.")]
+    Exists,
+    #[error("{0}")]
+    InvalidTimer(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<SudoError> for TimerError {
+    fn from(error: SudoError) -> Self {
+        TimerError::Sudo(error)
+    }
+}
+
+/// Whether `name` is a plain unit name without a type suffix that can be passed to `systemctl`.
+fn is_unit_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 200
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.@:\\".contains(c))
+}
+
+/// Escape `%`, which starts a specifier in unit files.
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Escape a command for a double quoted argument of `ExecStart=`.
+fn escape_command(command: &str) -> String {
+    escape_specifiers(command)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+}
+
+/// Reverse [`escape_command`].
+fn unescape_command(escaped: &str) -> String {
+    let mut command = String::new();
+    let mut chars = escaped.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next)) | ('%', Some(&next @ '%')) | ('$', Some(&next @ '$')) => {
+                command.push(next);
+                chars.next();
+            }
+            _ => command.push(c),
+        }
+    }
+    command
+}
+
+/// The command of a service written by [`TimerDefinition::service_unit`].
+fn managed_command(service_unit: &str) -> Option<String> {
+    service_unit
+        .lines()
+        .find_map(|l| l.strip_prefix("ExecStart=/bin/sh -c \""))
+        .and_then(|l| l.strip_suffix('"'))
+        .map(unescape_command)
+}
+
+/// Split the output of `systemctl show` into the properties of every unit. Properties that
+/// occur several times, like `TimersCalendar`, keep all of their values.
+fn parse_show(output: &str) -> Vec<HashMap<String, Vec<String>>> {
+    let mut units = Vec::new();
+    let mut current: HashMap<String, Vec<String>> = HashMap::new();
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                units.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            current
+                .entry(key.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+    }
+    if !current.is_empty() {
+        units.push(current);
+    }
+    units
+}
+
+/// A timestamp of `systemctl show`, which is either `@1705273200` with `--timestamp=unix` or
+/// `Mon 2024-01-15 00:00:00 CET` in the local time zone.
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Some(seconds) = value.strip_prefix('@') {
+        return seconds.parse().ok();
+    }
+    let mut parts = value.split_whitespace().skip(1);
+    let time = format!("{} {}", parts.next()?, parts.next()?);
+    let time = NaiveDateTime::parse_from_str(&time, "%Y-%m-%d %H:%M:%S").ok()?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+/// The first setting of a trigger like `{ OnCalendar=*-*-* 00:00:00 ; next_elapse=... }`, with
+/// `OnBootUSec` shortened to `OnBootSec` as in unit files.
+fn parse_trigger(value: &str) -> Option<(String, String)> {
+    let setting = value
+        .trim()
+        .trim_start_matches('{')
+        .split(" ; ")
+        .next()?
+        .trim();
+    let (key, value) = setting.split_once('=')?;
+    let key = key
+        .strip_suffix("USec")
+        .map_or(key.to_string(), |k| format!("{k}Sec"));
+    Some((key, value.trim().to_string()))
+}
+
+/// The command line of `ExecStart` as shown by `systemctl show`, e.g.
+/// `{ path=/usr/bin/fstrim ; argv[]=/usr/bin/fstrim --listed-in /etc/fstab ; ... }`.
+fn parse_exec_start(value: &str) -> Option<String> {
+    value
+        .split(" ; ")
+        .find_map(|p| p.trim().strip_prefix("argv[]="))
+        .map(String::from)
+}
+
+/// Convert the properties of a timer unit. `commands` maps services to their command lines.
+fn parse_timer(
+    scope: UnitScope,
+    properties: &HashMap<String, Vec<String>>,
+    commands: &HashMap<String, String>,
+) -> Option<SystemdTimer> {
+    let property = |key: &str| {
+        properties
+            .get(key)
+            .and_then(|v| v.last())
+            .map(|v| v.trim())
+            .unwrap_or_default()
+    };
+    let name = property("Id").strip_suffix(".timer")?.to_string();
+    let triggers = |key: &str| {
+        properties
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| parse_trigger(v))
+            .collect::<Vec<(String, String)>>()
+    };
+
+    let fragment_path = property("FragmentPath");
+    let managed = !fragment_path.is_empty()
+        && scope
+            .directory()
+            .is_some_and(|d| Path::new(fragment_path).starts_with(d))
+        && fs::read_to_string(fragment_path).is_ok_and(|c| c.starts_with(MANAGED_MARKER));
+    let unit = property("Triggers").to_string();
+
+    Some(SystemdTimer {
+        scope,
+        description: property("Description").to_string(),
+        command: commands.get(&unit).cloned(),
+        on_calendar: triggers("TimersCalendar")
+            .into_iter()
+            .map(|(_, expression)| expression)
+            .collect(),
+        monotonic: triggers("TimersMonotonic")
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect(),
+        persistent: property("Persistent") == "yes",
+        enabled: matches!(property("UnitFileState"), "enabled" | "enabled-runtime"),
+        active: property("ActiveState") == "active",
+        next_run: parse_timestamp(property("NextElapseUSecRealtime")),
+        last_run: parse_timestamp(property("LastTriggerUSec")),
+        managed,
+        unit,
+        name,
+    })
+}
+
+/// Run `systemctl show` for `units`, preferring UNIX timestamps which are only supported since
+/// systemd 248.
+fn show(scope: UnitScope, properties: &str, units: &[String]) -> Option<String> {
+    for timestamps in [Some("--timestamp=unix"), None] {
+        let mut command = scope.systemctl();
+        command.arg("show").arg(format!("--property={properties}"));
+        command.args(timestamps);
+        let output = command.arg("--").args(units).output().ok()?;
+        if output.status.success() {
+            return Some(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+    }
+    None
+}
+
+/// Names of the timer units known to systemd, including disabled timers that are not loaded.
+fn timer_units(scope: UnitScope) -> Option<Vec<String>> {
+    let mut units = Vec::new();
+    for listing in ["list-units", "list-unit-files"] {
+        let output = scope
+            .systemctl()
+            .args([
+                listing,
+                "--type=timer",
+                "--all",
+                "--plain",
+                "--no-legend",
+                "--no-pager",
+            ])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(unit) = line.split_whitespace().next() else {
+                continue;
+            };
+            // Templates can not be started without an instance
+            if unit.ends_with(".timer") && !unit.contains("@.") && !units.iter().any(|u| u == unit)
+            {
+                units.push(unit.to_string());
+            }
+        }
+    }
+    Some(units)
+}
+
+/// Whether systemd knows any of `units`, e.g. from a package in `/usr/lib/systemd` or a generator.
+/// Fails if systemd can not be reached.
+fn is_known(scope: UnitScope, units: &[String]) -> Option<bool> {
+    for listing in ["list-units", "list-unit-files"] {
+        let output = scope
+            .systemctl()
+            .args([
+                listing,
+                "--all",
+                "--plain",
+                "--no-legend",
+                "--no-pager",
+                "--",
+            ])
+            .args(units)
+            .output()
+            .ok()?;
+        // Exits with 1 and without an error if no unit matches
+        let no_match = output.status.code() == Some(1) && output.stderr.is_empty();
+        if !output.status.success() && !no_match {
+            return None;
+        }
+        let listed = String::from_utf8_lossy(&output.stdout);
+        // Units that are only referenced by other units are loaded as not-found
+        if listed.lines().any(|l| {
+            let mut columns = l.split_whitespace();
+            columns.next().is_some_and(|u| units.iter().any(|n| n == u))
+                && columns.next() != Some("not-found")
+        }) {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+/// The timers of a systemd instance. Fails if systemd can not be reached, e.g. because there is
+/// no user instance for the user running Zentrox.
+pub fn list(scope: UnitScope) -> Option<Vec<SystemdTimer>> {
+    let units = timer_units(scope)?;
+    if units.is_empty() {
+        return Some(Vec::new());
+    }
+    let timers = parse_show(&show(scope, TIMER_PROPERTIES, &units)?);
+
+    let services: Vec<String> = timers
+        .iter()
+        .filter_map(|t| t.get("Triggers")?.last().cloned())
+        .filter(|u| !u.is_empty())
+        .collect();
+    let mut commands: HashMap<String, String> = HashMap::new();
+    if !services.is_empty() {
+        for service in parse_show(&show(scope, "Id,ExecStart,FragmentPath", &services)?) {
+            let (Some(id), Some(exec_start)) = (service.get("Id"), service.get("ExecStart")) else {
+                continue;
+            };
+            // The original command of managed services is shown instead of `/bin/sh -c ...`
+            let command = service
+                .get("FragmentPath")
+                .and_then(|p| fs::read_to_string(&p[0]).ok())
+                .filter(|c| c.starts_with(MANAGED_MARKER))
+                .and_then(|c| managed_command(&c))
+                .or_else(|| parse_exec_start(&exec_start[0]));
+            if let Some(command) = command {
+                commands.insert(id[0].clone(), command);
+            }
+        }
+    }
+
+    Some(
+        timers
+            .iter()
+            .filter_map(|t| parse_timer(scope, t, &commands))
+            .collect(),
+    )
+}
+
+/// The timers of the system and of the user running Zentrox. User timers are left out if there is
+/// no user instance of systemd.
+pub fn list_all() -> Option<Vec<SystemdTimer>> {
+    let mut timers = list(UnitScope::System)?;
+    match list(UnitScope::User) {
+        Some(user_timers) => timers.extend(user_timers),
+        None => warn!("The systemd user instance could not be reached to list user timers."),
+    }
+    Some(timers)
+}
+
+/// Whether `expression` is a calendar expression systemd understands.
+pub fn validate_calendar(expression: &str) -> bool {
+    Command::new("systemd-analyze")
+        .arg("calendar")
+        .arg("--")
+        .arg(expression)
+        .stdin(Stdio::null())
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+/// Turn an output with a non-zero exit status into an error.
+fn check(output: SudoOutput) -> Result<SudoOutput, TimerError> {
+    output.checked().map_err(TimerError::Failed)
+}
+
+/// Run `systemctl`, using sudo for system units.
+fn systemctl(
+    scope: UnitScope,
+    credential: &SudoCredential,
+    args: &[&str],
+) -> Result<SudoOutput, TimerError> {
+    match scope {
+        UnitScope::System => check(
+            SudoCommand::with_credential(credential.clone(), "systemctl")
+                .args(args.to_vec())
+                .output()?,
+        ),
+        UnitScope::User => {
+            let output = scope
+                .systemctl()
+                .args(args)
+                .output()
+                .map_err(|e| TimerError::Failed(e.to_string()))?;
+            check(SudoOutput {
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                status: output.status.code(),
+            })
+        }
+    }
+}
+
+fn unit_path(scope: UnitScope, unit: &str) -> Result<PathBuf, TimerError> {
+    scope
+        .directory()
+        .map(|d| d.join(unit))
+        .ok_or_else(|| TimerError::Failed("The unit directory is unknown.".to_string()))
+}
+
+/// Write a unit file of the instance, using sudo for system units.
+fn write_unit(
+    scope: UnitScope,
+    credential: &SudoCredential,
+    unit: &str,
+    contents: &str,
+) -> Result<(), TimerError> {
+    let path = unit_path(scope, unit)?;
+    match scope {
+        UnitScope::System => {
+            check(write_system_file(credential.clone(), &path, contents)?)?;
+        }
+        UnitScope::User => {
+            let written = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, contents));
+            written.map_err(|e| TimerError::Failed(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a unit file of the instance, using sudo for system units.
+fn remove_unit(
+    scope: UnitScope,
+    credential: &SudoCredential,
+    unit: &str,
+) -> Result<(), TimerError> {
+    let path = unit_path(scope, unit)?;
+    match scope {
+        UnitScope::System => {
+            check(
+                SudoCommand::with_credential(credential.clone(), "rm")
+                    .arg("-f")
+                    .arg(path.display())
+                    .output()?,
+            )?;
+        }
+        UnitScope::User => match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(TimerError::Failed(e.to_string()));
+            }
+            _ => {}
+        },
+    }
+    Ok(())
+}
+
+/// The timer `name` of the instance if it exists.
+fn find(scope: UnitScope, name: &str) -> Result<SystemdTimer, TimerError> {
+    if !is_unit_name(name) {
+        return Err(TimerError::UnknownTimer);
+    }
+    list(scope)
+        .ok_or_else(|| TimerError::Failed("The timers could not be listed.".to_string()))?
+        .into_iter()
+        .find(|t| t.name == name)
+        .ok_or(TimerError::UnknownTimer)
+}
+
+/// Check a definition including its calendar expressions.
+fn validate(timer: &TimerDefinition) -> Result<(), TimerError> {
+    timer.validate().map_err(TimerError::InvalidTimer)?;
+    match timer.on_calendar.iter().find(|e| !validate_calendar(e)) {
+        Some(expression) => Err(TimerError::InvalidTimer(format!(
+            "{expression} is not a valid calendar expression."
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Create the timer and service units, enable the timer and start it.
+///
+/// The credential is only used for system timers.
+pub fn create(timer: &TimerDefinition, credential: SudoCredential) -> Result<(), TimerError> {
+    validate(timer)?;
+    let units = [
+        format!("{}.timer", timer.name),
+        format!("{}.service", timer.name),
+    ];
+    for unit in &units {
+        if unit_path(timer.scope, unit)?.exists() {
+            return Err(TimerError::Exists);
+        }
+    }
+    // Units shipped by packages would be overridden by the new units in the unit directory
+    match is_known(timer.scope, &units) {
+        Some(false) => {}
+        Some(true) => return Err(TimerError::Exists),
+        None => {
+            return Err(TimerError::Failed(
+                "The units known to systemd could not be listed.".to_string(),
+            ));
+        }
+    }
+
+    write_unit(
+        timer.scope,
+        &credential,
+        &format!("{}.service", timer.name),
+        &timer.service_unit(),
+    )?;
+    write_unit(
+        timer.scope,
+        &credential,
+        &format!("{}.timer", timer.name),
+        &timer.timer_unit(),
+    )?;
+    systemctl(timer.scope, &credential, &["daemon-reload"])?;
+    systemctl(
+        timer.scope,
+        &credential,
+        &["enable", "--now", &format!("{}.timer", timer.name)],
+    )?;
+    Ok(())
+}
+
+/// Replace the units of a timer created using Zentrox. A running timer is restarted to apply the
+/// new schedule.
+pub fn edit(timer: &TimerDefinition, credential: SudoCredential) -> Result<(), TimerError> {
+    validate(timer)?;
+    if !find(timer.scope, &timer.name)?.managed {
+        return Err(TimerError::Unmanaged);
+    }
+
+    write_unit(
+        timer.scope,
+        &credential,
+        &format!("{}.service", timer.name),
+        &timer.service_unit(),
+    )?;
+    write_unit(
+        timer.scope,
+        &credential,
+        &format!("{}.timer", timer.name),
+        &timer.timer_unit(),
+    )?;
+    systemctl(timer.scope, &credential, &["daemon-reload"])?;
+    systemctl(
+        timer.scope,
+        &credential,
+        &["try-restart", &format!("{}.timer", timer.name)],
+    )?;
+    Ok(())
+}
+
+/// Enable and start or disable and stop any timer.
+pub fn set_enabled(
+    scope: UnitScope,
+    name: &str,
+    enabled: bool,
+    credential: SudoCredential,
+) -> Result<(), TimerError> {
+    find(scope, name)?;
+    let action = if enabled { "enable" } else { "disable" };
+    systemctl(
+        scope,
+        &credential,
+        &[action, "--now", &format!("{name}.timer")],
+    )?;
+    Ok(())
+}
+
+/// Stop and remove a timer created using Zentrox along with its service.
+pub fn delete(scope: UnitScope, name: &str, credential: SudoCredential) -> Result<(), TimerError> {
+    if !find(scope, name)?.managed {
+        return Err(TimerError::Unmanaged);
+    }
+
+    systemctl(
+        scope,
+        &credential,
+        &["disable", "--now", &format!("{name}.timer")],
+    )?;
+    remove_unit(scope, &credential, &format!("{name}.timer"))?;
+    remove_unit(scope, &credential, &format!("{name}.service"))?;
+    systemctl(scope, &credential, &["daemon-reload"])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_timer_units() {
+        let timer = TimerDefinition {
+            name: "backup".to_string(),
+            scope: UnitScope::User,
+            description: "Nightly backup at 100%".to_string(),
+            command: r#"tar czf "$HOME/backup.tgz" ~/docs && echo \done"#.to_string(),
+            on_calendar: vec!["*-*-* 02:00:00".to_string()],
+            persistent: true,
+        };
+        assert!(timer.validate().is_ok());
+
+        let service = timer.service_unit();
+        assert_eq!(
+            service,
+            "# Managed by Zentrox\n[Unit]\nDescription=Nightly backup at 100%%\n\n[Service]\nType=oneshot\nExecStart=/bin/sh -c \"tar czf \\\"$$HOME/backup.tgz\\\" ~/docs && echo \\\\done\"\n"
+        );
+        assert_eq!(managed_command(&service).as_ref(), Some(&timer.command));
+        assert_eq!(
+            timer.timer_unit(),
+            "# Managed by Zentrox\n[Unit]\nDescription=Nightly backup at 100%%\n\n[Timer]\nOnCalendar=*-*-* 02:00:00\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n"
+        );
+
+        let invalid = |f: fn(&mut TimerDefinition)| {
+            let mut t = timer.clone();
+            f(&mut t);
+            t.validate().is_err()
+        };
+        assert!(invalid(|t| t.name = "../backup".to_string()));
+        assert!(invalid(|t| t.name = "backup.service".to_string()));
+        assert!(invalid(|t| t.name = "backup@daily".to_string()));
+        assert!(invalid(|t| t.command = " ".to_string()));
+        assert!(invalid(|t| t.on_calendar.clear()));
+        assert!(invalid(|t| t
+            .on_calendar
+            .push("daily\nExecStart=x".to_string())));
+    }
+
+    #[test]
+    fn parse_systemctl_show() {
+        let output = "\
+Id=logrotate.timer
+Description=Daily rotation of log files
+Triggers=logrotate.service
+TimersCalendar={ OnCalendar=*-*-* 00:00:00 ; next_elapse=@1705273200 }
+TimersCalendar={ OnCalendar=Mon *-*-* 12:00:00 ; next_elapse=@1705316400 }
+UnitFileState=enabled
+ActiveState=active
+NextElapseUSecRealtime=@1705273200
+LastTriggerUSec=@1705186800
+Persistent=yes
+FragmentPath=/usr/lib/systemd/system/logrotate.timer
+
+Id=fstrim.timer
+Description=Discard unused blocks once a week
+Triggers=fstrim.service
+TimersMonotonic={ OnBootUSec=15min ; next_elapse=0 }
+UnitFileState=disabled
+ActiveState=inactive
+NextElapseUSecRealtime=
+LastTriggerUSec=n/a
+Persistent=no
+FragmentPath=/usr/lib/systemd/system/fstrim.timer
+";
+        let units = parse_show(output);
+        assert_eq!(units.len(), 2);
+
+        let commands = HashMap::from([(
+            "logrotate.service".to_string(),
+            parse_exec_start(
+                "{ path=/usr/sbin/logrotate ; argv[]=/usr/sbin/logrotate /etc/logrotate.conf ; ignore_errors=no }",
+            )
+            .unwrap(),
+        )]);
+        let logrotate = parse_timer(UnitScope::System, &units[0], &commands).unwrap();
+        assert_eq!(logrotate.name, "logrotate");
+        assert_eq!(logrotate.unit, "logrotate.service");
+        assert_eq!(
+            logrotate.command.as_deref(),
+            Some("/usr/sbin/logrotate /etc/logrotate.conf")
+        );
+        assert_eq!(
+            logrotate.on_calendar,
+            vec!["*-*-* 00:00:00", "Mon *-*-* 12:00:00"]
+        );
+        assert!(logrotate.enabled && logrotate.active && logrotate.persistent);
+        assert_eq!(logrotate.next_run, Some(1705273200));
+        assert_eq!(logrotate.last_run, Some(1705186800));
+        assert!(!logrotate.managed);
+
+        let fstrim = parse_timer(UnitScope::System, &units[1], &commands).unwrap();
+        assert_eq!(fstrim.monotonic, vec!["OnBootSec=15min"]);
+        assert_eq!(fstrim.command, None);
+        assert!(!fstrim.enabled && !fstrim.active && !fstrim.persistent);
+        assert_eq!(fstrim.next_run, None);
+        assert_eq!(fstrim.last_run, None);
+
+        assert!(parse_timestamp("Mon 2024-01-15 00:00:00 CET").is_some());
+    }
+}
@@ -11,6 +11,7 @@
 //! - `rules_raw()` - [rules_raw] returns the raw user.rule file containing the iptables rules.
 //! - `set_defaults(password: String, defaults: Defaults)` - [set_defaults] sets the default actions for incoming, outgoing and forwarded requests.
 //! - `set_enabled(password: String, enabled: bool)` - [set_enabled] enables or disables the firewall.
+//! - `port_verdict(status: &Status, protocol: Protocol, address: IpAddr, port: u16)` - [port_verdict] checks whether incoming connections to a port are accepted.
 
 use std::{
     fmt::Display,
@@ -628,6 +629,186 @@ pub fn rules_raw() -> Result<String, std::io::Error> {
     fs::read_to_string(UFW_RULES)
 }
 
+/// How UFW treats incoming connections to a listening port, see [port_verdict]
+#[derive(Debug, Serialize, ToSchema, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PortVerdict {
+    /// Whether connections from anywhere are accepted
+    pub allowed: bool,
+    /// Index of the rule that decides, missing if the default policy decides
+    pub rule: Option<usize>,
+    /// Indexes of earlier rules that accept connections only from some sources, on some
+    /// interfaces or to some addresses
+    pub partially_allowed_by: Vec<usize>,
+}
+
+impl Port {
+    fn contains(&self, port: u16) -> bool {
+        match *self {
+            Port::Any => true,
+            Port::Specific(p) => p == port,
+            Port::Range(first, last) => (first..=last).contains(&port),
+        }
+    }
+}
+
+impl Point {
+    fn is_any(&self) -> bool {
+        matches!(self.address, Address::Any)
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        let network = match self.address {
+            Address::Any => return true,
+            Address::Specific(network) => network,
+        };
+        let (network, address, bits) = match (network, address) {
+            (IpAddr::V4(n), IpAddr::V4(a)) => (u32::from(n) as u128, u32::from(a) as u128, 32),
+            (IpAddr::V6(n), IpAddr::V6(a)) => (u128::from(n), u128::from(a), 128),
+            _ => return false,
+        };
+        let prefix = self.subnet.unwrap_or(bits).min(bits);
+        let mask = match prefix {
+            0 => 0,
+            p => (u128::MAX << (bits - p)) & (u128::MAX >> (128 - bits)),
+        };
+        network & mask == address & mask
+    }
+}
+
+/// Find out whether UFW accepts incoming connections to a socket listening on `address` and
+/// `port`. Rules are evaluated in order like UFW does and the first rule that applies to all
+/// connections decides, otherwise the default policy does. Application rules are ignored, as
+/// their ports are not known.
+///
+/// Sockets listening on `::` usually accept IPv4 connections as well, but are only checked
+/// against the IPv6 rules.
+pub fn port_verdict(
+    status: &Status,
+    protocol: Protocol,
+    address: IpAddr,
+    port: u16,
+) -> PortVerdict {
+    let mut partially_allowed_by = Vec::new();
+    for (index, rule) in status.rules.iter().enumerate() {
+        let applies = matches!(rule.direction, Direction::In)
+            && !rule.forward
+            && rule.v6 == address.is_ipv6()
+            && rule.destination_app.is_empty()
+            && rule.source_app.is_empty()
+            && matches!(
+                (rule.protocol, protocol),
+                (Protocol::Any, _) | (Protocol::Tcp, Protocol::Tcp) | (Protocol::Udp, Protocol::Udp)
+            )
+            && rule.destination_port.contains(port)
+            // Sockets listening on all addresses are reached through rules for any address
+            && (rule.destination.contains(address) || address.is_unspecified());
+        if !applies {
+            continue;
+        }
+        let allows = matches!(rule.action, Action::Allow | Action::Limit);
+        let applies_to_all = rule.source.is_any()
+            && matches!(rule.source_port, Port::Any)
+            && rule.interface_in.is_none()
+            && (rule.destination.is_any() || !address.is_unspecified());
+        if applies_to_all {
+            return PortVerdict {
+                allowed: allows,
+                rule: Some(rule.index.unwrap_or(index)),
+                partially_allowed_by,
+            };
+        }
+        if allows {
+            partially_allowed_by.push(rule.index.unwrap_or(index));
+        }
+    }
+    PortVerdict {
+        allowed: status.defaults.input == DefaultAction::Accept,
+        rule: None,
+        partially_allowed_by,
+    }
+}
+
+#[cfg(test)]
+/// These tests only evaluate rules and do not change the system.
+mod verdict_tests {
+    use super::*;
+
+    #[test]
+    fn port_verdicts() {
+        let rule = |index: usize, port: Port, action: Action, source: Option<&str>| Rule {
+            v6: false,
+            destination: Point::try_from("0.0.0.0/0").unwrap(),
+            source: Point::try_from(source.unwrap_or("0.0.0.0/0")).unwrap(),
+            destination_port: port,
+            source_port: Port::Any,
+            protocol: Protocol::Tcp,
+            destination_app: "".to_string(),
+            source_app: "".to_string(),
+            action,
+            interface_in: None,
+            interface_out: None,
+            direction: Direction::In,
+            comment: "".to_string(),
+            forward: false,
+            index: Some(index),
+        };
+        let status = Status {
+            enabled: true,
+            logging: LogLevel::Low,
+            defaults: HelperDefaults {
+                input: DefaultAction::Drop,
+                output: DefaultAction::Accept,
+                forward: DefaultAction::Drop,
+            },
+            rules: vec![
+                rule(0, Port::Specific(22), Action::Allow, Some("10.0.0.0/8")),
+                rule(1, Port::Specific(22), Action::Limit, None),
+                rule(
+                    2,
+                    Port::Range(8000, 8999),
+                    Action::Deny,
+                    Some("192.168.1.7"),
+                ),
+                rule(3, Port::Range(8000, 8999), Action::Allow, None),
+                rule(4, Port::Specific(9000), Action::Deny, None),
+                rule(5, Port::Specific(9000), Action::Allow, None),
+            ],
+        };
+        let any = IpAddr::from_str("0.0.0.0").unwrap();
+
+        let ssh = port_verdict(&status, Protocol::Tcp, any, 22);
+        assert!(ssh.allowed);
+        assert_eq!(ssh.rule, Some(1));
+        assert_eq!(ssh.partially_allowed_by, vec![0]);
+
+        let web = port_verdict(&status, Protocol::Tcp, any, 8443);
+        assert!(web.allowed);
+        assert_eq!(web.rule, Some(3));
+        assert!(web.partially_allowed_by.is_empty());
+
+        // The first rule decides
+        assert!(!port_verdict(&status, Protocol::Tcp, any, 9000).allowed);
+        // Rules for TCP do not apply to UDP, so the default policy decides
+        let dns = port_verdict(&status, Protocol::Udp, any, 8053);
+        assert!(!dns.allowed);
+        assert_eq!(dns.rule, None);
+        // IPv4 rules do not apply to IPv6 sockets
+        assert_eq!(
+            port_verdict(&status, Protocol::Tcp, IpAddr::from_str("::").unwrap(), 22).rule,
+            None
+        );
+
+        let network = Point::try_from("192.168.0.0/16").unwrap();
+        assert!(network.contains(IpAddr::from_str("192.168.4.2").unwrap()));
+        assert!(!network.contains(IpAddr::from_str("192.169.0.1").unwrap()));
+        assert!(!network.contains(IpAddr::from_str("::1").unwrap()));
+        let host = Point::try_from("fe80::1").unwrap();
+        assert!(host.contains(IpAddr::from_str("fe80::1").unwrap()));
+        assert!(!host.contains(IpAddr::from_str("fe80::2").unwrap()));
+    }
+}
+
 #[cfg(test)]
 /// These tests should be run in a virtual machine, as they will change system settings and may
 /// break configurations and system security.